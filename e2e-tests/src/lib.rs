@@ -0,0 +1,296 @@
+//! End-to-end tests that run the operators in a kind cluster
+//!
+//! The tests are ignored by default, since they need `docker`, `kind`, `kubectl`, and `helm`, and take several
+//! minutes. Run them with `cargo test -p e2e-tests -- --ignored --test-threads 1`.
+//!
+//! The kind cluster is named by `E2E_KIND_CLUSTER` (`stackable-e2e` by default) and created if it doesn't exist yet.
+//! It is kept after the tests, so that failures can be investigated, and the next run reuses it. The operators are
+//! built from the workspace, packaged by `e2e-tests/Dockerfile`, and loaded into the cluster on every run.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Once,
+    time::{Duration, Instant},
+};
+
+use k8s_openapi::api::core::v1::{ConfigMap, Pod};
+use kube::{
+    api::ListParams,
+    config::{KubeConfigOptions, Kubeconfig},
+};
+
+/// Namespace of the test clusters and the KDC
+pub const NAMESPACE: &str = "e2e";
+
+const OPERATOR_IMAGE: &str = "stackable-operators:e2e";
+const SECRET_OPERATOR_CHART_VERSION: &str = "23.1.0";
+
+static SETUP: Once = Once::new();
+
+/// Name of the kind cluster
+pub fn kind_cluster() -> String {
+    std::env::var("E2E_KIND_CLUSTER").unwrap_or_else(|_| "stackable-e2e".to_string())
+}
+
+fn kube_context() -> String {
+    format!("kind-{}", kind_cluster())
+}
+
+fn workspace_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("the e2e tests must be part of the workspace")
+        .to_path_buf()
+}
+
+/// Path of a manifest in `e2e-tests/manifests`
+pub fn manifest(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("manifests")
+        .join(name)
+}
+
+/// Runs `program` and returns its output, panicking if it fails
+pub fn run(program: &str, args: &[&str]) -> String {
+    let output = Command::new(program)
+        .args(args)
+        .current_dir(workspace_dir())
+        .output()
+        .unwrap_or_else(|err| panic!("failed to run {}: {}", program, err));
+    assert!(
+        output.status.success(),
+        "{} {} failed with {}:\n{}",
+        program,
+        args.join(" "),
+        output.status,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// Runs `kubectl` against the kind cluster
+pub fn kubectl(args: &[&str]) -> String {
+    let context = kube_context();
+    let mut all_args = vec!["--context", context.as_str()];
+    all_args.extend_from_slice(args);
+    run("kubectl", &all_args)
+}
+
+/// Applies the manifest `name` from `e2e-tests/manifests`
+pub fn apply_manifest(name: &str) {
+    kubectl(&["apply", "-f", manifest(name).to_str().unwrap()]);
+}
+
+/// Creates the kind cluster and deploys the operators, their CRDs, a KDC, and secret-operator, once per test run
+pub fn setup() {
+    SETUP.call_once(|| {
+        let cluster = kind_cluster();
+        if !run("kind", &["get", "clusters"])
+            .lines()
+            .any(|line| line == cluster)
+        {
+            run(
+                "kind",
+                &["create", "cluster", "--name", &cluster, "--wait", "120s"],
+            );
+        }
+        deploy_operators();
+        deploy_kdc();
+    });
+}
+
+/// Builds the operators, loads their image into the cluster, and (re)starts them
+fn deploy_operators() {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    run(&cargo, &["build", "--release", "-p", "stackable-operators"]);
+    let binary = workspace_dir().join("target/release/stackable-operators");
+    // Only the binary is sent to docker as the build context, rather than all of `target/release`
+    let image_dir = workspace_dir().join("target/e2e-image");
+    std::fs::create_dir_all(&image_dir).unwrap();
+    std::fs::copy(&binary, image_dir.join("stackable-operators")).unwrap();
+    let dockerfile = Path::new(env!("CARGO_MANIFEST_DIR")).join("Dockerfile");
+    run(
+        "docker",
+        &[
+            "build",
+            "-t",
+            OPERATOR_IMAGE,
+            "-f",
+            dockerfile.to_str().unwrap(),
+            image_dir.to_str().unwrap(),
+        ],
+    );
+    run(
+        "kind",
+        &[
+            "load",
+            "docker-image",
+            OPERATOR_IMAGE,
+            "--name",
+            &kind_cluster(),
+        ],
+    );
+    run(
+        binary.to_str().unwrap(),
+        &["crd", "--install", "--context", &kube_context()],
+    );
+    apply_manifest("operator.yaml");
+    // The image tag is reused, so an existing deployment has to be restarted to pick up the new build
+    kubectl(&[
+        "rollout",
+        "restart",
+        "deployment/stackable-operators",
+        "-n",
+        "stackable-operators",
+    ]);
+    kubectl(&[
+        "rollout",
+        "status",
+        "deployment/stackable-operators",
+        "-n",
+        "stackable-operators",
+        "--timeout",
+        "180s",
+    ]);
+}
+
+/// Deploys secret-operator and a KDC, and hands secret-operator the KDC's admin keytab
+fn deploy_kdc() {
+    run(
+        "helm",
+        &[
+            "repo",
+            "add",
+            "--force-update",
+            "stackable-stable",
+            "https://repo.stackable.tech/repository/helm-stable/",
+        ],
+    );
+    run(
+        "helm",
+        &[
+            "upgrade",
+            "--install",
+            "secret-operator",
+            "stackable-stable/secret-operator",
+            "--version",
+            SECRET_OPERATOR_CHART_VERSION,
+            "--kube-context",
+            &kube_context(),
+            "--wait",
+        ],
+    );
+    run_shell(&format!(
+        "kubectl --context {} create namespace {} --dry-run=client -o yaml | kubectl --context {} apply -f -",
+        kube_context(),
+        NAMESPACE,
+        kube_context()
+    ));
+    apply_manifest("kerberos.yaml");
+    kubectl(&[
+        "rollout",
+        "status",
+        "statefulset/krb5-kdc",
+        "-n",
+        NAMESPACE,
+        "--timeout",
+        "300s",
+    ]);
+    // `-norandkey` keeps the key that secret-operator may already be using from a previous run
+    run_shell(&format!(
+        "kubectl --context {context} exec -n {ns} krb5-kdc-0 -c kdc -- sh -c \
+         'kadmin.local ktadd -norandkey -k /tmp/admin.keytab stackable-secret-operator >/dev/null && \
+          base64 -w0 /tmp/admin.keytab && rm /tmp/admin.keytab' \
+         | base64 -d > {keytab}",
+        context = kube_context(),
+        ns = NAMESPACE,
+        keytab = workspace_dir().join("target/e2e-admin.keytab").display(),
+    ));
+    run_shell(&format!(
+        "kubectl --context {context} create secret generic secret-operator-keytab -n {ns} \
+         --from-file=keytab={keytab} --dry-run=client -o yaml | kubectl --context {context} apply -f -",
+        context = kube_context(),
+        ns = NAMESPACE,
+        keytab = workspace_dir().join("target/e2e-admin.keytab").display(),
+    ));
+}
+
+/// Runs a shell pipeline, panicking if it fails
+pub fn run_shell(script: &str) -> String {
+    run("sh", &["-euo", "pipefail", "-c", script])
+}
+
+/// A client for the kind cluster
+pub async fn client() -> kube::Client {
+    let kubeconfig = Kubeconfig::read().expect("kind must have written a kubeconfig");
+    let config = kube::Config::from_custom_kubeconfig(
+        kubeconfig,
+        &KubeConfigOptions {
+            context: Some(kube_context()),
+            ..KubeConfigOptions::default()
+        },
+    )
+    .await
+    .expect("the kind cluster must be in the kubeconfig");
+    kube::Client::try_from(config).expect("the client must be configurable")
+}
+
+/// Polls `check` every few seconds until it returns `Some`, panicking with `what` after `timeout`
+pub async fn wait_for<T, F, Fut>(what: &str, timeout: Duration, mut check: F) -> T
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Option<T>>,
+{
+    let started = Instant::now();
+    loop {
+        if let Some(value) = check().await {
+            return value;
+        }
+        assert!(
+            started.elapsed() < timeout,
+            "timed out after {:?} waiting for {}",
+            timeout,
+            what
+        );
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    }
+}
+
+/// Waits until `count` pods that match `selector` are ready
+pub async fn wait_for_ready_pods(kube: &kube::Client, selector: &str, count: usize) {
+    let pods = kube::Api::<Pod>::namespaced(kube.clone(), NAMESPACE);
+    let params = &ListParams::default().labels(selector);
+    let pods = &pods;
+    wait_for(
+        &format!("{} ready pods with {}", count, selector),
+        Duration::from_secs(600),
+        move || async move {
+            let pods = pods.list(params).await.ok()?;
+            let ready = pods.items.iter().filter(|pod| pod_ready(pod)).count();
+            (ready >= count).then(|| ())
+        },
+    )
+    .await
+}
+
+fn pod_ready(pod: &Pod) -> bool {
+    pod.status
+        .as_ref()
+        .and_then(|status| status.conditions.as_ref())
+        .into_iter()
+        .flatten()
+        .any(|condition| condition.type_ == "Ready" && condition.status == "True")
+}
+
+/// Waits until the `ConfigMap` `name` exists, and returns it
+pub async fn wait_for_config_map(kube: &kube::Client, name: &str) -> ConfigMap {
+    let config_maps = kube::Api::<ConfigMap>::namespaced(kube.clone(), NAMESPACE);
+    let config_maps = &config_maps;
+    wait_for(
+        &format!("ConfigMap {}", name),
+        Duration::from_secs(300),
+        move || async move { config_maps.get(name).await.ok() },
+    )
+    .await
+}
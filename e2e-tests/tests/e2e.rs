@@ -0,0 +1,75 @@
+//! Deploys the sample clusters of `e2e-tests/manifests` to kind, and checks that they become usable
+
+use e2e_tests::{
+    apply_manifest, client, kubectl, setup, wait_for_config_map, wait_for_ready_pods, NAMESPACE,
+};
+
+#[tokio::test]
+#[ignore = "needs docker, kind, kubectl, and helm"]
+async fn zookeeper_cluster_becomes_ready() {
+    setup();
+    let kube = client().await;
+    apply_manifest("zookeeper.yaml");
+    wait_for_ready_pods(
+        &kube,
+        "app.kubernetes.io/name=zookeeper,app.kubernetes.io/instance=zk",
+        3,
+    )
+    .await;
+    let znode = wait_for_config_map(&kube, "hdfs-znode").await;
+    let data = znode.data.unwrap_or_default();
+    assert!(
+        data["ZOOKEEPER_HOSTS"].contains(&format!("zk-servers-0.zk-servers.{}", NAMESPACE)),
+        "{:?}",
+        data
+    );
+    assert!(data["ZOOKEEPER_CHROOT"].starts_with('/'), "{:?}", data);
+}
+
+#[tokio::test]
+#[ignore = "needs docker, kind, kubectl, and helm"]
+async fn hdfs_cluster_stores_files() {
+    setup();
+    let kube = client().await;
+    apply_manifest("zookeeper.yaml");
+    apply_manifest("hdfs.yaml");
+    // 2 namenodes, 1 datanode, and 3 journalnodes
+    wait_for_ready_pods(
+        &kube,
+        "app.kubernetes.io/name=hdfs,app.kubernetes.io/instance=hdfs",
+        6,
+    )
+    .await;
+    let discovery = wait_for_config_map(&kube, "hdfs").await;
+    assert!(
+        discovery
+            .data
+            .unwrap_or_default()
+            .contains_key("hdfs-site.xml"),
+        "the discovery ConfigMap has no hdfs-site.xml"
+    );
+
+    // The namenode's keytab only contains its own principal, which is good enough for a client
+    let content = "stored by the e2e tests";
+    let output = kubectl(&[
+        "exec",
+        "-n",
+        NAMESPACE,
+        "hdfs-namenode-0",
+        "-c",
+        "namenode",
+        "--",
+        "bash",
+        "-euo",
+        "pipefail",
+        "-c",
+        &format!(
+            "kinit -kt /kerberos/keytab \"$(klist -kt /kerberos/keytab | awk 'NR == 4 {{ print $4 }}')\" && \
+             echo '{content}' > /tmp/e2e.txt && \
+             /opt/hadoop/bin/hdfs dfs -put -f /tmp/e2e.txt /e2e.txt && \
+             /opt/hadoop/bin/hdfs dfs -cat /e2e.txt",
+            content = content
+        ),
+    ]);
+    assert_eq!(output.trim(), content);
+}
@@ -0,0 +1,217 @@
+//! Runs the commands of [`HdfsAdminTask`]s as `Job`s, and reports their outcome
+
+use std::time::Duration;
+
+use crate::{
+    crd::{AdminTaskPhase, HdfsAdminTask, HdfsAdminTaskStatus, HdfsCluster},
+    resources::{self, admin_task_job_name, build_admin_task_job, ClusterContext},
+};
+use k8s_openapi::api::core::v1::Pod;
+use kube::api::{ListParams, LogParams, Patch, PatchParams};
+use kube_runtime::{
+    controller::{Context, ReconcilerAction},
+    reflector::ObjectRef,
+};
+use operator_commons::{apply_owned, ApplyParams, ImageOptions, RateLimiter, ReconcilePermits};
+use snafu::{ResultExt, Snafu};
+
+const FIELD_MANAGER: &str = "hdfs.stackable.tech/hdfsadmintask";
+
+/// Number of lines of the command's output that are kept in the status
+const OUTPUT_TAIL_LINES: i64 = 20;
+
+pub struct Ctx {
+    pub kube: kube::Client,
+    /// DNS domain of the Kubernetes cluster, such as `cluster.local`
+    pub cluster_domain: String,
+    /// Where the images of the Jobs' pods are pulled from
+    pub image_options: ImageOptions,
+    /// Only log the changes that would be made, instead of persisting them
+    pub dry_run: bool,
+    /// Bounds the number of reconciles that run at the same time
+    pub reconcile_permits: ReconcilePermits,
+    /// Throttles the writes of managed objects, shared by all controllers
+    pub apply_rate_limiter: RateLimiter,
+    /// Log the diff of each apply at debug level
+    pub log_apply_diffs: bool,
+    /// How often unfinished tasks are reconciled even if their `Job` didn't change
+    pub resync_interval: Duration,
+}
+
+#[derive(Snafu, Debug)]
+pub enum Error {
+    #[snafu(display("failed to get HdfsCluster {}", name))]
+    GetCluster {
+        source: kube::Error,
+        name: String,
+    },
+    #[snafu(display("failed to build the task's Job"))]
+    BuildJob {
+        source: resources::Error,
+    },
+    ApplyJob {
+        source: kube::Error,
+    },
+    UpdateStatus {
+        source: kube::Error,
+    },
+}
+
+/// Replaces the status of `task` with `status`
+async fn update_status(
+    ctx: &Ctx,
+    task: &HdfsAdminTask,
+    status: HdfsAdminTaskStatus,
+) -> Result<(), Error> {
+    kube::Api::<HdfsAdminTask>::namespaced(
+        ctx.kube.clone(),
+        task.metadata.namespace.as_deref().unwrap(),
+    )
+    .patch_status(
+        task.metadata.name.as_deref().unwrap(),
+        &PatchParams {
+            dry_run: ctx.dry_run,
+            ..PatchParams::default()
+        },
+        &Patch::Merge(serde_json::json!({ "status": status })),
+    )
+    .await
+    .context(UpdateStatus)?;
+    Ok(())
+}
+
+/// The last lines that the `Job`'s pod has logged, if they can still be retrieved
+async fn job_output(kube: &kube::Client, ns: &str, job_name: &str) -> Option<String> {
+    let pods = kube::Api::<Pod>::namespaced(kube.clone(), ns);
+    let pod_name = pods
+        .list(&ListParams::default().labels(&format!("job-name={}", job_name)))
+        .await
+        .ok()?
+        .items
+        .into_iter()
+        .filter_map(|pod| pod.metadata.name)
+        .last()?;
+    match pods
+        .logs(
+            &pod_name,
+            &LogParams {
+                tail_lines: Some(OUTPUT_TAIL_LINES),
+                ..LogParams::default()
+            },
+        )
+        .await
+    {
+        Ok(logs) => Some(logs),
+        Err(err) => {
+            tracing::warn!(
+                error = &err as &dyn std::error::Error,
+                pod = %pod_name,
+                "Failed to get the output of the admin task"
+            );
+            None
+        }
+    }
+}
+
+#[tracing::instrument(skip_all, fields(object = %ObjectRef::from_obj(&task)), err)]
+pub async fn reconcile_admin_task(
+    task: HdfsAdminTask,
+    ctx: Context<Ctx>,
+) -> Result<ReconcilerAction, Error> {
+    let ctx = ctx.get_ref();
+    let done = ReconcilerAction {
+        requeue_after: None,
+    };
+    let _permit = match ctx.reconcile_permits.acquire().await {
+        Some(permit) => permit,
+        // Shutting down, the object is reconciled again by the next instance of the operator
+        None => return Ok(done),
+    };
+    let status = task.status.clone().unwrap_or_default();
+    if status.phase.is_finished() {
+        return Ok(done);
+    }
+    let kube = ctx.kube.clone();
+    let ns = task.metadata.namespace.as_deref().unwrap();
+    let cluster_name = &task.spec.cluster_name;
+    let hdfs = match kube::Api::<HdfsCluster>::namespaced(kube.clone(), ns)
+        .get(cluster_name)
+        .await
+    {
+        Ok(hdfs) => hdfs,
+        Err(kube::Error::Api(err)) if err.code == 404 => {
+            update_status(
+                ctx,
+                &task,
+                HdfsAdminTaskStatus {
+                    phase: AdminTaskPhase::Pending,
+                    message: Some(format!("Waiting for HdfsCluster {}", cluster_name)),
+                    ..HdfsAdminTaskStatus::default()
+                },
+            )
+            .await?;
+            return Ok(ReconcilerAction {
+                requeue_after: Some(Duration::from_secs(30)),
+            });
+        }
+        Err(err) => return Err(err).context(GetCluster { name: cluster_name }),
+    };
+    let mut cluster = ClusterContext::new(&hdfs, &ctx.cluster_domain).context(BuildJob)?;
+    cluster.image_options = ctx.image_options.clone();
+    let job = match build_admin_task_job(&cluster, &task) {
+        Ok(job) => job,
+        // Retrying won't help, the task must be recreated with a valid spec
+        Err(err) => {
+            update_status(
+                ctx,
+                &task,
+                HdfsAdminTaskStatus {
+                    phase: AdminTaskPhase::Failed,
+                    message: Some(err.to_string()),
+                    ..HdfsAdminTaskStatus::default()
+                },
+            )
+            .await?;
+            return Ok(done);
+        }
+    };
+    let params = ApplyParams {
+        field_manager: FIELD_MANAGER.to_string(),
+        dry_run: ctx.dry_run,
+        log_diffs: ctx.log_apply_diffs,
+        rate_limiter: Some(ctx.apply_rate_limiter.clone()),
+    };
+    let job = apply_owned(&kube, &params, &job).await.context(ApplyJob)?;
+    let job_name = admin_task_job_name(&task);
+    let job_status = job.status.unwrap_or_default();
+    let phase = if job_status.succeeded.unwrap_or(0) > 0 {
+        AdminTaskPhase::Succeeded
+    } else if job_status.failed.unwrap_or(0) > 0 {
+        AdminTaskPhase::Failed
+    } else {
+        AdminTaskPhase::Running
+    };
+    let output = if phase.is_finished() {
+        job_output(&kube, ns, &job_name).await
+    } else {
+        None
+    };
+    update_status(
+        ctx,
+        &task,
+        HdfsAdminTaskStatus {
+            phase,
+            job_name: Some(job_name),
+            message: None,
+            output,
+        },
+    )
+    .await?;
+    if phase.is_finished() {
+        return Ok(done);
+    }
+    // The owned `Job` is watched, the resync only catches up on missed events
+    Ok(ReconcilerAction {
+        requeue_after: Some(ctx.resync_interval),
+    })
+}
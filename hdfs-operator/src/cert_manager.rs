@@ -0,0 +1,42 @@
+//! The parts of cert-manager's `Certificate` resource that the operator manages
+//!
+//! cert-manager owns the CRD itself, so it is never installed or printed by this operator.
+
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, CustomResource, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[kube(
+    group = "cert-manager.io",
+    version = "v1",
+    kind = "Certificate",
+    plural = "certificates",
+    namespaced
+)]
+#[kube(status = "CertificateStatus")]
+#[serde(rename_all = "camelCase")]
+pub struct CertificateSpec {
+    /// `Secret` that cert-manager stores the certificate (`tls.crt`), its key (`tls.key`), and the CA (`ca.crt`) in
+    pub secret_name: String,
+    pub dns_names: Vec<String>,
+    pub issuer_ref: IssuerRef,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IssuerRef {
+    pub name: String,
+    pub kind: String,
+    pub group: String,
+}
+
+/// The parts of the status that cert-manager reports about the issued certificate
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CertificateStatus {
+    /// When the current certificate expires
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_after: Option<Time>,
+}
@@ -0,0 +1,198 @@
+//! Merges the layers that the properties of the Hadoop configuration files are taken from
+//!
+//! From lowest to highest precedence, these are the operator's defaults for all roles, its defaults for a single role,
+//! the role's `configOverrides`, and the `configOverrides` of a datanode group's `pods`. The merged result is also
+//! written to the `effective-config` key of each role's `ConfigMap`, see [`effective_config`].
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::hadoop_xml::HadoopProperty;
+
+/// Key of the merged properties and their sources in each role's `ConfigMap`
+pub const EFFECTIVE_CONFIG_KEY: &str = "effective-config";
+
+/// Where a property's value was taken from, in order of precedence
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConfigLayer {
+    /// Generated by the operator for all roles
+    OperatorDefaults,
+    /// Generated by the operator for a single role
+    RoleDefaults,
+    /// The role's `configOverrides`
+    RoleOverrides,
+    /// The `configOverrides` of a datanode group's `pods`
+    RoleGroupOverrides,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct EffectiveProperty {
+    pub value: String,
+    pub source: ConfigLayer,
+    #[serde(rename = "final", skip_serializing_if = "std::ops::Not::not")]
+    pub final_: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+/// The properties of a configuration file, merged from all layers
+///
+/// Properties keep the position that they were first set at, so that overriding one doesn't reorder the file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MergedConfig {
+    properties: Vec<(String, EffectiveProperty)>,
+}
+
+impl MergedConfig {
+    /// Sets the property `name`, replacing its value from any previous layer but keeping its `final` flag and tags
+    pub fn set(&mut self, layer: ConfigLayer, name: String, value: String) {
+        match self.properties.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, existing)) => {
+                existing.value = value;
+                existing.source = layer;
+            }
+            None => self.properties.push((
+                name,
+                EffectiveProperty {
+                    value,
+                    source: layer,
+                    final_: false,
+                    tags: Vec::new(),
+                },
+            )),
+        }
+    }
+
+    /// Sets the `final` flag (if `Some`) and the tags (if not empty) of the property `name`
+    ///
+    /// Returns `false` if the property isn't set, since Hadoop ignores attributes without a value.
+    pub fn set_attributes(&mut self, name: &str, final_: Option<bool>, tags: &[String]) -> bool {
+        match self.properties.iter_mut().find(|(n, _)| n == name) {
+            Some((_, property)) => {
+                if let Some(final_) = final_ {
+                    property.final_ = final_;
+                }
+                if !tags.is_empty() {
+                    property.tags = tags.to_vec();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sets all `properties` of `layer`
+    pub fn merge(
+        &mut self,
+        layer: ConfigLayer,
+        properties: impl IntoIterator<Item = (String, String)>,
+    ) {
+        for (name, value) in properties {
+            self.set(layer, name, value);
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&EffectiveProperty> {
+        self.properties
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, property)| property)
+    }
+
+    /// Replaces the value of each property by `f` of it
+    pub fn map_values(&mut self, mut f: impl FnMut(&str) -> String) {
+        for (_, property) in &mut self.properties {
+            property.value = f(&property.value);
+        }
+    }
+
+    /// The properties as they are written to the configuration file, in file order
+    pub fn hadoop_properties(&self) -> impl Iterator<Item = HadoopProperty<'_>> {
+        self.properties
+            .iter()
+            .map(|(name, property)| HadoopProperty {
+                final_: property.final_,
+                tags: &property.tags,
+                ..HadoopProperty::new(name, &property.value)
+            })
+    }
+}
+
+/// YAML listing the value and source of every property of each of `files`, by file name
+pub fn effective_config(files: &BTreeMap<&str, MergedConfig>) -> String {
+    let files = files
+        .iter()
+        .map(|(file, config)| {
+            let properties = config
+                .properties
+                .iter()
+                .map(|(name, property)| (name.as_str(), property))
+                .collect::<BTreeMap<_, _>>();
+            (*file, properties)
+        })
+        .collect::<BTreeMap<_, _>>();
+    serde_yaml::to_string(&files).expect("effective config must be serializable")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn later_layers_take_precedence_in_place() {
+        let mut config = MergedConfig::default();
+        config.merge(
+            ConfigLayer::OperatorDefaults,
+            [
+                ("a".to_string(), "1".to_string()),
+                ("b".to_string(), "2".to_string()),
+            ],
+        );
+        config.set(ConfigLayer::RoleDefaults, "c".to_string(), "3".to_string());
+        config.set(ConfigLayer::RoleOverrides, "a".to_string(), "4".to_string());
+        config.set(
+            ConfigLayer::RoleGroupOverrides,
+            "a".to_string(),
+            "5".to_string(),
+        );
+        assert_eq!(
+            config
+                .hadoop_properties()
+                .map(|property| (property.name, property.value))
+                .collect::<Vec<_>>(),
+            [("a", "5"), ("b", "2"), ("c", "3")]
+        );
+        assert_eq!(
+            config.get("a").unwrap().source,
+            ConfigLayer::RoleGroupOverrides
+        );
+        assert_eq!(
+            config.get("b").unwrap().source,
+            ConfigLayer::OperatorDefaults
+        );
+    }
+
+    #[test]
+    fn attributes_survive_later_values() {
+        let mut config = MergedConfig::default();
+        config.set(
+            ConfigLayer::OperatorDefaults,
+            "a".to_string(),
+            "1".to_string(),
+        );
+        assert!(config.set_attributes("a", Some(true), &["SECURITY".to_string()]));
+        config.set(ConfigLayer::RoleOverrides, "a".to_string(), "2".to_string());
+        // Unset attributes keep those of lower layers
+        assert!(config.set_attributes("a", None, &[]));
+        assert!(!config.set_attributes("b", Some(true), &[]));
+        let a = config.get("a").unwrap();
+        assert_eq!(a.value, "2");
+        assert!(a.final_);
+        assert_eq!(a.tags, ["SECURITY"]);
+        let yaml = effective_config(&BTreeMap::from([("core-site.xml", config)]));
+        assert!(yaml.contains("final: true"), "{}", yaml);
+        assert!(yaml.contains("- SECURITY"), "{}", yaml);
+    }
+}
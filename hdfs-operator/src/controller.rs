@@ -1,738 +1,2153 @@
-use std::{collections::BTreeMap, fmt::Debug, time::Duration};
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use crate::crd::HdfsCluster;
+use crate::{
+    cert_manager::Certificate,
+    crd::{
+        AdminTaskPhase, BatchedRolloutConfig, FailedVolumes, HdfsAdminTask, HdfsCluster,
+        HdfsClusterStatus, HdfsEndpoints, RolePhase, RoleStatus,
+    },
+    jmx, preflight,
+    resources::{
+        self, autoscaled_datanode_replicas, build_allow_snapshot_task, build_certificate,
+        build_config_maps, build_datanode_services, build_datanode_statefulsets,
+        build_discovery_config_map, build_endpoints, build_journalnode_service,
+        build_journalnode_statefulset, build_keystore_secret, build_namenode_service,
+        build_namenode_statefulset, build_network_policy, build_pod_disruption_budget,
+        build_refresh_nodes_task, build_role_binding, build_service_account, check_placeholders,
+        datanode_group_role, datanode_roles, decommissioning_datanodes, maintenance_datanodes,
+        overdue_checkpoint, secret_hash, teardown_order, ClusterContext, ScaleDown, APP_NAME,
+        TLS_SECRET_HASH_ANNOTATION, ZOOKEEPER_VARIABLES,
+    },
+    rollout,
+    secret_operator::SecretClass,
+};
 use k8s_openapi::{
     api::{
-        apps::v1::{StatefulSet, StatefulSetSpec},
-        core::v1::{
-            ConfigMap, ConfigMapKeySelector, ConfigMapVolumeSource, Container, ContainerPort,
-            EnvVar, EnvVarSource, PersistentVolumeClaim, PersistentVolumeClaimSpec, PodSpec,
-            PodTemplateSpec, ResourceRequirements, SecretVolumeSource, Service, ServicePort,
-            ServiceSpec, Volume, VolumeMount,
-        },
-    },
-    apimachinery::pkg::{
-        api::resource::Quantity,
-        apis::meta::v1::{LabelSelector, OwnerReference},
-        util::intstr::IntOrString,
+        apps::v1::StatefulSet,
+        core::v1::{ConfigMap, Pod, Secret, SecretReference, Service, ServiceAccount},
+        networking::v1::NetworkPolicy,
+        policy::v1::PodDisruptionBudget,
+        rbac::v1::RoleBinding,
     },
+    apimachinery::pkg::apis::meta::v1::{Condition, Time},
+    chrono::{self, Utc},
 };
 use kube::{
-    api::{DynamicObject, ObjectMeta, Patch, PatchParams},
+    api::{DeleteParams, ListParams, Patch, PatchParams},
     Resource,
 };
 use kube_runtime::{
     controller::{Context, ReconcilerAction},
+    events::{Event, EventType, Recorder, Reporter},
     reflector::ObjectRef,
 };
+use operator_commons::{
+    apply_owned, delete_if_controlled_by, delete_if_exists, delete_orphans, diff_deleted,
+    diff_owned,
+    labels::{APP_INSTANCE_LABEL, APP_NAME_LABEL, APP_VERSION_LABEL},
+    publish_event, ApplyParams, ImageOptions, RateLimiter, ReconcilePermits,
+};
+use rand::{distributions::Alphanumeric, Rng};
 use serde::{de::DeserializeOwned, Serialize};
-use snafu::{OptionExt, ResultExt, Snafu};
+use snafu::{ensure, ResultExt, Snafu};
+
+const FIELD_MANAGER: &str = "hdfs.stackable.tech/hdfscluster";
+
+const ROLES: [&str; 3] = ["journalnode", "namenode", "datanode"];
+
+/// Holds back the deletion of `HdfsCluster`s with `orderedTeardown` until their roles have been stopped
+const TEARDOWN_FINALIZER: &str = "hdfs.stackable.tech/ordered-teardown";
+
+/// How long before a cert-manager certificate expires it is warned about, if it hasn't been renewed by then
+const CERTIFICATE_EXPIRY_WARNING_DAYS: i64 = 7;
 
 pub struct Ctx {
     pub kube: kube::Client,
+    /// DNS domain of the Kubernetes cluster, such as `cluster.local`
+    pub cluster_domain: String,
+    /// Where the images of the pods are pulled from
+    pub image_options: ImageOptions,
+    /// Only log the changes that would be made, instead of persisting them
+    pub dry_run: bool,
+    /// Bounds the number of reconciles that run at the same time
+    pub reconcile_permits: ReconcilePermits,
+    /// Throttles the writes of managed objects, shared by all controllers
+    pub apply_rate_limiter: RateLimiter,
+    /// Log the diff of each apply at debug level
+    pub log_apply_diffs: bool,
+    /// How often objects are reconciled even if nothing changed
+    pub resync_interval: Duration,
+}
+
+impl Ctx {
+    fn apply_params(&self) -> ApplyParams {
+        ApplyParams {
+            field_manager: FIELD_MANAGER.to_string(),
+            dry_run: self.dry_run,
+            log_diffs: self.log_apply_diffs,
+            rate_limiter: Some(self.apply_rate_limiter.clone()),
+        }
+    }
 }
 
 #[derive(Snafu, Debug)]
 #[allow(clippy::enum_variant_names)]
 pub enum Error {
-    ObjectHasNoNamespace { obj_ref: ObjectRef<DynamicObject> },
-    ApplyExternalService { source: kube::Error },
-    ApplyPeerService { source: kube::Error },
-    ApplyStatefulSet { source: kube::Error },
-}
-
-fn controller_reference_to_obj<K: Resource<DynamicType = ()>>(obj: &K) -> OwnerReference {
-    OwnerReference {
-        api_version: K::api_version(&()).into_owned(),
-        kind: K::kind(&()).into_owned(),
-        controller: Some(true),
-        name: obj.meta().name.clone().unwrap(),
-        uid: obj.meta().uid.clone().unwrap(),
-        ..OwnerReference::default()
-    }
-}
-
-fn hadoop_config_xml<I: IntoIterator<Item = (K, V)>, K: AsRef<str>, V: AsRef<str>>(
-    kvs: I,
-) -> String {
-    use std::fmt::Write;
-    let mut xml = "<configuration>\n".to_string();
-    for (k, v) in kvs {
-        writeln!(
-            xml,
-            "<property><name>{}</name><value>{}</value></property>",
-            k.as_ref(),
-            v.as_ref()
+    #[snafu(display("failed to build the cluster's objects"))]
+    BuildResources {
+        source: resources::Error,
+    },
+    ApplyExternalService {
+        source: kube::Error,
+    },
+    ApplyConfigMap {
+        source: kube::Error,
+    },
+    ApplyDiscoveryConfigMap {
+        source: kube::Error,
+    },
+    ApplyServiceAccount {
+        source: kube::Error,
+    },
+    ApplyRoleBinding {
+        source: kube::Error,
+    },
+    DeleteRoleBinding {
+        source: kube::Error,
+    },
+    ApplyNetworkPolicy {
+        source: kube::Error,
+    },
+    ApplyCertificate {
+        source: kube::Error,
+    },
+    ApplyKeystoreSecret {
+        source: kube::Error,
+    },
+    ApplyRefreshNodesTask {
+        source: kube::Error,
+    },
+    ApplyAllowSnapshotTask {
+        source: kube::Error,
+    },
+    ScaleDatanodes {
+        source: kube::Error,
+    },
+    #[snafu(display("failed to get StatefulSet {}", name))]
+    GetStatefulSet {
+        source: kube::Error,
+        name: String,
+    },
+    #[snafu(display("failed to get Pod {}", name))]
+    GetPod {
+        source: kube::Error,
+        name: String,
+    },
+    ListAdminTasks {
+        source: kube::Error,
+    },
+    #[snafu(display("failed to delete HdfsAdminTask {}", name))]
+    DeleteAdminTask {
+        source: kube::Error,
+        name: String,
+    },
+    DeleteNetworkPolicy {
+        source: kube::Error,
+    },
+    ApplyPodDisruptionBudget {
+        source: kube::Error,
+    },
+    #[snafu(display("failed to apply roles {}", roles))]
+    ApplyRoles {
+        roles: String,
+    },
+    GetZookeeperConfigMap {
+        source: kube::Error,
+    },
+    UpdateStatus {
+        source: kube::Error,
+    },
+    ListPods {
+        source: kube::Error,
+        role: String,
+    },
+    ListClusterPods {
+        source: kube::Error,
+    },
+    #[snafu(display("failed to delete Pod {}", name))]
+    DeletePod {
+        source: kube::Error,
+        name: String,
+    },
+    #[snafu(display("failed to get Secret {}", name))]
+    GetSecret {
+        source: kube::Error,
+        name: String,
+    },
+    #[snafu(display("failed to get SecretClass {}", name))]
+    GetSecretClass {
+        source: kube::Error,
+        name: String,
+    },
+    #[snafu(display("failed to diff {} {}", kind, name))]
+    Diff {
+        source: kube::Error,
+        kind: String,
+        name: String,
+    },
+    DeleteOrphans {
+        source: kube::Error,
+        kind: String,
+    },
+    UpdateFinalizers {
+        source: kube::Error,
+    },
+    #[snafu(display("failed to delete StatefulSet {}", name))]
+    DeleteStatefulSet {
+        source: kube::Error,
+        name: String,
+    },
+}
+
+/// The cluster whose Kerberos keytabs or cert-manager certificate are stored in `secret`, if any
+pub fn secret_cluster(secret: Secret) -> Option<ObjectRef<HdfsCluster>> {
+    let name = secret.metadata.name?;
+    let ns = secret.metadata.namespace?;
+    let role_name = name
+        .strip_suffix("-kerberos")
+        .or_else(|| name.strip_suffix("-tls"))?;
+    let cluster_name = ROLES
+        .iter()
+        .find_map(|role| role_name.strip_suffix(role)?.strip_suffix('-'))?;
+    Some(ObjectRef::new(cluster_name).within(&ns))
+}
+
+/// The cluster that `pod` belongs to, if any
+pub fn pod_cluster(pod: Pod) -> Option<ObjectRef<HdfsCluster>> {
+    let labels = pod.metadata.labels?;
+    if labels.get(APP_NAME_LABEL).map(String::as_str) != Some(APP_NAME) {
+        return None;
+    }
+    Some(ObjectRef::new(labels.get(APP_INSTANCE_LABEL)?).within(&pod.metadata.namespace?))
+}
+
+/// The scheduler's reason for each of `pods` that it can't find a node for, by pod name
+///
+/// Such as when no node has enough resources left, or a datanode's volume can't be provisioned.
+fn unschedulable_pods(pods: &[Pod]) -> Vec<(String, String)> {
+    pods.iter()
+        .filter_map(|pod| {
+            let status = pod.status.as_ref()?;
+            if status.phase.as_deref() != Some("Pending") {
+                return None;
+            }
+            let scheduled = status
+                .conditions
+                .iter()
+                .flatten()
+                .find(|cond| cond.type_ == "PodScheduled")?;
+            if scheduled.status != "False" || scheduled.reason.as_deref() != Some("Unschedulable") {
+                return None;
+            }
+            Some((
+                pod.metadata.name.clone()?,
+                scheduled.message.clone().unwrap_or_default(),
+            ))
+        })
+        .collect()
+}
+
+/// Hashes the `Secret` of each role in `secret_names`, so that the role is restarted when it changes
+///
+/// Roles whose `Secret` doesn't exist yet are skipped, their pods can't start until it is created anyway.
+async fn fetch_secret_hashes(
+    kube: &kube::Client,
+    cluster: &ClusterContext<'_>,
+    secret_names: impl IntoIterator<Item = (&str, String)>,
+) -> Result<BTreeMap<String, String>, Error> {
+    let secrets = kube::Api::<Secret>::namespaced(kube.clone(), &cluster.namespace);
+    let mut hashes = BTreeMap::new();
+    for (role, name) in secret_names {
+        match secrets.get(&name).await {
+            Ok(secret) => {
+                hashes.insert(role.to_string(), secret_hash(&secret));
+            }
+            Err(kube::Error::Api(err)) if err.code == 404 => {}
+            Err(err) => return Err(err).context(GetSecret { name }),
+        }
+    }
+    Ok(hashes)
+}
+
+/// Sets the hashes of the Kerberos and cert-manager TLS `Secret`s of all roles
+async fn update_secret_hashes(
+    kube: &kube::Client,
+    cluster: &mut ClusterContext<'_>,
+) -> Result<(), Error> {
+    let kerberos = ROLES.map(|role| (role, cluster.kerberos_secret_name(role)));
+    cluster.secret_hashes = fetch_secret_hashes(kube, cluster, kerberos).await?;
+    // secret-operator provisions a certificate for each pod when it starts, so only cert-manager's are hashed
+    let tls = ROLES
+        .into_iter()
+        .filter(|role| build_certificate(cluster, role).is_some())
+        .map(|role| (role, cluster.tls_secret_name(role)))
+        .collect::<Vec<_>>();
+    cluster.tls_secret_hashes = fetch_secret_hashes(kube, cluster, tls).await?;
+    Ok(())
+}
+
+/// The `ca.crt` of the `Secret` `name`, or `None` if it doesn't exist yet
+async fn fetch_ca(kube: &kube::Client, ns: &str, name: &str) -> Result<Option<Vec<u8>>, Error> {
+    match kube::Api::<Secret>::namespaced(kube.clone(), ns)
+        .get(name)
+        .await
+    {
+        Ok(secret) => Ok(secret
+            .data
+            .and_then(|mut data| data.remove("ca.crt"))
+            .map(|ca| ca.0)),
+        Err(kube::Error::Api(err)) if err.code == 404 => Ok(None),
+        Err(err) => Err(err).context(GetSecret { name }),
+    }
+}
+
+/// The CA that signed the daemons' TLS certificates, so that the operator can query their HTTPS-only web UIs
+///
+/// cert-manager stores the CA along with each role's certificate, which are all signed by the same issuer.
+/// secret-operator signs the certificates of a `SecretClass` with the CA of its `autoTls` backend.
+async fn fetch_tls_ca(
+    kube: &kube::Client,
+    cluster: &ClusterContext<'_>,
+) -> Result<Option<Vec<u8>>, Error> {
+    let tls = match &cluster.hdfs.spec.tls {
+        Some(tls) => tls,
+        None => return Ok(None),
+    };
+    let secret_class = match &tls.secret_class {
+        Some(secret_class) => secret_class,
+        None => {
+            return fetch_ca(
+                kube,
+                &cluster.namespace,
+                &cluster.tls_secret_name("namenode"),
+            )
+            .await
+        }
+    };
+    let ca_secret = match kube::Api::<SecretClass>::all(kube.clone())
+        .get(secret_class)
+        .await
+    {
+        Ok(class) => class
+            .spec
+            .backend
+            .auto_tls
+            .map(|auto_tls| auto_tls.ca.secret),
+        Err(kube::Error::Api(err)) if err.code == 404 => None,
+        Err(err) => return Err(err).context(GetSecretClass { name: secret_class }),
+    };
+    match ca_secret {
+        Some(SecretReference {
+            name: Some(name),
+            namespace: Some(ns),
+        }) => fetch_ca(kube, &ns, &name).await,
+        _ => Ok(None),
+    }
+}
+
+/// Publishes events about the cert-manager certificates of the roles
+///
+/// Roles are restarted when their certificate is renewed, see [`TLS_SECRET_HASH_ANNOTATION`], which is announced with
+/// the new certificate's expiry. Certificates that are about to expire are warned about, since cert-manager has
+/// evidently failed to renew them, and restarting won't help.
+async fn report_certificates(ctx: &Ctx, cluster: &ClusterContext<'_>) -> Result<(), Error> {
+    if ctx.dry_run {
+        return Ok(());
+    }
+    let certificates = kube::Api::<Certificate>::namespaced(ctx.kube.clone(), &cluster.namespace);
+    let statefulsets = kube::Api::<StatefulSet>::namespaced(ctx.kube.clone(), &cluster.namespace);
+    let recorder = recorder(ctx, cluster.hdfs);
+    for role in ROLES {
+        let certificate_name = match build_certificate(cluster, role) {
+            Some(certificate) => certificate.metadata.name.unwrap_or_default(),
+            None => continue,
+        };
+        let not_after = match certificates.get(&certificate_name).await {
+            Ok(certificate) => certificate.status.and_then(|status| status.not_after),
+            Err(_) => None,
+        };
+        let name = cluster.role_name(role);
+        let live_hash = match statefulsets.get(&name).await {
+            Ok(sts) => sts
+                .spec
+                .and_then(|spec| spec.template.metadata?.annotations)
+                .and_then(|mut annotations| annotations.remove(TLS_SECRET_HASH_ANNOTATION)),
+            Err(kube::Error::Api(err)) if err.code == 404 => None,
+            Err(err) => return Err(err).context(GetStatefulSet { name }),
+        };
+        let renewed = match (live_hash, cluster.tls_secret_hashes.get(role)) {
+            (Some(live_hash), Some(hash)) => live_hash != *hash,
+            _ => false,
+        };
+        let event = match not_after {
+            Some(Time(not_after)) if renewed => Event {
+                type_: EventType::Normal,
+                reason: "CertificateRenewed".to_string(),
+                note: Some(format!(
+                    "Restarting the {}s to load their renewed certificate, which expires at {}",
+                    role,
+                    not_after.to_rfc3339()
+                )),
+                action: "Restart".to_string(),
+                secondary: None,
+            },
+            Some(Time(not_after))
+                if not_after - Utc::now() < chrono::Duration::days(CERTIFICATE_EXPIRY_WARNING_DAYS) =>
+            {
+                Event {
+                    type_: EventType::Warning,
+                    reason: "CertificateExpiring".to_string(),
+                    note: Some(format!(
+                        "The certificate of the {}s expires at {}, but cert-manager has not renewed Certificate {}",
+                        role,
+                        not_after.to_rfc3339(),
+                        certificate_name
+                    )),
+                    action: "Renew".to_string(),
+                    secondary: None,
+                }
+            }
+            _ => continue,
+        };
+        publish_event(&recorder, event).await;
+    }
+    Ok(())
+}
+
+/// Creates the `Secret` with the keystore password, unless it already exists
+///
+/// The password is generated randomly, so the `Secret` is never reapplied, which would change it.
+async fn ensure_keystore_secret(
+    kube: &kube::Client,
+    params: &ApplyParams,
+    cluster: &ClusterContext<'_>,
+) -> Result<(), Error> {
+    let name = cluster.keystore_secret_name();
+    match kube::Api::<Secret>::namespaced(kube.clone(), &cluster.namespace)
+        .get(&name)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(kube::Error::Api(err)) if err.code == 404 => {
+            let password = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(32)
+                .map(char::from)
+                .collect();
+            apply_owned(kube, params, &build_keystore_secret(cluster, password))
+                .await
+                .context(ApplyKeystoreSecret)?;
+            Ok(())
+        }
+        Err(err) => Err(err).context(GetSecret { name }),
+    }
+}
+
+/// Roles whose cert-manager `Secret` has not been issued yet
+async fn pending_certificates(
+    kube: &kube::Client,
+    cluster: &ClusterContext<'_>,
+) -> Result<Vec<&'static str>, Error> {
+    let secrets = kube::Api::<Secret>::namespaced(kube.clone(), &cluster.namespace);
+    let mut pending = Vec::new();
+    for role in ROLES {
+        if build_certificate(cluster, role).is_none() {
+            continue;
+        }
+        let name = cluster.tls_secret_name(role);
+        match secrets.get(&name).await {
+            Ok(_) => {}
+            Err(kube::Error::Api(err)) if err.code == 404 => pending.push(role),
+            Err(err) => return Err(err).context(GetSecret { name }),
+        }
+    }
+    Ok(pending)
+}
+
+/// Whether every namenode sees at least `datanodes` live datanodes, so restarted datanodes have registered again
+async fn datanodes_registered(cluster: &ClusterContext<'_>, datanodes: i32) -> bool {
+    for i in 0..cluster.hdfs.spec.namenode_replicas.unwrap_or(1) {
+        let namenode = cluster.pod_fqdn("namenode", i);
+        match jmx::get_bean(
+            &namenode,
+            cluster.ports.namenode_web(),
+            cluster.tls_ca.as_deref(),
+            "Hadoop:service=NameNode,name=FSNamesystemState",
         )
-        .unwrap();
+        .await
+        {
+            Ok(bean) => {
+                let live = bean.get("NumLiveDataNodes").and_then(|live| live.as_i64());
+                if live.unwrap_or(0) < i64::from(datanodes) {
+                    return false;
+                }
+            }
+            Err(err) => {
+                tracing::debug!(
+                    error = &err as &dyn std::error::Error,
+                    %namenode,
+                    "Failed to count the live datanodes"
+                );
+                return false;
+            }
+        }
     }
-    xml.push_str("</configuration>");
-    xml
+    true
+}
+
+/// Name of the namenode pod that is currently active, if any of them can be reached
+async fn active_namenode(cluster: &ClusterContext<'_>) -> Option<String> {
+    for i in 0..cluster.hdfs.spec.namenode_replicas.unwrap_or(1) {
+        let state = jmx::get_bean(
+            &cluster.pod_fqdn("namenode", i),
+            cluster.ports.namenode_web(),
+            cluster.tls_ca.as_deref(),
+            "Hadoop:service=NameNode,name=NameNodeStatus",
+        )
+        .await;
+        if let Ok(bean) = state {
+            if bean.get("State").and_then(|state| state.as_str()) == Some("active") {
+                return Some(format!("{}-{}", cluster.role_name("namenode"), i));
+            }
+        }
+    }
+    None
+}
+
+/// Why the active namenode's last checkpoint is overdue, if it is
+///
+/// Only standby namenodes take checkpoints, so there are none to monitor with a single namenode.
+async fn checkpoint_issue(cluster: &ClusterContext<'_>) -> Option<String> {
+    let namenodes = cluster.hdfs.spec.namenode_replicas.unwrap_or(1);
+    if namenodes < 2 {
+        return None;
+    }
+    for i in 0..namenodes {
+        let bean = jmx::get_bean(
+            &cluster.pod_fqdn("namenode", i),
+            cluster.ports.namenode_web(),
+            cluster.tls_ca.as_deref(),
+            "Hadoop:service=NameNode,name=FSNamesystem",
+        )
+        .await;
+        let bean = match bean {
+            Ok(bean)
+                if bean.get("tag.HAState").and_then(|state| state.as_str()) == Some("active") =>
+            {
+                bean
+            }
+            _ => continue,
+        };
+        // Milliseconds since the epoch, which the standby sets when it uploads the new fsimage
+        let last_checkpoint = bean.get("LastCheckpointTime")?.as_i64()?;
+        let transactions_since = bean.get("TransactionsSinceLastCheckpoint")?.as_i64()?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_millis() as i64;
+        return overdue_checkpoint(cluster, (now - last_checkpoint) / 1000, transactions_since);
+    }
+    None
+}
+
+/// What the daemons report about themselves over JMX, for the cluster's status
+struct DaemonReport {
+    active_namenode: Option<String>,
+    failed_volumes: Vec<FailedVolumes>,
+    overdue_checkpoint: Option<String>,
 }
 
-fn local_disk_claim(name: &str, size: Quantity) -> PersistentVolumeClaim {
-    PersistentVolumeClaim {
-        metadata: ObjectMeta {
-            name: Some(name.to_string()),
-            ..ObjectMeta::default()
+impl DaemonReport {
+    /// Asks the daemons, which may take up to the JMX timeout if any of them don't respond
+    ///
+    /// Only worth it once the namenodes are ready, before that there is nobody to answer.
+    async fn query(cluster: &ClusterContext<'_>) -> Self {
+        let (active_namenode, failed_volumes, overdue_checkpoint) = futures::join!(
+            active_namenode(cluster),
+            failed_volumes(cluster),
+            checkpoint_issue(cluster)
+        );
+        Self {
+            active_namenode,
+            failed_volumes,
+            overdue_checkpoint,
+        }
+    }
+
+    /// The report of the last reconcile that queried the daemons, according to the cluster's status
+    fn previous(hdfs: &HdfsCluster) -> Self {
+        let status = hdfs.status.as_ref();
+        Self {
+            active_namenode: status.and_then(|status| status.active_namenode.clone()),
+            failed_volumes: status.map_or_else(Vec::new, |status| status.failed_volumes.clone()),
+            overdue_checkpoint: status
+                .and_then(|status| status.conditions.as_ref())
+                .and_then(|conditions| {
+                    conditions
+                        .iter()
+                        .find(|cond| cond.type_ == "Degraded" && cond.reason == "CheckpointOverdue")
+                })
+                .map(|cond| cond.message.clone()),
+        }
+    }
+}
+
+/// The ZooKeeper servers listed in the `zookeeperConfigMapName`, if it can be read
+async fn zookeeper_quorum(kube: &kube::Client, cluster: &ClusterContext<'_>) -> Option<String> {
+    let name = cluster.hdfs.spec.zookeeper_config_map_name()?;
+    let config_map = kube::Api::<ConfigMap>::namespaced(kube.clone(), &cluster.namespace)
+        .get(name)
+        .await
+        .ok()?;
+    config_map.data?.remove("ZOOKEEPER_HOSTS")
+}
+
+/// Values of the `${env.NAME}` placeholders that are known before the pods start, which are the keys of the
+/// `zookeeperConfigMapName`
+///
+/// A missing `ConfigMap` leaves them to the failover controllers, which can't start before it exists anyway.
+async fn config_variables(
+    kube: &kube::Client,
+    cluster: &ClusterContext<'_>,
+) -> Result<BTreeMap<String, String>, Error> {
+    let name = match cluster.hdfs.spec.zookeeper_config_map_name() {
+        Some(name) => name,
+        None => return Ok(BTreeMap::new()),
+    };
+    match kube::Api::<ConfigMap>::namespaced(kube.clone(), &cluster.namespace)
+        .get(name)
+        .await
+    {
+        Ok(config_map) => Ok(config_map
+            .data
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(key, _)| ZOOKEEPER_VARIABLES.contains(&key.as_str()))
+            .collect()),
+        Err(kube::Error::Api(err)) if err.code == 404 => Ok(BTreeMap::new()),
+        Err(err) => Err(err).context(GetZookeeperConfigMap),
+    }
+}
+
+/// The failed volumes of each datanode that can be reached
+///
+/// Datanodes only keep running with failed volumes if they are allowed to, so the datanodes aren't even asked otherwise.
+async fn failed_volumes(cluster: &ClusterContext<'_>) -> Vec<FailedVolumes> {
+    if cluster
+        .hdfs
+        .spec
+        .datanode_storage
+        .failed_volumes_tolerated
+        .unwrap_or(0)
+        == 0
+    {
+        return Vec::new();
+    }
+    let roles = datanode_roles(cluster);
+    let datanodes = roles
+        .iter()
+        .flat_map(|(role, replicas)| (0..*replicas).map(move |i| (role, i)))
+        .map(|(role, i)| async move {
+            // Datanode Services need not be headless, so their pods' DNS names may not resolve
+            let host = cluster
+                .datanode_ips
+                .get(&cluster.pod_name(role, i))
+                .cloned()
+                .unwrap_or_else(|| cluster.pod_fqdn(role, i));
+            let bean = jmx::get_bean(
+                &host,
+                cluster.ports.datanode_web(),
+                cluster.tls_ca.as_deref(),
+                "Hadoop:service=DataNode,name=FSDatasetState",
+            )
+            .await
+            .ok()?;
+            let locations = bean
+                .get("FailedStorageLocations")?
+                .as_array()?
+                .iter()
+                .filter_map(|location| Some(location.as_str()?.to_string()))
+                .collect::<Vec<_>>();
+            (!locations.is_empty()).then(|| FailedVolumes {
+                pod: cluster.pod_name(role, i),
+                locations,
+            })
+        });
+    futures::future::join_all(datanodes)
+        .await
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Percentage of the file system's capacity that is used, according to the first namenode that can be reached
+async fn dfs_used_percent(cluster: &ClusterContext<'_>) -> Option<f64> {
+    for i in 0..cluster.hdfs.spec.namenode_replicas.unwrap_or(1) {
+        let info = jmx::get_bean(
+            &cluster.pod_fqdn("namenode", i),
+            cluster.ports.namenode_web(),
+            cluster.tls_ca.as_deref(),
+            "Hadoop:service=NameNode,name=NameNodeInfo",
+        )
+        .await;
+        if let Some(percent) = info.ok().and_then(|bean| bean.get("PercentUsed")?.as_f64()) {
+            return Some(percent);
+        }
+    }
+    None
+}
+
+fn recorder(ctx: &Ctx, hdfs: &HdfsCluster) -> Recorder {
+    Recorder::new(
+        ctx.kube.clone(),
+        Reporter {
+            controller: FIELD_MANAGER.to_string(),
+            instance: None,
         },
-        spec: Some(PersistentVolumeClaimSpec {
-            access_modes: Some(vec!["ReadWriteOnce".to_string()]),
-            resources: Some(ResourceRequirements {
-                requests: Some(BTreeMap::from([("storage".to_string(), size)])),
-                ..ResourceRequirements::default()
-            }),
-            ..PersistentVolumeClaimSpec::default()
-        }),
-        ..PersistentVolumeClaim::default()
+        hdfs.object_ref(&()),
+    )
+}
+
+/// Adds the [`TEARDOWN_FINALIZER`] to the cluster, or removes it if `present` is false
+async fn set_teardown_finalizer(
+    ctx: &Ctx,
+    cluster: &ClusterContext<'_>,
+    present: bool,
+) -> Result<(), Error> {
+    let mut finalizers = cluster.hdfs.metadata.finalizers.clone().unwrap_or_default();
+    if finalizers.iter().any(|f| f == TEARDOWN_FINALIZER) == present {
+        return Ok(());
+    }
+    if present {
+        finalizers.push(TEARDOWN_FINALIZER.to_string());
+    } else {
+        finalizers.retain(|f| f != TEARDOWN_FINALIZER);
+    }
+    if ctx.dry_run {
+        tracing::info!(present, "Dry run, would update the teardown finalizer");
+        return Ok(());
     }
+    kube::Api::<HdfsCluster>::namespaced(ctx.kube.clone(), &cluster.namespace)
+        .patch(
+            &cluster.name,
+            &PatchParams::default(),
+            // The resource version makes the patch fail instead of dropping finalizers that were added concurrently
+            &Patch::Merge(serde_json::json!({
+                "metadata": {
+                    "finalizers": finalizers,
+                    "resourceVersion": cluster.hdfs.metadata.resource_version,
+                },
+            })),
+        )
+        .await
+        .context(UpdateFinalizers)?;
+    Ok(())
 }
 
-fn hadoop_container() -> Container {
-    Container {
-        image: Some("teozkr/hadoop:3.3.1".to_string()),
-        env: Some(vec![
-            EnvVar {
-                name: "HADOOP_HOME".to_string(),
-                value: Some("/opt/hadoop".to_string()),
-                ..EnvVar::default()
-            },
-            EnvVar {
-                name: "HADOOP_CONF_DIR".to_string(),
-                value: Some("/config".to_string()),
-                ..EnvVar::default()
-            },
-            // EnvVar {
-            //     name: "KRB5_TRACE".to_string(),
-            //     value: Some("/dev/stdout".to_string()),
-            //     ..EnvVar::default()
-            // },
-            // EnvVar {
-            //     name: "HADOOP_JAAS_DEBUG".to_string(),
-            //     value: Some("true".to_string()),
-            //     ..EnvVar::default()
-            // },
-            EnvVar {
-                name: "JAVA_TOOL_OPTIONS".to_string(),
-                value: Some(
-                    [
-                        "-Djava.security.krb5.conf=/config/krb5.conf",
-                        // "-Dsun.security.spnego.debug=true",
-                        // "-Dsun.security.krb5.debug=true",
-                        // "-Djava.security.debug=all",
-                    ]
-                    .join(" "),
-                ),
-                ..EnvVar::default()
-            },
-        ]),
-        volume_mounts: Some(vec![
-            VolumeMount {
-                mount_path: "/data".to_string(),
-                name: "data".to_string(),
-                ..VolumeMount::default()
+/// Stops the roles of a deleted cluster one at a time, in [`teardown_order`], then releases the cluster
+///
+/// Each role's `StatefulSet` is deleted once all pods of the previous roles are gone. Kubernetes then deletes the
+/// rest of the cluster's objects, as their owner is gone.
+async fn tear_down(ctx: &Ctx, cluster: &ClusterContext<'_>) -> Result<ReconcilerAction, Error> {
+    let done = ReconcilerAction {
+        requeue_after: None,
+    };
+    let finalizers = &cluster.hdfs.metadata.finalizers;
+    if !finalizers.iter().flatten().any(|f| f == TEARDOWN_FINALIZER) {
+        return Ok(done);
+    }
+    if ctx.dry_run {
+        tracing::info!("Dry run, would tear down the cluster");
+        return Ok(done);
+    }
+    let params = &ctx.apply_params();
+    let pods = kube::Api::<Pod>::namespaced(ctx.kube.clone(), &cluster.namespace);
+    for (role, statefulsets) in teardown_order(cluster) {
+        for name in statefulsets {
+            delete_if_exists::<StatefulSet>(&ctx.kube, params, &cluster.namespace, &name)
+                .await
+                .context(DeleteStatefulSet { name: &name })?;
+        }
+        let remaining = pods
+            .list(&ListParams::default().labels(&cluster.role_pod_selector(role)))
+            .await
+            .context(ListPods { role })?
+            .items
+            .len();
+        if remaining > 0 {
+            tracing::info!(role, remaining, "Waiting for the role's pods to stop");
+            return Ok(ReconcilerAction {
+                requeue_after: Some(Duration::from_secs(5)),
+            });
+        }
+    }
+    set_teardown_finalizer(ctx, cluster, false).await?;
+    Ok(done)
+}
+
+/// Adds `datanodeReplicas` datanodes through the `scale` subresource, as `datanodeAutoscaling` calls for
+///
+/// Only runs once all datanodes have registered, since the capacity of the others isn't known to the namenodes yet.
+async fn autoscale_datanodes(ctx: &Ctx, cluster: &ClusterContext<'_>) -> Result<(), Error> {
+    let hdfs = cluster.hdfs;
+    if hdfs.spec.datanode_autoscaling.is_none() {
+        return Ok(());
+    }
+    let datanodes = datanode_roles(cluster)
+        .iter()
+        .map(|(_, replicas)| replicas)
+        .sum();
+    if !datanodes_registered(cluster, datanodes).await {
+        return Ok(());
+    }
+    let used_percent = match dfs_used_percent(cluster).await {
+        Some(used_percent) => used_percent,
+        None => return Ok(()),
+    };
+    let replicas = hdfs.spec.datanode_replicas.unwrap_or(1);
+    let desired = match autoscaled_datanode_replicas(cluster, used_percent) {
+        Some(desired) if desired != replicas => desired,
+        _ => return Ok(()),
+    };
+    kube::Api::<HdfsCluster>::namespaced(ctx.kube.clone(), &cluster.namespace)
+        .patch_scale(
+            &cluster.name,
+            &PatchParams {
+                dry_run: ctx.dry_run,
+                ..PatchParams::default()
             },
-            VolumeMount {
-                mount_path: "/config".to_string(),
-                name: "config".to_string(),
-                ..VolumeMount::default()
+            &Patch::Merge(serde_json::json!({ "spec": { "replicas": desired } })),
+        )
+        .await
+        .context(ScaleDatanodes)?;
+    // Events are only written for changes that are actually made
+    if !ctx.dry_run {
+        publish_event(
+            &recorder(ctx, hdfs),
+            Event {
+                type_: EventType::Normal,
+                reason: "Autoscaled".to_string(),
+                note: Some(format!(
+                    "Scaled the datanodes from {} to {} replicas, since {:.1}% of the file system's capacity is used",
+                    replicas, desired, used_percent
+                )),
+                action: "Scale".to_string(),
+                secondary: None,
             },
-            VolumeMount {
-                mount_path: "/kerberos".to_string(),
-                name: "kerberos".to_string(),
-                ..VolumeMount::default()
+        )
+        .await;
+    }
+    Ok(())
+}
+
+/// The admin states (such as `Decommissioned`) that the namenodes report for the datanodes at `ips`
+///
+/// Datanodes that a namenode doesn't consider live are skipped. Returns `None` if any namenode can't be reached.
+async fn datanode_admin_states(
+    cluster: &ClusterContext<'_>,
+    ips: &[String],
+) -> Option<Vec<String>> {
+    let mut states = Vec::new();
+    for i in 0..cluster.hdfs.spec.namenode_replicas.unwrap_or(1) {
+        let bean = jmx::get_bean(
+            &cluster.pod_fqdn("namenode", i),
+            cluster.ports.namenode_web(),
+            cluster.tls_ca.as_deref(),
+            "Hadoop:service=NameNode,name=NameNodeInfo",
+        )
+        .await
+        .ok()?;
+        // Keyed by the datanodes' hostnames, which are the nodes' ones if the pods use the host network
+        let live_nodes: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(bean.get("LiveNodes")?.as_str()?).ok()?;
+        for node in live_nodes.values() {
+            let ip = node
+                .get("xferaddr")
+                .and_then(|addr| addr.as_str())
+                .and_then(|addr| addr.rsplit_once(':'))
+                .map(|(ip, _)| ip);
+            if ip.map_or(false, |ip| ips.iter().any(|pod_ip| pod_ip == ip)) {
+                let state = node.get("adminState").and_then(|state| state.as_str());
+                states.push(state.unwrap_or_default().to_string());
+            }
+        }
+    }
+    Some(states)
+}
+
+/// The datanode groups that have more pods than replicas, such as after being scaled down by an autoscaler
+async fn scale_downs(
+    kube: &kube::Client,
+    cluster: &ClusterContext<'_>,
+) -> Result<BTreeMap<String, ScaleDown>, Error> {
+    let statefulsets = kube::Api::<StatefulSet>::namespaced(kube.clone(), &cluster.namespace);
+    let pods = kube::Api::<Pod>::namespaced(kube.clone(), &cluster.namespace);
+    let mut scale_downs = BTreeMap::new();
+    for (role, replicas) in datanode_roles(cluster) {
+        let name = cluster.role_name(&role);
+        let sts = match statefulsets.get(&name).await {
+            Ok(sts) => sts,
+            Err(kube::Error::Api(err)) if err.code == 404 => continue,
+            Err(err) => return Err(err).context(GetStatefulSet { name }),
+        };
+        let sts_replicas = sts
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.replicas)
+            .unwrap_or(1);
+        let sts_pods = sts.status.as_ref().map_or(0, |status| status.replicas);
+        let scale_down = if sts_replicas <= replicas {
+            // Already decommissioned, the surplus pods are being deleted
+            ScaleDown {
+                pods: sts_pods,
+                excluded: true,
+                decommissioned: true,
+            }
+        } else {
+            let mut ips = Vec::new();
+            for i in replicas..sts_replicas {
+                let name = format!("{}-{}", name, i);
+                match pods.get(&name).await {
+                    Ok(pod) => ips.extend(pod.status.and_then(|status| status.pod_ip)),
+                    Err(kube::Error::Api(err)) if err.code == 404 => {}
+                    Err(err) => return Err(err).context(GetPod { name }),
+                }
+            }
+            let states = datanode_admin_states(cluster, &ips).await;
+            ScaleDown {
+                pods: sts_replicas,
+                // Unreachable namenodes are not told to refresh again, that wouldn't help them
+                excluded: states.as_ref().map_or(true, |states| {
+                    states.iter().all(|state| state != "In Service")
+                }),
+                decommissioned: states.map_or(false, |states| {
+                    states.iter().all(|state| state == "Decommissioned")
+                }),
+            }
+        };
+        if scale_down.pods > replicas {
+            scale_downs.insert(role, scale_down);
+        }
+    }
+    Ok(scale_downs)
+}
+
+/// The IPs of the cluster's datanode pods by pod name, for the namenodes' hosts file
+async fn datanode_ips(
+    kube: &kube::Client,
+    cluster: &ClusterContext<'_>,
+) -> Result<BTreeMap<String, String>, Error> {
+    let api = kube::Api::<Pod>::namespaced(kube.clone(), &cluster.namespace);
+    // The selector of the datanodes outside of any group also matches the groups' pods
+    let pods = api
+        .list(&ListParams::default().labels(&cluster.role_pod_selector("datanode")))
+        .await
+        .context(ListPods { role: "datanode" })?;
+    Ok(pods
+        .into_iter()
+        .filter_map(|pod| Some((pod.metadata.name?, pod.status?.pod_ip?)))
+        .collect())
+}
+
+/// Whether the namenodes still report any of the datanodes that should be in maintenance as in service
+///
+/// Datanodes whose maintenance has already ended are skipped, the namenodes put them back into service themselves.
+async fn maintenance_pending(cluster: &ClusterContext<'_>) -> bool {
+    let now = Utc::now().timestamp_millis();
+    let ips = maintenance_datanodes(cluster)
+        .into_iter()
+        .filter(|(_, until)| until.map_or(true, |until| until > now))
+        .filter_map(|(pod, _)| cluster.datanode_ips.get(&pod).cloned())
+        .collect::<Vec<_>>();
+    if ips.is_empty() {
+        return false;
+    }
+    // Unreachable namenodes are not told to refresh again, that wouldn't help them
+    datanode_admin_states(cluster, &ips)
+        .await
+        .map_or(false, |states| {
+            states.iter().any(|state| state == "In Service")
+        })
+}
+
+/// Makes the namenodes reread the datanode hosts file, allows the configured snapshots, and removes the operator's
+/// finished admin tasks
+///
+/// The namenodes may still have seen the old contents of the `ConfigMap`, so refreshing is retried as long as any of
+/// the decommissioned datanodes or those in maintenance is still in service. Allowing snapshots is retried until it
+/// succeeds, since it fails until the namenodes are up.
+async fn run_admin_tasks(
+    kube: &kube::Client,
+    params: &ApplyParams,
+    cluster: &ClusterContext<'_>,
+) -> Result<(), Error> {
+    let tasks = kube::Api::<HdfsAdminTask>::namespaced(kube.clone(), &cluster.namespace);
+    let refresh_task = build_refresh_nodes_task(cluster);
+    let refresh_task_name = refresh_task
+        .as_ref()
+        .and_then(|task| task.metadata.name.clone());
+    let snapshot_task = build_allow_snapshot_task(cluster);
+    let snapshot_task_name = snapshot_task
+        .as_ref()
+        .and_then(|task| task.metadata.name.clone());
+    let retry = cluster
+        .scale_downs
+        .values()
+        .any(|scale_down| !scale_down.excluded)
+        || maintenance_pending(cluster).await;
+    // Recreated by the next reconcile, once the Jobs of the deleted tasks have been garbage collected
+    let mut deleted = Vec::new();
+    for task in tasks
+        .list(&ListParams::default())
+        .await
+        .context(ListAdminTasks)?
+    {
+        let owned = task
+            .metadata
+            .owner_references
+            .iter()
+            .flatten()
+            .any(|owner| owner.controller == Some(true) && owner.uid == cluster.owner_ref.uid);
+        let phase = task.status.as_ref().map(|status| status.phase);
+        let name = task.metadata.name.unwrap_or_default();
+        let rerun = if refresh_task_name.as_ref() == Some(&name) {
+            retry
+        } else if snapshot_task_name.as_ref() == Some(&name) {
+            phase == Some(AdminTaskPhase::Failed)
+        } else {
+            true
+        };
+        if owned && phase.map_or(false, AdminTaskPhase::is_finished) && rerun {
+            delete_if_exists::<HdfsAdminTask>(kube, params, &cluster.namespace, &name)
+                .await
+                .context(DeleteAdminTask { name: &name })?;
+            deleted.push(name);
+        }
+    }
+    let is_deleted = |task: &HdfsAdminTask| {
+        task.metadata
+            .name
+            .as_ref()
+            .map_or(false, |name| deleted.contains(name))
+    };
+    if let Some(refresh_task) = refresh_task.filter(|task| !is_deleted(task)) {
+        apply_owned(kube, params, &refresh_task)
+            .await
+            .context(ApplyRefreshNodesTask)?;
+    }
+    if let Some(snapshot_task) = snapshot_task.filter(|task| !is_deleted(task)) {
+        apply_owned(kube, params, &snapshot_task)
+            .await
+            .context(ApplyAllowSnapshotTask)?;
+    }
+    Ok(())
+}
+
+/// Deletes the next batch of outdated datanodes of `sts`, returning whether its rollout is still in progress
+///
+/// `datanodes` is the number of datanodes in all groups, which must all have registered before the next batch starts.
+async fn roll_out_datanodes(
+    ctx: &Ctx,
+    cluster: &ClusterContext<'_>,
+    rollout: &BatchedRolloutConfig,
+    sts: &StatefulSet,
+    datanodes: i32,
+) -> Result<bool, Error> {
+    let update_revision = match sts
+        .status
+        .as_ref()
+        .and_then(|status| status.update_revision.as_deref())
+    {
+        Some(update_revision) => update_revision,
+        None => return Ok(false),
+    };
+    let replicas = sts
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.replicas)
+        .unwrap_or(1);
+    let api = kube::Api::<Pod>::namespaced(ctx.kube.clone(), &cluster.namespace);
+    // The selector of the datanodes outside of any group also matches the groups' pods
+    let pods = api
+        .list(&ListParams::default().labels(&cluster.role_pod_selector("datanode")))
+        .await
+        .context(ListPods { role: "datanode" })?
+        .items
+        .into_iter()
+        .filter(|pod| {
+            pod.metadata.owner_references.iter().flatten().any(|owner| {
+                owner.kind == "StatefulSet" && Some(&owner.name) == sts.metadata.name.as_ref()
+            })
+        })
+        .collect::<Vec<_>>();
+    if !pods
+        .iter()
+        .any(|pod| rollout::is_outdated(pod, update_revision))
+    {
+        return Ok(false);
+    }
+    let max_unavailable =
+        rollout::max_unavailable(rollout.max_unavailable.as_ref(), replicas).unwrap_or(1);
+    let settled = datanodes_registered(cluster, datanodes).await;
+    let batch = rollout::next_batch(&pods, replicas, update_revision, max_unavailable, settled);
+    if !batch.is_empty() {
+        tracing::info!(pods = ?batch, "Restarting the next batch of datanodes");
+    }
+    for name in batch {
+        api.delete(
+            &name,
+            &DeleteParams {
+                dry_run: ctx.dry_run,
+                ..DeleteParams::default()
             },
-        ]),
-        ..Container::default()
+        )
+        .await
+        .context(DeletePod { name })?;
     }
+    Ok(true)
 }
 
-async fn apply_owned<K>(kube: &kube::Client, obj: K) -> kube::Result<K>
-where
-    K: Resource<DynamicType = ()> + Serialize + DeserializeOwned + Clone + Debug,
-{
-    let api = if let Some(ns) = &obj.meta().namespace {
-        kube::Api::<K>::namespaced(kube.clone(), ns)
-    } else {
-        kube::Api::<K>::all(kube.clone())
+/// The Hadoop version that is running, if the cluster is about to be upgraded to `image.tag`
+///
+/// The journalnodes are updated first, so the upgrade has started once their `StatefulSet` has the new version.
+async fn pending_upgrade(
+    kube: &kube::Client,
+    cluster: &ClusterContext<'_>,
+) -> Result<Option<String>, Error> {
+    let name = cluster.role_name("journalnode");
+    let live_version = match kube::Api::<StatefulSet>::namespaced(kube.clone(), &cluster.namespace)
+        .get(&name)
+        .await
+    {
+        Ok(sts) => sts
+            .metadata
+            .labels
+            .and_then(|mut labels| labels.remove(APP_VERSION_LABEL)),
+        Err(kube::Error::Api(err)) if err.code == 404 => None,
+        Err(err) => return Err(err).context(GetStatefulSet { name }),
     };
-    api.patch(
-        &obj.meta().name.clone().unwrap(),
-        &PatchParams {
-            force: true,
-            field_manager: Some("hdfs.stackable.tech/hdfscluster".to_string()),
-            ..PatchParams::default()
+    Ok(live_version.filter(|version| version != cluster.hdfs.spec.image.tag()))
+}
+
+/// Whether all replicas of the `StatefulSet` are ready and running its current revision
+fn statefulset_ready(sts: &StatefulSet) -> bool {
+    let replicas = sts
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.replicas)
+        .unwrap_or(1);
+    sts.status.as_ref().map_or(false, |status| {
+        status.observed_generation >= sts.metadata.generation
+            && status.ready_replicas.unwrap_or(0) >= replicas
+            && status.updated_replicas.unwrap_or(0) >= replicas
+    })
+}
+
+/// Sets the condition of type `type_`, keeping its transition time if its status is unchanged
+fn set_condition(
+    conditions: &mut Vec<Condition>,
+    hdfs: &HdfsCluster,
+    type_: &str,
+    status: bool,
+    reason: &str,
+    message: &str,
+) {
+    let status = if status { "True" } else { "False" };
+    let old_condition = conditions.iter().position(|cond| cond.type_ == type_);
+    let condition = Condition {
+        type_: type_.to_string(),
+        status: status.to_string(),
+        reason: reason.to_string(),
+        message: message.to_string(),
+        observed_generation: hdfs.metadata.generation,
+        last_transition_time: match old_condition.map(|i| &conditions[i]) {
+            Some(old) if old.status == status => old.last_transition_time.clone(),
+            _ => Time(Utc::now()),
         },
-        &Patch::Apply(obj),
-    )
-    .await
+    };
+    match old_condition {
+        Some(i) => conditions[i] = condition,
+        None => conditions.push(condition),
+    }
 }
 
-pub async fn reconcile_hdfs(
-    hdfs: HdfsCluster,
-    ctx: Context<Ctx>,
-) -> Result<ReconcilerAction, Error> {
-    let ns = hdfs
-        .metadata
-        .namespace
-        .as_deref()
-        .with_context(|| ObjectHasNoNamespace {
-            obj_ref: ObjectRef::from_obj(&hdfs).erase(),
-        })?;
-    let kube = ctx.get_ref().kube.clone();
-
-    let name = hdfs.metadata.name.clone().unwrap();
-    let hdfs_owner_ref = controller_reference_to_obj(&hdfs);
-    let config_name = format!("{}-config", name);
-    let pod_labels = BTreeMap::from([("app".to_string(), "hdfs".to_string())]);
-
-    let nameservice_id = name.clone();
-    let namenode_name = format!("{}-namenode", name);
-    let namenode_fqdn = format!("{}.{}.svc.cluster.local", namenode_name, ns);
-    let namenode_pod_fqdn = |i: i32| format!("{}-{}.{}", namenode_name, i, namenode_fqdn);
-    let mut namenode_pod_labels = pod_labels.clone();
-    namenode_pod_labels.extend([("role".to_string(), "namenode".to_string())]);
-
-    let datanode_name = format!("{}-datanode", name);
-    let mut datanode_pod_labels = pod_labels.clone();
-    datanode_pod_labels.extend([("role".to_string(), "datanode".to_string())]);
-
-    let journalnode_name = format!("{}-journalnode", name);
-    let journalnode_fqdn = format!("{}.{}.svc.cluster.local", journalnode_name, ns);
-    let journalnode_pod_fqdn = |i: i32| format!("{}-{}.{}", journalnode_name, i, journalnode_fqdn);
-    let mut journalnode_pod_labels = pod_labels.clone();
-    journalnode_pod_labels.extend([("role".to_string(), "journalnode".to_string())]);
-
-    let kerberos_realm = hdfs.spec.kerberos.realm.as_deref().unwrap_or("LOCAL");
-    let hdfs_site_config = [
-        ("dfs.namenode.name.dir".to_string(), "/data".to_string()),
-        ("dfs.datanode.data.dir".to_string(), "/data".to_string()),
-        ("dfs.journalnode.edits.dir".to_string(), "/data".to_string()),
-        ("dfs.nameservices".to_string(), nameservice_id.clone()),
-        (
-            format!("dfs.ha.namenodes.{}", nameservice_id),
-            (0..hdfs.spec.namenode_replicas.unwrap_or(1))
-                .map(|i| format!("name-{}", i))
-                .collect::<Vec<_>>()
-                .join(", "),
-        ),
-        (
-            "dfs.namenode.shared.edits.dir".to_string(),
-            format!(
-                "qjournal://{}/{}",
-                (0..hdfs.spec.journalnode_replicas.unwrap_or(1))
-                    .map(journalnode_pod_fqdn)
-                    .collect::<Vec<_>>()
-                    .join(";"),
-                nameservice_id
-            ),
-        ),
-        (
-            format!("dfs.client.failover.proxy.provider.{}", nameservice_id),
-            "org.apache.hadoop.hdfs.server.namenode.ha.ConfiguredFailoverProxyProvider".to_string(),
-        ),
-        (
-            "dfs.ha.fencing.methods".to_string(),
-            "shell(/bin/true)".to_string(),
-        ),
-        (
-            "dfs.ha.nn.not-become-active-in-safemode".to_string(),
-            "true".to_string(),
-        ),
-        (
-            "dfs.ha.automatic-failover.enabled".to_string(),
-            "true".to_string(),
-        ),
-        (
-            "ha.zookeeper.quorum".to_string(),
-            "${env.ZOOKEEPER_BROKERS}".to_string(),
-        ),
-        (
-            "dfs.block.access.token.enable".to_string(),
-            "true".to_string(),
-        ),
-        // (
-        //     "dfs.data.transfer.protection".to_string(),
-        //     "authentication".to_string(),
-        // ),
-        // ("dfs.http.policy".to_string(), "HTTPS_ONLY".to_string()),
-        // TODO: "Privileged ports" don't really make sense in K8s, but we ought to sort out TLS anyway
-        (
-            "ignore.secure.ports.for.testing".to_string(),
-            "true".to_string(),
-        ),
-        (
-            "dfs.journalnode.kerberos.principal".to_string(),
-            format!("jn/{}@{}", namenode_fqdn, kerberos_realm),
-        ),
-        (
-            "dfs.journalnode.keytab.file".to_string(),
-            "/kerberos/jn.service.keytab".to_string(),
-        ),
-        (
-            "dfs.namenode.kerberos.principal".to_string(),
-            format!("nn/{}@{}", namenode_fqdn, kerberos_realm),
-        ),
-        (
-            "dfs.namenode.keytab.file".to_string(),
-            "/kerberos/nn.service.keytab".to_string(),
-        ),
-        (
-            "dfs.datanode.kerberos.principal".to_string(),
-            format!("dn/{}@{}", namenode_fqdn, kerberos_realm),
-        ),
-        (
-            "dfs.datanode.keytab.file".to_string(),
-            "/kerberos/dn.service.keytab".to_string(),
-        ),
-        // JournalNode SPNEGO
-        // (
-        //     "dfs.web.authentication.kerberos.principal".to_string(),
-        //     format!("HTTP/stackable-knode-1.kvm@{}", kerberos_realm),
-        //     // format!("HTTP/_HOST@{}", kerberos_realm),
-        // ),
-        // (
-        //     "dfs.web.authentication.kerberos.keytab".to_string(),
-        //     "/kerberos/spnego.service.keytab".to_string(),
-        // ),
-    ]
-    .into_iter()
-    .chain((0..hdfs.spec.namenode_replicas.unwrap_or(1)).flat_map(|i| {
-        [
-            (
-                format!("dfs.namenode.rpc-address.{}.name-{}", nameservice_id, i),
-                format!("{}:8020", namenode_pod_fqdn(i)),
+/// Outcome of applying each role's objects
+///
+/// Collected so that one role failing to apply doesn't hide the state of the others.
+#[derive(Default)]
+struct RoleResults(BTreeMap<String, RoleStatus>);
+
+impl RoleResults {
+    /// Records the outcome of applying a role's objects, returning the applied object if successful
+    fn record<T>(&mut self, role: &str, result: kube::Result<T>) -> Option<T> {
+        let (status, obj) = match result {
+            Ok(obj) => (
+                RoleStatus {
+                    phase: RolePhase::Applied,
+                    message: None,
+                },
+                Some(obj),
             ),
-            (
-                format!("dfs.namenode.http-address.{}.name-{}", nameservice_id, i),
-                format!("{}:9870", namenode_pod_fqdn(i)),
+            Err(err) => (
+                RoleStatus {
+                    phase: RolePhase::Failed,
+                    message: Some(err.to_string()),
+                },
+                None,
             ),
-        ]
-    }));
-    apply_owned(
-        &kube,
-        ConfigMap {
-            metadata: ObjectMeta {
-                owner_references: Some(vec![hdfs_owner_ref.clone()]),
-                name: Some(config_name.clone()),
-                namespace: Some(ns.to_string()),
-                ..ObjectMeta::default()
+        };
+        self.0.insert(role.to_string(), status);
+        obj
+    }
+
+    fn waiting(&mut self, role: &str, message: &str) {
+        self.0.insert(
+            role.to_string(),
+            RoleStatus {
+                phase: RolePhase::Waiting,
+                message: Some(message.to_string()),
             },
-            data: Some(BTreeMap::from([
-                (
-                    "core-site.xml".to_string(),
-                    hadoop_config_xml([
-                        ("fs.defaultFS", format!("hdfs://{}/", name)),
-                        ("hadoop.security.authentication", "kerberos".to_string()),
-                        ("hadoop.security.authorization", "false".to_string()),
-                        // JournalNode/WebHDFS SPNEGO
-                        // ("hadoop.http.authentication.type", "kerberos".to_string()),
-                        // (
-                        //     "hadoop.http.authentication.kerberos.principal",
-                        //     // format!("HTTP/stackable-knode-1.kvm@{}", kerberos_realm),
-                        //     format!("HTTP/_HOST@{}", kerberos_realm),
-                        // ),
-                        // (
-                        //     "hadoop.http.authentication.kerberos.keytab",
-                        //     "/kerberos/spnego.service.keytab".to_string(),
-                        // ),
-                    ]),
-                ),
-                (
-                    "hdfs-site.xml".to_string(),
-                    hadoop_config_xml(hdfs_site_config),
-                ),
-                ("krb5.conf".to_string(), hdfs.spec.kerberos.to_string()),
-                (
-                    "log4j.properties".to_string(),
-                    // "log4j.logger.org.apache.hadoop.security=DEBUG".to_string(),
-                    include_str!("log4j.properties").to_string(),
-                ),
-            ])),
-            ..ConfigMap::default()
-        },
-    )
-    .await
-    .unwrap();
-    apply_owned(
-        &kube,
-        Service {
-            metadata: ObjectMeta {
-                owner_references: Some(vec![hdfs_owner_ref.clone()]),
-                name: Some(journalnode_name.clone()),
-                namespace: Some(ns.to_string()),
-                ..ObjectMeta::default()
+        );
+    }
+
+    fn failed_roles(&self) -> Vec<&str> {
+        self.0
+            .iter()
+            .filter(|(_, status)| status.phase == RolePhase::Failed)
+            .map(|(role, _)| role.as_str())
+            .collect()
+    }
+}
+
+/// Publishes the per-role results and the `Progressing` condition, failing if any role could not be applied
+async fn report_progress(
+    ctx: &Ctx,
+    cluster: &ClusterContext<'_>,
+    roles: RoleResults,
+    daemons: DaemonReport,
+    progressing: bool,
+    reason: &str,
+    message: &str,
+) -> Result<(), Error> {
+    let hdfs = cluster.hdfs;
+    let failed_roles = roles.failed_roles().join(", ");
+    let mut conditions = hdfs
+        .status
+        .as_ref()
+        .and_then(|status| status.conditions.clone())
+        .unwrap_or_default();
+    if failed_roles.is_empty() {
+        set_condition(
+            &mut conditions,
+            hdfs,
+            "Progressing",
+            progressing,
+            reason,
+            message,
+        );
+    } else {
+        set_condition(
+            &mut conditions,
+            hdfs,
+            "Progressing",
+            true,
+            "ApplyFailed",
+            &format!("Failed to apply roles {}", failed_roles),
+        );
+    }
+    let issues = resources::config_override_issues(cluster).join("; ");
+    let reported = conditions
+        .iter()
+        .any(|cond| cond.type_ == "ConfigOverridesValid" && cond.message == issues);
+    // Only warn once per set of problems, rather than on every reconcile
+    if !issues.is_empty() && !reported && !ctx.dry_run {
+        publish_event(
+            &recorder(ctx, hdfs),
+            Event {
+                type_: EventType::Warning,
+                reason: "InvalidConfigOverrides".to_string(),
+                note: Some(issues.clone()),
+                action: "Validate".to_string(),
+                secondary: None,
             },
-            spec: Some(ServiceSpec {
-                ports: Some(vec![ServicePort {
-                    name: Some("ipc".to_string()),
-                    port: 8485,
-                    protocol: Some("TCP".to_string()),
-                    ..ServicePort::default()
-                }]),
-                selector: Some(journalnode_pod_labels.clone()),
-                cluster_ip: Some("None".to_string()),
-                publish_not_ready_addresses: Some(true),
-                ..ServiceSpec::default()
+        )
+        .await;
+    }
+    if issues.is_empty() {
+        set_condition(
+            &mut conditions,
+            hdfs,
+            "ConfigOverridesValid",
+            true,
+            "Valid",
+            "No problems were found in the configOverrides",
+        );
+    } else {
+        set_condition(
+            &mut conditions,
+            hdfs,
+            "ConfigOverridesValid",
+            false,
+            "InvalidConfigOverrides",
+            &issues,
+        );
+    }
+    let deprecations = hdfs
+        .spec
+        .deprecations()
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ");
+    let reported = conditions
+        .iter()
+        .any(|cond| cond.type_ == "Deprecated" && cond.message == deprecations);
+    if !deprecations.is_empty() && !reported && !ctx.dry_run {
+        publish_event(
+            &recorder(ctx, hdfs),
+            Event {
+                type_: EventType::Warning,
+                reason: "DeprecatedFields".to_string(),
+                note: Some(deprecations.clone()),
+                action: "Validate".to_string(),
+                secondary: None,
+            },
+        )
+        .await;
+    }
+    if deprecations.is_empty() {
+        set_condition(
+            &mut conditions,
+            hdfs,
+            "Deprecated",
+            false,
+            "NoDeprecatedFields",
+            "No deprecated fields are used",
+        );
+    } else {
+        set_condition(
+            &mut conditions,
+            hdfs,
+            "Deprecated",
+            true,
+            "DeprecatedFields",
+            &deprecations,
+        );
+    }
+    let pods = kube::Api::<Pod>::namespaced(ctx.kube.clone(), &cluster.namespace);
+    let list_params = ListParams::default().labels(&cluster.cluster_pod_selector());
+    let (pods, zookeeper_quorum) = futures::join!(
+        pods.list(&list_params),
+        zookeeper_quorum(&ctx.kube, cluster)
+    );
+    let pods = pods.context(ListClusterPods)?;
+    let unschedulable = unschedulable_pods(&pods.items)
+        .into_iter()
+        .map(|(pod, message)| format!("{}: {}", pod, message))
+        .collect::<Vec<_>>()
+        .join("; ");
+    let reported = conditions
+        .iter()
+        .any(|cond| cond.type_ == "Degraded" && cond.message == unschedulable);
+    if !unschedulable.is_empty() && !reported && !ctx.dry_run {
+        publish_event(
+            &recorder(ctx, hdfs),
+            Event {
+                type_: EventType::Warning,
+                reason: "Unschedulable".to_string(),
+                note: Some(format!("Pods can't be scheduled, {}", unschedulable)),
+                action: "Schedule".to_string(),
+                secondary: None,
+            },
+        )
+        .await;
+    }
+    // Unschedulable namenodes would explain overdue checkpoints, so they are reported first
+    let overdue = daemons
+        .overdue_checkpoint
+        .filter(|_| unschedulable.is_empty());
+    let reported = conditions
+        .iter()
+        .any(|cond| cond.type_ == "Degraded" && cond.reason == "CheckpointOverdue");
+    if let Some(issue) = overdue.as_ref().filter(|_| !reported && !ctx.dry_run) {
+        publish_event(
+            &recorder(ctx, hdfs),
+            Event {
+                type_: EventType::Warning,
+                reason: "CheckpointOverdue".to_string(),
+                note: Some(issue.clone()),
+                action: "MonitorCheckpoints".to_string(),
+                secondary: None,
+            },
+        )
+        .await;
+    }
+    if !unschedulable.is_empty() {
+        set_condition(
+            &mut conditions,
+            hdfs,
+            "Degraded",
+            true,
+            "Unschedulable",
+            &unschedulable,
+        );
+    } else if let Some(issue) = &overdue {
+        set_condition(
+            &mut conditions,
+            hdfs,
+            "Degraded",
+            true,
+            "CheckpointOverdue",
+            issue,
+        );
+    } else {
+        set_condition(
+            &mut conditions,
+            hdfs,
+            "Degraded",
+            false,
+            "AllPodsScheduled",
+            "All pods have been scheduled",
+        );
+    }
+    // Merge patches never remove keys, so the roles that are no longer reported are cleared explicitly
+    let stale_roles = hdfs
+        .status
+        .iter()
+        .flat_map(|status| status.roles.keys())
+        .filter(|role| !roles.0.contains_key(*role))
+        .cloned()
+        .collect::<Vec<_>>();
+    let mut status = serde_json::json!({
+        "status": HdfsClusterStatus {
+            conditions: Some(conditions),
+            roles: roles.0,
+            active_namenode: daemons.active_namenode,
+            failed_volumes: daemons.failed_volumes,
+            datanode_replicas: Some(
+                cluster
+                    .scale_downs
+                    .get("datanode")
+                    .map_or(hdfs.spec.datanode_replicas.unwrap_or(1), |scale_down| {
+                        scale_down.pods
+                    }),
+            ),
+            datanode_selector: Some(cluster.datanode_pod_selector()),
+            decommissioning_datanodes: decommissioning_datanodes(cluster)
+                .into_iter()
+                .map(|(role, i)| cluster.pod_name(&role, i))
+                .collect(),
+            maintenance_datanodes: maintenance_datanodes(cluster)
+                .into_iter()
+                .map(|(pod, _)| pod)
+                .collect(),
+            endpoints: Some(HdfsEndpoints {
+                zookeeper_quorum,
+                ..build_endpoints(cluster)
             }),
-            status: None,
         },
+    });
+    for role in stale_roles {
+        status["status"]["roles"][role] = serde_json::Value::Null;
+    }
+    kube::Api::<HdfsCluster>::namespaced(
+        ctx.kube.clone(),
+        hdfs.metadata.namespace.as_deref().unwrap(),
+    )
+    .patch_status(
+        hdfs.metadata.name.as_deref().unwrap(),
+        &PatchParams {
+            dry_run: ctx.dry_run,
+            ..PatchParams::default()
+        },
+        &Patch::Merge(status),
     )
     .await
-    .context(ApplyPeerService)?;
-    let journalnode_pod_template = PodTemplateSpec {
-        metadata: Some(ObjectMeta {
-            labels: Some(journalnode_pod_labels.clone()),
-            ..ObjectMeta::default()
-        }),
-        spec: Some(PodSpec {
-            containers: vec![Container {
-                name: "journalnode".to_string(),
-                args: Some(vec![
-                    "/opt/hadoop/bin/hdfs".to_string(),
-                    "journalnode".to_string(),
-                ]),
-                ports: Some(vec![ContainerPort {
-                    name: Some("ipc".to_string()),
-                    container_port: 8485,
-                    protocol: Some("TCP".to_string()),
-                    ..ContainerPort::default()
-                }]),
-                ..hadoop_container()
-            }],
-            volumes: Some(vec![
-                Volume {
-                    name: "config".to_string(),
-                    config_map: Some(ConfigMapVolumeSource {
-                        name: Some(format!("{}-config", name)),
-                        ..ConfigMapVolumeSource::default()
-                    }),
-                    ..Volume::default()
-                },
-                Volume {
-                    name: "kerberos".to_string(),
-                    secret: Some(SecretVolumeSource {
-                        secret_name: Some(format!("{}-kerberos", journalnode_name)),
-                        ..SecretVolumeSource::default()
-                    }),
-                    ..Volume::default()
-                },
-            ]),
-            host_network: Some(true),
-            dns_policy: Some("ClusterFirstWithHostNet".to_string()),
-            ..PodSpec::default()
-        }),
+    .context(UpdateStatus)?;
+    ensure!(
+        failed_roles.is_empty(),
+        ApplyRoles {
+            roles: failed_roles
+        }
+    );
+    Ok(())
+}
+
+/// Applies a role's `Service`, replacing it if it has to gain or lose its cluster IP, which can't be changed in place
+async fn apply_role_service(
+    kube: &kube::Client,
+    params: &ApplyParams,
+    svc: &Service,
+) -> kube::Result<Service> {
+    let ns = svc.metadata.namespace.as_deref().unwrap_or_default();
+    let name = svc.metadata.name.as_deref().unwrap_or_default();
+    let headless = |svc: &Service| {
+        svc.spec
+            .as_ref()
+            .and_then(|spec| spec.cluster_ip.as_deref())
+            == Some("None")
     };
-    apply_owned(
-        &kube,
-        StatefulSet {
-            metadata: ObjectMeta {
-                owner_references: Some(vec![hdfs_owner_ref.clone()]),
-                name: Some(journalnode_name.clone()),
-                namespace: Some(ns.to_string()),
-                ..ObjectMeta::default()
-            },
-            spec: Some(StatefulSetSpec {
-                pod_management_policy: Some("Parallel".to_string()),
-                replicas: hdfs.spec.journalnode_replicas,
-                selector: LabelSelector {
-                    match_labels: Some(journalnode_pod_labels.clone()),
-                    ..LabelSelector::default()
-                },
-                service_name: journalnode_name.clone(),
-                template: journalnode_pod_template,
-                volume_claim_templates: Some(vec![local_disk_claim(
-                    "data",
-                    Quantity("1Gi".to_string()),
-                )]),
-                ..StatefulSetSpec::default()
-            }),
-            status: None,
-        },
+    match kube::Api::<Service>::namespaced(kube.clone(), ns)
+        .get(name)
+        .await
+    {
+        Ok(live) if headless(&live) != headless(svc) => {
+            delete_if_exists::<Service>(kube, params, ns, name).await?
+        }
+        Ok(_) => {}
+        Err(kube::Error::Api(err)) if err.code == 404 => {}
+        Err(err) => return Err(err),
+    }
+    apply_owned(kube, params, svc).await
+}
+
+/// Deletes the objects of roles and optional features that have been removed from the spec
+///
+/// Runs before waiting for any prerequisite, since the desired objects are known up front.
+async fn delete_orphaned_objects(
+    kube: &kube::Client,
+    params: &ApplyParams,
+    cluster: &ClusterContext<'_>,
+    config_maps: &[ConfigMap],
+    certificates: &[Certificate],
+    pod_disruption_budgets: &[PodDisruptionBudget],
+) -> Result<(), Error> {
+    let hdfs = cluster.hdfs;
+    let ns = cluster.namespace.as_str();
+    let owner_ref = &cluster.owner_ref;
+    let service_account_name = cluster.service_account_name();
+    let role_binding_name = cluster.role_binding_name();
+    let network_policy_name = cluster.network_policy_name();
+    let selector = &cluster.cluster_pod_selector();
+    let role_names = ROLES
+        .iter()
+        .map(|role| cluster.role_name(role))
+        .chain(
+            hdfs.spec
+                .datanode_groups
+                .keys()
+                .map(|group| cluster.role_name(&datanode_group_role(group))),
+        )
+        .collect::<Vec<_>>();
+    let role_names = role_names.iter().map(String::as_str).collect::<Vec<_>>();
+    delete_orphans::<StatefulSet>(kube, params, ns, owner_ref, selector, &role_names)
+        .await
+        .context(DeleteOrphans {
+            kind: "StatefulSet",
+        })?;
+    delete_orphans::<Service>(kube, params, ns, owner_ref, selector, &role_names)
+        .await
+        .context(DeleteOrphans { kind: "Service" })?;
+    let discovery_name = cluster.discovery_name();
+    let config_map_names = config_maps
+        .iter()
+        .filter_map(|config_map| config_map.metadata.name.as_deref())
+        .chain([discovery_name.as_str()])
+        .collect::<Vec<_>>();
+    delete_orphans::<ConfigMap>(kube, params, ns, owner_ref, selector, &config_map_names)
+        .await
+        .context(DeleteOrphans { kind: "ConfigMap" })?;
+    let certificate_names = certificates
+        .iter()
+        .filter_map(|certificate| certificate.metadata.name.as_deref())
+        .collect::<Vec<_>>();
+    match delete_orphans::<Certificate>(kube, params, ns, owner_ref, selector, &certificate_names)
+        .await
+    {
+        // cert-manager is not installed, so there can't be any Certificates either
+        Err(kube::Error::Api(err)) if err.code == 404 => {}
+        result => result.context(DeleteOrphans {
+            kind: "Certificate",
+        })?,
+    }
+    let keystore_secret_name = cluster.keystore_secret_name();
+    let secret_names = match &hdfs.spec.tls {
+        Some(_) => vec![keystore_secret_name.as_str()],
+        None => Vec::new(),
+    };
+    delete_orphans::<Secret>(kube, params, ns, owner_ref, selector, &secret_names)
+        .await
+        .context(DeleteOrphans { kind: "Secret" })?;
+    // Earlier versions also generated the account named by `serviceAccount.name`, which the pods still run as
+    delete_orphans::<ServiceAccount>(
+        kube,
+        params,
+        ns,
+        owner_ref,
+        selector,
+        &[&service_account_name],
     )
     .await
-    .context(ApplyStatefulSet)?;
-    apply_owned(
-        &kube,
-        Service {
-            metadata: ObjectMeta {
-                owner_references: Some(vec![hdfs_owner_ref.clone()]),
-                name: Some(namenode_name.clone()),
-                namespace: Some(ns.to_string()),
-                ..ObjectMeta::default()
-            },
-            spec: Some(ServiceSpec {
-                ports: Some(vec![
-                    ServicePort {
-                        name: Some("ipc".to_string()),
-                        port: 8020,
-                        protocol: Some("TCP".to_string()),
-                        ..ServicePort::default()
-                    },
-                    ServicePort {
-                        name: Some("http".to_string()),
-                        port: 80,
-                        target_port: Some(IntOrString::String("http".to_string())),
-                        protocol: Some("TCP".to_string()),
-                        ..ServicePort::default()
-                    },
-                ]),
-                selector: Some(namenode_pod_labels.clone()),
-                cluster_ip: Some("None".to_string()),
-                publish_not_ready_addresses: Some(true),
-                ..ServiceSpec::default()
-            }),
-            status: None,
-        },
+    .context(DeleteOrphans {
+        kind: "ServiceAccount",
+    })?;
+    delete_orphans::<RoleBinding>(kube, params, ns, owner_ref, selector, &[&role_binding_name])
+        .await
+        .context(DeleteOrphans {
+            kind: "RoleBinding",
+        })?;
+    delete_orphans::<NetworkPolicy>(
+        kube,
+        params,
+        ns,
+        owner_ref,
+        selector,
+        &[&network_policy_name],
     )
     .await
-    .context(ApplyPeerService)?;
-    let mut namenode_zkfc_container = hadoop_container();
-    namenode_zkfc_container
-        .env
-        .get_or_insert_with(Vec::new)
-        .push(EnvVar {
-            name: "ZOOKEEPER_BROKERS".to_string(),
-            value_from: Some(EnvVarSource {
-                config_map_key_ref: Some(ConfigMapKeySelector {
-                    name: hdfs.spec.namenode_znode_config_map,
-                    key: "ZOOKEEPER_BROKERS".to_string(),
-                    ..ConfigMapKeySelector::default()
-                }),
-                ..EnvVarSource::default()
-            }),
-            ..EnvVar::default()
-        });
-    let namenode_pod_template = PodTemplateSpec {
-        metadata: Some(ObjectMeta {
-            labels: Some(namenode_pod_labels.clone()),
-            ..ObjectMeta::default()
-        }),
-        spec: Some(PodSpec {
-            init_containers: Some(vec![Container {
-                name: "format-namenode".to_string(),
-                args: Some(vec![
-                    "sh".to_string(),
-                    "-c".to_string(),
-                    "/opt/hadoop/bin/hdfs namenode -bootstrapStandby -nonInteractive \
-                     || /opt/hadoop/bin/hdfs namenode -format -noninteractive \
-                     || true
-                     /opt/hadoop/bin/hdfs zkfc -formatZK -nonInteractive || true"
-                        .to_string(),
-                ]),
-                ..namenode_zkfc_container.clone()
-            }]),
-            containers: vec![
-                Container {
-                    name: "namenode".to_string(),
-                    args: Some(vec![
-                        "/opt/hadoop/bin/hdfs".to_string(),
-                        "namenode".to_string(),
-                    ]),
-                    ports: Some(vec![
-                        ContainerPort {
-                            name: Some("ipc".to_string()),
-                            container_port: 8020,
-                            protocol: Some("TCP".to_string()),
-                            ..ContainerPort::default()
-                        },
-                        ContainerPort {
-                            name: Some("http".to_string()),
-                            container_port: 9870,
-                            protocol: Some("TCP".to_string()),
-                            ..ContainerPort::default()
-                        },
-                    ]),
-                    ..hadoop_container()
-                },
-                Container {
-                    name: "zkfc".to_string(),
-                    args: Some(vec!["/opt/hadoop/bin/hdfs".to_string(), "zkfc".to_string()]),
-                    ..namenode_zkfc_container
-                },
-            ],
-            volumes: Some(vec![
-                Volume {
-                    name: "config".to_string(),
-                    config_map: Some(ConfigMapVolumeSource {
-                        name: Some(format!("{}-config", name)),
-                        ..ConfigMapVolumeSource::default()
-                    }),
-                    ..Volume::default()
-                },
-                Volume {
-                    name: "kerberos".to_string(),
-                    secret: Some(SecretVolumeSource {
-                        secret_name: Some(format!("{}-kerberos", namenode_name)),
-                        ..SecretVolumeSource::default()
-                    }),
-                    ..Volume::default()
-                },
-            ]),
-            host_network: Some(true),
-            dns_policy: Some("ClusterFirstWithHostNet".to_string()),
-            ..PodSpec::default()
-        }),
-    };
-    apply_owned(
-        &kube,
-        StatefulSet {
-            metadata: ObjectMeta {
-                owner_references: Some(vec![hdfs_owner_ref.clone()]),
-                name: Some(namenode_name.clone()),
-                namespace: Some(ns.to_string()),
-                ..ObjectMeta::default()
-            },
-            spec: Some(StatefulSetSpec {
-                pod_management_policy: Some("Parallel".to_string()),
-                replicas: hdfs.spec.namenode_replicas,
-                selector: LabelSelector {
-                    match_labels: Some(namenode_pod_labels.clone()),
-                    ..LabelSelector::default()
-                },
-                service_name: namenode_name.clone(),
-                template: namenode_pod_template,
-                volume_claim_templates: Some(vec![local_disk_claim(
-                    "data",
-                    Quantity("1Gi".to_string()),
-                )]),
-                // volume_claim_templates: todo!(),
-                ..StatefulSetSpec::default()
-            }),
-            status: None,
-        },
+    .context(DeleteOrphans {
+        kind: "NetworkPolicy",
+    })?;
+    let pod_disruption_budget_names = pod_disruption_budgets
+        .iter()
+        .filter_map(|pod_disruption_budget| pod_disruption_budget.metadata.name.as_deref())
+        .collect::<Vec<_>>();
+    delete_orphans::<PodDisruptionBudget>(
+        kube,
+        params,
+        ns,
+        owner_ref,
+        selector,
+        &pod_disruption_budget_names,
     )
     .await
-    .context(ApplyStatefulSet)?;
-    apply_owned(
+    .context(DeleteOrphans {
+        kind: "PodDisruptionBudget",
+    })?;
+    Ok(())
+}
+
+/// Defers the `pending` roles until a prerequisite is ready
+///
+/// Owned `StatefulSet`s are watched, so this is retried as soon as their pods change.
+async fn wait_for_prerequisite(
+    ctx: &Ctx,
+    cluster: &ClusterContext<'_>,
+    mut roles: RoleResults,
+    pending: &[&str],
+    reason: &str,
+    message: &str,
+) -> Result<ReconcilerAction, Error> {
+    for role in pending {
+        roles.waiting(role, message);
+    }
+    // The daemons are not asked again while waiting, since they would mostly time out
+    let daemons = DaemonReport::previous(cluster.hdfs);
+    report_progress(ctx, cluster, roles, daemons, true, reason, message).await?;
+    Ok(ReconcilerAction {
+        requeue_after: Some(Duration::from_secs(10)),
+    })
+}
+
+#[tracing::instrument(skip_all, fields(object = %ObjectRef::from_obj(&hdfs)), err)]
+pub async fn reconcile_hdfs(
+    hdfs: HdfsCluster,
+    ctx: Context<Ctx>,
+) -> Result<ReconcilerAction, Error> {
+    let ctx = ctx.get_ref();
+    let _permit = match ctx.reconcile_permits.acquire().await {
+        Some(permit) => permit,
+        // Shutting down, the object is reconciled again by the next instance of the operator
+        None => {
+            return Ok(ReconcilerAction {
+                requeue_after: None,
+            })
+        }
+    };
+    let kube = ctx.kube.clone();
+    let params = &ctx.apply_params();
+    let mut cluster = ClusterContext::new(&hdfs, &ctx.cluster_domain).context(BuildResources)?;
+    cluster.image_options = ctx.image_options.clone();
+    if hdfs.metadata.deletion_timestamp.is_some() {
+        return tear_down(ctx, &cluster).await;
+    }
+    resources::validate(&cluster).context(BuildResources)?;
+    set_teardown_finalizer(ctx, &cluster, hdfs.spec.ordered_teardown.unwrap_or(false)).await?;
+    update_secret_hashes(&kube, &mut cluster).await?;
+    cluster.tls_ca = fetch_tls_ca(&kube, &cluster).await?;
+    cluster.scale_downs = scale_downs(&kube, &cluster).await?;
+    cluster.datanode_ips = datanode_ips(&kube, &cluster).await?;
+    cluster.config_variables = config_variables(&kube, &cluster).await?;
+    let ns = cluster.namespace.as_str();
+    let owner_ref = &cluster.owner_ref;
+    // Build everything up front, so that invalid settings are rejected before anything is changed
+    let journalnode_sts = build_journalnode_statefulset(&cluster).context(BuildResources)?;
+    let namenode_sts = build_namenode_statefulset(&cluster).context(BuildResources)?;
+    let datanode_sts = build_datanode_statefulsets(&cluster).context(BuildResources)?;
+    let config_maps = build_config_maps(&cluster);
+    for sts in [&journalnode_sts, &namenode_sts]
+        .into_iter()
+        .chain(&datanode_sts)
+    {
+        check_placeholders(sts, &config_maps).context(BuildResources)?;
+    }
+
+    for config_map in &config_maps {
+        apply_owned(&kube, params, config_map)
+            .await
+            .context(ApplyConfigMap)?;
+    }
+    run_admin_tasks(&kube, params, &cluster).await?;
+    apply_owned(&kube, params, &build_discovery_config_map(&cluster))
+        .await
+        .context(ApplyDiscoveryConfigMap)?;
+    if let Some(service_account) = build_service_account(&cluster) {
+        apply_owned(&kube, params, &service_account)
+            .await
+            .context(ApplyServiceAccount)?;
+    }
+    let role_binding_name = cluster.role_binding_name();
+    if let Some(role_binding) = build_role_binding(&cluster) {
+        apply_owned(&kube, params, &role_binding)
+            .await
+            .context(ApplyRoleBinding)?;
+    } else {
+        delete_if_controlled_by::<RoleBinding>(&kube, params, ns, &role_binding_name, owner_ref)
+            .await
+            .context(DeleteRoleBinding)?;
+    }
+    let network_policy_name = cluster.network_policy_name();
+    if let Some(network_policy) = build_network_policy(&cluster) {
+        apply_owned(&kube, params, &network_policy)
+            .await
+            .context(ApplyNetworkPolicy)?;
+    } else {
+        delete_if_controlled_by::<NetworkPolicy>(
+            &kube,
+            params,
+            ns,
+            &network_policy_name,
+            owner_ref,
+        )
+        .await
+        .context(DeleteNetworkPolicy)?;
+    }
+    let pod_disruption_budgets = ROLES
+        .iter()
+        .filter_map(|role| build_pod_disruption_budget(&cluster, role))
+        .collect::<Vec<_>>();
+    for pod_disruption_budget in &pod_disruption_budgets {
+        apply_owned(&kube, params, pod_disruption_budget)
+            .await
+            .context(ApplyPodDisruptionBudget)?;
+    }
+
+    if hdfs.spec.tls.is_some() {
+        ensure_keystore_secret(&kube, params, &cluster).await?;
+    }
+    let certificates = ROLES
+        .iter()
+        .filter_map(|role| build_certificate(&cluster, role))
+        .collect::<Vec<_>>();
+    for certificate in &certificates {
+        apply_owned(&kube, params, certificate)
+            .await
+            .context(ApplyCertificate)?;
+    }
+
+    delete_orphaned_objects(
         &kube,
-        Service {
+        params,
+        &cluster,
+        &config_maps,
+        &certificates,
+        &pod_disruption_budgets,
+    )
+    .await?;
+
+    let mut role_results = RoleResults::default();
+    // cert-manager issues the certificates asynchronously, and the pods can't start without them
+    let pending = pending_certificates(&kube, &cluster).await?;
+    report_certificates(ctx, &cluster).await?;
+    if !pending.is_empty() {
+        return wait_for_prerequisite(
+            ctx,
+            &cluster,
+            role_results,
+            &ROLES,
+            "WaitingForCertificates",
+            &format!(
+                "Waiting for cert-manager to issue the certificates of {}",
+                pending.join(", ")
+            ),
+        )
+        .await;
+    }
+    if let Some(live_version) = pending_upgrade(&kube, &cluster).await? {
+        let failures = if hdfs.spec.upgrade.force.unwrap_or(false) {
+            Vec::new()
+        } else {
+            preflight::check(&cluster).await
+        };
+        if !failures.is_empty() {
+            return wait_for_prerequisite(
+                ctx,
+                &cluster,
+                role_results,
+                &ROLES,
+                "UpgradePreflightFailed",
+                &format!(
+                    "Not upgrading from Hadoop {} to {}, since {}; set upgrade.force to upgrade anyway",
+                    live_version,
+                    hdfs.spec.image.tag(),
+                    failures.join(", ")
+                ),
+            )
+            .await;
+        }
+    }
+    let journalnode_svc = apply_owned(&kube, params, &build_journalnode_service(&cluster)).await;
+    let journalnode_sts = apply_owned(&kube, params, &journalnode_sts).await;
+    let journalnode_sts = role_results.record("journalnode", journalnode_svc.and(journalnode_sts));
+    if !journalnode_sts.as_ref().map_or(false, statefulset_ready) {
+        return wait_for_prerequisite(
+            ctx,
+            &cluster,
+            role_results,
+            &["namenode", "datanode"],
+            "WaitingForJournalnodes",
+            "Waiting for the journalnodes to become ready before starting the namenodes",
+        )
+        .await;
+    }
+    // The namenodes' zkfc formats its znode on startup, so ZooKeeper must be available first
+    if let Some(znode_config_map) = hdfs.spec.zookeeper_config_map_name() {
+        match kube::Api::<ConfigMap>::namespaced(kube.clone(), ns)
+            .get(znode_config_map)
+            .await
+        {
+            Ok(_) => {}
+            Err(kube::Error::Api(err)) if err.code == 404 => {
+                return wait_for_prerequisite(
+                    ctx,
+                    &cluster,
+                    role_results,
+                    &["namenode", "datanode"],
+                    "WaitingForZookeeper",
+                    &format!(
+                        "Waiting for the ZooKeeper znode ConfigMap {}",
+                        znode_config_map
+                    ),
+                )
+                .await
+            }
+            Err(err) => return Err(err).context(GetZookeeperConfigMap),
+        }
+    }
+    let namenode_svc = apply_owned(&kube, params, &build_namenode_service(&cluster)).await;
+    let namenode_sts = apply_owned(&kube, params, &namenode_sts).await;
+    let namenode_sts = role_results.record("namenode", namenode_svc.and(namenode_sts));
+    if !namenode_sts.as_ref().map_or(false, statefulset_ready) {
+        return wait_for_prerequisite(
+            ctx,
+            &cluster,
+            role_results,
+            &["datanode"],
+            "WaitingForNamenodes",
+            "Waiting for the namenodes to become ready before starting the datanodes",
+        )
+        .await;
+    }
+    let mut applied_datanode_sts = Vec::new();
+    for (svc, sts) in build_datanode_services(&cluster).iter().zip(&datanode_sts) {
+        let svc = apply_role_service(&kube, params, svc).await;
+        let sts = apply_owned(&kube, params, sts).await;
+        applied_datanode_sts.push(svc.and(sts));
+    }
+    let datanode_sts = role_results.record(
+        "datanode",
+        applied_datanode_sts
+            .into_iter()
+            .collect::<kube::Result<Vec<_>>>(),
+    );
+
+    let mut rolling_out = false;
+    if let (Some(rollout), Some(datanode_sts)) = (&hdfs.spec.datanode_rollout, &datanode_sts) {
+        let datanodes = datanode_roles(&cluster)
+            .iter()
+            .map(|(_, replicas)| replicas)
+            .sum();
+        // One group at a time, so that maxUnavailable also holds across the groups
+        for sts in datanode_sts {
+            if roll_out_datanodes(ctx, &cluster, rollout, sts, datanodes).await? {
+                rolling_out = true;
+                break;
+            }
+        }
+    }
+    let decommissioning = !cluster.scale_downs.is_empty();
+    let (progressing, reason, message) = if rolling_out {
+        (
+            true,
+            "RollingOutDatanodes",
+            "Replacing the datanodes in batches",
+        )
+    } else if decommissioning {
+        (
+            true,
+            "DecommissioningDatanodes",
+            "Decommissioning the surplus datanodes before removing them",
+        )
+    } else if datanode_sts.as_ref().map_or(false, |datanode_sts| {
+        datanode_sts.iter().all(statefulset_ready)
+    }) {
+        (false, "ReconcileComplete", "All roles are ready")
+    } else {
+        (
+            true,
+            "WaitingForDatanodes",
+            "Waiting for the datanodes to become ready",
+        )
+    };
+    // The namenodes are ready by now, see `WaitingForNamenodes`
+    let daemons = DaemonReport::query(&cluster).await;
+    report_progress(
+        ctx,
+        &cluster,
+        role_results,
+        daemons,
+        progressing,
+        reason,
+        message,
+    )
+    .await?;
+    if !progressing {
+        autoscale_datanodes(ctx, &cluster).await?;
+    }
+
+    Ok(ReconcilerAction {
+        // Whether the previous batch has registered with the namenodes, or the surplus datanodes have been
+        // decommissioned, is polled rather than watched
+        requeue_after: Some(if rolling_out || decommissioning {
+            Duration::from_secs(10)
+        } else {
+            ctx.resync_interval
+        }),
+    })
+}
+
+/// Appends how applying `obj` would change the live object to `diff`
+async fn diff_applied<K>(kube: &kube::Client, obj: &K, diff: &mut String) -> Result<(), Error>
+where
+    K: Resource<DynamicType = ()> + Serialize + DeserializeOwned + Clone + Debug,
+{
+    diff.push_str(
+        &diff_owned(kube, FIELD_MANAGER, obj)
+            .await
+            .with_context(|| Diff {
+                kind: K::kind(&()),
+                name: obj.meta().name.clone().unwrap_or_default(),
+            })?,
+    );
+    Ok(())
+}
+
+/// Appends how deleting the object `name` would change it to `diff`
+async fn diff_removed<K>(
+    kube: &kube::Client,
+    ns: &str,
+    name: &str,
+    diff: &mut String,
+) -> Result<(), Error>
+where
+    K: Resource<DynamicType = ()> + Serialize + DeserializeOwned + Clone + Debug,
+{
+    diff.push_str(
+        &diff_deleted::<K>(kube, ns, name)
+            .await
+            .with_context(|| Diff {
+                kind: K::kind(&()),
+                name,
+            })?,
+    );
+    Ok(())
+}
+
+/// Shows how reconciling `hdfs` would change its objects, without changing anything
+///
+/// Unlike the controller, this covers all roles at once, regardless of whether their prerequisites are ready yet.
+/// Objects of roles that have been removed from the spec are not listed.
+pub async fn diff_hdfs(
+    kube: &kube::Client,
+    hdfs: &HdfsCluster,
+    cluster_domain: &str,
+    image_options: &ImageOptions,
+) -> Result<String, Error> {
+    let mut cluster = ClusterContext::new(hdfs, cluster_domain).context(BuildResources)?;
+    cluster.image_options = image_options.clone();
+    resources::validate(&cluster).context(BuildResources)?;
+    update_secret_hashes(kube, &mut cluster).await?;
+    cluster.scale_downs = scale_downs(kube, &cluster).await?;
+    cluster.datanode_ips = datanode_ips(kube, &cluster).await?;
+    cluster.config_variables = config_variables(kube, &cluster).await?;
+    let ns = cluster.namespace.as_str();
+    let journalnode_sts = build_journalnode_statefulset(&cluster).context(BuildResources)?;
+    let namenode_sts = build_namenode_statefulset(&cluster).context(BuildResources)?;
+    let datanode_sts = build_datanode_statefulsets(&cluster).context(BuildResources)?;
+
+    let mut diff = String::new();
+    for config_map in build_config_maps(&cluster) {
+        diff_applied(kube, &config_map, &mut diff).await?;
+    }
+    if let Some(refresh_task) = build_refresh_nodes_task(&cluster) {
+        diff_applied(kube, &refresh_task, &mut diff).await?;
+    }
+    if let Some(snapshot_task) = build_allow_snapshot_task(&cluster) {
+        diff_applied(kube, &snapshot_task, &mut diff).await?;
+    }
+    diff_applied(kube, &build_discovery_config_map(&cluster), &mut diff).await?;
+    if let Some(service_account) = build_service_account(&cluster) {
+        diff_applied(kube, &service_account, &mut diff).await?;
+    }
+    match build_role_binding(&cluster) {
+        Some(role_binding) => diff_applied(kube, &role_binding, &mut diff).await?,
+        None => {
+            diff_removed::<RoleBinding>(kube, ns, &cluster.role_binding_name(), &mut diff).await?
+        }
+    }
+    match build_network_policy(&cluster) {
+        Some(network_policy) => diff_applied(kube, &network_policy, &mut diff).await?,
+        None => {
+            diff_removed::<NetworkPolicy>(kube, ns, &cluster.network_policy_name(), &mut diff)
+                .await?
+        }
+    }
+    for role in ROLES {
+        match build_pod_disruption_budget(&cluster, role) {
+            Some(pod_disruption_budget) => {
+                diff_applied(kube, &pod_disruption_budget, &mut diff).await?
+            }
+            None => {
+                diff_removed::<PodDisruptionBudget>(kube, ns, &cluster.role_name(role), &mut diff)
+                    .await?
+            }
+        }
+    }
+    for role in ROLES {
+        if let Some(certificate) = build_certificate(&cluster, role) {
+            diff_applied(kube, &certificate, &mut diff).await?;
+        }
+    }
+    diff_applied(kube, &build_journalnode_service(&cluster), &mut diff).await?;
+    diff_applied(kube, &journalnode_sts, &mut diff).await?;
+    diff_applied(kube, &build_namenode_service(&cluster), &mut diff).await?;
+    diff_applied(kube, &namenode_sts, &mut diff).await?;
+    for (svc, sts) in build_datanode_services(&cluster).iter().zip(&datanode_sts) {
+        diff_applied(kube, svc, &mut diff).await?;
+        diff_applied(kube, sts, &mut diff).await?;
+    }
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pod_cluster, secret_cluster, unschedulable_pods};
+    use k8s_openapi::api::core::v1::{Pod, PodCondition, PodStatus, Secret};
+    use kube::api::ObjectMeta;
+    use kube_runtime::reflector::ObjectRef;
+    use std::collections::BTreeMap;
+
+    fn secret(name: &str) -> Secret {
+        Secret {
             metadata: ObjectMeta {
-                owner_references: Some(vec![hdfs_owner_ref.clone()]),
-                name: Some(datanode_name.clone()),
-                namespace: Some(ns.to_string()),
+                name: Some(name.to_string()),
+                namespace: Some("hdfs".to_string()),
                 ..ObjectMeta::default()
             },
-            spec: Some(ServiceSpec {
-                ports: Some(vec![
-                    ServicePort {
-                        name: Some("ipc".to_string()),
-                        port: 9867,
-                        protocol: Some("TCP".to_string()),
-                        ..ServicePort::default()
-                    },
-                    ServicePort {
-                        name: Some("http".to_string()),
-                        port: 80,
-                        target_port: Some(IntOrString::String("http".to_string())),
-                        protocol: Some("TCP".to_string()),
-                        ..ServicePort::default()
-                    },
-                ]),
-                selector: Some(datanode_pod_labels.clone()),
-                cluster_ip: Some("None".to_string()),
-                ..ServiceSpec::default()
-            }),
-            status: None,
-        },
-    )
-    .await
-    .context(ApplyPeerService)?;
-    let datanode_pod_template = PodTemplateSpec {
-        metadata: Some(ObjectMeta {
-            labels: Some(datanode_pod_labels.clone()),
-            ..ObjectMeta::default()
-        }),
-        spec: Some(PodSpec {
-            containers: vec![Container {
-                name: "datanode".to_string(),
-                args: Some(vec![
-                    "/opt/hadoop/bin/hdfs".to_string(),
-                    "datanode".to_string(),
-                ]),
-                ports: Some(vec![
-                    ContainerPort {
-                        name: Some("ipc".to_string()),
-                        container_port: 9867,
-                        protocol: Some("TCP".to_string()),
-                        ..ContainerPort::default()
-                    },
-                    ContainerPort {
-                        name: Some("data".to_string()),
-                        container_port: 9866,
-                        protocol: Some("TCP".to_string()),
-                        ..ContainerPort::default()
-                    },
-                    ContainerPort {
-                        name: Some("http".to_string()),
-                        container_port: 9864,
-                        protocol: Some("TCP".to_string()),
-                        ..ContainerPort::default()
-                    },
-                ]),
-                ..hadoop_container()
-            }],
-            volumes: Some(vec![
-                Volume {
-                    name: "config".to_string(),
-                    config_map: Some(ConfigMapVolumeSource {
-                        name: Some(format!("{}-config", name)),
-                        ..ConfigMapVolumeSource::default()
-                    }),
-                    ..Volume::default()
-                },
-                Volume {
-                    name: "kerberos".to_string(),
-                    secret: Some(SecretVolumeSource {
-                        secret_name: Some(format!("{}-kerberos", datanode_name)),
-                        ..SecretVolumeSource::default()
-                    }),
-                    ..Volume::default()
-                },
-            ]),
-            host_network: Some(true),
-            dns_policy: Some("ClusterFirstWithHostNet".to_string()),
-            ..PodSpec::default()
-        }),
-    };
-    apply_owned(
-        &kube,
-        StatefulSet {
+            ..Secret::default()
+        }
+    }
+
+    #[test]
+    fn kerberos_secrets_map_to_their_cluster() {
+        assert_eq!(
+            secret_cluster(secret("my-hdfs-namenode-kerberos")),
+            Some(ObjectRef::new("my-hdfs").within("hdfs"))
+        );
+        assert_eq!(secret_cluster(secret("my-hdfs-kerberos")), None);
+        assert_eq!(
+            secret_cluster(secret("my-hdfs-namenode-tls")),
+            Some(ObjectRef::new("my-hdfs").within("hdfs"))
+        );
+        assert_eq!(secret_cluster(secret("my-hdfs-keystore")), None);
+    }
+
+    fn pod(name: &str, phase: &str, scheduled: Option<(&str, &str)>) -> Pod {
+        Pod {
             metadata: ObjectMeta {
-                owner_references: Some(vec![hdfs_owner_ref.clone()]),
-                name: Some(datanode_name.clone()),
-                namespace: Some(ns.to_string()),
+                name: Some(name.to_string()),
+                namespace: Some("hdfs".to_string()),
+                labels: Some(BTreeMap::from([
+                    ("app.kubernetes.io/name".to_string(), "hdfs".to_string()),
+                    (
+                        "app.kubernetes.io/instance".to_string(),
+                        "my-hdfs".to_string(),
+                    ),
+                ])),
                 ..ObjectMeta::default()
             },
-            spec: Some(StatefulSetSpec {
-                pod_management_policy: Some("Parallel".to_string()),
-                replicas: hdfs.spec.datanode_replicas,
-                selector: LabelSelector {
-                    match_labels: Some(datanode_pod_labels.clone()),
-                    ..LabelSelector::default()
-                },
-                service_name: datanode_name.clone(),
-                template: datanode_pod_template,
-                volume_claim_templates: Some(vec![local_disk_claim(
-                    "data",
-                    Quantity("1Gi".to_string()),
-                )]),
-                // volume_claim_templates: todo!(),
-                ..StatefulSetSpec::default()
+            status: Some(PodStatus {
+                phase: Some(phase.to_string()),
+                conditions: scheduled.map(|(status, reason)| {
+                    vec![PodCondition {
+                        type_: "PodScheduled".to_string(),
+                        status: status.to_string(),
+                        reason: Some(reason.to_string()),
+                        message: Some("0/3 nodes are available".to_string()),
+                        ..PodCondition::default()
+                    }]
+                }),
+                ..PodStatus::default()
             }),
-            status: None,
-        },
-    )
-    .await
-    .context(ApplyStatefulSet)?;
+            ..Pod::default()
+        }
+    }
 
-    Ok(ReconcilerAction {
-        requeue_after: None,
-    })
-}
+    #[test]
+    fn pods_map_to_their_cluster() {
+        let mut datanode = pod("my-hdfs-datanode-0", "Running", None);
+        assert_eq!(
+            pod_cluster(datanode.clone()),
+            Some(ObjectRef::new("my-hdfs").within("hdfs"))
+        );
+        datanode.metadata.labels.as_mut().unwrap().insert(
+            "app.kubernetes.io/name".to_string(),
+            "zookeeper".to_string(),
+        );
+        assert_eq!(pod_cluster(datanode), None);
+    }
 
-pub fn error_policy(_error: &Error, _ctx: Context<Ctx>) -> ReconcilerAction {
-    ReconcilerAction {
-        requeue_after: Some(Duration::from_secs(5)),
+    #[test]
+    fn unschedulable_pods_are_detected() {
+        let pods = [
+            pod("my-hdfs-namenode-0", "Running", Some(("True", ""))),
+            pod(
+                "my-hdfs-datanode-0",
+                "Pending",
+                Some(("False", "Unschedulable")),
+            ),
+            pod("my-hdfs-datanode-1", "Pending", None),
+        ];
+        assert_eq!(
+            unschedulable_pods(&pods),
+            [(
+                "my-hdfs-datanode-0".to_string(),
+                "0/3 nodes are available".to_string()
+            )]
+        );
     }
 }
@@ -0,0 +1,163 @@
+//! Converting `HdfsCluster`s between `v1alpha1` and `v1alpha2`
+
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::{
+    CustomResourceConversion, CustomResourceDefinition,
+};
+use kube::CustomResourceExt;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{v1alpha1, v1alpha2};
+
+pub const V1ALPHA1: &str = "hdfs.stackable.tech/v1alpha1";
+pub const V1ALPHA2: &str = "hdfs.stackable.tech/v1alpha2";
+
+/// The `HdfsCluster` CRD, which only serves `v1alpha2` if the API server can convert it through `conversion`
+pub fn hdfs_cluster_crd(conversion: Option<CustomResourceConversion>) -> CustomResourceDefinition {
+    let mut crd = v1alpha1::HdfsCluster::crd();
+    if let Some(conversion) = conversion {
+        let mut versions = v1alpha2::HdfsCluster::crd().spec.versions;
+        for version in &mut versions {
+            version.storage = false;
+        }
+        crd.spec.versions.extend(versions);
+        crd.spec.conversion = Some(conversion);
+    }
+    crd
+}
+
+fn convert_spec<S, T>(spec: serde_json::Value) -> Result<serde_json::Value, String>
+where
+    S: DeserializeOwned,
+    T: From<S> + Serialize,
+{
+    let spec = serde_json::from_value::<S>(spec).map_err(|err| err.to_string())?;
+    serde_json::to_value(T::from(spec)).map_err(|err| err.to_string())
+}
+
+/// Converts the `HdfsCluster` `obj` to `desired_api_version`, only the `spec` differs between the versions
+pub fn convert(
+    mut obj: serde_json::Value,
+    desired_api_version: &str,
+) -> Result<serde_json::Value, String> {
+    let api_version = obj
+        .get("apiVersion")
+        .and_then(serde_json::Value::as_str)
+        .ok_or("HdfsCluster has no apiVersion")?
+        .to_string();
+    let spec = obj
+        .get_mut("spec")
+        .map(serde_json::Value::take)
+        .unwrap_or_else(|| serde_json::json!({}));
+    let spec = match (api_version.as_str(), desired_api_version) {
+        (from, to) if from == to => spec,
+        (V1ALPHA1, V1ALPHA2) => {
+            convert_spec::<v1alpha1::HdfsClusterSpec, v1alpha2::HdfsClusterSpec>(spec)?
+        }
+        (V1ALPHA2, V1ALPHA1) => {
+            convert_spec::<v1alpha2::HdfsClusterSpec, v1alpha1::HdfsClusterSpec>(spec)?
+        }
+        (from, to) => {
+            return Err(format!(
+                "cannot convert HdfsCluster from {} to {}",
+                from, to
+            ))
+        }
+    };
+    obj["spec"] = spec;
+    obj["apiVersion"] = desired_api_version.into();
+    Ok(obj)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v1alpha1_cluster() -> serde_json::Value {
+        serde_json::json!({
+            "apiVersion": V1ALPHA1,
+            "kind": "HdfsCluster",
+            "metadata": { "name": "simple", "namespace": "default" },
+            "spec": {
+                "namenodeReplicas": 2,
+                "datanodeReplicas": 3,
+                "journalnodeReplicas": 3,
+                "datanode": { "priorityClassName": "storage" },
+                "datanodeStorage": { "volumes": 2 },
+                "datanodeGroups": { "ssd": { "replicas": 1, "storageType": "SSD" } },
+                "kerberos": { "realm": "EXAMPLE.COM" },
+                "security": { "authorization": {} },
+                "dfsReplication": 2,
+            },
+            "status": { "datanodeReplicas": 3 },
+        })
+    }
+
+    #[test]
+    fn v1alpha1_is_grouped_by_role() {
+        let converted = convert(v1alpha1_cluster(), V1ALPHA2).unwrap();
+        assert_eq!(converted["apiVersion"], V1ALPHA2);
+        assert_eq!(converted["metadata"]["name"], "simple");
+        assert_eq!(converted["status"]["datanodeReplicas"], 3);
+        let spec = &converted["spec"];
+        assert_eq!(spec["namenodes"]["replicas"], 2);
+        assert_eq!(spec["datanodes"]["replicas"], 3);
+        assert_eq!(spec["datanodes"]["priorityClassName"], "storage");
+        assert_eq!(spec["datanodes"]["storage"]["volumes"], 2);
+        assert_eq!(spec["datanodes"]["roleGroups"]["ssd"]["replicas"], 1);
+        assert_eq!(spec["journalnodes"]["replicas"], 3);
+        assert_eq!(spec["security"]["kerberos"]["realm"], "EXAMPLE.COM");
+        assert!(spec["security"]["authorization"].is_object());
+        assert_eq!(spec["dfsReplication"], 2);
+        assert_eq!(spec.get("datanodeReplicas"), None);
+    }
+
+    #[test]
+    fn conversion_round_trips() {
+        let original = v1alpha1_cluster();
+        let round_tripped =
+            convert(convert(original.clone(), V1ALPHA2).unwrap(), V1ALPHA1).unwrap();
+        assert_eq!(round_tripped["apiVersion"], V1ALPHA1);
+        assert_eq!(round_tripped["status"], original["status"]);
+        assert_eq!(
+            serde_json::from_value::<v1alpha1::HdfsClusterSpec>(round_tripped["spec"].clone())
+                .unwrap(),
+            serde_json::from_value::<v1alpha1::HdfsClusterSpec>(original["spec"].clone()).unwrap()
+        );
+    }
+
+    #[test]
+    fn unknown_versions_are_rejected() {
+        assert!(convert(v1alpha1_cluster(), "hdfs.stackable.tech/v1").is_err());
+        assert_eq!(
+            convert(v1alpha1_cluster(), V1ALPHA1).unwrap(),
+            v1alpha1_cluster()
+        );
+    }
+
+    #[test]
+    fn crd_only_serves_v1alpha2_with_a_webhook() {
+        let versions = |crd: CustomResourceDefinition| {
+            crd.spec
+                .versions
+                .into_iter()
+                .map(|version| (version.name, version.storage))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            versions(hdfs_cluster_crd(None)),
+            vec![("v1alpha1".to_string(), true)]
+        );
+        let crd = hdfs_cluster_crd(Some(CustomResourceConversion {
+            strategy: "Webhook".to_string(),
+            webhook: None,
+        }));
+        assert!(crd.spec.conversion.is_some());
+        assert_eq!(
+            versions(crd),
+            vec![
+                ("v1alpha1".to_string(), true),
+                ("v1alpha2".to_string(), false)
+            ]
+        );
+    }
+}
@@ -0,0 +1,10 @@
+//! The custom resources of the HDFS operator
+//!
+//! `HdfsCluster`s are served in `v1alpha1` and `v1alpha2`, and stored in `v1alpha1`. The API server converts
+//! between them through the operator's conversion webhook, see [`conversion`].
+
+pub mod conversion;
+pub mod v1alpha1;
+pub mod v1alpha2;
+
+pub use v1alpha1::*;
@@ -0,0 +1,1204 @@
+//! The storage version of the custom resources, which the controllers work with
+
+use std::{collections::BTreeMap, fmt::Display};
+
+use k8s_openapi::{
+    api::{
+        apps::v1::StatefulSetUpdateStrategy,
+        core::v1::{ResourceRequirements, TopologySpreadConstraint, Volume, VolumeMount},
+    },
+    apimachinery::pkg::{
+        api::resource::Quantity,
+        apis::meta::v1::{Condition, Time},
+        util::intstr::IntOrString,
+    },
+};
+use kube::CustomResource;
+pub use operator_commons::spec::{
+    AllowedClient, NetworkIsolationConfig, PodSecurityConfig, ServiceAccountConfig,
+};
+use operator_commons::{merge::pod_overrides_schema, spec::ProductImage};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, CustomResource, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[kube(
+    group = "hdfs.stackable.tech",
+    version = "v1alpha1",
+    kind = "HdfsCluster",
+    plural = "hdfsclusters",
+    shortname = "hdfs",
+    namespaced
+)]
+#[kube(status = "HdfsClusterStatus")]
+#[kube(
+    scale = r#"{"specReplicasPath":".spec.datanodeReplicas","statusReplicasPath":".status.datanodeReplicas","labelSelectorPath":".status.datanodeSelector"}"#
+)]
+#[serde(rename_all = "camelCase")]
+pub struct HdfsClusterSpec {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namenode_replicas: Option<i32>,
+    /// Number of datanodes outside of the `datanodeGroups`, which is also exposed by the `scale` subresource
+    ///
+    /// Surplus datanodes are decommissioned before they are removed, so their blocks are re-replicated first.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub datanode_replicas: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub journalnode_replicas: Option<i32>,
+    #[serde(default)]
+    pub ports: HdfsPortsConfig,
+    #[serde(default)]
+    pub namenode_rpc: NamenodeRpcConfig,
+    #[serde(default)]
+    pub namenode: HdfsRoleConfig,
+    #[serde(default)]
+    pub datanode: HdfsRoleConfig,
+    #[serde(default)]
+    pub journalnode: HdfsRoleConfig,
+    #[serde(default)]
+    pub property_validation: PropertyValidationConfig,
+    /// ConfigMap of a ZookeeperZnode, whose `ZOOKEEPER_HOSTS` and `ZOOKEEPER_CHROOT` the failover controllers use
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zookeeper_config_map_name: Option<String>,
+    /// Deprecated, use `zookeeperConfigMapName`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namenode_znode_config_map: Option<String>,
+    #[serde(default)]
+    pub failover_controller: FailoverControllerConfig,
+    /// The Hadoop image, defaults to `teozkr/hadoop:3.3.1`
+    #[serde(default)]
+    pub image: ImageConfig,
+    /// Default number of replicas for each block (`dfs.replication`), may not exceed the number of datanodes
+    ///
+    /// Hadoop defaults to `3`, blocks of clusters with fewer datanodes then stay under-replicated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dfs_replication: Option<i32>,
+    /// Minimal block replication required for a write to succeed (`dfs.namenode.replication.min`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dfs_replication_min: Option<i32>,
+    /// Space in bytes per volume that datanodes reserve for non-DFS use (`dfs.datanode.du.reserved`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dfs_datanode_du_reserved: Option<i64>,
+    #[serde(default)]
+    pub datanode_storage: DatanodeStorageConfig,
+    #[serde(default)]
+    pub journalnode_storage: JournalnodeStorageConfig,
+    /// Additional groups of datanodes by name, each in its own `StatefulSet`, next to the `datanodeReplicas` datanodes
+    ///
+    /// Allows e.g. mixing SSD- and HDD-backed datanodes in a single cluster.
+    #[serde(default)]
+    pub datanode_groups: BTreeMap<String, DatanodeGroupConfig>,
+    /// Lets the operator add `datanodeReplicas` datanodes when the file system runs out of space
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub datanode_autoscaling: Option<DatanodeAutoscalingConfig>,
+    #[serde(default)]
+    pub trash: TrashConfig,
+    #[serde(default)]
+    pub snapshot: SnapshotConfig,
+    #[serde(default)]
+    pub checkpoint: CheckpointConfig,
+    #[serde(default)]
+    pub delegation_tokens: DelegationTokenConfig,
+    #[serde(default)]
+    pub kerberos: KerberosConfig,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+    #[serde(default)]
+    pub security: SecurityConfig,
+    /// The defaults only satisfy the `restricted` Pod Security Standard with `hostNetwork: false`, since both the
+    /// `baseline` and the `restricted` standard forbid the host network
+    #[serde(default)]
+    pub pod_security: PodSecurityConfig,
+    /// Whether the pods use the network of their nodes, defaults to `true`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_network: Option<bool>,
+    /// Generates `NetworkPolicy` objects that only admit traffic from cluster members and the allowed clients
+    ///
+    /// Requires `hostNetwork: false`, since `NetworkPolicy`s don't apply to pods on the host network.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network_isolation: Option<NetworkIsolationConfig>,
+    #[serde(default)]
+    pub service_account: ServiceAccountConfig,
+    /// Enables the NameNode's HDFS audit log
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit_log: Option<AuditLogConfig>,
+    #[serde(default)]
+    pub webhdfs: WebHdfsConfig,
+    /// Traces the namenodes, datanodes, and journalnodes with the OpenTelemetry Java agent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tracing: Option<TracingConfig>,
+    /// Lets the operator replace the datanodes in batches, instead of one at a time
+    ///
+    /// Takes precedence over `datanode.updateStrategy`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub datanode_rollout: Option<BatchedRolloutConfig>,
+    /// Datanodes that the namenodes put into maintenance, such as before their nodes are drained, so that their blocks
+    /// aren't re-replicated while they are down
+    #[serde(default)]
+    pub datanode_maintenance: Vec<DatanodeMaintenance>,
+    #[serde(default)]
+    pub upgrade: UpgradeConfig,
+    /// Stops the roles one at a time when the cluster is deleted, datanodes first and journalnodes last, before
+    /// Kubernetes deletes the rest of its objects, defaults to `false`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ordered_teardown: Option<bool>,
+    /// Labels that are added to all generated objects and pods, generated labels take precedence
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Annotations that are added to all generated objects and pods
+    #[serde(default)]
+    pub annotations: BTreeMap<String, String>,
+}
+
+/// Kerberos realm of the principals if `kerberos.realm` is not set
+pub const DEFAULT_KERBEROS_REALM: &str = "LOCAL";
+
+impl HdfsClusterSpec {
+    /// Sets the defaults that the operator otherwise assumes, so that they show up in the object
+    pub fn set_defaults(&mut self) {
+        self.namenode_replicas.get_or_insert(1);
+        self.datanode_replicas.get_or_insert(1);
+        self.journalnode_replicas.get_or_insert(1);
+        self.kerberos
+            .realm
+            .get_or_insert_with(|| DEFAULT_KERBEROS_REALM.to_string());
+    }
+
+    /// The ZookeeperZnode's ConfigMap, `zookeeperConfigMapName` takes precedence over its deprecated predecessor
+    pub fn zookeeper_config_map_name(&self) -> Option<&str> {
+        self.zookeeper_config_map_name
+            .as_deref()
+            .or(self.namenode_znode_config_map.as_deref())
+    }
+
+    /// The deprecated fields that are set
+    pub fn deprecations(&self) -> Vec<Deprecation> {
+        let mut deprecations = Vec::new();
+        if self.namenode_znode_config_map.is_some() {
+            deprecations.push(Deprecation {
+                field: "namenodeZnodeConfigMap",
+                replacement: "zookeeperConfigMapName",
+            });
+        }
+        deprecations
+    }
+}
+
+/// A deprecated field that is used, which is going to be removed in favor of `replacement`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Deprecation {
+    pub field: &'static str,
+    pub replacement: &'static str,
+}
+
+impl Display for Deprecation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} is deprecated, use {} instead",
+            self.field, self.replacement
+        )
+    }
+}
+
+/// Checks that the cluster is healthy before the pods are replaced with a new `image.tag`
+///
+/// If any check fails, the new version is not rolled out, and the failures are reported in the `Progressing`
+/// condition. Once the rollout has started, it is not held back anymore.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct UpgradeConfig {
+    /// Rolls out the new version even if the checks fail, defaults to `false`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub force: Option<bool>,
+    /// Maximum age in seconds of the last fsimage checkpoint, defaults to `7200`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_checkpoint_age: Option<i64>,
+    /// Maximum number of under-replicated blocks, defaults to `0`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_under_replicated_blocks: Option<i64>,
+}
+
+/// How the `configOverrides` of all roles are checked against the Hadoop properties that the operator knows about
+///
+/// Problems are reported in the `ConfigOverridesValid` condition and as events, the overrides are applied regardless.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PropertyValidationConfig {
+    /// Stops reporting overrides of properties that the operator doesn't know about, defaults to `false`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_unknown: Option<bool>,
+}
+
+/// Overrides for the ports that the HDFS daemons listen on
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct HdfsPortsConfig {
+    /// NameNode RPC port, defaults to `8020`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namenode_rpc: Option<i32>,
+    /// NameNode web UI port, defaults to `9870`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namenode_http: Option<i32>,
+    /// DataNode data transfer port, defaults to `9866`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub datanode_data: Option<i32>,
+    /// DataNode IPC port, defaults to `9867`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub datanode_ipc: Option<i32>,
+    /// DataNode web UI port, defaults to `9864`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub datanode_http: Option<i32>,
+    /// JournalNode RPC port, defaults to `8485`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub journalnode_rpc: Option<i32>,
+    /// JournalNode web server port, which the namenodes fetch edits from, defaults to `8480`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub journalnode_http: Option<i32>,
+    /// NameNode web UI port over HTTPS, which is only served with `tls`, defaults to `9871`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namenode_https: Option<i32>,
+    /// DataNode web UI port over HTTPS, which is only served with `tls`, defaults to `9865`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub datanode_https: Option<i32>,
+    /// JournalNode web server port over HTTPS, which is only served with `tls`, defaults to `8481`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub journalnode_https: Option<i32>,
+    /// Separate NameNode RPC port for the datanodes and the other HDFS daemons, so that they aren't starved by clients
+    ///
+    /// Unset by default, which makes the daemons share `namenodeRpc` with clients.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namenode_service_rpc: Option<i32>,
+}
+
+/// The WebHDFS REST API, which the namenodes and datanodes serve on their web UI ports
+///
+/// Clients authenticate to the namenodes with SPNEGO as the `HTTP` principal of the namenode `Service`, which
+/// secret-operator provisions. Without `kerberos.secretClass`, the `<cluster>-namenode-kerberos` `Secret` has to contain
+/// its keytab as `HTTP.service.keytab`. The datanodes only accept the delegation tokens that the namenodes redirect
+/// clients with.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct WebHdfsConfig {
+    /// Whether WebHDFS is served (`dfs.webhdfs.enabled`), defaults to `false`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+}
+
+/// Where the daemons send their traces to
+///
+/// Hadoop 3.3.1 replaced HTrace with a tracer that does nothing, so the daemons are traced by the OpenTelemetry Java
+/// agent instead. An init container copies the agent from `agentImage` into the pods, and it exports spans over OTLP.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TracingConfig {
+    /// OTLP endpoint of the collector, such as `http://otel-collector.monitoring.svc:4317`
+    pub endpoint: String,
+    /// Fraction of the traces that are started by the daemons that is sampled, defaults to `0.1`
+    ///
+    /// Traces that are propagated from clients keep the clients' decision.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sampling_ratio: Option<f64>,
+    /// Image with the agent at `/javaagent.jar`, defaults to
+    /// `ghcr.io/open-telemetry/opentelemetry-operator/autoinstrumentation-java:1.9.0`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_image: Option<String>,
+    /// `Secret` with the CA certificate (`ca.crt`) that the collector's certificate is verified against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_secret_name: Option<String>,
+}
+
+/// Sizes of the namenodes' RPC handler pools
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NamenodeRpcConfig {
+    /// Threads that serve client RPCs (`dfs.namenode.handler.count`), Hadoop defaults to `10`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub handler_count: Option<i32>,
+    /// Threads that serve the daemons' RPCs on `ports.namenodeServiceRpc` (`dfs.namenode.service.handler.count`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service_handler_count: Option<i32>,
+}
+
+/// Customizations for the pods of a single role
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HdfsRoleConfig {
+    /// Volumes that are added to the role's pods, in addition to the ones generated by the operator
+    #[serde(default)]
+    pub extra_volumes: Vec<Volume>,
+    /// Mounts that are added to all of the role's containers, typically referring to `extraVolumes`
+    #[serde(default)]
+    pub extra_volume_mounts: Vec<VolumeMount>,
+    /// Name of the `PriorityClass` assigned to the pods
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority_class_name: Option<String>,
+    /// Time that the pods are given to shut down gracefully before they are killed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub termination_grace_period_seconds: Option<i64>,
+    /// How the role's pods are spread across topology domains, such as zones or racks
+    ///
+    /// Constraints without a `labelSelector` select the role's pods, so that those of `datanode` balance all datanodes
+    /// including the groups. The constraints of a datanode group replace the ones of `datanode`, and only select the
+    /// group's pods by default.
+    #[serde(default)]
+    pub topology_spread_constraints: Vec<TopologySpreadConstraint>,
+    /// Fragment of a `PodTemplateSpec` that is merged over the generated pod template
+    ///
+    /// Lists of named objects (such as `containers`, `volumes`, or `env`) are merged by name, other values replace the generated ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "pod_overrides_schema")]
+    pub pod_overrides: Option<serde_json::Value>,
+    /// Whether the role's pods are started and stopped one at a time, defaults to `Parallel`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pod_management_policy: Option<PodManagementPolicy>,
+    /// How the role's pods are replaced when its pod template changes, defaults to rolling updates of one pod at a time
+    ///
+    /// A `rollingUpdate.partition` only updates the pods with higher ordinals, which allows staged rollouts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update_strategy: Option<StatefulSetUpdateStrategy>,
+    /// Labels that are added to the role's objects and pods, over the cluster-wide `labels`
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Annotations that are added to the role's objects and pods, over the cluster-wide `annotations`
+    #[serde(default)]
+    pub annotations: BTreeMap<String, String>,
+    /// Properties that are set over the generated ones, by configuration file (`core-site.xml` or `hdfs-site.xml`)
+    ///
+    /// The merged result is listed in the `effective-config` key of the role's `ConfigMap`.
+    #[serde(default)]
+    pub config_overrides: BTreeMap<String, BTreeMap<String, String>>,
+    /// `final` flags and tags of properties, by configuration file like `configOverrides`
+    ///
+    /// Apply to generated and overridden properties alike, the operator marks the security settings final by default.
+    #[serde(default)]
+    pub property_attributes: BTreeMap<String, BTreeMap<String, PropertyAttributes>>,
+    /// Sets the cluster autoscaler's `cluster-autoscaler.kubernetes.io/safe-to-evict` annotation on the pods, so that
+    /// it may (`true`) or may not (`false`) remove their nodes, left to the autoscaler if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub safe_to_evict: Option<bool>,
+    /// Limits how many of the role's pods node drains may evict at once, all datanode groups share the budget of
+    /// `datanode`
+    #[serde(default)]
+    pub disruption_budget: DisruptionBudgetConfig,
+    /// How the role's `Service` publishes its pods, datanode groups use the settings of `datanode`
+    #[serde(default)]
+    pub service: RoleServiceConfig,
+    /// Garbage collection and diagnostics of the JVM that runs the role's daemon, datanode groups use the settings of
+    /// `datanode`
+    #[serde(default)]
+    pub jvm: JvmConfig,
+    /// Whether the root filesystems of the role's containers are mounted read-only, defaults to
+    /// `podSecurity.readOnlyRootFilesystem`, datanode groups use the setting of `datanode`
+    ///
+    /// Hadoop's scratch directories, `/tmp` and its log directory, are then backed by `emptyDir` volumes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_only_root_filesystem: Option<bool>,
+    /// Size limit of each scratch volume of a read-only root filesystem, unlimited by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scratch_volume_size: Option<Quantity>,
+}
+
+/// Settings of a daemon's JVM
+///
+/// Diagnostics for post-mortems are written to the `diagnostics` volume at `/diagnostics`, which is kept when the
+/// daemon's container restarts, so that the files can be copied out of the pod afterwards.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct JvmConfig {
+    /// Garbage collector of the daemon, left to the JVM by default
+    #[serde(default)]
+    pub gc: GcConfig,
+    /// Writes a heap dump when the daemon runs out of memory (`-XX:+HeapDumpOnOutOfMemoryError`), defaults to `false`
+    ///
+    /// Existing dumps aren't overwritten, so they have to be deleted to capture the next one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heap_dump_on_out_of_memory: Option<bool>,
+    /// Keeps a JFR flight recording of the daemon, which is written when it exits, defaults to `false`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flight_recorder: Option<bool>,
+    /// Size limit of the diagnostics volume, unlimited by default
+    ///
+    /// The pod is evicted once the volume outgrows its limit, so it should leave room for a heap dump the size of the
+    /// maximum heap.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diagnostics_volume_size: Option<Quantity>,
+}
+
+/// Garbage collector settings of a daemon's JVM
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct GcConfig {
+    /// Tuned collector settings, which `extraArgs` are added after
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<GcPreset>,
+    /// JVM flags that are passed to the daemon after the preset's, so that they override them
+    ///
+    /// The flags aren't checked by the operator, a daemon that is given invalid flags fails to start.
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+pub enum GcPreset {
+    /// G1 with short pauses, for the large heaps of namenodes (and busy datanodes)
+    G1,
+    /// The serial collector, which returns unused heap to the OS, for the small heaps of ZKFCs and journalnodes
+    SmallHeap,
+}
+
+/// Attributes of a property of a Hadoop configuration file, other than its value
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PropertyAttributes {
+    /// Whether configuration files that are loaded later, such as those of user jobs, may not override the property
+    #[serde(rename = "final", default, skip_serializing_if = "Option::is_none")]
+    pub final_: Option<bool>,
+    /// Tags that group the property, such as `SECURITY`, replacing those of lower layers
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// The `Service` of a role, which the `StatefulSet` uses to give each pod a stable DNS name
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleServiceConfig {
+    /// Whether the `Service` has no cluster IP, so that its DNS name resolves to the pods themselves, defaults to `true`
+    ///
+    /// Only the datanodes can do without, the other roles' pods are addressed by their own DNS names, which require a
+    /// headless `Service`. Changing this replaces the `Service`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headless: Option<bool>,
+    /// Whether pods are published before they are ready, defaults to `true` for the namenodes and journalnodes, which
+    /// need to reach each other while they start, and to `false` for the datanodes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publish_not_ready_addresses: Option<bool>,
+}
+
+/// The `PodDisruptionBudget` of a role
+///
+/// Node drains and the cluster autoscaler evict pods through the eviction API, which respects the budget. Since an
+/// active namenode fails over to a standby before it stops, evicting one namenode at a time keeps HDFS available.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DisruptionBudgetConfig {
+    /// Whether the operator generates the budget, defaults to `true`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// Number (such as `1`) or percentage (such as `10%`) of pods that may be evicted at once, defaults to `1`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_unavailable: Option<IntOrString>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+pub enum PodManagementPolicy {
+    /// Pods are created in order, each waiting for its predecessor to become ready, and deleted in reverse order
+    OrderedReady,
+    /// Pods are created and deleted all at once
+    Parallel,
+}
+
+impl Display for PodManagementPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OrderedReady => write!(f, "OrderedReady"),
+            Self::Parallel => write!(f, "Parallel"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchedRolloutConfig {
+    /// Number (such as `5`) or percentage (such as `10%`) of datanodes that may be restarted at once, defaults to `1`
+    ///
+    /// The next batch is only started once all datanodes are ready and have registered with every namenode again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_unavailable: Option<IntOrString>,
+}
+
+/// A datanode in maintenance, whose blocks only need `dfs.namenode.maintenance.replication.min` live replicas
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DatanodeMaintenance {
+    /// Name of the datanode's pod, such as `simple-datanode-2`
+    pub pod: String,
+    /// When the maintenance ends, after which the datanode's blocks are re-replicated if it is still down, defaults
+    /// to never
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<Time>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashConfig {
+    /// Minutes after which deleted files are removed from the trash, `0` disables the trash (`fs.trash.interval`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval: Option<i64>,
+    /// Minutes between trash checkpoints, should not exceed `interval` (`fs.trash.checkpoint.interval`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checkpoint_interval: Option<i64>,
+}
+
+/// How often the standby namenode merges the edit log into a new fsimage
+///
+/// The operator warns with a `Degraded` condition when the active namenode's last checkpoint falls behind twice the
+/// `period` or `transactions`, since the namenodes would otherwise only notice when they are restarted.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckpointConfig {
+    /// Seconds between two checkpoints (`dfs.namenode.checkpoint.period`), Hadoop defaults to `3600`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub period: Option<i64>,
+    /// Transactions after which a checkpoint is taken before the `period` is up (`dfs.namenode.checkpoint.txns`),
+    /// Hadoop defaults to `1000000`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transactions: Option<i64>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotConfig {
+    /// Whether snapshots include files that are still open for writing (`dfs.namenode.snapshot.capture.openfiles`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capture_open_files: Option<bool>,
+    /// Maximum number of snapshots per snapshottable directory (`dfs.namenode.snapshot.max.limit`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_limit: Option<i32>,
+    /// Absolute paths of existing directories that snapshots may be taken of
+    ///
+    /// The operator allows snapshots of them with an `allowSnapshot` `HdfsAdminTask`, which is retried until it
+    /// succeeds. Removing a directory from the list doesn't disallow its snapshots again, use a `disallowSnapshot`
+    /// task for that.
+    #[serde(default)]
+    pub allow_snapshot: Vec<String>,
+}
+
+/// The volumes that each datanode stores blocks on, and how datanodes deal with failing ones
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DatanodeStorageConfig {
+    /// Number of volumes (`PersistentVolumeClaim`s) per datanode, defaults to `1`
+    ///
+    /// The volumes of a `StatefulSet` can't be changed, so the datanode `StatefulSet` must be deleted (orphaning its
+    /// pods) to change this.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volumes: Option<i32>,
+    /// Number of volumes that may fail before a datanode shuts down (`dfs.datanode.failed.volumes.tolerated`),
+    /// must be less than `volumes`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed_volumes_tolerated: Option<i32>,
+    /// Minimum milliseconds between two checks of the same volume (`dfs.datanode.disk.check.min.gap`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disk_check_min_gap: Option<i64>,
+    /// Milliseconds after which a hanging volume check fails the volume (`dfs.datanode.disk.check.timeout`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disk_check_timeout: Option<i64>,
+    /// Storage type that the volumes are tagged with in `dfs.datanode.data.dir`, Hadoop defaults to `DISK`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_type: Option<StorageType>,
+}
+
+/// The volume that each journalnode keeps the edit log on, and how much of the edit log the namenodes keep
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalnodeStorageConfig {
+    /// Size of the volume, defaults to `1Gi`
+    ///
+    /// The volumes of a `StatefulSet` can't be changed, so the journalnode `StatefulSet` must be deleted (orphaning its
+    /// pods) to change this or `storageClassName`, and existing volumes have to be resized separately.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<Quantity>,
+    /// `StorageClass` of the volume, defaults to the Kubernetes cluster's default `StorageClass`
+    ///
+    /// The journalnodes sync the edit log on every write, so a class with provisioned IOPS keeps the namenodes fast.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_class_name: Option<String>,
+    /// Directory within the volume that the edit log is kept in (`dfs.journalnode.edits.dir`), such as `journal`,
+    /// defaults to the volume's root
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edits_subdirectory: Option<String>,
+    /// Transactions that are kept beyond the ones needed to restart the namenodes
+    /// (`dfs.namenode.num.extra.edits.retained`), Hadoop defaults to `1000000`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extra_edits_retained: Option<i64>,
+    /// Most edit log segments that are kept beyond the ones needed to restart the namenodes
+    /// (`dfs.namenode.max.extra.edits.segments.retained`), Hadoop defaults to `10000`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_extra_edit_segments_retained: Option<i32>,
+}
+
+/// Storage types that HDFS storage policies choose between when placing block replicas
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum StorageType {
+    RamDisk,
+    Ssd,
+    Disk,
+    Archive,
+    Nvdimm,
+}
+
+impl Display for StorageType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RamDisk => write!(f, "RAM_DISK"),
+            Self::Ssd => write!(f, "SSD"),
+            Self::Disk => write!(f, "DISK"),
+            Self::Archive => write!(f, "ARCHIVE"),
+            Self::Nvdimm => write!(f, "NVDIMM"),
+        }
+    }
+}
+
+/// A group of datanodes with its own storage profile and placement
+///
+/// All other datanode settings, such as `datanodeStorage.failedVolumesTolerated`, are shared with the other datanodes.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DatanodeGroupConfig {
+    /// Number of datanodes in the group, defaults to `1`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replicas: Option<i32>,
+    /// Number of volumes per datanode, defaults to `datanodeStorage.volumes`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volumes: Option<i32>,
+    /// Defaults to `datanodeStorage.storageType`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_type: Option<StorageType>,
+    /// `StorageClass` of the group's volumes, defaults to the Kubernetes cluster's default `StorageClass`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_class_name: Option<String>,
+    /// Labels of the nodes that the group's pods may be scheduled on
+    #[serde(default)]
+    pub node_selector: BTreeMap<String, String>,
+    /// Customizations for the group's pods, which are applied over the ones in `datanode`
+    #[serde(default)]
+    pub pods: HdfsRoleConfig,
+}
+
+/// Bounds and threshold of the capacity-based datanode autoscaler
+///
+/// The operator only ever scales up, through the `scale` subresource, so it can't fight with a user who scales down.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DatanodeAutoscalingConfig {
+    /// Fewest `datanodeReplicas` datanodes to scale up to, defaults to `1`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_replicas: Option<i32>,
+    /// Most `datanodeReplicas` datanodes that the operator scales up to
+    pub max_replicas: i32,
+    /// Percentage of the file system's capacity that may be used before datanodes are added, defaults to `80`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub used_percent_threshold: Option<f64>,
+}
+
+/// How the namenodes' failover controllers (ZKFCs) use ZooKeeper to elect the active namenode
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FailoverControllerConfig {
+    /// ZNode below the chroot that the election is held in (`ha.zookeeper.parent-znode`), defaults to `hadoop-ha`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_znode: Option<String>,
+    /// Milliseconds after which an unresponsive ZKFC loses its ZooKeeper session, and a standby takes over
+    /// (`ha.zookeeper.session-timeout.ms`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_timeout: Option<i64>,
+    /// Authenticates the ZKFCs to ZooKeeper, and restricts the election's ZNodes to them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<ZookeeperAuth>,
+    /// Methods that stop a failed active namenode before a standby takes over (`dfs.ha.fencing.methods`), which are
+    /// tried in order until one succeeds
+    ///
+    /// Defaults to `shell(/bin/true)`, which relies on the journalnodes alone to reject a former active's edits.
+    #[serde(default)]
+    pub fencing_methods: Vec<FencingMethod>,
+    /// Compute resources of the ZKFC container, which runs next to each namenode
+    ///
+    /// The ZKFC's heap is sized to fit its memory limit (or request), independently of the namenode's. Defaults to
+    /// 256Mi of memory and 100m of CPU.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceRequirements>,
+    /// Garbage collector of the ZKFC, left to the JVM by default
+    #[serde(default)]
+    pub gc: GcConfig,
+}
+
+impl FailoverControllerConfig {
+    /// The compute resources of the ZKFC container, including the defaults
+    pub fn resources(&self) -> ResourceRequirements {
+        self.resources.clone().unwrap_or_else(|| {
+            let memory = ("memory".to_string(), Quantity("256Mi".to_string()));
+            ResourceRequirements {
+                requests: Some(BTreeMap::from([
+                    ("cpu".to_string(), Quantity("100m".to_string())),
+                    memory.clone(),
+                ])),
+                limits: Some(BTreeMap::from([memory])),
+                ..ResourceRequirements::default()
+            }
+        })
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FencingMethod {
+    /// Kills the former active namenode over SSH
+    #[serde(rename_all = "camelCase")]
+    Sshfence {
+        /// `Secret` with the SSH private key in the key `id_rsa`
+        private_key_secret: String,
+        /// User to log in as, defaults to the user that the ZKFC runs as
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        user: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        port: Option<u16>,
+    },
+    /// Runs a script from a `ConfigMap` with `sh`, which fences successfully if it exits with 0
+    ///
+    /// Hadoop passes the target namenode to the script in environment variables such as `$target_host`.
+    #[serde(rename_all = "camelCase")]
+    Shell {
+        config_map: String,
+        /// Key of the script in the `ConfigMap`
+        script: String,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ZookeeperAuth {
+    /// Digest authentication, with credentials from a `Secret`
+    ///
+    /// The `Secret` must contain the keys `auth` (such as `digest:hdfs:<password>`) and `acl` (such as
+    /// `digest:hdfs:<base64 of SHA-1 of hdfs:<password>>:rwcda`), see `ha.zookeeper.auth` and `ha.zookeeper.acl`.
+    #[serde(rename_all = "camelCase")]
+    Digest { secret_name: String },
+    /// SASL authentication as the namenodes' Kerberos principal
+    Sasl {},
+}
+
+/// Lifetimes of the delegation tokens that the namenodes issue, which let clients (such as Spark or Hive jobs)
+/// access HDFS without a keytab of their own
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DelegationTokenConfig {
+    /// Milliseconds after which a token expires unless it is renewed (`dfs.namenode.delegation.token.renew-interval`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renew_interval: Option<i64>,
+    /// Milliseconds after which a token expires regardless of renewals (`dfs.namenode.delegation.token.max-lifetime`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_lifetime: Option<i64>,
+    /// Milliseconds between rotations of the key that tokens are signed with (`dfs.namenode.delegation.key.update-interval`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_update_interval: Option<i64>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct KerberosConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub realm: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kdc: Option<String>,
+    /// Name of a secret-operator `SecretClass` that provisions each pod's keytab
+    ///
+    /// Replaces the `<cluster>-<role>-kerberos` Secrets that otherwise have to be created up front.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_class: Option<String>,
+}
+
+impl Display for KerberosConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "[libdefaults]")?;
+        if let Some(realm) = &self.realm {
+            writeln!(f, "default_realm = {}", realm)?;
+        }
+        writeln!(f, "[realms]")?;
+        if let Some(realm) = &self.realm {
+            writeln!(f, "{} = {{", realm)?;
+            if let Some(kdc) = &self.kdc {
+                writeln!(f, "kdc = {}", kdc)?;
+            }
+            writeln!(f, "}}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Certificates of the HDFS daemons, which are mounted at `/tls`
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TlsConfig {
+    /// Name of a secret-operator `SecretClass` that provisions a certificate for each pod
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_class: Option<String>,
+    /// Issue the certificates with cert-manager instead, may not be combined with `secretClass`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cert_manager: Option<CertManagerConfig>,
+}
+
+/// A cert-manager issuer that signs a `Certificate` for each role, covering the role's `Service` and all of its pods
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CertManagerConfig {
+    pub issuer_name: String,
+    #[serde(default)]
+    pub issuer_kind: IssuerKind,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+pub enum IssuerKind {
+    /// An `Issuer` in the cluster's namespace
+    Issuer,
+    /// A `ClusterIssuer`
+    ClusterIssuer,
+}
+
+impl Default for IssuerKind {
+    fn default() -> Self {
+        Self::Issuer
+    }
+}
+
+impl Display for IssuerKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Issuer => write!(f, "Issuer"),
+            Self::ClusterIssuer => write!(f, "ClusterIssuer"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SecurityConfig {
+    /// Enables service-level authorization, restricting which principals may call each Hadoop protocol
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authorization: Option<AuthorizationConfig>,
+    /// External authorization provider (such as OpenPolicyAgent) that the NameNode consults for file system permissions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authorizer: Option<AuthorizerConfig>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthorizerConfig {
+    /// Fully qualified name of the `INodeAttributeProvider` implementation (`dfs.namenode.inode.attributes.provider.class`)
+    pub provider_class: String,
+    /// Name of a ConfigMap containing the authorizer's jars, which are added to the NameNode's classpath
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lib_config_map: Option<String>,
+    /// Name of an OPA discovery ConfigMap, whose `OPA` connection string is exposed to the NameNode as `OPA_URL`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opa_config_map: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthorizationConfig {
+    /// ACL used for protocols that have no explicit entry in `acls` (`security.service.authorization.default.acl`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_acl: Option<AccessControlList>,
+    /// ACLs keyed by `hadoop-policy.xml` property, such as `security.client.protocol.acl`
+    #[serde(default)]
+    pub acls: BTreeMap<String, AccessControlList>,
+}
+
+/// A Hadoop access control list
+///
+/// A `*` user allows everyone, while an empty list denies everyone.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessControlList {
+    #[serde(default)]
+    pub users: Vec<String>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+impl Display for AccessControlList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.users.iter().any(|user| user == "*") {
+            write!(f, "*")
+        } else {
+            write!(f, "{} {}", self.users.join(","), self.groups.join(","))
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLogConfig {
+    #[serde(default)]
+    pub target: AuditLogTarget,
+    /// Prefix that distinguishes audit events from regular log lines when writing to stdout, defaults to `[AUDIT]`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout_prefix: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditLogTarget {
+    /// Write audit events to the container's stdout, next to (but separate from) the regular log on stderr
+    Stdout,
+    /// Write audit events to a rolling `hdfs-audit.log` file in the Hadoop log directory
+    File,
+}
+
+impl Default for AuditLogTarget {
+    fn default() -> Self {
+        Self::Stdout
+    }
+}
+
+/// Renders the log4j properties that route `FSNamesystem.audit` to the configured target
+///
+/// Meant to be appended to the base `log4j.properties`, overriding its `NullAppender` default.
+impl Display for AuditLogConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let appender = match self.target {
+            AuditLogTarget::Stdout => {
+                writeln!(
+                    f,
+                    "log4j.appender.AUDITSTDOUT=org.apache.log4j.ConsoleAppender"
+                )?;
+                writeln!(f, "log4j.appender.AUDITSTDOUT.target=System.out")?;
+                writeln!(
+                    f,
+                    "log4j.appender.AUDITSTDOUT.layout=org.apache.log4j.PatternLayout"
+                )?;
+                writeln!(
+                    f,
+                    "log4j.appender.AUDITSTDOUT.layout.ConversionPattern={} %d{{ISO8601}} %p %c{{2}}: %m%n",
+                    self.stdout_prefix.as_deref().unwrap_or("[AUDIT]")
+                )?;
+                "AUDITSTDOUT"
+            }
+            AuditLogTarget::File => "RFAAUDIT",
+        };
+        writeln!(
+            f,
+            "log4j.logger.org.apache.hadoop.hdfs.server.namenode.FSNamesystem.audit=INFO,{}",
+            appender
+        )
+    }
+}
+
+/// The parts of the image settings that only concern the Hadoop image
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct HadoopImage {
+    /// CPU architectures that the image is built for (such as `amd64`), the pods are only scheduled onto such nodes
+    ///
+    /// Defaults to the architectures of the default image's version, if the operator knows them. Set to `[]` to
+    /// schedule the pods onto any node.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub architectures: Option<Vec<String>>,
+}
+
+impl ProductImage for HadoopImage {
+    const DEFAULT_REPOSITORY: &'static str = "teozkr/hadoop";
+    const DEFAULT_TAG: &'static str = "3.3.1";
+}
+
+pub type ImageConfig = operator_commons::spec::ImageConfig<HadoopImage>;
+
+/// The architectures that each version of the default image is built for
+const DEFAULT_IMAGE_ARCHITECTURES: &[(&str, &[&str])] = &[("3.3.1", &["amd64"])];
+
+/// The architectures that the image is built for, or `None` if any architecture may be used
+pub fn image_architectures(image: &ImageConfig) -> Option<Vec<String>> {
+    let architectures = match &image.product.architectures {
+        Some(architectures) => architectures.clone(),
+        // Other repositories may well be built for other architectures
+        None if image.repository.is_some() => return None,
+        None => DEFAULT_IMAGE_ARCHITECTURES
+            .iter()
+            .find(|(version, _)| *version == image.tag())?
+            .1
+            .iter()
+            .map(|architecture| architecture.to_string())
+            .collect(),
+    };
+    Some(architectures).filter(|architectures| !architectures.is_empty())
+}
+
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HdfsClusterStatus {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conditions: Option<Vec<Condition>>,
+    /// Outcome of the last reconciliation of each role
+    #[serde(default)]
+    pub roles: BTreeMap<String, RoleStatus>,
+    /// Name of the namenode pod that is currently active, serialized as `null` so that merge patches clear it
+    #[serde(default)]
+    pub active_namenode: Option<String>,
+    /// Datanodes that keep running with failed volumes, as allowed by `datanodeStorage.failedVolumesTolerated`
+    #[serde(default)]
+    pub failed_volumes: Vec<FailedVolumes>,
+    /// Number of pods of the `datanodeReplicas` datanodes, for the `scale` subresource
+    #[serde(default)]
+    pub datanode_replicas: Option<i32>,
+    /// Label selector of the `datanodeReplicas` datanodes' pods, for the `scale` subresource
+    #[serde(default)]
+    pub datanode_selector: Option<String>,
+    /// Datanode pods that are being decommissioned, since their groups have been scaled down
+    #[serde(default)]
+    pub decommissioning_datanodes: Vec<String>,
+    /// Datanode pods that are in maintenance, see `datanodeMaintenance`
+    #[serde(default)]
+    pub maintenance_datanodes: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoints: Option<HdfsEndpoints>,
+}
+
+/// The addresses of the cluster's daemons, so that clients don't need to derive them from its naming conventions
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct HdfsEndpoints {
+    /// The clients' `fs.defaultFS`, such as `hdfs://simple/`
+    pub default_fs: String,
+    /// RPC address of each namenode
+    pub namenode_rpc: Vec<String>,
+    /// Web UI address of each namenode, which is the HTTPS address with `tls`
+    pub namenode_http: Vec<String>,
+    /// WebHDFS URL of each namenode, such as `swebhdfs://simple-namenode-0...:9871`, if `webhdfs.enabled`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub webhdfs: Vec<String>,
+    /// The journalnodes, as the namenodes' `qjournal://` URL
+    pub journalnode_quorum: String,
+    /// The ZooKeeper servers that the failover controllers elect the active namenode in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zookeeper_quorum: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedVolumes {
+    /// Name of the datanode pod
+    pub pod: String,
+    /// Paths of the failed volumes
+    pub locations: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleStatus {
+    pub phase: RolePhase,
+    /// Details about why the role is waiting or failed to apply
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq, Eq, Serialize)]
+pub enum RolePhase {
+    /// All of the role's objects have been applied
+    Applied,
+    /// Some of the role's objects could not be applied
+    Failed,
+    /// The role's objects were not applied since its prerequisites are not ready yet
+    Waiting,
+}
+
+/// An administrative command that is run once against an `HdfsCluster`
+///
+/// Tasks are not rerun when their spec changes after they have finished, create a new task instead.
+#[derive(Clone, CustomResource, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[kube(
+    group = "hdfs.stackable.tech",
+    version = "v1alpha1",
+    kind = "HdfsAdminTask",
+    plural = "hdfsadmintasks",
+    shortname = "hdfsadmin",
+    namespaced
+)]
+#[kube(status = "HdfsAdminTaskStatus")]
+#[serde(rename_all = "camelCase")]
+pub struct HdfsAdminTaskSpec {
+    /// Name of the `HdfsCluster` in the task's namespace that the command is run against
+    pub cluster_name: String,
+    pub action: HdfsAdminAction,
+    /// Quota to apply, required by the `setQuota` action
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota: Option<QuotaConfig>,
+    /// Absolute paths of the directories, required by the `allowSnapshot` and `disallowSnapshot` actions
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum HdfsAdminAction {
+    /// Put the namenodes into safe mode, making the file system read-only
+    SafemodeEnter,
+    /// Take the namenodes out of safe mode
+    SafemodeLeave,
+    /// Write the namespace to a new fsimage and reset the edit log, the namenodes must be in safe mode
+    SaveNamespace,
+    /// Reread the datanode include and exclude lists, starting any pending decommissions
+    RefreshNodes,
+    /// Set the name and/or space quota of a directory
+    SetQuota,
+    /// Allow snapshots of the `paths`
+    AllowSnapshot,
+    /// Disallow snapshots of the `paths`, which must not have any snapshots left
+    DisallowSnapshot,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct QuotaConfig {
+    /// Absolute path of the directory
+    pub path: String,
+    /// Maximum number of files and directories in the tree below `path`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace_quota: Option<i64>,
+    /// Maximum number of bytes used by the tree below `path` (including replicas), such as `10g`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub space_quota: Option<String>,
+}
+
+/// Unset fields are serialized as `null`, so that they are cleared when the status is merge-patched
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HdfsAdminTaskStatus {
+    #[serde(default)]
+    pub phase: AdminTaskPhase,
+    /// Name of the `Job` that runs the command
+    #[serde(default)]
+    pub job_name: Option<String>,
+    /// Why the task is pending or failed
+    #[serde(default)]
+    pub message: Option<String>,
+    /// Tail of the command's output, once it has finished
+    #[serde(default)]
+    pub output: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq, Eq, Serialize)]
+pub enum AdminTaskPhase {
+    /// The command has not been started yet
+    Pending,
+    /// The command's `Job` is running
+    Running,
+    /// The command has finished successfully
+    Succeeded,
+    /// The command could not be run, or has failed
+    Failed,
+}
+
+impl Default for AdminTaskPhase {
+    fn default() -> Self {
+        Self::Pending
+    }
+}
+
+impl AdminTaskPhase {
+    /// Whether the task has finished, and will not be changed any more
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Succeeded | Self::Failed)
+    }
+}
@@ -0,0 +1,283 @@
+//! Groups the settings of `v1alpha1` by role, and the security settings under `security`
+//!
+//! All settings can be converted losslessly from and to `v1alpha1`, see [`super::conversion`].
+
+use std::collections::BTreeMap;
+
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::v1alpha1::{
+    self, AuditLogConfig, AuthorizationConfig, AuthorizerConfig, BatchedRolloutConfig,
+    CheckpointConfig, DatanodeAutoscalingConfig, DatanodeGroupConfig, DatanodeMaintenance,
+    DatanodeStorageConfig, DelegationTokenConfig, FailoverControllerConfig, HdfsClusterStatus,
+    HdfsPortsConfig, HdfsRoleConfig, ImageConfig, JournalnodeStorageConfig, KerberosConfig,
+    NamenodeRpcConfig, NetworkIsolationConfig, PodSecurityConfig, PropertyValidationConfig,
+    ServiceAccountConfig, SnapshotConfig, TlsConfig, TracingConfig, TrashConfig, UpgradeConfig,
+    WebHdfsConfig,
+};
+
+#[derive(Clone, CustomResource, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[kube(
+    group = "hdfs.stackable.tech",
+    version = "v1alpha2",
+    kind = "HdfsCluster",
+    plural = "hdfsclusters",
+    shortname = "hdfs",
+    namespaced
+)]
+#[kube(status = "HdfsClusterStatus")]
+#[kube(
+    scale = r#"{"specReplicasPath":".spec.datanodes.replicas","statusReplicasPath":".status.datanodeReplicas","labelSelectorPath":".status.datanodeSelector"}"#
+)]
+#[serde(rename_all = "camelCase")]
+pub struct HdfsClusterSpec {
+    #[serde(default)]
+    pub namenodes: RoleSpec,
+    #[serde(default)]
+    pub datanodes: DatanodesSpec,
+    #[serde(default)]
+    pub journalnodes: JournalnodesSpec,
+    #[serde(default)]
+    pub security: SecuritySpec,
+    #[serde(default)]
+    pub ports: HdfsPortsConfig,
+    #[serde(default)]
+    pub namenode_rpc: NamenodeRpcConfig,
+    #[serde(default)]
+    pub property_validation: PropertyValidationConfig,
+    /// ConfigMap of a ZookeeperZnode, whose `ZOOKEEPER_HOSTS` and `ZOOKEEPER_CHROOT` the failover controllers use
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zookeeper_config_map_name: Option<String>,
+    /// Deprecated, use `zookeeperConfigMapName`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namenode_znode_config_map: Option<String>,
+    #[serde(default)]
+    pub failover_controller: FailoverControllerConfig,
+    /// The Hadoop image, defaults to `teozkr/hadoop:3.3.1`
+    #[serde(default)]
+    pub image: ImageConfig,
+    /// Default number of replicas for each block (`dfs.replication`), may not exceed the number of datanodes
+    ///
+    /// Hadoop defaults to `3`, blocks of clusters with fewer datanodes then stay under-replicated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dfs_replication: Option<i32>,
+    /// Minimal block replication required for a write to succeed (`dfs.namenode.replication.min`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dfs_replication_min: Option<i32>,
+    /// Space in bytes per volume that datanodes reserve for non-DFS use (`dfs.datanode.du.reserved`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dfs_datanode_du_reserved: Option<i64>,
+    #[serde(default)]
+    pub trash: TrashConfig,
+    #[serde(default)]
+    pub snapshot: SnapshotConfig,
+    #[serde(default)]
+    pub checkpoint: CheckpointConfig,
+    #[serde(default)]
+    pub delegation_tokens: DelegationTokenConfig,
+    /// The defaults only satisfy the `restricted` Pod Security Standard with `hostNetwork: false`, since both the
+    /// `baseline` and the `restricted` standard forbid the host network
+    #[serde(default)]
+    pub pod_security: PodSecurityConfig,
+    /// Whether the pods use the network of their nodes, defaults to `true`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_network: Option<bool>,
+    /// Generates `NetworkPolicy` objects that only admit traffic from cluster members and the allowed clients
+    ///
+    /// Requires `hostNetwork: false`, since `NetworkPolicy`s don't apply to pods on the host network.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network_isolation: Option<NetworkIsolationConfig>,
+    #[serde(default)]
+    pub service_account: ServiceAccountConfig,
+    /// Enables the NameNode's HDFS audit log
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit_log: Option<AuditLogConfig>,
+    #[serde(default)]
+    pub webhdfs: WebHdfsConfig,
+    /// Traces the namenodes, datanodes, and journalnodes with the OpenTelemetry Java agent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tracing: Option<TracingConfig>,
+    #[serde(default)]
+    pub upgrade: UpgradeConfig,
+    /// Stops the roles one at a time when the cluster is deleted, datanodes first and journalnodes last, before
+    /// Kubernetes deletes the rest of its objects, defaults to `false`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ordered_teardown: Option<bool>,
+    /// Labels that are added to all generated objects and pods, generated labels take precedence
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Annotations that are added to all generated objects and pods
+    #[serde(default)]
+    pub annotations: BTreeMap<String, String>,
+}
+
+/// The number of pods of a role, and customizations for them
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleSpec {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replicas: Option<i32>,
+    #[serde(flatten)]
+    pub config: HdfsRoleConfig,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DatanodesSpec {
+    /// Number of datanodes outside of the `roleGroups`, which is also exposed by the `scale` subresource
+    ///
+    /// Surplus datanodes are decommissioned before they are removed, so their blocks are re-replicated first.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replicas: Option<i32>,
+    #[serde(flatten)]
+    pub config: HdfsRoleConfig,
+    #[serde(default)]
+    pub storage: DatanodeStorageConfig,
+    /// Additional groups of datanodes by name, each in its own `StatefulSet`, next to the `replicas` datanodes
+    #[serde(default)]
+    pub role_groups: BTreeMap<String, DatanodeGroupConfig>,
+    /// Lets the operator add `replicas` datanodes when the file system runs out of space
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub autoscaling: Option<DatanodeAutoscalingConfig>,
+    /// Lets the operator replace the datanodes in batches, instead of one at a time, over `updateStrategy`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollout: Option<BatchedRolloutConfig>,
+    /// Datanodes that the namenodes put into maintenance, such as before their nodes are drained, so that their blocks
+    /// aren't re-replicated while they are down
+    #[serde(default)]
+    pub maintenance: Vec<DatanodeMaintenance>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalnodesSpec {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replicas: Option<i32>,
+    #[serde(flatten)]
+    pub config: HdfsRoleConfig,
+    #[serde(default)]
+    pub storage: JournalnodeStorageConfig,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SecuritySpec {
+    #[serde(default)]
+    pub kerberos: KerberosConfig,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+    /// Enables service-level authorization, restricting which principals may call each Hadoop protocol
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authorization: Option<AuthorizationConfig>,
+    /// External authorization provider (such as OpenPolicyAgent) that the NameNode consults for file system permissions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authorizer: Option<AuthorizerConfig>,
+}
+
+impl From<v1alpha1::HdfsClusterSpec> for HdfsClusterSpec {
+    fn from(spec: v1alpha1::HdfsClusterSpec) -> Self {
+        Self {
+            namenodes: RoleSpec {
+                replicas: spec.namenode_replicas,
+                config: spec.namenode,
+            },
+            datanodes: DatanodesSpec {
+                replicas: spec.datanode_replicas,
+                config: spec.datanode,
+                storage: spec.datanode_storage,
+                role_groups: spec.datanode_groups,
+                autoscaling: spec.datanode_autoscaling,
+                rollout: spec.datanode_rollout,
+                maintenance: spec.datanode_maintenance,
+            },
+            journalnodes: JournalnodesSpec {
+                replicas: spec.journalnode_replicas,
+                config: spec.journalnode,
+                storage: spec.journalnode_storage,
+            },
+            security: SecuritySpec {
+                kerberos: spec.kerberos,
+                tls: spec.tls,
+                authorization: spec.security.authorization,
+                authorizer: spec.security.authorizer,
+            },
+            ports: spec.ports,
+            namenode_rpc: spec.namenode_rpc,
+            property_validation: spec.property_validation,
+            zookeeper_config_map_name: spec.zookeeper_config_map_name,
+            namenode_znode_config_map: spec.namenode_znode_config_map,
+            failover_controller: spec.failover_controller,
+            image: spec.image,
+            dfs_replication: spec.dfs_replication,
+            dfs_replication_min: spec.dfs_replication_min,
+            dfs_datanode_du_reserved: spec.dfs_datanode_du_reserved,
+            trash: spec.trash,
+            snapshot: spec.snapshot,
+            checkpoint: spec.checkpoint,
+            delegation_tokens: spec.delegation_tokens,
+            pod_security: spec.pod_security,
+            host_network: spec.host_network,
+            network_isolation: spec.network_isolation,
+            service_account: spec.service_account,
+            audit_log: spec.audit_log,
+            webhdfs: spec.webhdfs,
+            tracing: spec.tracing,
+            upgrade: spec.upgrade,
+            ordered_teardown: spec.ordered_teardown,
+            labels: spec.labels,
+            annotations: spec.annotations,
+        }
+    }
+}
+
+impl From<HdfsClusterSpec> for v1alpha1::HdfsClusterSpec {
+    fn from(spec: HdfsClusterSpec) -> Self {
+        Self {
+            namenode_replicas: spec.namenodes.replicas,
+            datanode_replicas: spec.datanodes.replicas,
+            journalnode_replicas: spec.journalnodes.replicas,
+            ports: spec.ports,
+            namenode_rpc: spec.namenode_rpc,
+            namenode: spec.namenodes.config,
+            datanode: spec.datanodes.config,
+            journalnode: spec.journalnodes.config,
+            property_validation: spec.property_validation,
+            zookeeper_config_map_name: spec.zookeeper_config_map_name,
+            namenode_znode_config_map: spec.namenode_znode_config_map,
+            failover_controller: spec.failover_controller,
+            image: spec.image,
+            dfs_replication: spec.dfs_replication,
+            dfs_replication_min: spec.dfs_replication_min,
+            dfs_datanode_du_reserved: spec.dfs_datanode_du_reserved,
+            datanode_storage: spec.datanodes.storage,
+            journalnode_storage: spec.journalnodes.storage,
+            datanode_groups: spec.datanodes.role_groups,
+            datanode_autoscaling: spec.datanodes.autoscaling,
+            trash: spec.trash,
+            snapshot: spec.snapshot,
+            checkpoint: spec.checkpoint,
+            delegation_tokens: spec.delegation_tokens,
+            kerberos: spec.security.kerberos,
+            tls: spec.security.tls,
+            security: v1alpha1::SecurityConfig {
+                authorization: spec.security.authorization,
+                authorizer: spec.security.authorizer,
+            },
+            pod_security: spec.pod_security,
+            host_network: spec.host_network,
+            network_isolation: spec.network_isolation,
+            service_account: spec.service_account,
+            audit_log: spec.audit_log,
+            webhdfs: spec.webhdfs,
+            tracing: spec.tracing,
+            datanode_rollout: spec.datanodes.rollout,
+            datanode_maintenance: spec.datanodes.maintenance,
+            upgrade: spec.upgrade,
+            ordered_teardown: spec.ordered_teardown,
+            labels: spec.labels,
+            annotations: spec.annotations,
+        }
+    }
+}
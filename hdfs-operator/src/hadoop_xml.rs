@@ -0,0 +1,264 @@
+//! Writes Hadoop configuration files, such as `core-site.xml`
+//!
+//! Values are written verbatim, so they are escaped rather than validated. Characters that XML 1.0 can't represent
+//! at all (control characters other than tab, line feed, and carriage return) are replaced by `U+FFFD`.
+
+use std::fmt::Write;
+
+/// A property of a Hadoop configuration file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HadoopProperty<'a> {
+    pub name: &'a str,
+    pub value: &'a str,
+    /// Whether configuration files that are loaded later (such as those of jobs) may not override the property
+    pub final_: bool,
+    /// Tags that group the property, such as `SECURITY`
+    pub tags: &'a [String],
+    /// Written as a comment above the property
+    pub description: Option<&'a str>,
+}
+
+impl<'a> HadoopProperty<'a> {
+    pub fn new(name: &'a str, value: &'a str) -> Self {
+        HadoopProperty {
+            name,
+            value,
+            final_: false,
+            tags: &[],
+            description: None,
+        }
+    }
+}
+
+/// A configuration file that consists of `properties`, one per line
+pub fn configuration_xml<'a>(properties: impl IntoIterator<Item = HadoopProperty<'a>>) -> String {
+    let mut xml = "<configuration>\n".to_string();
+    for property in properties {
+        if let Some(description) = property.description {
+            writeln!(xml, "<!-- {} -->", comment_text(description)).unwrap();
+        }
+        write!(
+            xml,
+            "<property><name>{}</name><value>{}</value>",
+            escape(property.name),
+            escape(property.value)
+        )
+        .unwrap();
+        if property.final_ {
+            xml.push_str("<final>true</final>");
+        }
+        if !property.tags.is_empty() {
+            write!(xml, "<tag>{}</tag>", escape(&property.tags.join(","))).unwrap();
+        }
+        xml.push_str("</property>\n");
+    }
+    xml.push_str("</configuration>");
+    xml
+}
+
+/// A configuration file that sets each name of `kvs` to its value
+pub fn hadoop_config_xml<I: IntoIterator<Item = (K, V)>, K: AsRef<str>, V: AsRef<str>>(
+    kvs: I,
+) -> String {
+    let kvs = kvs.into_iter().collect::<Vec<_>>();
+    configuration_xml(
+        kvs.iter()
+            .map(|(k, v)| HadoopProperty::new(k.as_ref(), v.as_ref())),
+    )
+}
+
+/// Whether XML 1.0 documents may contain `c`
+fn is_xml_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r' | '\u{20}'..='\u{d7ff}' | '\u{e000}'..='\u{fffd}' | '\u{10000}'..='\u{10ffff}')
+}
+
+/// Escapes `text` as the content of an element
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            // Only required in `]]>`, but escaping it everywhere is simpler
+            '>' => escaped.push_str("&gt;"),
+            // Parsers would otherwise normalize line breaks to `\n`
+            '\r' => escaped.push_str("&#13;"),
+            c if is_xml_char(c) => escaped.push(c),
+            _ => escaped.push(char::REPLACEMENT_CHARACTER),
+        }
+    }
+    escaped
+}
+
+/// `text`, changed so that it can't end the comment that it is written in
+///
+/// Comments have no escapes, and may not contain `--`, so a space is inserted between consecutive dashes.
+fn comment_text(text: &str) -> String {
+    let mut comment = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        comment.push(if is_xml_char(c) {
+            c
+        } else {
+            char::REPLACEMENT_CHARACTER
+        });
+        if c == '-' && chars.peek() == Some(&'-') {
+            comment.push(' ');
+        }
+    }
+    comment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xml::reader::{EventReader, ParserConfig, XmlEvent};
+
+    /// A property as Hadoop would read it back
+    #[derive(Debug, Default, PartialEq)]
+    struct ParsedProperty {
+        name: String,
+        value: String,
+        final_: bool,
+        tags: Vec<String>,
+        description: Option<String>,
+    }
+
+    impl From<HadoopProperty<'_>> for ParsedProperty {
+        fn from(property: HadoopProperty) -> Self {
+            ParsedProperty {
+                name: property.name.to_string(),
+                value: property.value.to_string(),
+                final_: property.final_,
+                tags: property.tags.to_vec(),
+                description: property.description.map(str::to_string),
+            }
+        }
+    }
+
+    /// Parses a configuration file, failing on any XML that isn't well-formed
+    fn parse(xml: &str) -> Vec<ParsedProperty> {
+        let reader = EventReader::new_with_config(
+            xml.as_bytes(),
+            ParserConfig::new().ignore_comments(false),
+        );
+        let mut properties = Vec::new();
+        let mut property = ParsedProperty::default();
+        let mut description = None;
+        let mut text = String::new();
+        for event in reader {
+            match event.unwrap() {
+                XmlEvent::Comment(comment) => description = Some(comment.trim().to_string()),
+                XmlEvent::StartElement { .. } => text.clear(),
+                XmlEvent::Characters(chars) | XmlEvent::Whitespace(chars) => text.push_str(&chars),
+                XmlEvent::EndElement { name } => match name.local_name.as_str() {
+                    "name" => property.name = std::mem::take(&mut text),
+                    "value" => property.value = std::mem::take(&mut text),
+                    "final" => property.final_ = text == "true",
+                    "tag" => property.tags = text.split(',').map(str::to_string).collect(),
+                    "property" => {
+                        property.description = description.take();
+                        properties.push(std::mem::take(&mut property));
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        properties
+    }
+
+    fn round_trip(properties: &[HadoopProperty]) {
+        assert_eq!(
+            parse(&configuration_xml(properties.iter().copied())),
+            properties
+                .iter()
+                .copied()
+                .map(ParsedProperty::from)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn plain_properties_keep_their_format() {
+        assert_eq!(
+            hadoop_config_xml([("dfs.replication", "3"), ("dfs.blocksize", "128m")]),
+            "<configuration>\n\
+             <property><name>dfs.replication</name><value>3</value></property>\n\
+             <property><name>dfs.blocksize</name><value>128m</value></property>\n\
+             </configuration>"
+        );
+    }
+
+    #[test]
+    fn markup_in_values_is_escaped() {
+        let xml = hadoop_config_xml([("a", "x&y<z>")]);
+        assert!(xml.contains("<value>x&amp;y&lt;z&gt;</value>"), "{}", xml);
+        round_trip(&[
+            HadoopProperty::new("hadoop.proxyuser.hue.hosts", "a&b"),
+            HadoopProperty::new("ldap.filter", "(&(objectClass=user)(cn=<name>))"),
+            HadoopProperty::new("quotes", r#"'single' and "double""#),
+            HadoopProperty::new("cdata.end", "]]>"),
+            HadoopProperty::new("entity", "&amp; stays &amp;"),
+            HadoopProperty::new("na&me<", "value"),
+        ]);
+    }
+
+    #[test]
+    fn whitespace_is_preserved() {
+        round_trip(&[
+            HadoopProperty::new("empty", ""),
+            HadoopProperty::new("blank", "  "),
+            HadoopProperty::new("padded", " value\t"),
+            HadoopProperty::new("lines", "first\nsecond\r\nthird\r"),
+            HadoopProperty::new("unicode", "Grüße, 日本, 🦀"),
+        ]);
+    }
+
+    #[test]
+    fn final_properties_and_descriptions_round_trip() {
+        round_trip(&[
+            HadoopProperty {
+                final_: true,
+                description: Some("Kerberos is always required"),
+                ..HadoopProperty::new("hadoop.security.authentication", "kerberos")
+            },
+            HadoopProperty::new("dfs.replication", "3"),
+            HadoopProperty {
+                final_: true,
+                ..HadoopProperty::new("dfs.permissions.enabled", "true")
+            },
+        ]);
+    }
+
+    #[test]
+    fn tags_round_trip() {
+        let tags = ["SECURITY".to_string(), "HDFS".to_string()];
+        let property = HadoopProperty {
+            final_: true,
+            tags: &tags,
+            ..HadoopProperty::new("dfs.block.access.token.enable", "true")
+        };
+        assert!(configuration_xml([property])
+            .contains("<value>true</value><final>true</final><tag>SECURITY,HDFS</tag></property>"));
+        round_trip(&[property, HadoopProperty::new("dfs.replication", "3")]);
+    }
+
+    #[test]
+    fn descriptions_cannot_end_their_comment() {
+        let xml = configuration_xml([HadoopProperty {
+            description: Some("ends with -- and --> and -"),
+            ..HadoopProperty::new("a", "b")
+        }]);
+        assert_eq!(
+            parse(&xml)[0].description.as_deref(),
+            Some("ends with - - and - -> and -")
+        );
+    }
+
+    #[test]
+    fn unrepresentable_characters_are_replaced() {
+        let xml = hadoop_config_xml([("a", "bell\u{7}null\u{0}")]);
+        assert_eq!(parse(&xml)[0].value, "bell\u{fffd}null\u{fffd}");
+    }
+}
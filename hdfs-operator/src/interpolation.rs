@@ -0,0 +1,149 @@
+//! Resolves the `${env.NAME}` placeholders of the Hadoop configuration files
+//!
+//! Hadoop's `Configuration` replaces these with the environment variables of the process that reads the file, or with
+//! the fallback of `${env.NAME-fallback}` (if `NAME` is unset) and `${env.NAME:-fallback}` (if `NAME` is unset or
+//! empty). The operator substitutes the variables that it knows when it renders the files itself, such as the ZooKeeper
+//! quorum, so that the files show the values that are actually used. Only variables that differ between pods or must
+//! not end up in a `ConfigMap` (such as passwords) are left to Hadoop, which requires the containers to set them.
+
+use std::collections::BTreeMap;
+
+/// What a placeholder resolves to if its variable isn't set
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Fallback<'a> {
+    None,
+    IfUnset(&'a str),
+    IfUnsetOrEmpty(&'a str),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Placeholder<'a> {
+    /// Position of the placeholder's `$` in the value
+    start: usize,
+    /// Position after the placeholder's `}`
+    end: usize,
+    name: &'a str,
+    fallback: Fallback<'a>,
+}
+
+/// The `${env.NAME}` placeholders of `value`, in order
+fn placeholders(value: &str) -> Vec<Placeholder> {
+    const PREFIX: &str = "${env.";
+    let mut placeholders = Vec::new();
+    let mut start = 0;
+    while let Some(offset) = value[start..].find(PREFIX) {
+        let begin = start + offset;
+        let inner_begin = begin + PREFIX.len();
+        let end = match value[inner_begin..].find('}') {
+            Some(len) => inner_begin + len,
+            None => break,
+        };
+        let inner = &value[inner_begin..end];
+        let (name, fallback) = match inner.find('-') {
+            Some(i) if inner[..i].ends_with(':') => {
+                (&inner[..i - 1], Fallback::IfUnsetOrEmpty(&inner[i + 1..]))
+            }
+            Some(i) => (&inner[..i], Fallback::IfUnset(&inner[i + 1..])),
+            None => (inner, Fallback::None),
+        };
+        placeholders.push(Placeholder {
+            start: begin,
+            end: end + 1,
+            name,
+            fallback,
+        });
+        start = end + 1;
+    }
+    placeholders
+}
+
+/// Substitutes the placeholders of `value` whose variables are in `variables`, keeping the others for Hadoop
+pub fn interpolate(value: &str, variables: &BTreeMap<String, String>) -> String {
+    let mut interpolated = String::with_capacity(value.len());
+    let mut copied = 0;
+    for placeholder in placeholders(value) {
+        let resolved = match (variables.get(placeholder.name), placeholder.fallback) {
+            (Some(variable), Fallback::IfUnsetOrEmpty(fallback)) if variable.is_empty() => fallback,
+            (Some(variable), _) => variable.as_str(),
+            (None, _) => continue,
+        };
+        interpolated.push_str(&value[copied..placeholder.start]);
+        interpolated.push_str(resolved);
+        copied = placeholder.end;
+    }
+    interpolated.push_str(&value[copied..]);
+    interpolated
+}
+
+/// The variables of the placeholders of `value` that Hadoop can't resolve, since they have no fallback and aren't
+/// among the variables that are `set`
+pub fn unresolvable_variables<'a>(value: &'a str, set: &[&str]) -> Vec<&'a str> {
+    placeholders(value)
+        .into_iter()
+        .filter(|placeholder| {
+            placeholder.fallback == Fallback::None && !set.contains(&placeholder.name)
+        })
+        .map(|placeholder| placeholder.name)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables() -> BTreeMap<String, String> {
+        BTreeMap::from([
+            (
+                "ZOOKEEPER_HOSTS".to_string(),
+                "zk-0:2181,zk-1:2181".to_string(),
+            ),
+            ("EMPTY".to_string(), String::new()),
+        ])
+    }
+
+    #[test]
+    fn known_variables_are_substituted() {
+        assert_eq!(
+            interpolate("${env.ZOOKEEPER_HOSTS}", &variables()),
+            "zk-0:2181,zk-1:2181"
+        );
+        assert_eq!(
+            interpolate(
+                "${env.ZOOKEEPER_CHROOT}/ha at ${env.ZOOKEEPER_HOSTS}!",
+                &variables()
+            ),
+            "${env.ZOOKEEPER_CHROOT}/ha at zk-0:2181,zk-1:2181!"
+        );
+        assert_eq!(interpolate("${env.EMPTY-unset}", &variables()), "");
+        assert_eq!(interpolate("${env.EMPTY:-empty}", &variables()), "empty");
+        assert_eq!(
+            interpolate("${env.ZOOKEEPER_HOSTS:-none}", &variables()),
+            "zk-0:2181,zk-1:2181"
+        );
+    }
+
+    #[test]
+    fn other_placeholders_are_kept() {
+        for value in [
+            "${env.KEYSTORE_PASSWORD}",
+            "${env.MISSING:-fallback}",
+            "${hadoop.tmp.dir}/data",
+            "${env.UNTERMINATED",
+            "no placeholders",
+        ] {
+            assert_eq!(interpolate(value, &variables()), value);
+        }
+    }
+
+    #[test]
+    fn placeholders_without_variable_or_fallback_are_unresolvable() {
+        assert_eq!(
+            unresolvable_variables(
+                "${env.SET},${env.UNSET},${env.WITH-fallback},${env.WITH_EMPTY:-fallback},${hadoop.tmp.dir}",
+                &["SET"]
+            ),
+            ["UNSET"]
+        );
+        assert!(unresolvable_variables("${env.SET}", &["SET"]).is_empty());
+    }
+}
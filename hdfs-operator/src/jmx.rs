@@ -0,0 +1,91 @@
+//! Queries the JMX servlets that the HDFS daemons serve on their web UI ports
+
+use std::{net::IpAddr, time::Duration};
+
+use hyper::{body, client::HttpConnector, Client, Uri};
+use hyper_tls::HttpsConnector;
+use native_tls::{Certificate, TlsConnector};
+use serde_json::{Map, Value};
+use snafu::{OptionExt, ResultExt, Snafu};
+
+/// How long to wait for a daemon to respond, so that unresponsive daemons don't stall reconciles
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Snafu, Debug)]
+pub enum Error {
+    #[snafu(display("invalid JMX URL {}", url))]
+    InvalidUrl {
+        source: hyper::http::uri::InvalidUri,
+        url: String,
+    },
+    #[snafu(display("failed to set up TLS for {}", url))]
+    Tls {
+        source: native_tls::Error,
+        url: String,
+    },
+    #[snafu(display("failed to query {}", url))]
+    Request { source: hyper::Error, url: String },
+    #[snafu(display("{} did not respond within {:?}", url, REQUEST_TIMEOUT))]
+    Timeout { url: String },
+    #[snafu(display("failed to parse the response of {}", url))]
+    ParseResponse {
+        source: serde_json::Error,
+        url: String,
+    },
+    #[snafu(display("{} has no bean {}", url, bean))]
+    BeanNotFound { url: String, bean: String },
+}
+
+/// A client that only trusts `ca`, the CA that signed the daemons' certificates
+///
+/// The certificates only name the pods' DNS names, so hostnames can't be verified when a daemon is queried by its IP.
+/// The certificate must still be signed by `ca` then.
+fn client(
+    host: &str,
+    ca: Option<&[u8]>,
+) -> Result<Client<HttpsConnector<HttpConnector>>, native_tls::Error> {
+    let mut tls = TlsConnector::builder();
+    tls.disable_built_in_roots(true)
+        .danger_accept_invalid_hostnames(host.parse::<IpAddr>().is_ok());
+    if let Some(ca) = ca {
+        tls.add_root_certificate(Certificate::from_pem(ca)?);
+    }
+    let mut http = HttpConnector::new();
+    http.enforce_http(false);
+    Ok(Client::builder().build(HttpsConnector::from((http, tls.build()?.into()))))
+}
+
+/// Fetches the attributes of the bean `bean` (such as `Hadoop:service=NameNode,name=NameNodeStatus`)
+/// from the daemon whose web UI listens on `host:port`
+///
+/// The web UI is queried over HTTPS if the PEM-encoded `ca` is given, which must have signed the daemon's certificate.
+pub async fn get_bean(
+    host: &str,
+    port: i32,
+    ca: Option<&[u8]>,
+    bean: &str,
+) -> Result<Map<String, Value>, Error> {
+    let scheme = if ca.is_some() { "https" } else { "http" };
+    let url = format!("{}://{}:{}/jmx?qry={}", scheme, host, port, bean);
+    let uri = url.parse::<Uri>().context(InvalidUrl { url: &url })?;
+    let client = client(host, ca).context(Tls { url: &url })?;
+    let response = async {
+        let response = client.get(uri).await?;
+        body::to_bytes(response.into_body()).await
+    };
+    let response = tokio::time::timeout(REQUEST_TIMEOUT, response)
+        .await
+        .ok()
+        .context(Timeout { url: &url })?
+        .context(Request { url: &url })?;
+    let mut response: Value =
+        serde_json::from_slice(&response).context(ParseResponse { url: &url })?;
+    match response
+        .get_mut("beans")
+        .and_then(|beans| beans.get_mut(0))
+        .map(Value::take)
+    {
+        Some(Value::Object(attributes)) => Ok(attributes),
+        _ => BeanNotFound { url, bean }.fail(),
+    }
+}
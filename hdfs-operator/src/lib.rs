@@ -0,0 +1,137 @@
+//! Stackable Operator for Apache Hadoop HDFS
+//!
+//! The controllers are run by the `hdfs-operator` binary, or together with those of the other operators by
+//! `stackable-operators`.
+
+mod admin_task_controller;
+mod cert_manager;
+mod config;
+pub mod controller;
+pub mod crd;
+mod hadoop_xml;
+mod interpolation;
+mod jmx;
+mod ports;
+mod preflight;
+mod properties;
+pub mod resources;
+mod rollout;
+mod secret_operator;
+
+use crd::{HdfsAdminTask, HdfsCluster, HdfsClusterSpec};
+use futures::{stream::LocalBoxStream, StreamExt};
+use k8s_openapi::{
+    api::{
+        apps::v1::StatefulSet,
+        batch::v1::Job,
+        core::v1::{Pod, Secret, Service},
+    },
+    apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition,
+};
+use kube::{api::ListParams, CustomResourceExt};
+use kube_runtime::{controller::Context, Controller};
+use operator_commons::{
+    erase_controller_result,
+    labels::APP_NAME_LABEL,
+    webhook::{self, default_spec, WebhookServiceOptions, Webhooks},
+    OperatorVersion, ReconcileResult, SharedContext,
+};
+
+pub mod built_info {
+    include!(concat!(env!("OUT_DIR"), "/built.rs"));
+}
+
+/// Build of this operator, recorded on the objects that it manages
+pub const OPERATOR_VERSION: OperatorVersion = OperatorVersion {
+    name: built_info::PKG_NAME,
+    version: built_info::PKG_VERSION,
+    git_commit: built_info::GIT_COMMIT_HASH,
+};
+
+/// The CRDs of the objects that this operator manages
+pub fn crds(
+    webhook: &WebhookServiceOptions,
+) -> Result<Vec<CustomResourceDefinition>, webhook::Error> {
+    let hdfs_conversion = webhook.conversion(HdfsCluster::crd_name())?;
+    Ok(vec![
+        crd::conversion::hdfs_cluster_crd(hdfs_conversion),
+        HdfsAdminTask::crd(),
+    ])
+}
+
+/// The webhooks that this operator serves for its CRDs
+pub fn webhooks() -> Webhooks {
+    let mut webhooks = Webhooks::default();
+    webhooks.converters.insert(
+        HdfsCluster::crd_name().to_string(),
+        crd::conversion::convert,
+    );
+    webhooks
+        .defaulters
+        .insert(HdfsCluster::crd_name().to_string(), |obj| {
+            default_spec(obj, HdfsClusterSpec::set_defaults)
+        });
+    webhooks
+}
+
+/// Runs the `HdfsCluster` and `HdfsAdminTask` controllers, yielding the result of each reconcile
+pub fn run_controllers(shared: &SharedContext) -> LocalBoxStream<'static, ReconcileResult> {
+    let kube = &shared.kube;
+    let hdfses = kube::Api::<HdfsCluster>::all(kube.clone());
+    let admin_tasks = kube::Api::<HdfsAdminTask>::all(kube.clone());
+    let hdfs_controller = Controller::new(hdfses, ListParams::default())
+        .owns(
+            kube::Api::<Service>::all(kube.clone()),
+            ListParams::default(),
+        )
+        .owns(
+            kube::Api::<StatefulSet>::all(kube.clone()),
+            ListParams::default(),
+        )
+        .watches(
+            kube::Api::<Secret>::all(kube.clone()),
+            ListParams::default(),
+            controller::secret_cluster,
+        )
+        // Owned by the StatefulSets rather than the cluster, watched to notice unschedulable pods
+        .watches(
+            kube::Api::<Pod>::all(kube.clone()),
+            ListParams::default().labels(&format!("{}={}", APP_NAME_LABEL, resources::APP_NAME)),
+            controller::pod_cluster,
+        )
+        .run(
+            controller::reconcile_hdfs,
+            operator_commons::error_policy,
+            Context::new(controller::Ctx {
+                kube: kube.clone(),
+                cluster_domain: shared.cluster_domain.clone(),
+                image_options: shared.image_options.clone(),
+                dry_run: shared.dry_run,
+                reconcile_permits: shared.reconcile_permits.clone(),
+                apply_rate_limiter: shared.apply_rate_limiter.clone(),
+                log_apply_diffs: shared.log_apply_diffs,
+                resync_interval: shared.resync_interval,
+            }),
+        );
+    let task_controller = Controller::new(admin_tasks, ListParams::default())
+        .owns(kube::Api::<Job>::all(kube.clone()), ListParams::default())
+        .run(
+            admin_task_controller::reconcile_admin_task,
+            operator_commons::error_policy,
+            Context::new(admin_task_controller::Ctx {
+                kube: kube.clone(),
+                cluster_domain: shared.cluster_domain.clone(),
+                image_options: shared.image_options.clone(),
+                dry_run: shared.dry_run,
+                reconcile_permits: shared.reconcile_permits.clone(),
+                apply_rate_limiter: shared.apply_rate_limiter.clone(),
+                log_apply_diffs: shared.log_apply_diffs,
+                resync_interval: shared.resync_interval,
+            }),
+        );
+    futures::stream::select(
+        hdfs_controller.map(erase_controller_result),
+        task_controller.map(erase_controller_result),
+    )
+    .boxed_local()
+}
@@ -1,63 +1,225 @@
-mod controller;
-mod crd;
-
-use crd::HdfsCluster;
-use futures::StreamExt;
-use k8s_openapi::api::{apps::v1::StatefulSet, core::v1::Service};
-use kube::{api::ListParams, CustomResourceExt};
-use kube_runtime::{controller::Context, Controller};
+use hdfs_operator::{
+    controller, crd::HdfsCluster, crds, resources, run_controllers, webhooks, OPERATOR_VERSION,
+};
+use operator_commons::{
+    crd::{install_crds, render_crds, CrdFormat},
+    detect_cluster_domain,
+    health::serve_health,
+    log_reconcile_results, run_until_shutdown,
+    telemetry::{init_tracing, shutdown_tracing, TracingOptions},
+    webhook::{serve_webhooks, WebhookServerOptions, WebhookServiceOptions},
+    ClientOptions, ControllerOptions, ImageOptions, SharedContext,
+};
+use std::{fs::File, net::SocketAddr, path::PathBuf};
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
 struct Opts {
     #[structopt(subcommand)]
     cmd: Cmd,
+    #[structopt(flatten)]
+    tracing: TracingOptions,
 }
 
 #[derive(StructOpt)]
 enum Cmd {
     /// Print CRD objects
-    Crd,
-    Run,
+    Crd {
+        /// Output format, `yaml` or `json`
+        #[structopt(long, default_value = "yaml")]
+        format: CrdFormat,
+        /// Write the CRDs to this file instead of printing them
+        #[structopt(long, short)]
+        output: Option<PathBuf>,
+        /// Apply the CRDs to the cluster and wait until they are established, instead of printing them
+        #[structopt(long)]
+        install: bool,
+        #[structopt(flatten)]
+        client: ClientOptions,
+        #[structopt(flatten)]
+        webhook: WebhookServiceOptions,
+    },
+    /// Print the operator's build information
+    Version,
+    /// Run operator
+    Run {
+        /// DNS domain of the Kubernetes cluster, detected from `/etc/resolv.conf` if not specified
+        #[structopt(long)]
+        cluster_domain: Option<String>,
+        /// Only log the changes that would be made, using server-side dry-run, instead of persisting them
+        #[structopt(long)]
+        dry_run: bool,
+        #[structopt(flatten)]
+        client: ClientOptions,
+        #[structopt(flatten)]
+        controller_options: ControllerOptions,
+        #[structopt(flatten)]
+        image_options: ImageOptions,
+        /// Address to serve `/healthz` and `/version` on
+        #[structopt(long, default_value = "0.0.0.0:8080")]
+        health_address: SocketAddr,
+        #[structopt(flatten)]
+        webhook_server: WebhookServerOptions,
+    },
+    /// Print the objects that the operator would create for an HdfsCluster, without connecting to Kubernetes
+    Render {
+        /// HdfsCluster manifest to render, the namespace defaults to `default`
+        file: PathBuf,
+        /// DNS domain of the Kubernetes cluster
+        #[structopt(long, default_value = "cluster.local")]
+        cluster_domain: String,
+        #[structopt(flatten)]
+        image_options: ImageOptions,
+    },
+    /// Show how the operator would change the objects of an HdfsCluster, without changing anything
+    Diff {
+        /// HdfsCluster manifest to diff, the namespace defaults to that of the current kubeconfig context
+        file: PathBuf,
+        /// DNS domain of the Kubernetes cluster
+        #[structopt(long, default_value = "cluster.local")]
+        cluster_domain: String,
+        #[structopt(flatten)]
+        image_options: ImageOptions,
+        #[structopt(flatten)]
+        client: ClientOptions,
+    },
 }
 
+/// Stands in for the uid of clusters that don't exist yet
+const PLACEHOLDER_UID: &str = "00000000-0000-0000-0000-000000000000";
+
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
-    tracing_subscriber::fmt().init();
-
     let opts = Opts::from_args();
+    init_tracing("RUST_LOG", "hdfs-operator", &opts.tracing)?;
+    operator_commons::fault::log_active_faults();
     match opts.cmd {
-        Cmd::Crd => println!("{}", serde_yaml::to_string(&HdfsCluster::crd())?),
-        Cmd::Run => {
-            let kube = kube::Client::try_default().await?;
-            let zks = kube::Api::<HdfsCluster>::all(kube.clone());
-            Controller::new(zks, ListParams::default())
-                .owns(
-                    kube::Api::<Service>::all(kube.clone()),
-                    ListParams::default(),
-                )
-                .owns(
-                    kube::Api::<StatefulSet>::all(kube.clone()),
-                    ListParams::default(),
-                )
-                .run(
-                    controller::reconcile_hdfs,
-                    controller::error_policy,
-                    Context::new(controller::Ctx { kube }),
-                )
-                .for_each(|res| async {
-                    match res {
-                        Ok((obj, _)) => tracing::info!(object = %obj, "Reconciled object"),
-                        Err(err) => {
-                            tracing::error!(
-                                error = &err as &dyn std::error::Error,
-                                "Failed to reconcile object",
-                            )
-                        }
-                    }
-                })
-                .await;
+        Cmd::Crd {
+            format,
+            output,
+            install,
+            client,
+            webhook,
+        } => {
+            let crds = crds(&webhook)?;
+            let mutating_webhooks =
+                webhook.mutating_webhook_configuration("hdfs-operator", &crds, &webhooks())?;
+            if install {
+                let kube = client.client().await?;
+                install_crds(&kube, "hdfs-operator", &crds, mutating_webhooks.as_ref()).await?;
+            } else if let Some(output) = output {
+                std::fs::write(
+                    output,
+                    render_crds(&crds, mutating_webhooks.as_ref(), format)?,
+                )?;
+            } else {
+                println!(
+                    "{}",
+                    render_crds(&crds, mutating_webhooks.as_ref(), format)?
+                );
+            }
+        }
+        Cmd::Version => println!("{}", OPERATOR_VERSION),
+        Cmd::Run {
+            cluster_domain,
+            dry_run,
+            client,
+            controller_options,
+            image_options,
+            health_address,
+            webhook_server,
+        } => {
+            tracing::info!(version = %OPERATOR_VERSION, "Starting operator");
+            let cluster_domain = cluster_domain
+                .or_else(detect_cluster_domain)
+                .unwrap_or_else(|| "cluster.local".to_string());
+            tracing::info!(cluster_domain = %cluster_domain, "Using cluster domain");
+            if dry_run {
+                tracing::warn!("Running in dry-run mode, no changes will be persisted");
+            }
+            tokio::spawn(async move {
+                if let Err(err) = serve_health(health_address, OPERATOR_VERSION).await {
+                    tracing::error!(
+                        error = &err as &dyn std::error::Error,
+                        "Failed to serve health endpoints"
+                    );
+                }
+            });
+            tokio::spawn(async move {
+                if let Err(err) = serve_webhooks(webhook_server, webhooks()).await {
+                    tracing::error!(
+                        error = &err as &dyn std::error::Error,
+                        "Failed to serve webhooks"
+                    );
+                }
+            });
+            let kube = client.client().await?;
+            let reconcile_permits = controller_options.reconcile_permits();
+            let shared = SharedContext {
+                kube,
+                cluster_domain,
+                image_options,
+                dry_run,
+                reconcile_permits: reconcile_permits.clone(),
+                apply_rate_limiter: controller_options.apply_rate_limiter(),
+                log_apply_diffs: controller_options.log_apply_diffs,
+                resync_interval: controller_options.resync_interval,
+            };
+            let results = log_reconcile_results(run_controllers(&shared));
+            run_until_shutdown(
+                results,
+                &reconcile_permits,
+                controller_options.shutdown_grace_period,
+            )
+            .await;
+        }
+        Cmd::Render {
+            file,
+            cluster_domain,
+            image_options,
+        } => {
+            let mut hdfs: HdfsCluster = serde_yaml::from_reader(File::open(&file)?)?;
+            hdfs.metadata
+                .namespace
+                .get_or_insert_with(|| "default".to_string());
+            hdfs.metadata
+                .uid
+                .get_or_insert_with(|| PLACEHOLDER_UID.to_string());
+            let mut cluster = resources::ClusterContext::new(&hdfs, &cluster_domain)?;
+            cluster.image_options = image_options;
+            println!("{}", resources::render(&cluster)?);
+        }
+        Cmd::Diff {
+            file,
+            cluster_domain,
+            image_options,
+            client,
+        } => {
+            let config = client.config().await?;
+            let default_namespace = config.default_namespace.clone();
+            let kube = client.client_with_config(config)?;
+            let mut hdfs: HdfsCluster = serde_yaml::from_reader(File::open(&file)?)?;
+            let ns = hdfs
+                .metadata
+                .namespace
+                .get_or_insert(default_namespace)
+                .clone();
+            let name = hdfs.metadata.name.clone().unwrap_or_default();
+            // Owner references point to the live cluster, or to a placeholder if it hasn't been created yet
+            hdfs.metadata.uid = match kube::Api::<HdfsCluster>::namespaced(kube.clone(), &ns)
+                .get(&name)
+                .await
+            {
+                Ok(live) => live.metadata.uid,
+                Err(kube::Error::Api(err)) if err.code == 404 => Some(PLACEHOLDER_UID.to_string()),
+                Err(err) => return Err(err.into()),
+            };
+            print!(
+                "{}",
+                controller::diff_hdfs(&kube, &hdfs, &cluster_domain, &image_options).await?
+            );
         }
     }
+    shutdown_tracing();
     Ok(())
 }
@@ -0,0 +1,94 @@
+//! The network ports used by each HDFS role
+//!
+//! All generated `ContainerPort`s, `ServicePort`s, and Hadoop properties take their ports from [`HdfsPorts`],
+//! so that they stay consistent when users override them.
+
+use crate::crd::HdfsPortsConfig;
+
+pub const DEFAULT_NAMENODE_RPC: i32 = 8020;
+pub const DEFAULT_NAMENODE_HTTP: i32 = 9870;
+pub const DEFAULT_DATANODE_DATA: i32 = 9866;
+pub const DEFAULT_DATANODE_IPC: i32 = 9867;
+pub const DEFAULT_DATANODE_HTTP: i32 = 9864;
+pub const DEFAULT_JOURNALNODE_RPC: i32 = 8485;
+pub const DEFAULT_JOURNALNODE_HTTP: i32 = 8480;
+pub const DEFAULT_NAMENODE_HTTPS: i32 = 9871;
+pub const DEFAULT_DATANODE_HTTPS: i32 = 9865;
+pub const DEFAULT_JOURNALNODE_HTTPS: i32 = 8481;
+
+/// The ports of an [`HdfsCluster`](crate::crd::HdfsCluster), with the user's overrides applied
+#[derive(Clone, Copy, Debug)]
+pub struct HdfsPorts {
+    pub namenode_rpc: i32,
+    pub namenode_http: i32,
+    pub datanode_data: i32,
+    pub datanode_ipc: i32,
+    pub datanode_http: i32,
+    pub journalnode_rpc: i32,
+    pub journalnode_http: i32,
+    /// Only set if the namenodes serve the other daemons on a separate port
+    pub namenode_service_rpc: Option<i32>,
+    /// Only set if the web UIs are served over HTTPS, see `tls`
+    pub namenode_https: Option<i32>,
+    pub datanode_https: Option<i32>,
+    pub journalnode_https: Option<i32>,
+}
+
+impl HdfsPorts {
+    /// The ports of `config`, where the HTTPS ports are only set if the daemons serve HTTPS because of `tls`
+    pub fn new(config: &HdfsPortsConfig, tls: bool) -> Self {
+        Self {
+            namenode_rpc: config.namenode_rpc.unwrap_or(DEFAULT_NAMENODE_RPC),
+            namenode_http: config.namenode_http.unwrap_or(DEFAULT_NAMENODE_HTTP),
+            datanode_data: config.datanode_data.unwrap_or(DEFAULT_DATANODE_DATA),
+            datanode_ipc: config.datanode_ipc.unwrap_or(DEFAULT_DATANODE_IPC),
+            datanode_http: config.datanode_http.unwrap_or(DEFAULT_DATANODE_HTTP),
+            journalnode_rpc: config.journalnode_rpc.unwrap_or(DEFAULT_JOURNALNODE_RPC),
+            journalnode_http: config.journalnode_http.unwrap_or(DEFAULT_JOURNALNODE_HTTP),
+            namenode_service_rpc: config.namenode_service_rpc,
+            namenode_https: tls.then(|| config.namenode_https.unwrap_or(DEFAULT_NAMENODE_HTTPS)),
+            datanode_https: tls.then(|| config.datanode_https.unwrap_or(DEFAULT_DATANODE_HTTPS)),
+            journalnode_https: tls.then(|| {
+                config
+                    .journalnode_https
+                    .unwrap_or(DEFAULT_JOURNALNODE_HTTPS)
+            }),
+        }
+    }
+
+    /// The port of the namenodes' web UI, which only serves HTTPS if `tls` is set
+    pub fn namenode_web(&self) -> i32 {
+        self.namenode_https.unwrap_or(self.namenode_http)
+    }
+
+    /// The port of the datanodes' web UI, which only serves HTTPS if `tls` is set
+    pub fn datanode_web(&self) -> i32 {
+        self.datanode_https.unwrap_or(self.datanode_http)
+    }
+
+    /// Ports that clients of the cluster need to access
+    pub fn client_ports(&self) -> Vec<i32> {
+        [
+            self.namenode_rpc,
+            self.namenode_http,
+            self.datanode_data,
+            self.datanode_ipc,
+            self.datanode_http,
+        ]
+        .into_iter()
+        .chain(self.namenode_https)
+        .chain(self.datanode_https)
+        .collect()
+    }
+
+    /// Ports that only the cluster's own daemons need to access
+    ///
+    /// The namenodes download edits from the journalnodes' web servers.
+    pub fn internal_ports(&self) -> Vec<i32> {
+        [self.journalnode_rpc, self.journalnode_http]
+            .into_iter()
+            .chain(self.journalnode_https)
+            .chain(self.namenode_service_rpc)
+            .collect()
+    }
+}
@@ -0,0 +1,239 @@
+//! Checks that a cluster is healthy enough to roll out a new Hadoop version
+//!
+//! The checks only look at the active namenode, which knows about the checkpoints, blocks, and journalnodes.
+
+use k8s_openapi::chrono::{DateTime, Duration, TimeZone, Utc};
+use serde_json::{Map, Value};
+
+use crate::{crd::UpgradeConfig, jmx, resources::ClusterContext};
+
+/// Default for `upgrade.maxCheckpointAge`, twice Hadoop's default `dfs.namenode.checkpoint.period`
+const DEFAULT_MAX_CHECKPOINT_AGE_SECONDS: i64 = 2 * 60 * 60;
+
+/// What the active namenode reports about the file system's health
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NamenodeHealth {
+    /// When the last fsimage checkpoint was taken
+    pub last_checkpoint: Option<DateTime<Utc>>,
+    pub under_replicated_blocks: Option<i64>,
+    /// The namenode's safemode status, empty if safemode is off
+    pub safemode: String,
+    /// The status of the namenode's journals, with one entry per journalnode for the quorum journal
+    pub journal_status: String,
+}
+
+impl NamenodeHealth {
+    fn from_beans(fs_namesystem: &Map<String, Value>, info: &Map<String, Value>) -> Self {
+        Self {
+            last_checkpoint: fs_namesystem
+                .get("LastCheckpointTime")
+                .and_then(Value::as_i64)
+                .filter(|millis| *millis > 0)
+                .and_then(|millis| Utc.timestamp_millis_opt(millis).single()),
+            under_replicated_blocks: fs_namesystem
+                .get("UnderReplicatedBlocks")
+                .and_then(Value::as_i64),
+            safemode: info
+                .get("Safemode")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            journal_status: info
+                .get("NameJournalStatus")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+        }
+    }
+}
+
+/// Whether all `journalnodes` have acknowledged the edits written so far
+///
+/// The quorum journal reports `Written txid` for each journalnode that it writes to, and marks those that missed
+/// edits as out of sync until the next segment.
+fn journalnodes_in_sync(journal_status: &str, journalnodes: i32) -> bool {
+    let journals = match serde_json::from_str::<Vec<Map<String, Value>>>(journal_status) {
+        Ok(journals) => journals,
+        Err(_) => return false,
+    };
+    journals.iter().any(|journal| {
+        let stream = journal
+            .get("stream")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        stream.matches("Written txid").count() >= journalnodes as usize
+            && !stream.contains("re-sync")
+    })
+}
+
+/// The reasons why `health` is not good enough for an upgrade, empty if it is
+pub fn failures(
+    health: &NamenodeHealth,
+    config: &UpgradeConfig,
+    journalnodes: i32,
+    now: DateTime<Utc>,
+) -> Vec<String> {
+    let mut failures = Vec::new();
+    let max_checkpoint_age = Duration::seconds(
+        config
+            .max_checkpoint_age
+            .unwrap_or(DEFAULT_MAX_CHECKPOINT_AGE_SECONDS),
+    );
+    match health.last_checkpoint {
+        Some(last_checkpoint) if now - last_checkpoint <= max_checkpoint_age => {}
+        Some(last_checkpoint) => failures.push(format!(
+            "the last checkpoint was taken at {}, more than {}s ago",
+            last_checkpoint.to_rfc3339(),
+            max_checkpoint_age.num_seconds()
+        )),
+        None => failures.push("no checkpoint has been taken".to_string()),
+    }
+    let max_under_replicated_blocks = config.max_under_replicated_blocks.unwrap_or(0);
+    match health.under_replicated_blocks {
+        Some(blocks) if blocks <= max_under_replicated_blocks => {}
+        Some(blocks) => failures.push(format!(
+            "{} blocks are under-replicated, at most {} are allowed",
+            blocks, max_under_replicated_blocks
+        )),
+        None => failures.push("the number of under-replicated blocks is unknown".to_string()),
+    }
+    if !health.safemode.is_empty() {
+        failures.push(format!("the namenode is in safemode: {}", health.safemode));
+    }
+    if !journalnodes_in_sync(&health.journal_status, journalnodes) {
+        failures.push("not all journalnodes are in sync".to_string());
+    }
+    failures
+}
+
+/// What the active namenode of `cluster` reports, or `None` if no namenode is active or it can't be reached
+async fn namenode_health(cluster: &ClusterContext<'_>) -> Option<NamenodeHealth> {
+    for i in 0..cluster.hdfs.spec.namenode_replicas.unwrap_or(1) {
+        let host = cluster.pod_fqdn("namenode", i);
+        let port = cluster.ports.namenode_web();
+        let ca = cluster.tls_ca.as_deref();
+        let status = jmx::get_bean(
+            &host,
+            port,
+            ca,
+            "Hadoop:service=NameNode,name=NameNodeStatus",
+        )
+        .await
+        .ok();
+        if status.as_ref().and_then(|bean| bean.get("State")?.as_str()) != Some("active") {
+            continue;
+        }
+        let fs_namesystem =
+            jmx::get_bean(&host, port, ca, "Hadoop:service=NameNode,name=FSNamesystem")
+                .await
+                .ok()?;
+        let info = jmx::get_bean(&host, port, ca, "Hadoop:service=NameNode,name=NameNodeInfo")
+            .await
+            .ok()?;
+        return Some(NamenodeHealth::from_beans(&fs_namesystem, &info));
+    }
+    None
+}
+
+/// Runs the pre-flight checks against `cluster`, returning the reasons why it can't be upgraded
+pub async fn check(cluster: &ClusterContext<'_>) -> Vec<String> {
+    let spec = &cluster.hdfs.spec;
+    match namenode_health(cluster).await {
+        Some(health) => failures(
+            &health,
+            &spec.upgrade,
+            spec.journalnode_replicas.unwrap_or(1),
+            Utc::now(),
+        ),
+        None => vec!["no active namenode could be reached".to_string()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn healthy(now: DateTime<Utc>) -> NamenodeHealth {
+        NamenodeHealth {
+            last_checkpoint: Some(now - Duration::minutes(30)),
+            under_replicated_blocks: Some(0),
+            safemode: String::new(),
+            journal_status: serde_json::json!([{
+                "manager": "QJM to [10.0.0.1:8485, 10.0.0.2:8485, 10.0.0.3:8485]",
+                "stream": "Writing segment beginning at txid 42.\n10.0.0.1:8485 (Written txid 57), \
+                           10.0.0.2:8485 (Written txid 57), 10.0.0.3:8485 (Written txid 57)",
+                "disabled": "false",
+                "required": "false",
+            }])
+            .to_string(),
+        }
+    }
+
+    #[test]
+    fn healthy_clusters_pass() {
+        let now = Utc::now();
+        assert!(failures(&healthy(now), &UpgradeConfig::default(), 3, now).is_empty());
+    }
+
+    #[test]
+    fn each_problem_is_reported() {
+        let now = Utc::now();
+        let health = NamenodeHealth {
+            last_checkpoint: Some(now - Duration::hours(3)),
+            under_replicated_blocks: Some(12),
+            safemode: "Safe mode is ON.".to_string(),
+            journal_status: healthy(now).journal_status.replace(
+                "(Written txid 57), 10.0.0.3",
+                "(Written txid 57) (will try to re-sync on next segment), 10.0.0.3",
+            ),
+        };
+        let failures = failures(&health, &UpgradeConfig::default(), 3, now);
+        assert_eq!(failures.len(), 4, "{:?}", failures);
+        assert!(failures[0].contains("more than 7200s ago"));
+        assert!(failures[1].starts_with("12 blocks are under-replicated"));
+        assert!(failures[2].contains("Safe mode is ON."));
+        assert_eq!(failures[3], "not all journalnodes are in sync");
+    }
+
+    #[test]
+    fn thresholds_can_be_raised() {
+        let now = Utc::now();
+        let health = NamenodeHealth {
+            last_checkpoint: Some(now - Duration::hours(3)),
+            under_replicated_blocks: Some(12),
+            ..healthy(now)
+        };
+        let config = UpgradeConfig {
+            max_checkpoint_age: Some(4 * 60 * 60),
+            max_under_replicated_blocks: Some(20),
+            ..UpgradeConfig::default()
+        };
+        assert!(failures(&health, &config, 3, now).is_empty());
+    }
+
+    #[test]
+    fn missing_journalnodes_are_out_of_sync() {
+        let now = Utc::now();
+        assert!(!journalnodes_in_sync(&healthy(now).journal_status, 4));
+        assert!(!journalnodes_in_sync("", 3));
+    }
+
+    #[test]
+    fn beans_are_parsed() {
+        let fs_namesystem = serde_json::json!({
+            "LastCheckpointTime": 1_634_378_400_123_i64,
+            "UnderReplicatedBlocks": 3,
+        });
+        let info = serde_json::json!({ "Safemode": "", "NameJournalStatus": "[]" });
+        let health = NamenodeHealth::from_beans(
+            fs_namesystem.as_object().unwrap(),
+            info.as_object().unwrap(),
+        );
+        assert_eq!(
+            health.last_checkpoint.unwrap().to_rfc3339(),
+            "2021-10-16T10:00:00.123+00:00"
+        );
+        assert_eq!(health.under_replicated_blocks, Some(3));
+        assert_eq!(health.safemode, "");
+    }
+}
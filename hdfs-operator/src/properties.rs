@@ -0,0 +1,279 @@
+//! The Hadoop properties that `configOverrides` are checked against
+//!
+//! The schema only covers commonly tuned properties, so unknown properties are reported but still applied. Users
+//! who deliberately set exotic properties can silence the reports with `propertyValidation.allowUnknown`.
+
+use std::fmt::Display;
+
+use PropertyType::*;
+
+/// The format that Hadoop parses a property's value with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PropertyType {
+    Bool,
+    Int,
+    Float,
+    /// A number with an optional unit, such as `30s` (`Configuration.getTimeDuration`)
+    Duration,
+    /// A number of bytes with an optional binary prefix, such as `128m` (`Configuration.getLongBytes`)
+    Size,
+    Text,
+}
+
+impl Display for PropertyType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            PropertyType::Bool => "true or false",
+            PropertyType::Int => "an integer",
+            PropertyType::Float => "a number",
+            PropertyType::Duration => "a duration, such as 30s",
+            PropertyType::Size => "a size, such as 128m",
+            PropertyType::Text => "text",
+        })
+    }
+}
+
+/// A known property, or a family of properties (such as `dfs.namenode.rpc-address.<nameservice>.<namenode>`) if its
+/// name ends with a `.`
+struct KnownProperty {
+    name: &'static str,
+    type_: PropertyType,
+    /// The first Hadoop version that supports the property
+    since: Option<[u32; 3]>,
+}
+
+const fn known(name: &'static str, type_: PropertyType) -> KnownProperty {
+    KnownProperty {
+        name,
+        type_,
+        since: None,
+    }
+}
+
+const fn known_since(name: &'static str, type_: PropertyType, since: [u32; 3]) -> KnownProperty {
+    KnownProperty {
+        name,
+        type_,
+        since: Some(since),
+    }
+}
+
+const KNOWN_PROPERTIES: &[KnownProperty] = &[
+    // core-site.xml
+    known("fs.defaultFS", Text),
+    known("fs.trash.interval", Float),
+    known("fs.trash.checkpoint.interval", Float),
+    known("io.file.buffer.size", Int),
+    known("io.compression.codecs", Text),
+    known("hadoop.tmp.dir", Text),
+    known("hadoop.security.authentication", Text),
+    known("hadoop.security.authorization", Bool),
+    known("hadoop.security.auth_to_local", Text),
+    known("hadoop.security.group.mapping", Text),
+    known("hadoop.rpc.protection", Text),
+    known("hadoop.http.staticuser.user", Text),
+    known("hadoop.http.authentication.type", Text),
+    known("hadoop.http.authentication.kerberos.principal", Text),
+    known("hadoop.http.authentication.kerberos.keytab", Text),
+    known("hadoop.http.authentication.simple.anonymous.allowed", Bool),
+    known("hadoop.proxyuser.", Text),
+    known("ipc.client.connect.timeout", Int),
+    known("ipc.client.connect.max.retries", Int),
+    known("ipc.client.idlethreshold", Int),
+    known("ipc.server.listen.queue.size", Int),
+    known("ha.zookeeper.quorum", Text),
+    known("ha.zookeeper.session-timeout.ms", Int),
+    known("ha.zookeeper.parent-znode", Text),
+    known("ha.zookeeper.auth", Text),
+    known("ha.zookeeper.acl", Text),
+    known("ha.health-monitor.rpc-timeout.ms", Int),
+    known("net.topology.script.file.name", Text),
+    known("net.topology.node.switch.mapping.impl", Text),
+    // hdfs-site.xml
+    known("dfs.nameservices", Text),
+    known("dfs.ha.namenodes.", Text),
+    known("dfs.client.failover.proxy.provider.", Text),
+    known("dfs.namenode.rpc-address.", Text),
+    known("dfs.namenode.servicerpc-address.", Text),
+    known("dfs.namenode.http-address.", Text),
+    known("dfs.namenode.https-address.", Text),
+    known("dfs.namenode.shared.edits.dir", Text),
+    known("dfs.namenode.name.dir", Text),
+    known("dfs.namenode.handler.count", Int),
+    known("dfs.namenode.service.handler.count", Int),
+    known("dfs.namenode.replication.min", Int),
+    known("dfs.namenode.safemode.threshold-pct", Float),
+    known("dfs.namenode.safemode.min.datanodes", Int),
+    known("dfs.namenode.safemode.extension", Int),
+    known("dfs.namenode.checkpoint.period", Duration),
+    known("dfs.namenode.checkpoint.txns", Int),
+    known("dfs.namenode.num.extra.edits.retained", Int),
+    known("dfs.namenode.max.extra.edits.segments.retained", Int),
+    known("dfs.namenode.heartbeat.recheck-interval", Int),
+    known("dfs.namenode.stale.datanode.interval", Int),
+    known("dfs.namenode.avoid.read.stale.datanode", Bool),
+    known("dfs.namenode.avoid.write.stale.datanode", Bool),
+    known("dfs.namenode.acls.enabled", Bool),
+    known("dfs.namenode.fs-limits.max-directory-items", Int),
+    known("dfs.namenode.fs-limits.max-component-length", Int),
+    known("dfs.namenode.inode.attributes.provider.class", Text),
+    known("dfs.namenode.hosts.provider.classname", Text),
+    known("dfs.namenode.maintenance.replication.min", Int),
+    known("dfs.namenode.kerberos.principal", Text),
+    known("dfs.namenode.keytab.file", Text),
+    known_since("dfs.namenode.state.context.enabled", Bool, [3, 3, 0]),
+    known("dfs.ha.automatic-failover.enabled", Bool),
+    known("dfs.ha.fencing.methods", Text),
+    known("dfs.ha.fencing.ssh.private-key-files", Text),
+    known("dfs.ha.fencing.ssh.connect-timeout", Int),
+    known("dfs.ha.nn.not-become-active-in-safemode", Bool),
+    known("dfs.ha.tail-edits.period", Duration),
+    known("dfs.replication", Int),
+    known("dfs.replication.max", Int),
+    known("dfs.blocksize", Size),
+    known("dfs.heartbeat.interval", Duration),
+    known("dfs.permissions.enabled", Bool),
+    known("dfs.permissions.superusergroup", Text),
+    known("dfs.block.access.token.enable", Bool),
+    known("dfs.encrypt.data.transfer", Bool),
+    known("dfs.data.transfer.protection", Text),
+    known("dfs.http.policy", Text),
+    known("dfs.webhdfs.enabled", Bool),
+    known("dfs.storage.policy.enabled", Bool),
+    known("dfs.image.compress", Bool),
+    known("dfs.hosts", Text),
+    known("dfs.hosts.exclude", Text),
+    known("dfs.client.read.shortcircuit", Bool),
+    known("dfs.domain.socket.path", Text),
+    known("dfs.web.authentication.kerberos.principal", Text),
+    known("dfs.web.authentication.kerberos.keytab", Text),
+    known("dfs.datanode.data.dir", Text),
+    known("dfs.datanode.address", Text),
+    known("dfs.datanode.ipc.address", Text),
+    known("dfs.datanode.http.address", Text),
+    known("dfs.datanode.https.address", Text),
+    known("dfs.datanode.handler.count", Int),
+    known("dfs.datanode.du.reserved", Int),
+    known("dfs.datanode.failed.volumes.tolerated", Int),
+    known("dfs.datanode.max.transfer.threads", Int),
+    known("dfs.datanode.balance.bandwidthPerSec", Size),
+    known("dfs.datanode.disk.check.timeout", Duration),
+    known("dfs.datanode.disk.check.min.gap", Duration),
+    known("dfs.datanode.kerberos.principal", Text),
+    known("dfs.datanode.keytab.file", Text),
+    known("dfs.journalnode.edits.dir", Text),
+    known("dfs.journalnode.rpc-address", Text),
+    known("dfs.journalnode.http-address", Text),
+    known("dfs.journalnode.https-address", Text),
+    known("dfs.journalnode.kerberos.principal", Text),
+    known("dfs.journalnode.kerberos.internal.spnego.principal", Text),
+    known("dfs.journalnode.keytab.file", Text),
+];
+
+/// Parses the leading version number of an image tag, such as `[3, 3, 1]` for `3.3.1-stackable0.1.0`
+fn parse_version(tag: &str) -> Option<[u32; 3]> {
+    let mut parts = tag
+        .split(|c: char| !c.is_ascii_digit() && c != '.')
+        .next()?
+        .split('.')
+        .map(|part| part.parse().ok());
+    Some([
+        parts.next()??,
+        parts.next()??,
+        parts.next().flatten().unwrap_or(0),
+    ])
+}
+
+fn is_valid(type_: PropertyType, value: &str) -> bool {
+    let value = value.trim();
+    match type_ {
+        Bool => value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false"),
+        Int => {
+            value.parse::<i64>().is_ok()
+                || value
+                    .strip_prefix("0x")
+                    .map_or(false, |hex| i64::from_str_radix(hex, 16).is_ok())
+        }
+        Float => value.parse::<f64>().is_ok(),
+        Duration => {
+            let unit = value.trim_start_matches(|c: char| c.is_ascii_digit());
+            value.len() > unit.len()
+                && ["", "ns", "us", "ms", "s", "m", "h", "d"]
+                    .contains(&unit.to_ascii_lowercase().as_str())
+        }
+        Size => {
+            let number = value.trim_end_matches(|c: char| "kmgtpeKMGTPE".contains(c));
+            value.len() - number.len() <= 1 && number.parse::<i64>().is_ok()
+        }
+        Text => true,
+    }
+}
+
+/// Checks the override of the property `name` for Hadoop `version`, describing the problem if there is one
+///
+/// Values that refer to variables (`${...}`) are only resolved by Hadoop, so they are not checked.
+pub fn check(version: &str, name: &str, value: &str, allow_unknown: bool) -> Option<String> {
+    let version = parse_version(version);
+    let property = KNOWN_PROPERTIES.iter().find(|property| {
+        property.name == name || (property.name.ends_with('.') && name.starts_with(property.name))
+    });
+    match property {
+        Some(property)
+            if property
+                .since
+                .zip(version)
+                .map_or(false, |(since, version)| version < since) =>
+        {
+            Some(format!("{} is not supported by this Hadoop version", name))
+        }
+        Some(property) if !value.contains("${") && !is_valid(property.type_, value) => Some(
+            format!("{} must be {}, not {:?}", name, property.type_, value),
+        ),
+        Some(_) => None,
+        None if allow_unknown => None,
+        None => Some(format!("{} is not a known property", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_are_checked_against_the_schema() {
+        assert_eq!(check("3.3.1", "dfs.replication", "3", false), None);
+        assert_eq!(
+            check("3.3.1", "dfs.replicaton", "3", false).as_deref(),
+            Some("dfs.replicaton is not a known property")
+        );
+        assert_eq!(check("3.3.1", "dfs.replicaton", "3", true), None);
+        assert_eq!(
+            check("3.3.1", "dfs.replication", "three", true).as_deref(),
+            Some("dfs.replication must be an integer, not \"three\"")
+        );
+        assert_eq!(check("3.3.1", "dfs.blocksize", "256m", false), None);
+        assert_eq!(check("3.3.1", "dfs.heartbeat.interval", "3s", false), None);
+        assert!(check("3.3.1", "dfs.heartbeat.interval", "3 weeks", false).is_some());
+        assert_eq!(check("3.3.1", "dfs.webhdfs.enabled", "TRUE", false), None);
+        assert_eq!(
+            check("3.3.1", "hadoop.proxyuser.hive.hosts", "*", false),
+            None
+        );
+        assert_eq!(
+            check("3.3.1", "dfs.replication", "${env.REPLICATION}", false),
+            None
+        );
+    }
+
+    #[test]
+    fn properties_are_checked_against_the_version() {
+        assert_eq!(parse_version("3.3.1-stackable0.1.0"), Some([3, 3, 1]));
+        assert_eq!(parse_version("3.2"), Some([3, 2, 0]));
+        assert_eq!(parse_version("latest"), None);
+        let name = "dfs.namenode.state.context.enabled";
+        assert_eq!(check("3.3.1", name, "true", false), None);
+        assert!(check("3.2.2", name, "true", true).is_some());
+        // Unknown versions get the benefit of the doubt
+        assert_eq!(check("latest", name, "true", false), None);
+    }
+}
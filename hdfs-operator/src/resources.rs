@@ -0,0 +1,6735 @@
+//! Builds the objects that make up an [`HdfsCluster`]
+//!
+//! Nothing in here talks to Kubernetes, so the generated objects can be tested in isolation.
+//! Applying them is left to [`crate::controller`].
+
+use std::{
+    collections::BTreeMap,
+    hash::{Hash, Hasher},
+};
+
+use crate::{
+    cert_manager::{Certificate, CertificateSpec, IssuerRef},
+    config::{effective_config, ConfigLayer, MergedConfig, EFFECTIVE_CONFIG_KEY},
+    crd::{
+        image_architectures, AllowedClient, DatanodeGroupConfig, FencingMethod, GcConfig, GcPreset,
+        HdfsAdminAction, HdfsAdminTask, HdfsAdminTaskSpec, HdfsCluster, HdfsEndpoints,
+        HdfsRoleConfig, JournalnodeStorageConfig, JvmConfig, PodManagementPolicy, ZookeeperAuth,
+        DEFAULT_KERBEROS_REALM,
+    },
+    hadoop_xml::{configuration_xml, hadoop_config_xml, HadoopProperty},
+    interpolation,
+    ports::HdfsPorts,
+    properties, rollout,
+};
+use fnv::FnvHasher;
+use k8s_openapi::{
+    api::{
+        apps::v1::{StatefulSet, StatefulSetSpec, StatefulSetUpdateStrategy},
+        batch::v1::{Job, JobSpec},
+        core::v1::{
+            Affinity, ConfigMap, ConfigMapKeySelector, ConfigMapVolumeSource, Container,
+            ContainerPort, EmptyDirVolumeSource, EnvVar, EnvVarSource, EphemeralVolumeSource,
+            ExecAction, Handler, Lifecycle, LocalObjectReference, NodeAffinity, NodeSelector,
+            NodeSelectorRequirement, NodeSelectorTerm, ObjectFieldSelector, PersistentVolumeClaim,
+            PersistentVolumeClaimSpec, PersistentVolumeClaimTemplate, PodSpec, PodTemplateSpec,
+            ResourceRequirements, Secret, SecretKeySelector, SecretVolumeSource, SecurityContext,
+            Service, ServiceAccount, ServicePort, ServiceSpec, TopologySpreadConstraint, Volume,
+            VolumeMount,
+        },
+        networking::v1::{
+            NetworkPolicy, NetworkPolicyIngressRule, NetworkPolicyPeer, NetworkPolicyPort,
+            NetworkPolicySpec,
+        },
+        policy::v1::{PodDisruptionBudget, PodDisruptionBudgetSpec},
+        rbac::v1::{RoleBinding, RoleRef, Subject},
+    },
+    apimachinery::pkg::{
+        api::resource::Quantity,
+        apis::meta::v1::{LabelSelector, OwnerReference},
+        util::intstr::IntOrString,
+    },
+};
+use kube::api::{DynamicObject, ObjectMeta};
+use kube_runtime::reflector::ObjectRef;
+use operator_commons::{
+    controller_reference_to_obj,
+    labels::{
+        cluster_labels, managed_by_labels, recommended_labels, APP_INSTANCE_LABEL, APP_NAME_LABEL,
+        APP_ROLE_GROUP_LABEL,
+    },
+    merge_json, parse_memory_quantity, ImageOptions,
+};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+
+/// How long a stopping datanode waits for in-flight block transfers to finish before shutting down
+const DATANODE_DRAIN_TIMEOUT_SECONDS: i64 = 60;
+
+/// How long a stopping active namenode waits for the failover to a standby before shutting down anyway
+const NAMENODE_FAILOVER_TIMEOUT_SECONDS: i64 = 60;
+
+/// Share of the ZKFC container's memory that its heap may use, leaving the rest for the JVM's own overhead
+const ZKFC_HEAP_PERCENT: u64 = 75;
+
+pub const APP_NAME: &str = "hdfs";
+/// Role group of all objects, except for the ones of the `datanodeGroups`, whose role group is the group's name
+const ROLE_GROUP: &str = "default";
+
+/// Default `datanodeAutoscaling.usedPercentThreshold`
+const DEFAULT_USED_PERCENT_THRESHOLD: f64 = 80.0;
+/// Hadoop's `dfs.namenode.checkpoint.period`, in seconds
+const DEFAULT_CHECKPOINT_PERIOD: i64 = 3600;
+/// Hadoop's `dfs.namenode.checkpoint.txns`
+const DEFAULT_CHECKPOINT_TRANSACTIONS: i64 = 1_000_000;
+
+/// Label that tells the pods of each of the `datanodeGroups` apart
+///
+/// The selector of the other datanodes predates the groups and is immutable, so it matches these pods as well.
+const DATANODE_GROUP_LABEL: &str = "hdfs.stackable.tech/datanode-group";
+
+/// Keys of the `zookeeperConfigMapName` that the failover controllers use, which are also their environment variables
+pub const ZOOKEEPER_VARIABLES: [&str; 2] = ["ZOOKEEPER_HOSTS", "ZOOKEEPER_CHROOT"];
+
+/// The configuration files that `configOverrides` may set properties in
+const OVERRIDABLE_FILES: [&str; 2] = ["core-site.xml", "hdfs-site.xml"];
+
+/// Security settings that are marked final, so that user jobs can't override them
+const FINAL_PROPERTIES: [&str; 3] = [
+    "hadoop.security.authentication",
+    "hadoop.security.authorization",
+    "dfs.block.access.token.enable",
+];
+
+/// `StorageClass` of the ephemeral volumes that secret-operator provisions
+const SECRET_OPERATOR_STORAGE_CLASS: &str = "secrets.stackable.tech";
+
+/// Key of the keystore password in the cluster's keystore `Secret`
+pub const KEYSTORE_PASSWORD_KEY: &str = "password";
+
+/// Annotation on an `HdfsCluster` that restarts all roles whenever its value changes, such as to the current time
+///
+/// Roles can also be restarted individually with `hdfs.stackable.tech/restart-<role>`. Either annotation is copied to
+/// the pod templates, so the `StatefulSet`s replace their pods one by one, and the roles are restarted in the order
+/// that they are started in.
+pub const RESTART_ANNOTATION: &str = "hdfs.stackable.tech/restart";
+
+/// Pod template annotation with the hash of the role's Kerberos `Secret`, so that rotated keytabs restart the role
+pub const SECRET_HASH_ANNOTATION: &str = "hdfs.stackable.tech/secret-hash";
+
+/// Pod template annotation with the hash of the role's cert-manager TLS `Secret`
+///
+/// The keystores are only created when a pod starts, so this restarts the role whenever cert-manager renews its
+/// certificate, well before the previous one expires.
+pub const TLS_SECRET_HASH_ANNOTATION: &str = "hdfs.stackable.tech/tls-secret-hash";
+
+/// Pod annotation that tells the cluster autoscaler whether it may evict the pod to remove its node
+pub const SAFE_TO_EVICT_ANNOTATION: &str = "cluster-autoscaler.kubernetes.io/safe-to-evict";
+
+#[derive(Snafu, Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum Error {
+    ObjectHasNoNamespace {
+        obj_ref: ObjectRef<DynamicObject>,
+    },
+    #[snafu(display(
+        "dfsReplication {} must be between 1 and the number of datanodes ({})",
+        replication,
+        datanodes
+    ))]
+    InvalidReplication {
+        replication: i32,
+        datanodes: i32,
+    },
+    #[snafu(display(
+        "dfsReplicationMin {} must be between 1 and dfsReplication ({})",
+        replication_min,
+        replication
+    ))]
+    InvalidReplicationMin {
+        replication_min: i32,
+        replication: i32,
+    },
+    #[snafu(display(
+        "datanodeRollout.maxUnavailable {:?} must be a number or a percentage",
+        max_unavailable
+    ))]
+    InvalidMaxUnavailable {
+        max_unavailable: IntOrString,
+    },
+    #[snafu(display("podOverrides for role {} do not form a valid pod template", role))]
+    InvalidPodOverrides {
+        source: serde_json::Error,
+        role: String,
+    },
+    #[snafu(display(
+        "configOverrides and propertyAttributes of role {} may only refer to core-site.xml and hdfs-site.xml, not {}",
+        role,
+        file
+    ))]
+    InvalidConfigOverrides {
+        role: String,
+        file: String,
+    },
+    #[snafu(display(
+        "{} of {} refers to the environment variable {}, which its containers don't set",
+        file,
+        sts,
+        variable
+    ))]
+    UnresolvableVariable {
+        sts: String,
+        file: String,
+        variable: String,
+    },
+    #[snafu(display("the setQuota action requires a quota"))]
+    MissingQuota,
+    #[snafu(display("the quota of {} must set namespaceQuota and/or spaceQuota", path))]
+    EmptyQuota {
+        path: String,
+    },
+    #[snafu(display("the allowSnapshot and disallowSnapshot actions require paths"))]
+    MissingPaths,
+    #[snafu(display(
+        "datanodeStorage.failedVolumesTolerated {} must be less than the number of volumes ({})",
+        failed_volumes_tolerated,
+        volumes
+    ))]
+    InvalidFailedVolumesTolerated {
+        failed_volumes_tolerated: i32,
+        volumes: i32,
+    },
+    #[snafu(display(
+        "failoverController.parentZnode {:?} must be a relative ZNode path, such as hadoop-ha",
+        parent_znode
+    ))]
+    InvalidParentZnode {
+        parent_znode: String,
+    },
+    #[snafu(display(
+        "datanode group name {:?} may only contain lowercase letters, digits, and inner dashes",
+        group
+    ))]
+    InvalidDatanodeGroupName {
+        group: String,
+    },
+    #[snafu(display(
+        "journalnodeStorage.editsSubdirectory {:?} must be a relative path without . or .. components",
+        subdirectory
+    ))]
+    InvalidJournalEditsSubdirectory {
+        subdirectory: String,
+    },
+    #[snafu(display(
+        "{}.service.headless can't be disabled, since the {}s are addressed by the DNS names of their pods",
+        role,
+        role
+    ))]
+    NonHeadlessService {
+        role: String,
+    },
+    #[snafu(display(
+        "datanodeMaintenance pod {:?} is not one of the cluster's datanodes",
+        pod
+    ))]
+    InvalidDatanodeMaintenance {
+        pod: String,
+    },
+    #[snafu(display("failoverController.fencingMethods[{}] {}", index, reason))]
+    InvalidFencingMethod {
+        index: usize,
+        reason: String,
+    },
+    #[snafu(display(
+        "failoverController.resources memory {:?} must be a quantity of bytes, such as 256Mi",
+        memory
+    ))]
+    InvalidFailoverControllerMemory {
+        memory: String,
+    },
+    #[snafu(display("datanodeAutoscaling {}", reason))]
+    InvalidDatanodeAutoscaling {
+        reason: String,
+    },
+    #[snafu(display("checkpoint.{} must be positive, but is {}", field, value))]
+    InvalidCheckpointConfig {
+        field: &'static str,
+        value: i64,
+    },
+    #[snafu(display("tracing.samplingRatio {} must be between 0 and 1", ratio))]
+    InvalidTracingSamplingRatio {
+        ratio: f64,
+    },
+    #[snafu(display(
+        "namenodeRpc.serviceHandlerCount requires ports.namenodeServiceRpc to be set"
+    ))]
+    ServiceHandlersWithoutServiceRpc,
+    #[snafu(display("tls.secretClass and tls.certManager may not be combined"))]
+    ConflictingTlsProviders,
+    #[snafu(display(
+        "networkIsolation requires hostNetwork: false, since NetworkPolicies don't apply to pods on the host network"
+    ))]
+    NetworkIsolationWithHostNetwork,
+    #[snafu(display("failed to render objects as YAML"))]
+    RenderYaml {
+        source: serde_yaml::Error,
+    },
+}
+
+/// An [`HdfsCluster`], along with everything that its objects' names and addresses are derived from
+pub struct ClusterContext<'a> {
+    pub hdfs: &'a HdfsCluster,
+    pub name: String,
+    pub namespace: String,
+    /// DNS domain of the Kubernetes cluster, such as `cluster.local`
+    pub cluster_domain: String,
+    /// Where the operator is configured to pull images from
+    pub image_options: ImageOptions,
+    pub ports: HdfsPorts,
+    pub owner_ref: OwnerReference,
+    /// Hashes of the contents of each role's Kerberos `Secret`, see [`SECRET_HASH_ANNOTATION`]
+    pub secret_hashes: BTreeMap<String, String>,
+    /// Hashes of the contents of each role's cert-manager TLS `Secret`, see [`TLS_SECRET_HASH_ANNOTATION`]
+    pub tls_secret_hashes: BTreeMap<String, String>,
+    /// The PEM-encoded CA that signed the daemons' TLS certificates, which the operator trusts when it queries their
+    /// web UIs, if TLS is enabled and the CA could be found
+    pub tls_ca: Option<Vec<u8>>,
+    /// The datanode groups that are being scaled down by their role, see [`datanode_roles`]
+    pub scale_downs: BTreeMap<String, ScaleDown>,
+    /// IPs of the datanode pods by pod name, which the namenodes' hosts file lists, see [`datanode_hosts`]
+    pub datanode_ips: BTreeMap<String, String>,
+    /// Values of the `${env.NAME}` placeholders that are substituted when the configuration files are rendered, such as
+    /// the ZooKeeper quorum
+    pub config_variables: BTreeMap<String, String>,
+}
+
+/// A datanode group that has more pods than replicas
+///
+/// The surplus datanodes are decommissioned before their pods are deleted, so that their blocks are re-replicated
+/// to the remaining datanodes first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ScaleDown {
+    /// Number of pods that the group still has
+    pub pods: i32,
+    /// Whether the namenodes have picked up the exclusion of the surplus datanodes
+    pub excluded: bool,
+    /// Whether the surplus datanodes have been decommissioned, so that their pods can be deleted
+    pub decommissioned: bool,
+}
+
+impl<'a> ClusterContext<'a> {
+    pub fn new(hdfs: &'a HdfsCluster, cluster_domain: &str) -> Result<Self, Error> {
+        let namespace = hdfs
+            .metadata
+            .namespace
+            .clone()
+            .with_context(|| ObjectHasNoNamespace {
+                obj_ref: ObjectRef::from_obj(hdfs).erase(),
+            })?;
+        Ok(Self {
+            hdfs,
+            name: hdfs.metadata.name.clone().unwrap(),
+            namespace,
+            cluster_domain: cluster_domain.to_string(),
+            image_options: ImageOptions::default(),
+            ports: HdfsPorts::new(&hdfs.spec.ports, hdfs.spec.tls.is_some()),
+            owner_ref: controller_reference_to_obj(hdfs),
+            secret_hashes: BTreeMap::new(),
+            tls_secret_hashes: BTreeMap::new(),
+            tls_ca: None,
+            scale_downs: BTreeMap::new(),
+            datanode_ips: BTreeMap::new(),
+            config_variables: BTreeMap::new(),
+        })
+    }
+
+    /// Name of the `ConfigMap` that holds the configuration of the HDFS daemons of `role`
+    pub fn config_name(&self, role: &str) -> String {
+        format!("{}-config", self.role_name(role))
+    }
+
+    /// Name of the discovery `ConfigMap`, which holds the configuration that clients need
+    pub fn discovery_name(&self) -> String {
+        self.name.clone()
+    }
+
+    pub fn service_account_name(&self) -> String {
+        self.hdfs
+            .spec
+            .service_account
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("{}-serviceaccount", self.name))
+    }
+
+    /// Name of the `RoleBinding` that grants `serviceAccount.clusterRole`
+    ///
+    /// Derived from the cluster rather than the `ServiceAccount`, since an existing account's name may already be
+    /// taken by someone else's `RoleBinding`.
+    pub fn role_binding_name(&self) -> String {
+        format!("{}-serviceaccount", self.name)
+    }
+
+    pub fn network_policy_name(&self) -> String {
+        format!("{}-network-isolation", self.name)
+    }
+
+    /// Name of the `Service` and `StatefulSet` of `role`
+    pub fn role_name(&self, role: &str) -> String {
+        format!("{}-{}", self.name, role)
+    }
+
+    /// Name of the `Secret` with the keytabs of `role`, which is provided by the user
+    pub fn kerberos_secret_name(&self, role: &str) -> String {
+        format!("{}-kerberos", self.role_name(role))
+    }
+
+    /// Name of the `Secret` that cert-manager stores the certificate of `role` in
+    pub fn tls_secret_name(&self, role: &str) -> String {
+        format!("{}-tls", self.role_name(role))
+    }
+
+    /// Name of the `Secret` with the password of the keystores that are converted from the TLS certificates
+    pub fn keystore_secret_name(&self) -> String {
+        format!("{}-keystore", self.name)
+    }
+
+    fn role_fqdn(&self, role: &str) -> String {
+        format!(
+            "{}.{}.svc.{}",
+            self.role_name(role),
+            self.namespace,
+            self.cluster_domain
+        )
+    }
+
+    pub fn pod_name(&self, role: &str, i: i32) -> String {
+        format!("{}-{}", self.role_name(role), i)
+    }
+
+    pub fn pod_fqdn(&self, role: &str, i: i32) -> String {
+        format!("{}.{}", self.pod_name(role, i), self.role_fqdn(role))
+    }
+
+    /// Labels of all pods of the cluster
+    fn pod_labels(&self) -> BTreeMap<String, String> {
+        BTreeMap::from([("app".to_string(), "hdfs".to_string())])
+    }
+
+    /// Labels that select the pods of `role`
+    ///
+    /// These predate the recommended labels, and are kept since the selectors of `StatefulSet`s are immutable.
+    fn role_pod_labels(&self, role: &str) -> BTreeMap<String, String> {
+        let mut labels = self.pod_labels();
+        labels.insert("role".to_string(), role.to_string());
+        labels
+    }
+
+    /// Labels that select the pods of `role` in this cluster only, so that the budgets of clusters don't overlap
+    fn role_pod_disruption_labels(&self, role: &str) -> BTreeMap<String, String> {
+        let mut labels = self.role_pod_labels(role);
+        labels.insert(APP_INSTANCE_LABEL.to_string(), self.name.clone());
+        labels
+    }
+
+    /// Label selector of the pods of `role`, for listing them
+    pub fn role_pod_selector(&self, role: &str) -> String {
+        self.role_pod_labels(role)
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Labels that select the pods of all roles, but none of the other clusters in the namespace
+    fn cluster_pod_labels(&self) -> BTreeMap<String, String> {
+        BTreeMap::from([
+            (APP_NAME_LABEL.to_string(), APP_NAME.to_string()),
+            (APP_INSTANCE_LABEL.to_string(), self.name.clone()),
+        ])
+    }
+
+    /// Label selector of the pods of all roles, which also matches all other objects of the cluster
+    pub fn cluster_pod_selector(&self) -> String {
+        format!(
+            "{}={},{}={}",
+            APP_NAME_LABEL, APP_NAME, APP_INSTANCE_LABEL, self.name
+        )
+    }
+
+    /// Label selector of the `datanodeReplicas` datanodes, excluding the ones of the `datanodeGroups`
+    pub fn datanode_pod_selector(&self) -> String {
+        format!(
+            "{},!{}",
+            self.role_pod_selector("datanode"),
+            DATANODE_GROUP_LABEL
+        )
+    }
+
+    /// Recommended labels of the objects of `role`, or of the whole cluster
+    fn recommended_labels(&self, role: Option<&str>) -> BTreeMap<String, String> {
+        let version = self.hdfs.spec.image.tag();
+        match role {
+            Some(role) => recommended_labels(self.hdfs, APP_NAME, version, role, ROLE_GROUP),
+            None => cluster_labels(self.hdfs, APP_NAME, version),
+        }
+    }
+
+    /// The user's customizations for `role`
+    fn role_config(&self, role: &str) -> Option<&HdfsRoleConfig> {
+        let spec = &self.hdfs.spec;
+        match role {
+            "namenode" => Some(&spec.namenode),
+            "datanode" => Some(&spec.datanode),
+            "journalnode" => Some(&spec.journalnode),
+            _ => None,
+        }
+    }
+
+    /// User-supplied labels of the objects of `role`, or of the whole cluster
+    fn user_labels(&self, role: Option<&str>) -> BTreeMap<String, String> {
+        let mut labels = self.hdfs.spec.labels.clone();
+        if let Some(role_config) = role.and_then(|role| self.role_config(role)) {
+            labels.extend(role_config.labels.clone());
+        }
+        labels
+    }
+
+    /// User-supplied annotations of the objects of `role`, or of the whole cluster
+    fn user_annotations(&self, role: Option<&str>) -> Option<BTreeMap<String, String>> {
+        let mut annotations = self.hdfs.spec.annotations.clone();
+        if let Some(role_config) = role.and_then(|role| self.role_config(role)) {
+            annotations.extend(role_config.annotations.clone());
+        }
+        Some(annotations).filter(|annotations| !annotations.is_empty())
+    }
+
+    /// The cluster's restart annotations that apply to `role`, see [`RESTART_ANNOTATION`]
+    fn restart_annotations(&self, role: &str) -> BTreeMap<String, String> {
+        let role_annotation = format!("{}-{}", RESTART_ANNOTATION, role);
+        self.hdfs
+            .metadata
+            .annotations
+            .iter()
+            .flatten()
+            .filter(|(key, _)| **key == RESTART_ANNOTATION || **key == role_annotation)
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    fn metadata(&self, name: String, role: Option<&str>) -> ObjectMeta {
+        let mut labels = self.user_labels(role);
+        labels.extend(self.recommended_labels(role));
+        labels.extend(managed_by_labels(&crate::OPERATOR_VERSION));
+        ObjectMeta {
+            annotations: self.user_annotations(role),
+            labels: Some(labels),
+            owner_references: Some(vec![self.owner_ref.clone()]),
+            name: Some(name),
+            namespace: Some(self.namespace.clone()),
+            ..ObjectMeta::default()
+        }
+    }
+}
+
+/// The user's customizations for each role, followed by the ones of the datanode groups
+fn role_configs<'a>(cluster: &ClusterContext<'a>) -> Vec<(String, &'a HdfsRoleConfig)> {
+    let spec = &cluster.hdfs.spec;
+    [
+        ("journalnode".to_string(), &spec.journalnode),
+        ("namenode".to_string(), &spec.namenode),
+        ("datanode".to_string(), &spec.datanode),
+    ]
+    .into_iter()
+    .chain(
+        spec.datanode_groups
+            .iter()
+            .map(|(group, config)| (datanode_group_role(group), &config.pods)),
+    )
+    .collect()
+}
+
+/// Rejects settings that HDFS would fail to start with
+pub fn validate(cluster: &ClusterContext) -> Result<(), Error> {
+    let spec = &cluster.hdfs.spec;
+    let datanode_replicas: i32 = datanode_roles(cluster)
+        .iter()
+        .map(|(_, replicas)| replicas)
+        .sum();
+    for group in spec.datanode_groups.keys() {
+        // Ends up in the names of the group's objects and pods
+        ensure!(
+            !group.is_empty()
+                && !group.starts_with('-')
+                && !group.ends_with('-')
+                && group
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-'),
+            InvalidDatanodeGroupName { group }
+        );
+    }
+    for role in ["namenode", "journalnode"] {
+        ensure!(
+            cluster
+                .role_config(role)
+                .and_then(|config| config.service.headless)
+                != Some(false),
+            NonHeadlessService { role }
+        );
+    }
+    for maintenance in &spec.datanode_maintenance {
+        let pod = &maintenance.pod;
+        ensure!(
+            datanode_roles(cluster)
+                .iter()
+                .any(|(role, replicas)| (0..*replicas).any(|i| &cluster.pod_name(role, i) == pod)),
+            InvalidDatanodeMaintenance { pod }
+        );
+    }
+    for (role, config) in role_configs(cluster) {
+        if let Some(file) = config
+            .config_overrides
+            .keys()
+            .chain(config.property_attributes.keys())
+            .find(|file| !OVERRIDABLE_FILES.contains(&file.as_str()))
+        {
+            return InvalidConfigOverrides { role, file }.fail();
+        }
+    }
+    if let Some(max_unavailable) = spec
+        .datanode_rollout
+        .as_ref()
+        .and_then(|rollout| rollout.max_unavailable.as_ref())
+    {
+        ensure!(
+            rollout::max_unavailable(Some(max_unavailable), datanode_replicas).is_some(),
+            InvalidMaxUnavailable {
+                max_unavailable: max_unavailable.clone()
+            }
+        );
+    }
+    if let Some(failed_volumes_tolerated) = spec.datanode_storage.failed_volumes_tolerated {
+        let volumes = std::iter::once(None)
+            .chain(spec.datanode_groups.values().map(Some))
+            .map(|group| datanode_volumes(cluster, group))
+            .min()
+            .unwrap_or(1);
+        // Otherwise datanodes refuse to start
+        ensure!(
+            (0..volumes).contains(&failed_volumes_tolerated),
+            InvalidFailedVolumesTolerated {
+                failed_volumes_tolerated,
+                volumes,
+            }
+        );
+    }
+    if let Some(parent_znode) = &spec.failover_controller.parent_znode {
+        ensure!(
+            parent_znode
+                .split('/')
+                .all(|segment| !matches!(segment, "" | "." | "..")),
+            InvalidParentZnode { parent_znode }
+        );
+    }
+    for (index, method) in spec.failover_controller.fencing_methods.iter().enumerate() {
+        // Both end up in `dfs.ha.fencing.methods`, which has no way to escape them
+        let (field, value) = match method {
+            FencingMethod::Sshfence {
+                user: Some(user), ..
+            } => ("user", user),
+            FencingMethod::Sshfence { user: None, .. } => continue,
+            FencingMethod::Shell { script, .. } => ("script", script),
+        };
+        ensure!(
+            !value.is_empty()
+                && value
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c)),
+            InvalidFencingMethod {
+                index,
+                reason: format!(
+                    "{} {:?} may only contain letters, digits, and ._-",
+                    field, value
+                ),
+            }
+        );
+    }
+    if let Some(memory) = container_memory(&spec.failover_controller.resources()) {
+        ensure!(
+            parse_memory_quantity(&memory.0).is_some(),
+            InvalidFailoverControllerMemory { memory: &memory.0 }
+        );
+    }
+    ensure!(
+        spec.namenode_rpc.service_handler_count.is_none()
+            || cluster.ports.namenode_service_rpc.is_some(),
+        ServiceHandlersWithoutServiceRpc
+    );
+    if let Some(autoscaling) = &spec.datanode_autoscaling {
+        let min_replicas = autoscaling.min_replicas.unwrap_or(1);
+        ensure!(
+            (1..=autoscaling.max_replicas).contains(&min_replicas),
+            InvalidDatanodeAutoscaling {
+                reason: format!(
+                    "minReplicas {} must be between 1 and maxReplicas ({})",
+                    min_replicas, autoscaling.max_replicas
+                ),
+            }
+        );
+        let threshold = autoscaling
+            .used_percent_threshold
+            .unwrap_or(DEFAULT_USED_PERCENT_THRESHOLD);
+        ensure!(
+            threshold > 0.0 && threshold < 100.0,
+            InvalidDatanodeAutoscaling {
+                reason: format!(
+                    "usedPercentThreshold {} must be between 0 and 100",
+                    threshold
+                ),
+            }
+        );
+    }
+    for (field, value) in [
+        ("period", spec.checkpoint.period),
+        ("transactions", spec.checkpoint.transactions),
+    ] {
+        if let Some(value) = value {
+            ensure!(value > 0, InvalidCheckpointConfig { field, value });
+        }
+    }
+    if let Some(ratio) = spec
+        .tracing
+        .as_ref()
+        .and_then(|tracing| tracing.sampling_ratio)
+    {
+        ensure!(
+            (0.0..=1.0).contains(&ratio),
+            InvalidTracingSamplingRatio { ratio }
+        );
+    }
+    if let Some(subdirectory) = &spec.journalnode_storage.edits_subdirectory {
+        ensure!(
+            !subdirectory.starts_with('/')
+                && subdirectory
+                    .trim_end_matches('/')
+                    .split('/')
+                    .all(|component| !matches!(component, "" | "." | "..")),
+            InvalidJournalEditsSubdirectory { subdirectory }
+        );
+    }
+    if let Some(tls) = &spec.tls {
+        ensure!(
+            tls.secret_class.is_none() || tls.cert_manager.is_none(),
+            ConflictingTlsProviders
+        );
+    }
+    ensure!(
+        spec.network_isolation.is_none() || !host_network_enabled(cluster),
+        NetworkIsolationWithHostNetwork
+    );
+    if let Some(replication) = spec.dfs_replication {
+        ensure!(
+            (1..=datanode_replicas).contains(&replication),
+            InvalidReplication {
+                replication,
+                datanodes: datanode_replicas,
+            }
+        );
+    }
+    if let Some(replication_min) = spec.dfs_replication_min {
+        // Hadoop defaults dfs.replication to 3
+        let replication = spec.dfs_replication.unwrap_or(3);
+        ensure!(
+            (1..=replication).contains(&replication_min),
+            InvalidReplicationMin {
+                replication_min,
+                replication,
+            }
+        );
+    }
+    Ok(())
+}
+
+/// Drops all unset properties from a list of optional Hadoop properties
+fn optional_properties<'a>(
+    props: impl IntoIterator<Item = (&'a str, Option<String>)>,
+) -> Vec<(String, String)> {
+    props
+        .into_iter()
+        .filter_map(|(k, v)| Some((k.to_string(), v?)))
+        .collect()
+}
+
+/// Kerberos service name of the principal that `role` authenticates as
+fn kerberos_service(role: &str) -> &'static str {
+    match role {
+        "journalnode" => "jn",
+        "datanode" => "dn",
+        _ => "nn",
+    }
+}
+
+/// Whether the pods use the network of their nodes, see `hostNetwork`
+fn host_network_enabled(cluster: &ClusterContext) -> bool {
+    cluster.hdfs.spec.host_network.unwrap_or(true)
+}
+
+/// Whether the namenodes and datanodes serve WebHDFS, see `webhdfs`
+fn webhdfs_enabled(cluster: &ClusterContext) -> bool {
+    cluster.hdfs.spec.webhdfs.enabled.unwrap_or(false)
+}
+
+/// Kerberos service names of the principals in the keytab of `role`
+///
+/// The namenodes also authenticate WebHDFS clients with SPNEGO, as `HTTP`.
+fn kerberos_service_names(cluster: &ClusterContext, role: &str) -> String {
+    let service = kerberos_service(role);
+    if role == "namenode" && webhdfs_enabled(cluster) {
+        format!("{},HTTP", service)
+    } else {
+        service.to_string()
+    }
+}
+
+/// Path of the keytab of the Kerberos `service` (such as `nn`)
+fn keytab_path(cluster: &ClusterContext, service: &str) -> String {
+    if cluster.hdfs.spec.kerberos.secret_class.is_some() {
+        // secret-operator provisions a single keytab per pod, with only the pod's own principal
+        "/kerberos/keytab".to_string()
+    } else {
+        format!("/kerberos/{}.service.keytab", service)
+    }
+}
+
+fn kerberos_realm<'a>(cluster: &'a ClusterContext) -> &'a str {
+    cluster
+        .hdfs
+        .spec
+        .kerberos
+        .realm
+        .as_deref()
+        .unwrap_or(DEFAULT_KERBEROS_REALM)
+}
+
+/// `core-site.xml` properties that clients need to talk to the cluster
+fn core_site_client_config(cluster: &ClusterContext) -> Vec<(String, String)> {
+    let spec = &cluster.hdfs.spec;
+    [
+        (
+            "fs.defaultFS".to_string(),
+            format!("hdfs://{}/", cluster.name),
+        ),
+        (
+            "hadoop.security.authentication".to_string(),
+            "kerberos".to_string(),
+        ),
+    ]
+    .into_iter()
+    .chain(optional_properties([
+        (
+            "fs.trash.interval",
+            spec.trash.interval.map(|i| i.to_string()),
+        ),
+        (
+            "fs.trash.checkpoint.interval",
+            spec.trash.checkpoint_interval.map(|i| i.to_string()),
+        ),
+    ]))
+    .collect()
+}
+
+/// The address of each namenode's `port`, such as its RPC port
+fn namenode_addresses(cluster: &ClusterContext, port: i32) -> Vec<String> {
+    (0..cluster.hdfs.spec.namenode_replicas.unwrap_or(1))
+        .map(|i| format!("{}:{}", cluster.pod_fqdn("namenode", i), port))
+        .collect()
+}
+
+/// The namenodes' `dfs.namenode.shared.edits.dir`, which lists all journalnodes
+fn journalnode_quorum(cluster: &ClusterContext) -> String {
+    format!(
+        "qjournal://{}/{}",
+        (0..cluster.hdfs.spec.journalnode_replicas.unwrap_or(1))
+            .map(|i| format!(
+                "{}:{}",
+                cluster.pod_fqdn("journalnode", i),
+                cluster.ports.journalnode_rpc
+            ))
+            .collect::<Vec<_>>()
+            .join(";"),
+        cluster.name
+    )
+}
+
+/// The addresses that clients and tooling connect to, as published in the cluster's status
+///
+/// The ZooKeeper quorum is only known to the `zookeeperConfigMapName`, so it is left to the controller.
+pub fn build_endpoints(cluster: &ClusterContext) -> HdfsEndpoints {
+    let ports = &cluster.ports;
+    let webhdfs = match (webhdfs_enabled(cluster), ports.namenode_https) {
+        (false, _) => Vec::new(),
+        (true, Some(https)) => namenode_addresses(cluster, https)
+            .into_iter()
+            .map(|address| format!("swebhdfs://{}", address))
+            .collect(),
+        (true, None) => namenode_addresses(cluster, ports.namenode_http)
+            .into_iter()
+            .map(|address| format!("webhdfs://{}", address))
+            .collect(),
+    };
+    HdfsEndpoints {
+        default_fs: format!("hdfs://{}/", cluster.name),
+        namenode_rpc: namenode_addresses(cluster, ports.namenode_rpc),
+        namenode_http: namenode_addresses(cluster, ports.namenode_web()),
+        webhdfs,
+        journalnode_quorum: journalnode_quorum(cluster),
+        zookeeper_quorum: None,
+    }
+}
+
+/// `hdfs-site.xml` properties that clients need to talk to the cluster
+fn hdfs_site_client_config(cluster: &ClusterContext) -> Vec<(String, String)> {
+    let spec = &cluster.hdfs.spec;
+    let ports = &cluster.ports;
+    let nameservice_id = &cluster.name;
+    let namenode_replicas = spec.namenode_replicas.unwrap_or(1);
+    [
+        ("dfs.nameservices".to_string(), nameservice_id.clone()),
+        (
+            format!("dfs.ha.namenodes.{}", nameservice_id),
+            (0..namenode_replicas)
+                .map(|i| format!("name-{}", i))
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+        (
+            format!("dfs.client.failover.proxy.provider.{}", nameservice_id),
+            "org.apache.hadoop.hdfs.server.namenode.ha.ConfiguredFailoverProxyProvider".to_string(),
+        ),
+        (
+            "dfs.namenode.kerberos.principal".to_string(),
+            format!(
+                "nn/{}@{}",
+                cluster.role_fqdn("namenode"),
+                kerberos_realm(cluster)
+            ),
+        ),
+    ]
+    .into_iter()
+    .chain(
+        namenode_addresses(cluster, ports.namenode_rpc)
+            .into_iter()
+            .zip(namenode_addresses(cluster, ports.namenode_http))
+            .enumerate()
+            .flat_map(|(i, (rpc, http))| {
+                [
+                    (
+                        format!("dfs.namenode.rpc-address.{}.name-{}", nameservice_id, i),
+                        rpc,
+                    ),
+                    (
+                        format!("dfs.namenode.http-address.{}.name-{}", nameservice_id, i),
+                        http,
+                    ),
+                ]
+            }),
+    )
+    .chain(ports.namenode_https.into_iter().flat_map(|port| {
+        namenode_addresses(cluster, port)
+            .into_iter()
+            .enumerate()
+            .map(move |(i, https)| {
+                (
+                    format!("dfs.namenode.https-address.{}.name-{}", nameservice_id, i),
+                    https,
+                )
+            })
+    }))
+    .chain(optional_properties([
+        // Published to clients, so that token renewers know how long the tokens stay valid
+        (
+            "dfs.namenode.delegation.token.renew-interval",
+            spec.delegation_tokens.renew_interval.map(|i| i.to_string()),
+        ),
+        (
+            "dfs.namenode.delegation.token.max-lifetime",
+            spec.delegation_tokens.max_lifetime.map(|l| l.to_string()),
+        ),
+        (
+            "dfs.namenode.delegation.key.update-interval",
+            spec.delegation_tokens
+                .key_update_interval
+                .map(|i| i.to_string()),
+        ),
+    ]))
+    .collect()
+}
+
+/// The role that the objects and pods of the datanode group `group` are named after, such as `datanode-ssd`
+pub fn datanode_group_role(group: &str) -> String {
+    format!("datanode-{}", group)
+}
+
+/// Each group of datanodes with its number of replicas, starting with the `datanodeReplicas` datanodes
+///
+/// The groups are identified by their role, see [`datanode_group_role`].
+pub fn datanode_roles(cluster: &ClusterContext) -> Vec<(String, i32)> {
+    let spec = &cluster.hdfs.spec;
+    std::iter::once(("datanode".to_string(), spec.datanode_replicas.unwrap_or(1)))
+        .chain(
+            spec.datanode_groups
+                .iter()
+                .map(|(group, config)| (datanode_group_role(group), config.replicas.unwrap_or(1))),
+        )
+        .collect()
+}
+
+/// The `StatefulSet`s of each role by the role's pod label, in the order that they are stopped in by `orderedTeardown`
+///
+/// This is the reverse of the order that the roles are started in, so that no role loses the ones it depends on.
+pub fn teardown_order(cluster: &ClusterContext) -> Vec<(&'static str, Vec<String>)> {
+    let datanodes = datanode_roles(cluster)
+        .into_iter()
+        .map(|(role, _)| cluster.role_name(&role))
+        .collect();
+    vec![
+        ("datanode", datanodes),
+        ("namenode", vec![cluster.role_name("namenode")]),
+        ("journalnode", vec![cluster.role_name("journalnode")]),
+    ]
+}
+
+/// The surplus datanodes of the groups that are being scaled down, by their role and ordinal
+///
+/// These are marked as decommissioned in the hosts file, which makes the namenodes decommission them once it is
+/// refreshed, see [`datanode_hosts`].
+pub fn decommissioning_datanodes(cluster: &ClusterContext) -> Vec<(String, i32)> {
+    datanode_roles(cluster)
+        .into_iter()
+        .flat_map(|(role, replicas)| {
+            let pods = cluster
+                .scale_downs
+                .get(&role)
+                .map_or(replicas, |scale_down| scale_down.pods);
+            (replicas..pods).map(move |i| (role.clone(), i))
+        })
+        .collect()
+}
+
+/// The datanode pods that `datanodeMaintenance` puts into maintenance, with the end of their maintenance in
+/// milliseconds since the epoch, if any
+///
+/// Datanodes that are being decommissioned are left out, since decommissioning them takes precedence.
+pub fn maintenance_datanodes(cluster: &ClusterContext) -> Vec<(String, Option<i64>)> {
+    let decommissioning = decommissioning_datanodes(cluster)
+        .into_iter()
+        .map(|(role, i)| cluster.pod_name(&role, i))
+        .collect::<Vec<_>>();
+    cluster
+        .hdfs
+        .spec
+        .datanode_maintenance
+        .iter()
+        .filter(|maintenance| !decommissioning.contains(&maintenance.pod))
+        .map(|maintenance| {
+            (
+                maintenance.pod.clone(),
+                maintenance
+                    .until
+                    .as_ref()
+                    .map(|until| until.0.timestamp_millis()),
+            )
+        })
+        .collect()
+}
+
+/// Entries of the namenodes' combined hosts file (`dfs.hosts`), the admin state of each datanode
+///
+/// Empty unless a datanode is decommissioned or in maintenance, since the namenodes reject all datanodes that a
+/// non-empty file doesn't list. Datanodes are listed by the IPs of their pods, which are only known once they have
+/// been scheduled, see [`ClusterContext::datanode_ips`].
+pub fn datanode_hosts(cluster: &ClusterContext) -> Vec<serde_json::Value> {
+    let decommissioning = decommissioning_datanodes(cluster);
+    let maintenance = maintenance_datanodes(cluster);
+    if decommissioning.is_empty() && maintenance.is_empty() {
+        return Vec::new();
+    }
+    datanode_roles(cluster)
+        .into_iter()
+        .flat_map(|(role, replicas)| {
+            let pods = cluster
+                .scale_downs
+                .get(&role)
+                .map_or(replicas, |scale_down| scale_down.pods);
+            (0..pods).map(move |i| (role.clone(), i))
+        })
+        .filter_map(|(role, i)| {
+            let pod = cluster.pod_name(&role, i);
+            let mut host = serde_json::json!({
+                "hostName": cluster.datanode_ips.get(&pod)?,
+                "adminState": "NORMAL",
+            });
+            if decommissioning.contains(&(role, i)) {
+                host["adminState"] = "DECOMMISSIONED".into();
+            } else if let Some((_, until)) = maintenance.iter().find(|(name, _)| name == &pod) {
+                host["adminState"] = "IN_MAINTENANCE".into();
+                if let Some(until) = until {
+                    host["maintenanceExpireTimeInMS"] = (*until).into();
+                }
+            }
+            Some(host)
+        })
+        .collect()
+}
+
+/// Number of `datanodeReplicas` datanodes that `datanodeAutoscaling` calls for, if enabled, while `used_percent` of the
+/// file system's capacity is used
+///
+/// Assumes that all datanodes have about the same capacity, so that the utilization drops in proportion to the
+/// number of datanodes that are added. Never fewer than the current replicas, scaling down is left to the user.
+pub fn autoscaled_datanode_replicas(cluster: &ClusterContext, used_percent: f64) -> Option<i32> {
+    let autoscaling = cluster.hdfs.spec.datanode_autoscaling.as_ref()?;
+    let replicas = cluster.hdfs.spec.datanode_replicas.unwrap_or(1);
+    let threshold = autoscaling
+        .used_percent_threshold
+        .unwrap_or(DEFAULT_USED_PERCENT_THRESHOLD);
+    let mut desired = replicas;
+    if used_percent >= threshold {
+        let datanodes: i32 = datanode_roles(cluster)
+            .iter()
+            .map(|(_, replicas)| replicas)
+            .sum();
+        let needed = (f64::from(datanodes) * used_percent / threshold).ceil() as i32;
+        desired += (needed - datanodes).max(1);
+    }
+    Some(
+        desired
+            .max(autoscaling.min_replicas.unwrap_or(1))
+            .min(autoscaling.max_replicas)
+            .max(replicas),
+    )
+}
+
+/// Why the active namenode's last checkpoint is overdue, if it was taken `seconds_since` ago and `transactions_since`
+/// have happened since
+///
+/// Checkpoints are only overdue after twice the `checkpoint.period` or `checkpoint.transactions`, which leaves the
+/// standby namenode time for slow checkpoints. Without new transactions there is nothing to checkpoint.
+pub fn overdue_checkpoint(
+    cluster: &ClusterContext,
+    seconds_since: i64,
+    transactions_since: i64,
+) -> Option<String> {
+    let checkpoint = &cluster.hdfs.spec.checkpoint;
+    let period = checkpoint.period.unwrap_or(DEFAULT_CHECKPOINT_PERIOD);
+    let transactions = checkpoint
+        .transactions
+        .unwrap_or(DEFAULT_CHECKPOINT_TRANSACTIONS);
+    (transactions_since > 0
+        && (seconds_since > 2 * period || transactions_since > 2 * transactions))
+        .then(|| {
+            format!(
+                "The last checkpoint was taken {}s ago, and {} transactions have happened since",
+                seconds_since, transactions_since
+            )
+        })
+}
+
+/// Number of volumes that each datanode of `group` (or of the `datanodeReplicas` datanodes) stores blocks on
+fn datanode_volumes(cluster: &ClusterContext, group: Option<&DatanodeGroupConfig>) -> i32 {
+    group
+        .and_then(|group| group.volumes)
+        .or(cluster.hdfs.spec.datanode_storage.volumes)
+        .unwrap_or(1)
+        .max(1)
+}
+
+/// The journalnodes' `dfs.journalnode.edits.dir`, within their `data` volume
+fn journal_edits_dir(cluster: &ClusterContext) -> String {
+    match &cluster.hdfs.spec.journalnode_storage.edits_subdirectory {
+        Some(subdirectory) => format!("/data/{}", subdirectory.trim_end_matches('/')),
+        None => "/data".to_string(),
+    }
+}
+
+/// Mount paths of the datanodes' volumes
+///
+/// The first volume is the `data` volume that all roles have, so that single-volume datanodes keep their blocks.
+fn datanode_data_dirs(
+    cluster: &ClusterContext,
+    group: Option<&DatanodeGroupConfig>,
+) -> Vec<String> {
+    (0..datanode_volumes(cluster, group))
+        .map(|i| match i {
+            0 => "/data".to_string(),
+            _ => format!("/data-{}", i),
+        })
+        .collect()
+}
+
+/// The datanodes' `dfs.datanode.data.dir`, with each volume tagged with the storage type of `group`
+fn datanode_data_dir_config(
+    cluster: &ClusterContext,
+    group: Option<&DatanodeGroupConfig>,
+) -> String {
+    let storage_type = group.and_then(|group| group.storage_type).or(cluster
+        .hdfs
+        .spec
+        .datanode_storage
+        .storage_type);
+    datanode_data_dirs(cluster, group)
+        .into_iter()
+        .map(|dir| match storage_type {
+            Some(storage_type) => format!("[{}]{}", storage_type, dir),
+            None => dir,
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// The role that a daemon property only concerns, or `None` if all daemons need it
+fn property_role(name: &str) -> Option<&'static str> {
+    match name {
+        "dfs.namenode.name.dir"
+        | "dfs.namenode.hosts.provider.classname"
+        | "dfs.hosts"
+        | "dfs.namenode.shared.edits.dir"
+        | "dfs.ha.nn.not-become-active-in-safemode"
+        | "dfs.ha.automatic-failover.enabled"
+        | "dfs.namenode.handler.count"
+        | "dfs.namenode.service.handler.count"
+        | "dfs.namenode.replication.min"
+        | "dfs.namenode.num.extra.edits.retained"
+        | "dfs.namenode.checkpoint.period"
+        | "dfs.namenode.checkpoint.txns"
+        | "dfs.namenode.snapshot.capture.openfiles"
+        | "dfs.namenode.snapshot.max.limit"
+        | "dfs.namenode.max.extra.edits.segments.retained"
+        | "dfs.namenode.inode.attributes.provider.class" => Some("namenode"),
+        // Read by the ZKFCs, which run in the namenode pods
+        _ if name.starts_with("ha.zookeeper.") || name.starts_with("dfs.ha.fencing.") => {
+            Some("namenode")
+        }
+        "dfs.datanode.data.dir"
+        | "dfs.datanode.address"
+        | "dfs.datanode.ipc.address"
+        | "dfs.datanode.http.address"
+        | "dfs.datanode.https.address"
+        | "dfs.datanode.failed.volumes.tolerated"
+        | "dfs.datanode.du.reserved" => Some("datanode"),
+        _ if name.starts_with("dfs.datanode.disk.check.") => Some("datanode"),
+        "dfs.journalnode.edits.dir"
+        | "dfs.journalnode.rpc-address"
+        | "dfs.journalnode.http-address"
+        | "dfs.journalnode.https-address" => Some("journalnode"),
+        _ => None,
+    }
+}
+
+/// `hdfs-site.xml` properties of the HDFS daemons of `role`
+///
+/// Properties that only concern another role are left out, so that changing them doesn't touch this role's
+/// `ConfigMap`, see [`property_role`].
+fn hdfs_site_config(cluster: &ClusterContext, role: &str) -> Vec<(String, String)> {
+    let spec = &cluster.hdfs.spec;
+    let ports = &cluster.ports;
+    let namenode_fqdn = cluster.role_fqdn("namenode");
+    let kerberos_realm = kerberos_realm(cluster);
+    hdfs_site_client_config(cluster)
+        .into_iter()
+        .chain([
+            ("dfs.namenode.name.dir".to_string(), "/data".to_string()),
+            // Differs between the datanode groups, see `datanode_statefulset`
+            (
+                "dfs.datanode.data.dir".to_string(),
+                "${env.DATANODE_DATA_DIRS}".to_string(),
+            ),
+            (
+                "dfs.journalnode.edits.dir".to_string(),
+                journal_edits_dir(cluster),
+            ),
+            // Lists the datanodes that are decommissioned or in maintenance, see `datanode_hosts`
+            (
+                "dfs.namenode.hosts.provider.classname".to_string(),
+                "org.apache.hadoop.hdfs.server.blockmanagement.CombinedHostFileManager".to_string(),
+            ),
+            (
+                "dfs.hosts".to_string(),
+                "/config/dfs.hosts.json".to_string(),
+            ),
+            (
+                "dfs.datanode.address".to_string(),
+                format!("0.0.0.0:{}", ports.datanode_data),
+            ),
+            (
+                "dfs.datanode.ipc.address".to_string(),
+                format!("0.0.0.0:{}", ports.datanode_ipc),
+            ),
+            (
+                "dfs.datanode.http.address".to_string(),
+                format!("0.0.0.0:{}", ports.datanode_http),
+            ),
+            (
+                "dfs.journalnode.rpc-address".to_string(),
+                format!("0.0.0.0:{}", ports.journalnode_rpc),
+            ),
+            (
+                "dfs.journalnode.http-address".to_string(),
+                format!("0.0.0.0:{}", ports.journalnode_http),
+            ),
+            (
+                "dfs.namenode.shared.edits.dir".to_string(),
+                journalnode_quorum(cluster),
+            ),
+            (
+                "dfs.ha.nn.not-become-active-in-safemode".to_string(),
+                "true".to_string(),
+            ),
+            (
+                "dfs.ha.automatic-failover.enabled".to_string(),
+                "true".to_string(),
+            ),
+            // The chroot is passed separately, so that the ZKFCs' election is confined to it
+            (
+                "ha.zookeeper.quorum".to_string(),
+                "${env.ZOOKEEPER_HOSTS}".to_string(),
+            ),
+            (
+                "ha.zookeeper.parent-znode".to_string(),
+                format!(
+                    "${{env.ZOOKEEPER_CHROOT}}/{}",
+                    spec.failover_controller
+                        .parent_znode
+                        .as_deref()
+                        .unwrap_or("hadoop-ha")
+                ),
+            ),
+            (
+                "dfs.block.access.token.enable".to_string(),
+                "true".to_string(),
+            ),
+            // (
+            //     "dfs.data.transfer.protection".to_string(),
+            //     "authentication".to_string(),
+            // ),
+            // TODO: "Privileged ports" don't really make sense in K8s, but we ought to sort out TLS anyway
+            (
+                "ignore.secure.ports.for.testing".to_string(),
+                "true".to_string(),
+            ),
+            (
+                "dfs.journalnode.kerberos.principal".to_string(),
+                format!("jn/{}@{}", namenode_fqdn, kerberos_realm),
+            ),
+            (
+                "dfs.journalnode.keytab.file".to_string(),
+                keytab_path(cluster, "jn"),
+            ),
+            (
+                "dfs.namenode.keytab.file".to_string(),
+                keytab_path(cluster, "nn"),
+            ),
+            (
+                "dfs.datanode.kerberos.principal".to_string(),
+                format!("dn/{}@{}", namenode_fqdn, kerberos_realm),
+            ),
+            (
+                "dfs.datanode.keytab.file".to_string(),
+                keytab_path(cluster, "dn"),
+            ),
+            // JournalNode SPNEGO
+            // (
+            //     "dfs.web.authentication.kerberos.principal".to_string(),
+            //     format!("HTTP/stackable-knode-1.kvm@{}", kerberos_realm),
+            //     // format!("HTTP/_HOST@{}", kerberos_realm),
+            // ),
+            // (
+            //     "dfs.web.authentication.kerberos.keytab".to_string(),
+            //     "/kerberos/spnego.service.keytab".to_string(),
+            // ),
+        ])
+        .chain(webhdfs_config(cluster))
+        .chain(namenode_service_rpc_config(cluster))
+        .chain(fencing_config(cluster))
+        .chain(zookeeper_auth_config(cluster))
+        .chain(optional_properties([
+            (
+                "dfs.namenode.handler.count",
+                spec.namenode_rpc
+                    .handler_count
+                    .map(|count| count.to_string()),
+            ),
+            (
+                "dfs.namenode.service.handler.count",
+                spec.namenode_rpc
+                    .service_handler_count
+                    .map(|count| count.to_string()),
+            ),
+            (
+                "dfs.datanode.failed.volumes.tolerated",
+                spec.datanode_storage
+                    .failed_volumes_tolerated
+                    .map(|tolerated| tolerated.to_string()),
+            ),
+            (
+                "dfs.datanode.disk.check.min.gap",
+                spec.datanode_storage
+                    .disk_check_min_gap
+                    .map(|gap| gap.to_string()),
+            ),
+            (
+                "dfs.datanode.disk.check.timeout",
+                spec.datanode_storage
+                    .disk_check_timeout
+                    .map(|timeout| timeout.to_string()),
+            ),
+            (
+                "ha.zookeeper.session-timeout.ms",
+                spec.failover_controller
+                    .session_timeout
+                    .map(|timeout| timeout.to_string()),
+            ),
+            // The web UIs, including the JMX servlets that the operator queries, are only served over HTTPS
+            (
+                "dfs.http.policy",
+                spec.tls.as_ref().map(|_| "HTTPS_ONLY".to_string()),
+            ),
+            (
+                "dfs.datanode.https.address",
+                ports.datanode_https.map(|port| format!("0.0.0.0:{}", port)),
+            ),
+            (
+                "dfs.journalnode.https-address",
+                ports
+                    .journalnode_https
+                    .map(|port| format!("0.0.0.0:{}", port)),
+            ),
+            (
+                "dfs.replication",
+                spec.dfs_replication.map(|r| r.to_string()),
+            ),
+            (
+                "dfs.namenode.checkpoint.period",
+                spec.checkpoint.period.map(|period| period.to_string()),
+            ),
+            (
+                "dfs.namenode.checkpoint.txns",
+                spec.checkpoint
+                    .transactions
+                    .map(|transactions| transactions.to_string()),
+            ),
+            (
+                "dfs.namenode.num.extra.edits.retained",
+                spec.journalnode_storage
+                    .extra_edits_retained
+                    .map(|txns| txns.to_string()),
+            ),
+            (
+                "dfs.namenode.max.extra.edits.segments.retained",
+                spec.journalnode_storage
+                    .max_extra_edit_segments_retained
+                    .map(|segments| segments.to_string()),
+            ),
+            (
+                "dfs.namenode.replication.min",
+                spec.dfs_replication_min.map(|r| r.to_string()),
+            ),
+            (
+                "dfs.namenode.snapshot.capture.openfiles",
+                spec.snapshot.capture_open_files.map(|c| c.to_string()),
+            ),
+            (
+                "dfs.namenode.snapshot.max.limit",
+                spec.snapshot.max_limit.map(|l| l.to_string()),
+            ),
+            (
+                "dfs.datanode.du.reserved",
+                spec.dfs_datanode_du_reserved.map(|r| r.to_string()),
+            ),
+            (
+                "dfs.namenode.inode.attributes.provider.class",
+                spec.security
+                    .authorizer
+                    .as_ref()
+                    .map(|authorizer| authorizer.provider_class.clone()),
+            ),
+        ]))
+        .filter(|(name, _)| property_role(name).map_or(true, |only| only == role))
+        .collect()
+}
+
+/// Enables or disables WebHDFS explicitly, since Hadoop enables it by default, and sets up its SPNEGO authentication
+fn webhdfs_config(cluster: &ClusterContext) -> Vec<(String, String)> {
+    let enabled = webhdfs_enabled(cluster);
+    std::iter::once(("dfs.webhdfs.enabled".to_string(), enabled.to_string()))
+        .chain(
+            enabled
+                .then(|| {
+                    [
+                        (
+                            "dfs.web.authentication.kerberos.principal".to_string(),
+                            format!(
+                                "HTTP/{}@{}",
+                                cluster.role_fqdn("namenode"),
+                                kerberos_realm(cluster)
+                            ),
+                        ),
+                        (
+                            "dfs.web.authentication.kerberos.keytab".to_string(),
+                            keytab_path(cluster, "HTTP"),
+                        ),
+                    ]
+                })
+                .into_iter()
+                .flatten(),
+        )
+        .collect()
+}
+
+/// The service RPC address of each namenode, if the daemons don't share the client RPC port
+///
+/// Only the daemons need these, clients keep using `dfs.namenode.rpc-address`.
+fn namenode_service_rpc_config(cluster: &ClusterContext) -> Vec<(String, String)> {
+    let port = match cluster.ports.namenode_service_rpc {
+        Some(port) => port,
+        None => return Vec::new(),
+    };
+    (0..cluster.hdfs.spec.namenode_replicas.unwrap_or(1))
+        .map(|i| {
+            (
+                format!(
+                    "dfs.namenode.servicerpc-address.{}.name-{}",
+                    cluster.name, i
+                ),
+                format!("{}:{}", cluster.pod_fqdn("namenode", i), port),
+            )
+        })
+        .collect()
+}
+
+/// Where the ZKFCs find the files of the `index`th fencing method
+fn fencing_dir(index: usize) -> String {
+    format!("/fencing/{}", index)
+}
+
+/// `dfs.ha.fencing.methods`, along with the settings of the individual methods
+fn fencing_config(cluster: &ClusterContext) -> Vec<(String, String)> {
+    let methods = &cluster.hdfs.spec.failover_controller.fencing_methods;
+    if methods.is_empty() {
+        return vec![(
+            "dfs.ha.fencing.methods".to_string(),
+            "shell(/bin/true)".to_string(),
+        )];
+    }
+    let mut private_keys = Vec::new();
+    let methods = methods
+        .iter()
+        .enumerate()
+        .map(|(i, method)| match method {
+            FencingMethod::Sshfence { user, port, .. } => {
+                private_keys.push(format!("{}/id_rsa", fencing_dir(i)));
+                let target = format!(
+                    "{}{}",
+                    user.as_deref().unwrap_or_default(),
+                    port.map(|port| format!(":{}", port)).unwrap_or_default()
+                );
+                if target.is_empty() {
+                    "sshfence".to_string()
+                } else {
+                    format!("sshfence({})", target)
+                }
+            }
+            FencingMethod::Shell { script, .. } => {
+                format!("shell(sh {}/{})", fencing_dir(i), script)
+            }
+        })
+        .collect::<Vec<_>>();
+    let mut props = vec![("dfs.ha.fencing.methods".to_string(), methods.join("\n"))];
+    if !private_keys.is_empty() {
+        props.push((
+            "dfs.ha.fencing.ssh.private-key-files".to_string(),
+            private_keys.join(","),
+        ));
+    }
+    props
+}
+
+/// Where the ZKFCs read their digest credentials from, see [`ZookeeperAuth::Digest`]
+const ZOOKEEPER_AUTH_DIR: &str = "/zookeeper-auth";
+
+/// The ZKFCs' ZooKeeper credentials (`ha.zookeeper.auth`) and the ACL of the election's ZNodes (`ha.zookeeper.acl`)
+fn zookeeper_auth_config(cluster: &ClusterContext) -> Vec<(String, String)> {
+    match &cluster.hdfs.spec.failover_controller.auth {
+        // Hadoop reads values that start with @ from the file
+        Some(ZookeeperAuth::Digest { .. }) => vec![
+            (
+                "ha.zookeeper.auth".to_string(),
+                format!("@{}/auth", ZOOKEEPER_AUTH_DIR),
+            ),
+            (
+                "ha.zookeeper.acl".to_string(),
+                format!("@{}/acl", ZOOKEEPER_AUTH_DIR),
+            ),
+        ],
+        Some(ZookeeperAuth::Sasl {}) => vec![(
+            "ha.zookeeper.acl".to_string(),
+            format!("sasl:{}:rwcda", namenode_principal(cluster)),
+        )],
+        None => Vec::new(),
+    }
+}
+
+/// Kerberos principal of the namenodes, which the ZKFCs also authenticate to ZooKeeper as
+fn namenode_principal(cluster: &ClusterContext) -> String {
+    format!(
+        "nn/{}@{}",
+        cluster.role_fqdn("namenode"),
+        kerberos_realm(cluster)
+    )
+}
+
+/// JAAS configuration that lets the ZKFCs authenticate to ZooKeeper with SASL
+fn zookeeper_jaas_config(cluster: &ClusterContext) -> String {
+    format!(
+        "Client {{\n  \
+         com.sun.security.auth.module.Krb5LoginModule required\n  \
+         useKeyTab=true\n  \
+         keyTab=\"{}\"\n  \
+         storeKey=true\n  \
+         useTicketCache=false\n  \
+         principal=\"{}\";\n\
+         }};\n",
+        keytab_path(cluster, "nn"),
+        namenode_principal(cluster)
+    )
+}
+
+/// `ssl-server.xml` or `ssl-client.xml` (for `side` `server` or `client`), pointing at the keystores that
+/// [`keystore_init_container`] creates
+fn ssl_config(side: &str) -> String {
+    let password = "${env.KEYSTORE_PASSWORD}";
+    let mut props = vec![
+        (
+            format!("ssl.{}.truststore.location", side),
+            "/keystore/truststore.p12".to_string(),
+        ),
+        (
+            format!("ssl.{}.truststore.type", side),
+            "pkcs12".to_string(),
+        ),
+        (
+            format!("ssl.{}.truststore.password", side),
+            password.to_string(),
+        ),
+    ];
+    if side == "server" {
+        props.extend([
+            (
+                "ssl.server.keystore.location".to_string(),
+                "/keystore/keystore.p12".to_string(),
+            ),
+            ("ssl.server.keystore.type".to_string(), "pkcs12".to_string()),
+            (
+                "ssl.server.keystore.password".to_string(),
+                password.to_string(),
+            ),
+            (
+                "ssl.server.keystore.keypassword".to_string(),
+                password.to_string(),
+            ),
+        ]);
+    }
+    hadoop_config_xml(props)
+}
+
+/// The `ConfigMap` that is mounted into the pods of `role`
+pub fn build_config_map(cluster: &ClusterContext, role: &str) -> ConfigMap {
+    config_map(cluster, role, None)
+}
+
+/// The `ConfigMap` of each role, followed by the ones of the datanode groups
+pub fn build_config_maps(cluster: &ClusterContext) -> Vec<ConfigMap> {
+    ["journalnode", "namenode", "datanode"]
+        .iter()
+        .map(|role| build_config_map(cluster, role))
+        .chain(
+            cluster
+                .hdfs
+                .spec
+                .datanode_groups
+                .iter()
+                .map(|(group, config)| {
+                    config_map(cluster, "datanode", Some((group.as_str(), config)))
+                }),
+        )
+        .collect()
+}
+
+/// The properties that the operator generates for the Hadoop configuration `file` of `role`
+fn generated_config(cluster: &ClusterContext, role: &str, file: &str) -> Vec<(String, String)> {
+    match file {
+        "core-site.xml" => core_site_client_config(cluster)
+            .into_iter()
+            .chain([
+                (
+                    "hadoop.security.authorization".to_string(),
+                    cluster
+                        .hdfs
+                        .spec
+                        .security
+                        .authorization
+                        .is_some()
+                        .to_string(),
+                ),
+                // JournalNode/WebHDFS SPNEGO
+                // ("hadoop.http.authentication.type".to_string(), "kerberos".to_string()),
+                // (
+                //     "hadoop.http.authentication.kerberos.principal".to_string(),
+                //     // format!("HTTP/stackable-knode-1.kvm@{}", kerberos_realm),
+                //     format!("HTTP/_HOST@{}", kerberos_realm),
+                // ),
+                // (
+                //     "hadoop.http.authentication.kerberos.keytab".to_string(),
+                //     "/kerberos/spnego.service.keytab".to_string(),
+                // ),
+            ])
+            .collect(),
+        _ => hdfs_site_config(cluster, role),
+    }
+}
+
+/// Merges the generated properties of the Hadoop configuration `file` of `role` with the `configOverrides` of the role
+/// and of the datanode group `group`
+fn merged_config(
+    cluster: &ClusterContext,
+    role: &str,
+    group: Option<&DatanodeGroupConfig>,
+    file: &str,
+) -> MergedConfig {
+    let mut config = MergedConfig::default();
+    for (name, value) in generated_config(cluster, role, file) {
+        let layer = match property_role(&name) {
+            Some(_) => ConfigLayer::RoleDefaults,
+            None => ConfigLayer::OperatorDefaults,
+        };
+        config.set(layer, name, value);
+    }
+    for name in FINAL_PROPERTIES {
+        config.set_attributes(name, Some(true), &[]);
+    }
+    let overrides = [
+        (ConfigLayer::RoleOverrides, cluster.role_config(role)),
+        (
+            ConfigLayer::RoleGroupOverrides,
+            group.map(|group| &group.pods),
+        ),
+    ];
+    for (layer, role_config) in overrides {
+        if let Some(role_config) = role_config {
+            if let Some(properties) = role_config.config_overrides.get(file) {
+                config.merge(layer, properties.clone());
+            }
+            for (name, attributes) in role_config
+                .property_attributes
+                .get(file)
+                .into_iter()
+                .flatten()
+            {
+                config.set_attributes(name, attributes.final_, &attributes.tags);
+            }
+        }
+    }
+    config.map_values(|value| interpolation::interpolate(value, &cluster.config_variables));
+    config
+}
+
+/// Problems with the `configOverrides` of all roles, such as unknown properties or invalid values
+///
+/// These don't stop the overrides from being applied, since the operator's schema doesn't cover every property.
+pub fn config_override_issues(cluster: &ClusterContext) -> Vec<String> {
+    let spec = &cluster.hdfs.spec;
+    let allow_unknown = spec.property_validation.allow_unknown.unwrap_or(false);
+    let mut issues = Vec::new();
+    for (role, config) in role_configs(cluster) {
+        for (file, properties) in &config.config_overrides {
+            // The operator's own properties are known, even if the schema lacks them
+            let base_role = if role.starts_with("datanode") {
+                "datanode"
+            } else {
+                role.as_str()
+            };
+            let generated = generated_config(cluster, base_role, file);
+            for (name, value) in properties {
+                let allow_unknown =
+                    allow_unknown || generated.iter().any(|(known, _)| known == name);
+                if let Some(issue) = properties::check(spec.image.tag(), name, value, allow_unknown)
+                {
+                    issues.push(format!("{} {}: {}", role, file, issue));
+                }
+            }
+        }
+    }
+    // Hadoop ignores attributes without a value, so they are most likely meant for a misspelled property
+    let roles = ["journalnode", "namenode", "datanode"]
+        .into_iter()
+        .map(|role| (role.to_string(), role, None))
+        .chain(
+            spec.datanode_groups
+                .iter()
+                .map(|(group, config)| (datanode_group_role(group), "datanode", Some(config))),
+        );
+    for (name, role, group) in roles {
+        let role_config = match group {
+            Some(group) => Some(&group.pods),
+            None => cluster.role_config(role),
+        };
+        for (file, attributes) in role_config
+            .into_iter()
+            .flat_map(|config| &config.property_attributes)
+        {
+            let config = merged_config(cluster, role, group, file);
+            for property in attributes
+                .keys()
+                .filter(|property| config.get(property).is_none())
+            {
+                issues.push(format!(
+                    "{} {}: {} has propertyAttributes but is not set",
+                    name, file, property
+                ));
+            }
+        }
+    }
+    issues
+}
+
+/// The `ConfigMap` of the datanode group `group`, or of all other pods of `role`
+fn config_map(
+    cluster: &ClusterContext,
+    role: &str,
+    group: Option<(&str, &DatanodeGroupConfig)>,
+) -> ConfigMap {
+    let spec = &cluster.hdfs.spec;
+    let authorization = spec.security.authorization.as_ref();
+    let group_config = group.map(|(_, config)| config);
+    let hadoop_configs = OVERRIDABLE_FILES
+        .iter()
+        .map(|file| (*file, merged_config(cluster, role, group_config, file)))
+        .collect::<BTreeMap<_, _>>();
+    let mut config_data = hadoop_configs
+        .iter()
+        .map(|(file, config)| {
+            (
+                file.to_string(),
+                configuration_xml(config.hadoop_properties()),
+            )
+        })
+        .collect::<BTreeMap<_, _>>();
+    config_data.extend([
+        (
+            EFFECTIVE_CONFIG_KEY.to_string(),
+            effective_config(&hadoop_configs),
+        ),
+        ("krb5.conf".to_string(), spec.kerberos.to_string()),
+        (
+            "log4j.properties".to_string(),
+            // "log4j.logger.org.apache.hadoop.security=DEBUG".to_string(),
+            match (&spec.audit_log, role) {
+                (Some(audit_log), "namenode") => {
+                    format!("{}\n{}", include_str!("log4j.properties"), audit_log)
+                }
+                _ => include_str!("log4j.properties").to_string(),
+            },
+        ),
+    ]);
+    if role == "namenode" {
+        config_data.insert(
+            "dfs.hosts.json".to_string(),
+            serde_json::Value::from(datanode_hosts(cluster)).to_string(),
+        );
+        if let Some(ZookeeperAuth::Sasl {}) = spec.failover_controller.auth {
+            config_data.insert(
+                "zookeeper-jaas.conf".to_string(),
+                zookeeper_jaas_config(cluster),
+            );
+        }
+    }
+    if spec.tls.is_some() {
+        for side in ["server", "client"] {
+            config_data.insert(format!("ssl-{}.xml", side), ssl_config(side));
+        }
+    }
+    if let Some(authorization) = authorization {
+        config_data.insert(
+            "hadoop-policy.xml".to_string(),
+            hadoop_config_xml(
+                authorization
+                    .default_acl
+                    .iter()
+                    .map(|acl| {
+                        (
+                            "security.service.authorization.default.acl".to_string(),
+                            acl.to_string(),
+                        )
+                    })
+                    .chain(
+                        authorization
+                            .acls
+                            .iter()
+                            .map(|(key, acl)| (key.clone(), acl.to_string())),
+                    ),
+            ),
+        );
+    }
+    let mut metadata = cluster.metadata(cluster.config_name(role), Some(role));
+    if let Some((group, config)) = group {
+        metadata.name = Some(cluster.config_name(&datanode_group_role(group)));
+        let labels = metadata.labels.get_or_insert_with(BTreeMap::new);
+        labels.extend(config.pods.labels.clone());
+        labels.extend(datanode_group_labels(group));
+    }
+    ConfigMap {
+        metadata,
+        data: Some(config_data),
+        ..ConfigMap::default()
+    }
+}
+
+/// The discovery `ConfigMap`, which clients can mount as their `HADOOP_CONF_DIR`
+///
+/// With `webhdfs.enabled`, `WEBHDFS_URL` is the nameservice's WebHDFS URL, which fails over between the namenodes
+/// like `fs.defaultFS` does.
+pub fn build_discovery_config_map(cluster: &ClusterContext) -> ConfigMap {
+    let mut data = BTreeMap::from([
+        (
+            "core-site.xml".to_string(),
+            client_config_xml(core_site_client_config(cluster)),
+        ),
+        (
+            "hdfs-site.xml".to_string(),
+            client_config_xml(hdfs_site_client_config(cluster)),
+        ),
+    ]);
+    if webhdfs_enabled(cluster) {
+        let scheme = match cluster.ports.namenode_https {
+            Some(_) => "swebhdfs",
+            None => "webhdfs",
+        };
+        data.insert(
+            "WEBHDFS_URL".to_string(),
+            format!("{}://{}/", scheme, cluster.name),
+        );
+    }
+    ConfigMap {
+        metadata: cluster.metadata(cluster.discovery_name(), None),
+        data: Some(data),
+        ..ConfigMap::default()
+    }
+}
+
+/// Checks that Hadoop can resolve each `${env.NAME}` placeholder in the configuration files that the pods of `sts`
+/// mount, from the environment of the pods' containers
+///
+/// Generated placeholders always are, but `configOverrides` may refer to variables that only `podOverrides` set.
+pub fn check_placeholders(sts: &StatefulSet, config_maps: &[ConfigMap]) -> Result<(), Error> {
+    let pod = match sts
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.template.spec.as_ref())
+    {
+        Some(pod) => pod,
+        None => return Ok(()),
+    };
+    // Variables from envFrom are only known once the pods start
+    if pod
+        .containers
+        .iter()
+        .any(|container| container.env_from.is_some())
+    {
+        return Ok(());
+    }
+    let set = pod
+        .containers
+        .iter()
+        .flat_map(|container| container.env.iter().flatten())
+        .map(|env| env.name.as_str())
+        // Set by Kubernetes in all containers
+        .chain(["HOSTNAME"])
+        .collect::<Vec<_>>();
+    let mounted = pod
+        .volumes
+        .iter()
+        .flatten()
+        .filter_map(|volume| volume.config_map.as_ref()?.name.as_deref())
+        .collect::<Vec<_>>();
+    let mounted_config_maps = config_maps.iter().filter(|config_map| {
+        config_map
+            .metadata
+            .name
+            .as_deref()
+            .map_or(false, |name| mounted.contains(&name))
+    });
+    for config_map in mounted_config_maps {
+        let files = config_map
+            .data
+            .iter()
+            .flatten()
+            .filter(|(file, _)| file.ends_with(".xml"));
+        for (file, content) in files {
+            if let Some(variable) = interpolation::unresolvable_variables(content, &set).first() {
+                return UnresolvableVariable {
+                    sts: sts.metadata.name.clone().unwrap_or_default(),
+                    file,
+                    variable: *variable,
+                }
+                .fail();
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A configuration file for clients, whose jobs may not override the security settings
+fn client_config_xml(properties: Vec<(String, String)>) -> String {
+    configuration_xml(properties.iter().map(|(name, value)| HadoopProperty {
+        final_: FINAL_PROPERTIES.contains(&name.as_str()),
+        ..HadoopProperty::new(name, value)
+    }))
+}
+
+/// The cluster's own `ServiceAccount`, unless `serviceAccount.name` refers to an existing one
+///
+/// An existing `ServiceAccount` is only referenced, so that it is neither taken over nor deleted along with the cluster.
+pub fn build_service_account(cluster: &ClusterContext) -> Option<ServiceAccount> {
+    if cluster.hdfs.spec.service_account.name.is_some() {
+        return None;
+    }
+    Some(ServiceAccount {
+        metadata: cluster.metadata(cluster.service_account_name(), None),
+        image_pull_secrets: Some(
+            cluster
+                .hdfs
+                .spec
+                .service_account
+                .image_pull_secrets
+                .iter()
+                .map(|secret| LocalObjectReference {
+                    name: Some(secret.clone()),
+                })
+                .collect(),
+        ),
+        ..ServiceAccount::default()
+    })
+}
+
+/// Grants the configured `ClusterRole` to the cluster's `ServiceAccount`, if any
+pub fn build_role_binding(cluster: &ClusterContext) -> Option<RoleBinding> {
+    let cluster_role = cluster.hdfs.spec.service_account.cluster_role.as_ref()?;
+    Some(RoleBinding {
+        metadata: cluster.metadata(cluster.role_binding_name(), None),
+        role_ref: RoleRef {
+            api_group: "rbac.authorization.k8s.io".to_string(),
+            kind: "ClusterRole".to_string(),
+            name: cluster_role.clone(),
+        },
+        subjects: Some(vec![Subject {
+            kind: "ServiceAccount".to_string(),
+            name: cluster.service_account_name(),
+            namespace: Some(cluster.namespace.clone()),
+            ..Subject::default()
+        }]),
+    })
+}
+
+/// Limits how many pods of `role` may be evicted at once, unless its `disruptionBudget` is disabled
+///
+/// The selector of the datanodes outside of any group also matches the groups' pods, so they share one budget.
+pub fn build_pod_disruption_budget(
+    cluster: &ClusterContext,
+    role: &str,
+) -> Option<PodDisruptionBudget> {
+    let budget = &cluster.role_config(role)?.disruption_budget;
+    if !budget.enabled.unwrap_or(true) {
+        return None;
+    }
+    Some(PodDisruptionBudget {
+        metadata: cluster.metadata(cluster.role_name(role), Some(role)),
+        spec: Some(PodDisruptionBudgetSpec {
+            max_unavailable: Some(
+                budget
+                    .max_unavailable
+                    .clone()
+                    .unwrap_or(IntOrString::Int(1)),
+            ),
+            selector: Some(LabelSelector {
+                match_labels: Some(cluster.role_pod_disruption_labels(role)),
+                ..LabelSelector::default()
+            }),
+            ..PodDisruptionBudgetSpec::default()
+        }),
+        status: None,
+    })
+}
+
+/// Restricts ingress to the cluster's pods, if network isolation is enabled
+pub fn build_network_policy(cluster: &ClusterContext) -> Option<NetworkPolicy> {
+    let network_isolation = cluster.hdfs.spec.network_isolation.as_ref()?;
+    let member_peer = NetworkPolicyPeer {
+        pod_selector: Some(LabelSelector {
+            match_labels: Some(cluster.cluster_pod_labels()),
+            ..LabelSelector::default()
+        }),
+        ..NetworkPolicyPeer::default()
+    };
+    let tcp_ports = |port_numbers: &[i32]| {
+        port_numbers
+            .iter()
+            .map(|port| NetworkPolicyPort {
+                port: Some(IntOrString::Int(*port)),
+                protocol: Some("TCP".to_string()),
+                ..NetworkPolicyPort::default()
+            })
+            .collect::<Vec<_>>()
+    };
+    Some(NetworkPolicy {
+        metadata: cluster.metadata(cluster.network_policy_name(), None),
+        spec: Some(NetworkPolicySpec {
+            pod_selector: LabelSelector {
+                match_labels: Some(cluster.cluster_pod_labels()),
+                ..LabelSelector::default()
+            },
+            policy_types: Some(vec!["Ingress".to_string()]),
+            ingress: Some(vec![
+                NetworkPolicyIngressRule {
+                    from: Some(
+                        std::iter::once(member_peer.clone())
+                            .chain(
+                                network_isolation
+                                    .allowed_clients
+                                    .iter()
+                                    .map(AllowedClient::network_policy_peer),
+                            )
+                            .collect(),
+                    ),
+                    ports: Some(tcp_ports(&cluster.ports.client_ports())),
+                },
+                // JournalNodes (and the NameNodes' service RPC) are only ever accessed by the other daemons
+                NetworkPolicyIngressRule {
+                    from: Some(vec![member_peer]),
+                    ports: Some(tcp_ports(&cluster.ports.internal_ports())),
+                },
+            ]),
+            ..NetworkPolicySpec::default()
+        }),
+    })
+}
+
+fn tcp_service_port(name: &str, port: i32) -> ServicePort {
+    ServicePort {
+        name: Some(name.to_string()),
+        port,
+        protocol: Some("TCP".to_string()),
+        ..ServicePort::default()
+    }
+}
+
+/// Exposes the web UI of a role's pods on port 80
+fn http_service_port() -> ServicePort {
+    ServicePort {
+        target_port: Some(IntOrString::String("http".to_string())),
+        ..tcp_service_port("http", 80)
+    }
+}
+
+/// Exposes the web UI of a role's pods over HTTPS on port 443, if they serve it on `port`
+fn https_service_port(port: Option<i32>) -> Option<ServicePort> {
+    port.map(|_| ServicePort {
+        target_port: Some(IntOrString::String("https".to_string())),
+        ..tcp_service_port("https", 443)
+    })
+}
+
+/// The `Service` that gives each of the role's pods a stable DNS name, headless unless its `service` says otherwise
+///
+/// `publish_not_ready_addresses` is the role's default, if its `service` doesn't set it.
+fn role_service(
+    cluster: &ClusterContext,
+    role: &str,
+    ports: Vec<ServicePort>,
+    publish_not_ready_addresses: Option<bool>,
+) -> Service {
+    let config = cluster
+        .role_config(role)
+        .map(|config| config.service.clone())
+        .unwrap_or_default();
+    Service {
+        metadata: cluster.metadata(cluster.role_name(role), Some(role)),
+        spec: Some(ServiceSpec {
+            ports: Some(ports),
+            selector: Some(cluster.role_pod_labels(role)),
+            cluster_ip: config.headless.unwrap_or(true).then(|| "None".to_string()),
+            publish_not_ready_addresses: config
+                .publish_not_ready_addresses
+                .or(publish_not_ready_addresses),
+            ..ServiceSpec::default()
+        }),
+        status: None,
+    }
+}
+
+pub fn build_journalnode_service(cluster: &ClusterContext) -> Service {
+    role_service(
+        cluster,
+        "journalnode",
+        [
+            tcp_service_port("ipc", cluster.ports.journalnode_rpc),
+            http_service_port(),
+        ]
+        .into_iter()
+        .chain(https_service_port(cluster.ports.journalnode_https))
+        .collect(),
+        Some(true),
+    )
+}
+
+pub fn build_namenode_service(cluster: &ClusterContext) -> Service {
+    role_service(
+        cluster,
+        "namenode",
+        std::iter::once(tcp_service_port("ipc", cluster.ports.namenode_rpc))
+            .chain(
+                cluster
+                    .ports
+                    .namenode_service_rpc
+                    .map(|port| tcp_service_port("service-ipc", port)),
+            )
+            .chain([http_service_port()])
+            .chain(https_service_port(cluster.ports.namenode_https))
+            .collect(),
+        Some(true),
+    )
+}
+
+pub fn build_datanode_service(cluster: &ClusterContext) -> Service {
+    role_service(
+        cluster,
+        "datanode",
+        [
+            tcp_service_port("ipc", cluster.ports.datanode_ipc),
+            http_service_port(),
+        ]
+        .into_iter()
+        .chain(https_service_port(cluster.ports.datanode_https))
+        .collect(),
+        None,
+    )
+}
+
+/// Labels that the objects and pods of the datanode group `group` have in addition to the datanodes' ones
+fn datanode_group_labels(group: &str) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        (APP_ROLE_GROUP_LABEL.to_string(), group.to_string()),
+        (DATANODE_GROUP_LABEL.to_string(), group.to_string()),
+    ])
+}
+
+/// The `Service` of each group of datanodes, starting with the `datanodeReplicas` datanodes
+pub fn build_datanode_services(cluster: &ClusterContext) -> Vec<Service> {
+    std::iter::once(build_datanode_service(cluster))
+        .chain(cluster.hdfs.spec.datanode_groups.keys().map(|group| {
+            let mut svc = build_datanode_service(cluster);
+            svc.metadata.name = Some(cluster.role_name(&datanode_group_role(group)));
+            svc.metadata
+                .labels
+                .get_or_insert_with(BTreeMap::new)
+                .extend(datanode_group_labels(group));
+            if let Some(spec) = &mut svc.spec {
+                spec.selector
+                    .get_or_insert_with(BTreeMap::new)
+                    .insert(DATANODE_GROUP_LABEL.to_string(), group.clone());
+            }
+            svc
+        }))
+        .collect()
+}
+
+fn tcp_container_port(name: &str, port: i32) -> ContainerPort {
+    ContainerPort {
+        name: Some(name.to_string()),
+        container_port: port,
+        protocol: Some("TCP".to_string()),
+        ..ContainerPort::default()
+    }
+}
+
+fn local_disk_claim(name: &str, size: Quantity) -> PersistentVolumeClaim {
+    PersistentVolumeClaim {
+        metadata: ObjectMeta {
+            name: Some(name.to_string()),
+            ..ObjectMeta::default()
+        },
+        spec: Some(PersistentVolumeClaimSpec {
+            access_modes: Some(vec!["ReadWriteOnce".to_string()]),
+            resources: Some(ResourceRequirements {
+                requests: Some(BTreeMap::from([("storage".to_string(), size)])),
+                ..ResourceRequirements::default()
+            }),
+            ..PersistentVolumeClaimSpec::default()
+        }),
+        ..PersistentVolumeClaim::default()
+    }
+}
+
+/// The memory that a container is limited to, or else the memory it requests
+fn container_memory(resources: &ResourceRequirements) -> Option<&Quantity> {
+    [&resources.limits, &resources.requests]
+        .into_iter()
+        .flatten()
+        .find_map(|quantities| quantities.get("memory"))
+}
+
+/// JVM flags that size the ZKFC's heap to fit its own container, rather than the namenode's
+fn zkfc_heap_opts(cluster: &ClusterContext) -> Option<String> {
+    let resources = cluster.hdfs.spec.failover_controller.resources();
+    let bytes = parse_memory_quantity(&container_memory(&resources)?.0)?;
+    let heap_mib = (bytes * ZKFC_HEAP_PERCENT / 100) >> 20;
+    Some(format!("-Xms{0}m -Xmx{0}m", heap_mib.max(1)))
+}
+
+fn hadoop_container(cluster: &ClusterContext) -> Container {
+    let hdfs = cluster.hdfs;
+    let mut container = Container {
+        image: Some(cluster.image_options.rewrite(&hdfs.spec.image.image())),
+        image_pull_policy: hdfs.spec.image.pull_policy.clone(),
+        env: Some(vec![
+            EnvVar {
+                name: "HADOOP_HOME".to_string(),
+                value: Some("/opt/hadoop".to_string()),
+                ..EnvVar::default()
+            },
+            EnvVar {
+                name: "HADOOP_CONF_DIR".to_string(),
+                value: Some("/config".to_string()),
+                ..EnvVar::default()
+            },
+            // EnvVar {
+            //     name: "KRB5_TRACE".to_string(),
+            //     value: Some("/dev/stdout".to_string()),
+            //     ..EnvVar::default()
+            // },
+            // EnvVar {
+            //     name: "HADOOP_JAAS_DEBUG".to_string(),
+            //     value: Some("true".to_string()),
+            //     ..EnvVar::default()
+            // },
+            EnvVar {
+                name: "JAVA_TOOL_OPTIONS".to_string(),
+                value: Some(
+                    [
+                        "-Djava.security.krb5.conf=/config/krb5.conf",
+                        // "-Dsun.security.spnego.debug=true",
+                        // "-Dsun.security.krb5.debug=true",
+                        // "-Djava.security.debug=all",
+                    ]
+                    .join(" "),
+                ),
+                ..EnvVar::default()
+            },
+        ]),
+        volume_mounts: Some(vec![
+            VolumeMount {
+                mount_path: "/data".to_string(),
+                name: "data".to_string(),
+                ..VolumeMount::default()
+            },
+            VolumeMount {
+                mount_path: "/config".to_string(),
+                name: "config".to_string(),
+                ..VolumeMount::default()
+            },
+            VolumeMount {
+                mount_path: "/kerberos".to_string(),
+                name: "kerberos".to_string(),
+                ..VolumeMount::default()
+            },
+        ]),
+        security_context: Some(hdfs.spec.pod_security.container_security_context()),
+        ..Container::default()
+    };
+    if hdfs.spec.tls.is_some() {
+        container.env.get_or_insert_with(Vec::new).push(EnvVar {
+            name: "KEYSTORE_PASSWORD".to_string(),
+            value_from: Some(EnvVarSource {
+                secret_key_ref: Some(SecretKeySelector {
+                    name: Some(cluster.keystore_secret_name()),
+                    key: KEYSTORE_PASSWORD_KEY.to_string(),
+                    ..SecretKeySelector::default()
+                }),
+                ..EnvVarSource::default()
+            }),
+            ..EnvVar::default()
+        });
+        container
+            .volume_mounts
+            .get_or_insert_with(Vec::new)
+            .extend(["tls", "keystore"].map(|name| VolumeMount {
+                mount_path: format!("/{}", name),
+                name: name.to_string(),
+                ..VolumeMount::default()
+            }));
+    }
+    container
+}
+
+/// Converts the PEM certificates at `/tls` into the PKCS#12 keystore and truststore that `ssl-server.xml` and
+/// `ssl-client.xml` refer to
+///
+/// Both secret-operator and cert-manager provide `tls.crt`, `tls.key`, and `ca.crt`, which Hadoop can't read directly.
+fn keystore_init_container(cluster: &ClusterContext) -> Container {
+    Container {
+        name: "create-keystores".to_string(),
+        args: Some(vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            "rm -f /keystore/keystore.p12 /keystore/truststore.p12 \
+             && openssl pkcs12 -export -name hdfs -in /tls/tls.crt -inkey /tls/tls.key \
+             -out /keystore/keystore.p12 -passout env:KEYSTORE_PASSWORD \
+             && keytool -importcert -noprompt -alias ca -file /tls/ca.crt -storetype pkcs12 \
+             -keystore /keystore/truststore.p12 -storepass \"$KEYSTORE_PASSWORD\""
+                .to_string(),
+        ]),
+        ..hadoop_container(cluster)
+    }
+}
+
+/// An ephemeral volume that secret-operator populates from `secret_class` when the pod is scheduled
+///
+/// `attributes` are passed on to secret-operator, and select what the volume contains.
+fn secret_operator_volume(name: &str, secret_class: &str, attributes: &[(&str, String)]) -> Volume {
+    let annotations = [("secrets.stackable.tech/class", secret_class.to_string())]
+        .iter()
+        .chain(attributes)
+        .map(|(key, value)| (key.to_string(), value.clone()))
+        .collect();
+    Volume {
+        name: name.to_string(),
+        ephemeral: Some(EphemeralVolumeSource {
+            volume_claim_template: Some(PersistentVolumeClaimTemplate {
+                metadata: Some(ObjectMeta {
+                    annotations: Some(annotations),
+                    ..ObjectMeta::default()
+                }),
+                spec: PersistentVolumeClaimSpec {
+                    access_modes: Some(vec!["ReadWriteOnce".to_string()]),
+                    resources: Some(ResourceRequirements {
+                        requests: Some(BTreeMap::from([(
+                            "storage".to_string(),
+                            Quantity("1".to_string()),
+                        )])),
+                        ..ResourceRequirements::default()
+                    }),
+                    storage_class_name: Some(SECRET_OPERATOR_STORAGE_CLASS.to_string()),
+                    ..PersistentVolumeClaimSpec::default()
+                },
+            }),
+            ..EphemeralVolumeSource::default()
+        }),
+        ..Volume::default()
+    }
+}
+
+/// The pod settings that are shared by all roles, with the role's config, Kerberos, and TLS volumes
+fn role_pod_spec(cluster: &ClusterContext, role: &str) -> PodSpec {
+    let hdfs = cluster.hdfs;
+    let kerberos_volume = match &hdfs.spec.kerberos.secret_class {
+        // All principals are named after the namenode service, see `hdfs_site_config`
+        Some(secret_class) => secret_operator_volume(
+            "kerberos",
+            secret_class,
+            &[
+                (
+                    "secrets.stackable.tech/scope",
+                    format!("service={}", cluster.role_name("namenode")),
+                ),
+                (
+                    "secrets.stackable.tech/kerberos.service.names",
+                    kerberos_service_names(cluster, role),
+                ),
+            ],
+        ),
+        None => Volume {
+            name: "kerberos".to_string(),
+            secret: Some(SecretVolumeSource {
+                secret_name: Some(cluster.kerberos_secret_name(role)),
+                ..SecretVolumeSource::default()
+            }),
+            ..Volume::default()
+        },
+    };
+    let tls_volume = hdfs.spec.tls.as_ref().map(|tls| match &tls.secret_class {
+        Some(secret_class) => secret_operator_volume(
+            "tls",
+            secret_class,
+            &[(
+                "secrets.stackable.tech/scope",
+                format!("node,pod,service={}", cluster.role_name(role)),
+            )],
+        ),
+        // Issued by cert-manager, see `build_certificate`
+        None => Volume {
+            name: "tls".to_string(),
+            secret: Some(SecretVolumeSource {
+                secret_name: Some(cluster.tls_secret_name(role)),
+                ..SecretVolumeSource::default()
+            }),
+            ..Volume::default()
+        },
+    });
+    let host_network = host_network_enabled(cluster);
+    PodSpec {
+        volumes: Some(
+            [
+                Volume {
+                    name: "config".to_string(),
+                    config_map: Some(ConfigMapVolumeSource {
+                        name: Some(cluster.config_name(role)),
+                        ..ConfigMapVolumeSource::default()
+                    }),
+                    ..Volume::default()
+                },
+                kerberos_volume,
+            ]
+            .into_iter()
+            .chain(tls_volume)
+            .chain(hdfs.spec.tls.as_ref().map(|_| Volume {
+                name: "keystore".to_string(),
+                empty_dir: Some(EmptyDirVolumeSource::default()),
+                ..Volume::default()
+            }))
+            .collect(),
+        ),
+        host_network: Some(host_network),
+        dns_policy: host_network.then(|| "ClusterFirstWithHostNet".to_string()),
+        security_context: Some(hdfs.spec.pod_security.pod_security_context()),
+        service_account_name: Some(cluster.service_account_name()),
+        image_pull_secrets: hdfs.spec.image.pull_secret_refs(),
+        affinity: architecture_affinity(cluster),
+        ..PodSpec::default()
+    }
+}
+
+/// Restricts the pods to the nodes whose architecture the image is built for, if that is known
+fn architecture_affinity(cluster: &ClusterContext) -> Option<Affinity> {
+    let architectures = image_architectures(&cluster.hdfs.spec.image)?;
+    Some(Affinity {
+        node_affinity: Some(NodeAffinity {
+            required_during_scheduling_ignored_during_execution: Some(NodeSelector {
+                node_selector_terms: vec![NodeSelectorTerm {
+                    match_expressions: Some(vec![NodeSelectorRequirement {
+                        key: "kubernetes.io/arch".to_string(),
+                        operator: "In".to_string(),
+                        values: Some(architectures),
+                    }]),
+                    ..NodeSelectorTerm::default()
+                }],
+            }),
+            ..NodeAffinity::default()
+        }),
+        ..Affinity::default()
+    })
+}
+
+/// Applies the user's customizations for a role to its pod template, whose pods are selected by `pod_labels`
+fn apply_role_config(
+    pod_template: &mut PodTemplateSpec,
+    role: &HdfsRoleConfig,
+    pod_labels: &BTreeMap<String, String>,
+) -> Result<(), serde_json::Error> {
+    if let Some(safe_to_evict) = role.safe_to_evict {
+        pod_template
+            .metadata
+            .get_or_insert_with(ObjectMeta::default)
+            .annotations
+            .get_or_insert_with(BTreeMap::new)
+            .insert(
+                SAFE_TO_EVICT_ANNOTATION.to_string(),
+                safe_to_evict.to_string(),
+            );
+    }
+    let pod = pod_template.spec.get_or_insert_with(PodSpec::default);
+    if role.priority_class_name.is_some() {
+        pod.priority_class_name = role.priority_class_name.clone();
+    }
+    if role.termination_grace_period_seconds.is_some() {
+        pod.termination_grace_period_seconds = role.termination_grace_period_seconds;
+    }
+    if !role.topology_spread_constraints.is_empty() {
+        pod.topology_spread_constraints = Some(
+            role.topology_spread_constraints
+                .iter()
+                .map(|constraint| TopologySpreadConstraint {
+                    label_selector: constraint.label_selector.clone().or_else(|| {
+                        Some(LabelSelector {
+                            match_labels: Some(pod_labels.clone()),
+                            ..LabelSelector::default()
+                        })
+                    }),
+                    ..constraint.clone()
+                })
+                .collect(),
+        );
+    }
+    pod.volumes
+        .get_or_insert_with(Vec::new)
+        .extend(role.extra_volumes.iter().cloned());
+    for container in pod
+        .containers
+        .iter_mut()
+        .chain(pod.init_containers.iter_mut().flatten())
+    {
+        container
+            .volume_mounts
+            .get_or_insert_with(Vec::new)
+            .extend(role.extra_volume_mounts.iter().cloned());
+    }
+    if let Some(pod_overrides) = &role.pod_overrides {
+        let mut merged = serde_json::to_value(&*pod_template)?;
+        merge_json(&mut merged, pod_overrides.clone());
+        *pod_template = serde_json::from_value(merged)?;
+    }
+    Ok(())
+}
+
+/// Where the daemons write heap dumps and flight recordings, see [`JvmConfig`]
+const DIAGNOSTICS_DIR: &str = "/diagnostics";
+
+/// The environment variable with the JVM flags of the daemon of `role`, which only its own command picks up
+fn daemon_opts_env(role: &str) -> String {
+    format!("HDFS_{}_OPTS", role.to_uppercase())
+}
+
+/// JVM flags of the garbage collector `preset`, followed by the extra flags that override them
+fn gc_opts(gc: &GcConfig) -> Vec<String> {
+    let preset: &[&str] = match gc.preset {
+        Some(GcPreset::G1) => &[
+            "-XX:+UseG1GC",
+            "-XX:MaxGCPauseMillis=200",
+            "-XX:+ParallelRefProcEnabled",
+        ],
+        // The serial collector shrinks the heap once it is mostly free, which G1 rarely does
+        Some(GcPreset::SmallHeap) => &[
+            "-XX:+UseSerialGC",
+            "-XX:MinHeapFreeRatio=10",
+            "-XX:MaxHeapFreeRatio=30",
+        ],
+        None => &[],
+    };
+    preset
+        .iter()
+        .map(|opt| opt.to_string())
+        .chain(gc.extra_args.iter().cloned())
+        .collect()
+}
+
+/// Whether `jvm` writes anything to the diagnostics volume
+fn writes_diagnostics(jvm: &JvmConfig) -> bool {
+    jvm.heap_dump_on_out_of_memory.unwrap_or(false) || jvm.flight_recorder.unwrap_or(false)
+}
+
+/// JVM flags of a daemon that are configured by `jvm`
+fn daemon_jvm_opts(jvm: &JvmConfig) -> Vec<String> {
+    let mut opts = gc_opts(&jvm.gc);
+    if jvm.heap_dump_on_out_of_memory.unwrap_or(false) {
+        opts.push("-XX:+HeapDumpOnOutOfMemoryError".to_string());
+        opts.push(format!("-XX:HeapDumpPath={}", DIAGNOSTICS_DIR));
+    }
+    if jvm.flight_recorder.unwrap_or(false) {
+        opts.push(format!(
+            "-XX:StartFlightRecording=disk=true,dumponexit=true,filename={}/flight-recording.jfr",
+            DIAGNOSTICS_DIR
+        ));
+    }
+    opts
+}
+
+/// Passes the JVM flags of `jvm` to the container of `role`'s daemon, and mounts the diagnostics volume that they
+/// write to
+fn apply_jvm_config(cluster: &ClusterContext, pod: &mut PodSpec, role: &str, jvm: &JvmConfig) {
+    let mut opts = daemon_jvm_opts(jvm);
+    if cluster.hdfs.spec.tracing.is_some() {
+        opts.push(format!("-javaagent:{}/javaagent.jar", OTEL_AGENT_DIR));
+    }
+    if opts.is_empty() {
+        return;
+    }
+    let container = match pod
+        .containers
+        .iter_mut()
+        .find(|container| container.name == role)
+    {
+        Some(container) => container,
+        None => return,
+    };
+    container.env.get_or_insert_with(Vec::new).push(EnvVar {
+        name: daemon_opts_env(role),
+        value: Some(opts.join(" ")),
+        ..EnvVar::default()
+    });
+    if !writes_diagnostics(jvm) {
+        return;
+    }
+    container
+        .volume_mounts
+        .get_or_insert_with(Vec::new)
+        .push(VolumeMount {
+            mount_path: DIAGNOSTICS_DIR.to_string(),
+            name: "diagnostics".to_string(),
+            ..VolumeMount::default()
+        });
+    pod.volumes.get_or_insert_with(Vec::new).push(Volume {
+        name: "diagnostics".to_string(),
+        empty_dir: Some(EmptyDirVolumeSource {
+            size_limit: jvm.diagnostics_volume_size.clone(),
+            ..EmptyDirVolumeSource::default()
+        }),
+        ..Volume::default()
+    });
+}
+
+/// Where the init container copies the OpenTelemetry Java agent to, see [`crate::crd::TracingConfig`]
+const OTEL_AGENT_DIR: &str = "/otel-agent";
+const DEFAULT_OTEL_AGENT_IMAGE: &str =
+    "ghcr.io/open-telemetry/opentelemetry-operator/autoinstrumentation-java:1.9.0";
+const DEFAULT_TRACING_SAMPLING_RATIO: f64 = 0.1;
+
+/// Copies the OpenTelemetry Java agent into the pod, and configures the agent of `role`'s daemon to export its spans
+/// to the collector of `spec.tracing`
+///
+/// The agent is loaded by [`apply_jvm_config`], since it is passed in the same variable as the other JVM flags.
+fn apply_tracing(cluster: &ClusterContext, pod: &mut PodSpec, role: &str) {
+    let tracing = match &cluster.hdfs.spec.tracing {
+        Some(tracing) => tracing,
+        None => return,
+    };
+    let agent_mount = VolumeMount {
+        mount_path: OTEL_AGENT_DIR.to_string(),
+        name: "otel-agent".to_string(),
+        ..VolumeMount::default()
+    };
+    let container = match pod
+        .containers
+        .iter_mut()
+        .find(|container| container.name == role)
+    {
+        Some(container) => container,
+        None => return,
+    };
+    let mut env = vec![
+        ("OTEL_SERVICE_NAME", cluster.role_name(role)),
+        ("OTEL_EXPORTER_OTLP_ENDPOINT", tracing.endpoint.clone()),
+        (
+            "OTEL_TRACES_SAMPLER",
+            "parentbased_traceidratio".to_string(),
+        ),
+        (
+            "OTEL_TRACES_SAMPLER_ARG",
+            tracing
+                .sampling_ratio
+                .unwrap_or(DEFAULT_TRACING_SAMPLING_RATIO)
+                .to_string(),
+        ),
+        // The daemons' metrics are already scraped from JMX
+        ("OTEL_METRICS_EXPORTER", "none".to_string()),
+    ];
+    let mut mounts = vec![agent_mount.clone()];
+    if tracing.ca_secret_name.is_some() {
+        env.push((
+            "OTEL_EXPORTER_OTLP_CERTIFICATE",
+            "/otel-ca/ca.crt".to_string(),
+        ));
+        mounts.push(VolumeMount {
+            mount_path: "/otel-ca".to_string(),
+            name: "otel-ca".to_string(),
+            read_only: Some(true),
+            ..VolumeMount::default()
+        });
+    }
+    container
+        .env
+        .get_or_insert_with(Vec::new)
+        .extend(env.into_iter().map(|(name, value)| EnvVar {
+            name: name.to_string(),
+            value: Some(value),
+            ..EnvVar::default()
+        }));
+    container
+        .volume_mounts
+        .get_or_insert_with(Vec::new)
+        .extend(mounts);
+    let image = tracing
+        .agent_image
+        .as_deref()
+        .unwrap_or(DEFAULT_OTEL_AGENT_IMAGE);
+    pod.init_containers
+        .get_or_insert_with(Vec::new)
+        .push(Container {
+            name: "copy-otel-agent".to_string(),
+            image: Some(cluster.image_options.rewrite(image)),
+            image_pull_policy: cluster.hdfs.spec.image.pull_policy.clone(),
+            command: Some(vec![
+                "cp".to_string(),
+                "/javaagent.jar".to_string(),
+                format!("{}/javaagent.jar", OTEL_AGENT_DIR),
+            ]),
+            volume_mounts: Some(vec![agent_mount]),
+            security_context: Some(cluster.hdfs.spec.pod_security.container_security_context()),
+            ..Container::default()
+        });
+    let volumes = pod.volumes.get_or_insert_with(Vec::new);
+    volumes.push(Volume {
+        name: "otel-agent".to_string(),
+        empty_dir: Some(EmptyDirVolumeSource::default()),
+        ..Volume::default()
+    });
+    if let Some(ca_secret_name) = &tracing.ca_secret_name {
+        volumes.push(Volume {
+            name: "otel-ca".to_string(),
+            secret: Some(SecretVolumeSource {
+                secret_name: Some(ca_secret_name.clone()),
+                ..SecretVolumeSource::default()
+            }),
+            ..Volume::default()
+        });
+    }
+}
+
+/// Directories outside of the volumes that Hadoop writes to, by the name of the volume that backs them when the root
+/// filesystem is read-only
+///
+/// `/tmp` holds Java's and Hadoop's temporary files (such as Jetty's work directories and Kerberos ticket caches), and
+/// `/opt/hadoop/logs` is the `hadoop.log.dir` that file appenders (such as the audit log's) write to.
+const SCRATCH_DIRS: [(&str, &str); 2] = [("tmp", "/tmp"), ("logs", "/opt/hadoop/logs")];
+
+/// Mounts the root filesystems of `pod`'s containers read-only if `role_config` or `podSecurity` asks for it, and backs
+/// the [`SCRATCH_DIRS`] with `emptyDir` volumes instead
+fn apply_read_only_root_filesystem(
+    cluster: &ClusterContext,
+    pod: &mut PodSpec,
+    role_config: &HdfsRoleConfig,
+) {
+    let pod_security = &cluster.hdfs.spec.pod_security;
+    let read_only = role_config
+        .read_only_root_filesystem
+        .or(pod_security.read_only_root_filesystem)
+        .unwrap_or(false);
+    for container in pod
+        .containers
+        .iter_mut()
+        .chain(pod.init_containers.iter_mut().flatten())
+    {
+        container
+            .security_context
+            .get_or_insert_with(|| pod_security.container_security_context())
+            .read_only_root_filesystem = Some(read_only);
+        if read_only {
+            container
+                .volume_mounts
+                .get_or_insert_with(Vec::new)
+                .extend(SCRATCH_DIRS.iter().map(|(name, path)| VolumeMount {
+                    mount_path: path.to_string(),
+                    name: name.to_string(),
+                    ..VolumeMount::default()
+                }));
+        }
+    }
+    if !read_only {
+        return;
+    }
+    pod.volumes
+        .get_or_insert_with(Vec::new)
+        .extend(SCRATCH_DIRS.iter().map(|(name, _)| Volume {
+            name: name.to_string(),
+            empty_dir: Some(EmptyDirVolumeSource {
+                size_limit: role_config.scratch_volume_size.clone(),
+                ..EmptyDirVolumeSource::default()
+            }),
+            ..Volume::default()
+        }));
+}
+
+/// The `StatefulSet` of `role`, running `pod` with the user's customizations in `role_config` applied
+fn role_statefulset(
+    cluster: &ClusterContext,
+    role: &str,
+    replicas: Option<i32>,
+    role_config: &HdfsRoleConfig,
+    mut pod: PodSpec,
+) -> Result<StatefulSet, Error> {
+    let mut labels = cluster.user_labels(Some(role));
+    labels.extend(cluster.role_pod_labels(role));
+    labels.extend(cluster.recommended_labels(Some(role)));
+    let mut annotations = cluster.user_annotations(Some(role)).unwrap_or_default();
+    annotations.extend(cluster.restart_annotations(role));
+    if let Some(secret_hash) = cluster.secret_hashes.get(role) {
+        annotations.insert(SECRET_HASH_ANNOTATION.to_string(), secret_hash.clone());
+    }
+    if let Some(tls_secret_hash) = cluster.tls_secret_hashes.get(role) {
+        annotations.insert(
+            TLS_SECRET_HASH_ANNOTATION.to_string(),
+            tls_secret_hash.clone(),
+        );
+    }
+    if cluster.hdfs.spec.tls.is_some() {
+        pod.init_containers
+            .get_or_insert_with(Vec::new)
+            .insert(0, keystore_init_container(cluster));
+    }
+    apply_jvm_config(cluster, &mut pod, role, &role_config.jvm);
+    apply_tracing(cluster, &mut pod, role);
+    apply_read_only_root_filesystem(cluster, &mut pod, role_config);
+    let mut pod_template = PodTemplateSpec {
+        metadata: Some(ObjectMeta {
+            annotations: Some(annotations).filter(|annotations| !annotations.is_empty()),
+            labels: Some(labels),
+            ..ObjectMeta::default()
+        }),
+        spec: Some(pod),
+    };
+    apply_role_config(
+        &mut pod_template,
+        role_config,
+        &cluster.role_pod_labels(role),
+    )
+    .context(InvalidPodOverrides { role })?;
+    Ok(StatefulSet {
+        metadata: cluster.metadata(cluster.role_name(role), Some(role)),
+        spec: Some(StatefulSetSpec {
+            pod_management_policy: Some(
+                role_config
+                    .pod_management_policy
+                    .unwrap_or(PodManagementPolicy::Parallel)
+                    .to_string(),
+            ),
+            update_strategy: role_config.update_strategy.clone(),
+            replicas,
+            selector: LabelSelector {
+                match_labels: Some(cluster.role_pod_labels(role)),
+                ..LabelSelector::default()
+            },
+            service_name: cluster.role_name(role),
+            template: pod_template,
+            volume_claim_templates: Some(vec![local_disk_claim(
+                "data",
+                Quantity("1Gi".to_string()),
+            )]),
+            ..StatefulSetSpec::default()
+        }),
+        status: None,
+    })
+}
+
+/// The journalnodes' `data` volume, as configured by `journalnodeStorage`
+fn journal_claim(storage: &JournalnodeStorageConfig) -> PersistentVolumeClaim {
+    let size = storage
+        .size
+        .clone()
+        .unwrap_or_else(|| Quantity("1Gi".to_string()));
+    let mut claim = local_disk_claim("data", size);
+    if let Some(claim_spec) = &mut claim.spec {
+        claim_spec.storage_class_name = storage.storage_class_name.clone();
+    }
+    claim
+}
+
+pub fn build_journalnode_statefulset(cluster: &ClusterContext) -> Result<StatefulSet, Error> {
+    let hdfs = cluster.hdfs;
+    let mut sts = role_statefulset(
+        cluster,
+        "journalnode",
+        hdfs.spec.journalnode_replicas,
+        &hdfs.spec.journalnode,
+        PodSpec {
+            containers: vec![Container {
+                name: "journalnode".to_string(),
+                args: Some(vec![
+                    "/opt/hadoop/bin/hdfs".to_string(),
+                    "journalnode".to_string(),
+                ]),
+                ports: Some(
+                    [
+                        tcp_container_port("ipc", cluster.ports.journalnode_rpc),
+                        tcp_container_port("http", cluster.ports.journalnode_http),
+                    ]
+                    .into_iter()
+                    .chain(
+                        cluster
+                            .ports
+                            .journalnode_https
+                            .map(|port| tcp_container_port("https", port)),
+                    )
+                    .collect(),
+                ),
+                ..hadoop_container(cluster)
+            }],
+            ..role_pod_spec(cluster, "journalnode")
+        },
+    )?;
+    if let Some(spec) = &mut sts.spec {
+        spec.volume_claim_templates = Some(vec![journal_claim(&hdfs.spec.journalnode_storage)]);
+    }
+    Ok(sts)
+}
+
+/// Shell commands that authenticate as the namenode, and set `$self` to the namenode ID of the pod
+fn namenode_haadmin_preamble(cluster: &ClusterContext) -> String {
+    format!(
+        "kinit -kt {} {} || exit 0; \
+         self=name-${{POD_NAME##*-}}; ",
+        keytab_path(cluster, "nn"),
+        shell_quote(&format!(
+            "nn/{}@{}",
+            cluster.role_fqdn("namenode"),
+            kerberos_realm(cluster)
+        ))
+    )
+}
+
+/// Exposes the name of the pod as `$POD_NAME`, since the host network (if used) hides it from `$HOSTNAME`
+fn pod_name_env() -> EnvVar {
+    EnvVar {
+        name: "POD_NAME".to_string(),
+        value_from: Some(EnvVarSource {
+            field_ref: Some(ObjectFieldSelector {
+                field_path: "metadata.name".to_string(),
+                ..ObjectFieldSelector::default()
+            }),
+            ..EnvVarSource::default()
+        }),
+        ..EnvVar::default()
+    }
+}
+
+/// A `preStop` hook that runs the shell `script`
+fn pre_stop_hook(script: String) -> Lifecycle {
+    Lifecycle {
+        pre_stop: Some(Handler {
+            exec: Some(ExecAction {
+                command: Some(vec!["sh".to_string(), "-c".to_string(), script]),
+            }),
+            ..Handler::default()
+        }),
+        ..Lifecycle::default()
+    }
+}
+
+pub fn build_namenode_statefulset(cluster: &ClusterContext) -> Result<StatefulSet, Error> {
+    let hdfs = cluster.hdfs;
+    let mut pod = role_pod_spec(cluster, "namenode");
+    let mut zkfc_container = hadoop_container(cluster);
+    zkfc_container
+        .env
+        .get_or_insert_with(Vec::new)
+        .extend(ZOOKEEPER_VARIABLES.map(|key| EnvVar {
+            name: key.to_string(),
+            value_from: Some(EnvVarSource {
+                config_map_key_ref: Some(ConfigMapKeySelector {
+                    name: hdfs.spec.zookeeper_config_map_name().map(str::to_string),
+                    key: key.to_string(),
+                    ..ConfigMapKeySelector::default()
+                }),
+                ..EnvVarSource::default()
+            }),
+            ..EnvVar::default()
+        }));
+    let mut zkfc_opts = zkfc_heap_opts(cluster).into_iter().collect::<Vec<_>>();
+    // After the heap size, so that `extraArgs` may override it
+    zkfc_opts.extend(gc_opts(&hdfs.spec.failover_controller.gc));
+    match &hdfs.spec.failover_controller.auth {
+        Some(ZookeeperAuth::Digest { secret_name }) => {
+            zkfc_container
+                .volume_mounts
+                .get_or_insert_with(Vec::new)
+                .push(VolumeMount {
+                    mount_path: ZOOKEEPER_AUTH_DIR.to_string(),
+                    name: "zookeeper-auth".to_string(),
+                    ..VolumeMount::default()
+                });
+            pod.volumes.get_or_insert_with(Vec::new).push(Volume {
+                name: "zookeeper-auth".to_string(),
+                secret: Some(SecretVolumeSource {
+                    secret_name: Some(secret_name.clone()),
+                    ..SecretVolumeSource::default()
+                }),
+                ..Volume::default()
+            });
+        }
+        Some(ZookeeperAuth::Sasl {}) => {
+            // Only `hdfs zkfc` talks to ZooKeeper
+            zkfc_opts
+                .push("-Djava.security.auth.login.config=/config/zookeeper-jaas.conf".to_string());
+        }
+        None => {}
+    }
+    // Only picked up by `hdfs zkfc`, so formatting the namenode with the same container keeps its default heap
+    if !zkfc_opts.is_empty() {
+        zkfc_container
+            .env
+            .get_or_insert_with(Vec::new)
+            .push(EnvVar {
+                name: "HDFS_ZKFC_OPTS".to_string(),
+                value: Some(zkfc_opts.join(" ")),
+                ..EnvVar::default()
+            });
+    }
+    for (i, method) in hdfs
+        .spec
+        .failover_controller
+        .fencing_methods
+        .iter()
+        .enumerate()
+    {
+        let name = format!("fencing-{}", i);
+        zkfc_container
+            .volume_mounts
+            .get_or_insert_with(Vec::new)
+            .push(VolumeMount {
+                mount_path: fencing_dir(i),
+                name: name.clone(),
+                ..VolumeMount::default()
+            });
+        pod.volumes.get_or_insert_with(Vec::new).push(match method {
+            FencingMethod::Sshfence {
+                private_key_secret, ..
+            } => Volume {
+                name,
+                secret: Some(SecretVolumeSource {
+                    secret_name: Some(private_key_secret.clone()),
+                    ..SecretVolumeSource::default()
+                }),
+                ..Volume::default()
+            },
+            FencingMethod::Shell { config_map, .. } => Volume {
+                name,
+                config_map: Some(ConfigMapVolumeSource {
+                    name: Some(config_map.clone()),
+                    ..ConfigMapVolumeSource::default()
+                }),
+                ..Volume::default()
+            },
+        });
+    }
+    let namenode_replicas = hdfs.spec.namenode_replicas.unwrap_or(1);
+    let mut zkfc_lifecycle = None;
+    if namenode_replicas > 1 {
+        zkfc_container
+            .env
+            .get_or_insert_with(Vec::new)
+            .push(pod_name_env());
+        // The failover is coordinated by the ZKFCs, so the local one must outlive it
+        zkfc_lifecycle = Some(pre_stop_hook(format!(
+            "{}for i in $(seq {}); do \
+               [ \"$(/opt/hadoop/bin/hdfs haadmin -getServiceState $self)\" = active ] || break; \
+               sleep 1; \
+             done",
+            namenode_haadmin_preamble(cluster),
+            NAMENODE_FAILOVER_TIMEOUT_SECONDS
+        )));
+        pod.termination_grace_period_seconds = Some(NAMENODE_FAILOVER_TIMEOUT_SECONDS + 30);
+    }
+    let mut namenode_container = Container {
+        name: "namenode".to_string(),
+        args: Some(vec![
+            "/opt/hadoop/bin/hdfs".to_string(),
+            "namenode".to_string(),
+        ]),
+        ports: Some(
+            std::iter::once(tcp_container_port("ipc", cluster.ports.namenode_rpc))
+                .chain(
+                    cluster
+                        .ports
+                        .namenode_service_rpc
+                        .map(|port| tcp_container_port("service-ipc", port)),
+                )
+                .chain([tcp_container_port("http", cluster.ports.namenode_http)])
+                .chain(
+                    cluster
+                        .ports
+                        .namenode_https
+                        .map(|port| tcp_container_port("https", port)),
+                )
+                .collect(),
+        ),
+        ..hadoop_container(cluster)
+    };
+    if namenode_replicas > 1 {
+        namenode_container
+            .env
+            .get_or_insert_with(Vec::new)
+            .push(pod_name_env());
+        // Hand over to a standby before stopping, rather than leaving clients without an active namenode until the
+        // ZKFCs notice
+        let others = (0..namenode_replicas)
+            .map(|i| format!("name-{}", i))
+            .collect::<Vec<_>>()
+            .join(" ");
+        namenode_container.lifecycle = Some(pre_stop_hook(format!(
+            "{}[ \"$(/opt/hadoop/bin/hdfs haadmin -getServiceState $self)\" = active ] || exit 0; \
+             for other in {}; do \
+               [ \"$other\" != \"$self\" ] \
+                 && [ \"$(/opt/hadoop/bin/hdfs haadmin -getServiceState $other)\" = standby ] \
+                 && /opt/hadoop/bin/hdfs haadmin -failover $self $other \
+                 && exit 0; \
+             done",
+            namenode_haadmin_preamble(cluster),
+            others
+        )));
+    }
+    if let Some(authorizer) = &hdfs.spec.security.authorizer {
+        if let Some(lib_config_map) = &authorizer.lib_config_map {
+            namenode_container
+                .env
+                .get_or_insert_with(Vec::new)
+                .push(EnvVar {
+                    name: "HADOOP_CLASSPATH".to_string(),
+                    value: Some("/authorizer-lib/*".to_string()),
+                    ..EnvVar::default()
+                });
+            namenode_container
+                .volume_mounts
+                .get_or_insert_with(Vec::new)
+                .push(VolumeMount {
+                    mount_path: "/authorizer-lib".to_string(),
+                    name: "authorizer-lib".to_string(),
+                    ..VolumeMount::default()
+                });
+            pod.volumes.get_or_insert_with(Vec::new).push(Volume {
+                name: "authorizer-lib".to_string(),
+                config_map: Some(ConfigMapVolumeSource {
+                    name: Some(lib_config_map.clone()),
+                    ..ConfigMapVolumeSource::default()
+                }),
+                ..Volume::default()
+            });
+        }
+        if let Some(opa_config_map) = &authorizer.opa_config_map {
+            namenode_container
+                .env
+                .get_or_insert_with(Vec::new)
+                .push(EnvVar {
+                    name: "OPA_URL".to_string(),
+                    value_from: Some(EnvVarSource {
+                        config_map_key_ref: Some(ConfigMapKeySelector {
+                            name: Some(opa_config_map.clone()),
+                            key: "OPA".to_string(),
+                            ..ConfigMapKeySelector::default()
+                        }),
+                        ..EnvVarSource::default()
+                    }),
+                    ..EnvVar::default()
+                });
+        }
+    }
+    pod.init_containers = Some(vec![Container {
+        name: "format-namenode".to_string(),
+        args: Some(vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            "/opt/hadoop/bin/hdfs namenode -bootstrapStandby -nonInteractive \
+             || /opt/hadoop/bin/hdfs namenode -format -noninteractive \
+             || true
+             /opt/hadoop/bin/hdfs zkfc -formatZK -nonInteractive || true"
+                .to_string(),
+        ]),
+        ..zkfc_container.clone()
+    }]);
+    pod.containers = vec![
+        namenode_container,
+        Container {
+            name: "zkfc".to_string(),
+            args: Some(vec!["/opt/hadoop/bin/hdfs".to_string(), "zkfc".to_string()]),
+            lifecycle: zkfc_lifecycle,
+            // Accounted for separately, so that the ZKFC can't eat into the namenode's memory unnoticed
+            resources: Some(hdfs.spec.failover_controller.resources()),
+            ..zkfc_container
+        },
+    ];
+    role_statefulset(
+        cluster,
+        "namenode",
+        hdfs.spec.namenode_replicas,
+        &hdfs.spec.namenode,
+        pod,
+    )
+}
+
+/// The datanodes' container, which is told where the volumes of its group are mounted
+fn datanode_container(cluster: &ClusterContext, group: Option<&DatanodeGroupConfig>) -> Container {
+    let mut container = hadoop_container(cluster);
+    container.env.get_or_insert_with(Vec::new).push(EnvVar {
+        name: "DATANODE_DATA_DIRS".to_string(),
+        value: Some(datanode_data_dir_config(cluster, group)),
+        ..EnvVar::default()
+    });
+    container
+}
+
+/// Turns a datanode `StatefulSet` into the one of the datanode group `group`
+fn apply_datanode_group(
+    cluster: &ClusterContext,
+    sts: &mut StatefulSet,
+    group: &str,
+    config: &DatanodeGroupConfig,
+) -> Result<(), Error> {
+    let role = datanode_group_role(group);
+    let pods = &config.pods;
+    let mut labels = pods.labels.clone();
+    labels.extend(datanode_group_labels(group));
+    sts.metadata.name = Some(cluster.role_name(&role));
+    sts.metadata
+        .labels
+        .get_or_insert_with(BTreeMap::new)
+        .extend(labels.clone());
+    if !pods.annotations.is_empty() {
+        sts.metadata
+            .annotations
+            .get_or_insert_with(BTreeMap::new)
+            .extend(pods.annotations.clone());
+    }
+    let spec = match &mut sts.spec {
+        Some(spec) => spec,
+        None => return Ok(()),
+    };
+    spec.replicas = Some(config.replicas.unwrap_or(1));
+    spec.service_name = cluster.role_name(&role);
+    let pod_labels = spec.selector.match_labels.get_or_insert_with(BTreeMap::new);
+    pod_labels.insert(DATANODE_GROUP_LABEL.to_string(), group.to_string());
+    let pod_labels = pod_labels.clone();
+    if let Some(pod_management_policy) = pods.pod_management_policy {
+        spec.pod_management_policy = Some(pod_management_policy.to_string());
+    }
+    if pods.update_strategy.is_some() {
+        spec.update_strategy = pods.update_strategy.clone();
+    }
+    for claim in spec.volume_claim_templates.iter_mut().flatten() {
+        if let Some(claim_spec) = &mut claim.spec {
+            claim_spec.storage_class_name = config.storage_class_name.clone();
+        }
+    }
+    let template = &mut spec.template;
+    let template_metadata = template.metadata.get_or_insert_with(ObjectMeta::default);
+    template_metadata
+        .labels
+        .get_or_insert_with(BTreeMap::new)
+        .extend(labels);
+    if !pods.annotations.is_empty() {
+        template_metadata
+            .annotations
+            .get_or_insert_with(BTreeMap::new)
+            .extend(pods.annotations.clone());
+    }
+    // Each group has its own ConfigMap, with the group's configOverrides
+    for volume in template
+        .spec
+        .iter_mut()
+        .flat_map(|spec| spec.volumes.iter_mut().flatten())
+        .filter(|volume| volume.name == "config")
+    {
+        if let Some(config_map) = &mut volume.config_map {
+            config_map.name = Some(cluster.config_name(&role));
+        }
+    }
+    if !config.node_selector.is_empty() {
+        template
+            .spec
+            .get_or_insert_with(PodSpec::default)
+            .node_selector
+            .get_or_insert_with(BTreeMap::new)
+            .extend(config.node_selector.clone());
+    }
+    apply_role_config(template, pods, &pod_labels).context(InvalidPodOverrides { role })
+}
+
+pub fn build_datanode_statefulset(cluster: &ClusterContext) -> Result<StatefulSet, Error> {
+    datanode_statefulset(cluster, None)
+}
+
+/// The `StatefulSet` of each group of datanodes, starting with the `datanodeReplicas` datanodes
+pub fn build_datanode_statefulsets(cluster: &ClusterContext) -> Result<Vec<StatefulSet>, Error> {
+    std::iter::once(datanode_statefulset(cluster, None))
+        .chain(
+            cluster
+                .hdfs
+                .spec
+                .datanode_groups
+                .iter()
+                .map(|(group, config)| {
+                    datanode_statefulset(cluster, Some((group.as_str(), config)))
+                }),
+        )
+        .collect()
+}
+
+/// The `StatefulSet` of the datanode group `group`, or of the `datanodeReplicas` datanodes
+fn datanode_statefulset(
+    cluster: &ClusterContext,
+    group: Option<(&str, &DatanodeGroupConfig)>,
+) -> Result<StatefulSet, Error> {
+    let hdfs = cluster.hdfs;
+    let ports = &cluster.ports;
+    let group_config = group.map(|(_, config)| config);
+    let mut sts = role_statefulset(
+        cluster,
+        "datanode",
+        hdfs.spec.datanode_replicas,
+        &hdfs.spec.datanode,
+        PodSpec {
+            containers: vec![Container {
+                name: "datanode".to_string(),
+                args: Some(vec![
+                    "/opt/hadoop/bin/hdfs".to_string(),
+                    "datanode".to_string(),
+                ]),
+                lifecycle: Some(Lifecycle {
+                    pre_stop: Some(Handler {
+                        exec: Some(ExecAction {
+                            command: Some(vec![
+                                "sh".to_string(),
+                                "-c".to_string(),
+                                // The DataXceiverServer thread itself is also counted as a xceiver. The certificate
+                                // doesn't name localhost, so it isn't verified. Stopping right away without an HTTP
+                                // client would silently skip the drain, so that fails the hook instead.
+                                format!(
+                                    "if command -v curl >/dev/null; then get='curl -sk'; \
+                                     elif command -v wget >/dev/null; then get='wget -qO- --no-check-certificate'; \
+                                     else echo 'preStop needs curl or wget to query the datanode' >&2; exit 1; \
+                                     fi; \
+                                     for i in $(seq {}); do \
+                                       $get '{}://localhost:{}/jmx?qry=Hadoop:service=DataNode,name=DataNodeInfo' \
+                                         | grep -Eq '\"XceiverCount\" : [01],?$' && break; \
+                                       sleep 1; \
+                                     done",
+                                    DATANODE_DRAIN_TIMEOUT_SECONDS,
+                                    if ports.datanode_https.is_some() { "https" } else { "http" },
+                                    ports.datanode_web()
+                                ),
+                            ]),
+                        }),
+                        ..Handler::default()
+                    }),
+                    ..Lifecycle::default()
+                }),
+                ports: Some(
+                    [
+                        tcp_container_port("ipc", ports.datanode_ipc),
+                        tcp_container_port("data", ports.datanode_data),
+                        tcp_container_port("http", ports.datanode_http),
+                    ]
+                    .into_iter()
+                    .chain(ports.datanode_https.map(|port| tcp_container_port("https", port)))
+                    .collect(),
+                ),
+                ..datanode_container(cluster, group_config)
+            }],
+            termination_grace_period_seconds: Some(DATANODE_DRAIN_TIMEOUT_SECONDS + 30),
+            ..role_pod_spec(cluster, "datanode")
+        },
+    )?;
+    if let Some(spec) = &mut sts.spec {
+        for (i, data_dir) in datanode_data_dirs(cluster, group_config)
+            .iter()
+            .enumerate()
+            .skip(1)
+        {
+            let name = format!("data-{}", i);
+            for container in spec
+                .template
+                .spec
+                .iter_mut()
+                .flat_map(|pod| &mut pod.containers)
+                .filter(|container| container.name == "datanode")
+            {
+                container
+                    .volume_mounts
+                    .get_or_insert_with(Vec::new)
+                    .push(VolumeMount {
+                        mount_path: data_dir.clone(),
+                        name: name.clone(),
+                        ..VolumeMount::default()
+                    });
+            }
+            spec.volume_claim_templates
+                .get_or_insert_with(Vec::new)
+                .push(local_disk_claim(&name, Quantity("1Gi".to_string())));
+        }
+    }
+    if let Some((group, config)) = group {
+        apply_datanode_group(cluster, &mut sts, group, config)?;
+    }
+    let role = group.map_or_else(
+        || "datanode".to_string(),
+        |(group, _)| datanode_group_role(group),
+    );
+    if let Some(scale_down) = cluster.scale_downs.get(&role) {
+        // The surplus pods are only deleted once they have been decommissioned
+        if let (false, Some(spec)) = (scale_down.decommissioned, &mut sts.spec) {
+            spec.replicas = Some(scale_down.pods);
+        }
+    }
+    if hdfs.spec.datanode_rollout.is_some() {
+        // The operator deletes outdated pods itself, see `crate::rollout`
+        if let Some(spec) = &mut sts.spec {
+            spec.update_strategy = Some(StatefulSetUpdateStrategy {
+                type_: Some("OnDelete".to_string()),
+                rolling_update: None,
+            });
+        }
+    }
+    Ok(sts)
+}
+
+/// Quotes `arg` so that `sh` passes it to the command verbatim
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+/// The `hdfs dfsadmin` arguments that perform the action of `task`, one command per entry
+fn admin_task_commands(task: &HdfsAdminTask) -> Result<Vec<Vec<String>>, Error> {
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+    Ok(match task.spec.action {
+        HdfsAdminAction::SafemodeEnter => vec![args(&["-safemode", "enter"])],
+        HdfsAdminAction::SafemodeLeave => vec![args(&["-safemode", "leave"])],
+        HdfsAdminAction::SaveNamespace => vec![args(&["-saveNamespace"])],
+        HdfsAdminAction::RefreshNodes => vec![args(&["-refreshNodes"])],
+        HdfsAdminAction::SetQuota => {
+            let quota = task.spec.quota.as_ref().context(MissingQuota)?;
+            let mut commands = Vec::new();
+            if let Some(namespace_quota) = quota.namespace_quota {
+                commands.push(args(&[
+                    "-setQuota",
+                    namespace_quota.to_string().as_str(),
+                    quota.path.as_str(),
+                ]));
+            }
+            if let Some(space_quota) = &quota.space_quota {
+                commands.push(args(&[
+                    "-setSpaceQuota",
+                    space_quota.as_str(),
+                    quota.path.as_str(),
+                ]));
+            }
+            ensure!(
+                !commands.is_empty(),
+                EmptyQuota {
+                    path: quota.path.clone()
+                }
+            );
+            commands
+        }
+        HdfsAdminAction::AllowSnapshot | HdfsAdminAction::DisallowSnapshot => {
+            let flag = match task.spec.action {
+                HdfsAdminAction::AllowSnapshot => "-allowSnapshot",
+                _ => "-disallowSnapshot",
+            };
+            ensure!(!task.spec.paths.is_empty(), MissingPaths);
+            task.spec
+                .paths
+                .iter()
+                .map(|path| args(&[flag, path.as_str()]))
+                .collect()
+        }
+    })
+}
+
+/// Hashes the contents of `secret`, so that changes to it can be detected
+pub fn secret_hash(secret: &Secret) -> String {
+    let mut hasher = FnvHasher::default();
+    for (key, value) in secret.data.iter().flatten() {
+        key.hash(&mut hasher);
+        value.0.hash(&mut hasher);
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+/// Name of the `Job` that runs `task`
+pub fn admin_task_job_name(task: &HdfsAdminTask) -> String {
+    format!(
+        "{}-admintask",
+        task.metadata.name.as_deref().unwrap_or_default()
+    )
+}
+
+/// The `Job` that runs the command of `task` against the cluster, authenticated as the namenode
+pub fn build_admin_task_job(cluster: &ClusterContext, task: &HdfsAdminTask) -> Result<Job, Error> {
+    let hdfs = cluster.hdfs;
+    let mut script = vec![format!(
+        "kinit -kt {} {}",
+        keytab_path(cluster, "nn"),
+        shell_quote(&format!(
+            "nn/{}@{}",
+            cluster.role_fqdn("namenode"),
+            kerberos_realm(cluster)
+        ))
+    )];
+    script.extend(admin_task_commands(task)?.into_iter().map(|args| {
+        std::iter::once("/opt/hadoop/bin/hdfs dfsadmin".to_string())
+            .chain(args.iter().map(|arg| shell_quote(arg)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }));
+    let mut container = Container {
+        name: "dfsadmin".to_string(),
+        args: Some(vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            script.join(" && "),
+        ]),
+        ..hadoop_container(cluster)
+    };
+    // Admin commands don't store anything locally
+    if let Some(volume_mounts) = &mut container.volume_mounts {
+        volume_mounts.retain(|mount| mount.name != "data");
+    }
+    let mut pod = PodSpec {
+        containers: vec![container],
+        restart_policy: Some("Never".to_string()),
+        host_network: None,
+        dns_policy: None,
+        ..role_pod_spec(cluster, "namenode")
+    };
+    // The ticket cache is written to /tmp, so the job needs the namenodes' scratch volumes
+    apply_read_only_root_filesystem(cluster, &mut pod, &hdfs.spec.namenode);
+    let mut pod_labels = cluster.user_labels(None);
+    pod_labels.extend(cluster.recommended_labels(None));
+    Ok(Job {
+        metadata: ObjectMeta {
+            owner_references: Some(vec![controller_reference_to_obj(task)]),
+            ..cluster.metadata(admin_task_job_name(task), None)
+        },
+        spec: Some(JobSpec {
+            // Commands such as saveNamespace should not be repeated behind the user's back
+            backoff_limit: Some(0),
+            template: PodTemplateSpec {
+                metadata: Some(ObjectMeta {
+                    annotations: cluster.user_annotations(None),
+                    labels: Some(pod_labels),
+                    ..ObjectMeta::default()
+                }),
+                spec: Some(pod),
+            },
+            ..JobSpec::default()
+        }),
+        ..Job::default()
+    })
+}
+
+/// The `Secret` with the keystore password, which is only created once so that it stays stable across reconciles
+pub fn build_keystore_secret(cluster: &ClusterContext, password: String) -> Secret {
+    Secret {
+        metadata: cluster.metadata(cluster.keystore_secret_name(), None),
+        string_data: Some(BTreeMap::from([(
+            KEYSTORE_PASSWORD_KEY.to_string(),
+            password,
+        )])),
+        ..Secret::default()
+    }
+}
+
+/// The `HdfsAdminTask` that makes the namenodes reread the hosts file, if it has changed
+///
+/// The task is named after the hosts file, so that it is rerun whenever the file changes. It is also run once no
+/// datanode is decommissioned or in maintenance anymore, since the namenodes would otherwise keep treating any
+/// datanode that takes over one of their (host network) addresses like them.
+pub fn build_refresh_nodes_task(cluster: &ClusterContext) -> Option<HdfsAdminTask> {
+    let hosts = datanode_hosts(cluster);
+    let was_listed = cluster.hdfs.status.as_ref().map_or(false, |status| {
+        !status.decommissioning_datanodes.is_empty() || !status.maintenance_datanodes.is_empty()
+    });
+    if hosts.is_empty() && !was_listed {
+        return None;
+    }
+    let mut hasher = FnvHasher::default();
+    serde_json::Value::from(hosts).to_string().hash(&mut hasher);
+    let name = format!(
+        "{}-refresh-nodes-{:08x}",
+        cluster.name,
+        hasher.finish() as u32
+    );
+    let mut task = HdfsAdminTask::new(
+        &name,
+        HdfsAdminTaskSpec {
+            cluster_name: cluster.name.clone(),
+            action: HdfsAdminAction::RefreshNodes,
+            quota: None,
+            paths: Vec::new(),
+        },
+    );
+    task.metadata = cluster.metadata(name, Some("datanode"));
+    Some(task)
+}
+
+/// The `HdfsAdminTask` that allows snapshots of the `snapshot.allowSnapshot` directories, if there are any
+///
+/// The task is named after the directories, so that it is rerun whenever they change.
+pub fn build_allow_snapshot_task(cluster: &ClusterContext) -> Option<HdfsAdminTask> {
+    let paths = &cluster.hdfs.spec.snapshot.allow_snapshot;
+    if paths.is_empty() {
+        return None;
+    }
+    let mut hasher = FnvHasher::default();
+    paths.hash(&mut hasher);
+    let name = format!(
+        "{}-allow-snapshot-{:08x}",
+        cluster.name,
+        hasher.finish() as u32
+    );
+    let mut task = HdfsAdminTask::new(
+        &name,
+        HdfsAdminTaskSpec {
+            cluster_name: cluster.name.clone(),
+            action: HdfsAdminAction::AllowSnapshot,
+            quota: None,
+            paths: paths.clone(),
+        },
+    );
+    task.metadata = cluster.metadata(name, Some("namenode"));
+    Some(task)
+}
+
+/// The cert-manager `Certificate` of `role`, if its certificates are issued by cert-manager
+///
+/// The certificate is valid for the role's `Service` and each of its pods, so that clients can verify whichever
+/// address they connect to. All datanode groups share the datanodes' certificate.
+pub fn build_certificate(cluster: &ClusterContext, role: &str) -> Option<Certificate> {
+    let spec = &cluster.hdfs.spec;
+    let cert_manager = spec.tls.as_ref()?.cert_manager.as_ref()?;
+    let roles = match role {
+        "journalnode" => vec![(role.to_string(), spec.journalnode_replicas.unwrap_or(1))],
+        "namenode" => vec![(role.to_string(), spec.namenode_replicas.unwrap_or(1))],
+        _ => datanode_roles(cluster),
+    };
+    let name = cluster.role_name(role);
+    let mut certificate = Certificate::new(
+        &name,
+        CertificateSpec {
+            secret_name: cluster.tls_secret_name(role),
+            dns_names: roles
+                .iter()
+                .flat_map(|(role, replicas)| {
+                    std::iter::once(cluster.role_fqdn(role))
+                        .chain((0..*replicas).map(move |i| cluster.pod_fqdn(role, i)))
+                })
+                .collect(),
+            issuer_ref: IssuerRef {
+                name: cert_manager.issuer_name.clone(),
+                kind: cert_manager.issuer_kind.to_string(),
+                group: "cert-manager.io".to_string(),
+            },
+        },
+    );
+    certificate.metadata = cluster.metadata(name, Some(role));
+    Some(certificate)
+}
+
+/// Renders all objects of the cluster as a YAML stream, in the order that they are applied
+pub fn render(cluster: &ClusterContext) -> Result<String, Error> {
+    validate(cluster)?;
+    let config_maps = build_config_maps(cluster);
+    for sts in [
+        build_journalnode_statefulset(cluster)?,
+        build_namenode_statefulset(cluster)?,
+    ]
+    .into_iter()
+    .chain(build_datanode_statefulsets(cluster)?)
+    {
+        check_placeholders(&sts, &config_maps)?;
+    }
+    let mut docs = config_maps
+        .iter()
+        .map(serde_yaml::to_string)
+        .collect::<Vec<_>>();
+    docs.push(serde_yaml::to_string(&build_discovery_config_map(cluster)));
+    if let Some(service_account) = build_service_account(cluster) {
+        docs.push(serde_yaml::to_string(&service_account));
+    }
+    if let Some(role_binding) = build_role_binding(cluster) {
+        docs.push(serde_yaml::to_string(&role_binding));
+    }
+    if let Some(network_policy) = build_network_policy(cluster) {
+        docs.push(serde_yaml::to_string(&network_policy));
+    }
+    docs.extend(
+        ["journalnode", "namenode", "datanode"]
+            .iter()
+            .filter_map(|role| build_certificate(cluster, role))
+            .map(|certificate| serde_yaml::to_string(&certificate)),
+    );
+    docs.extend([
+        serde_yaml::to_string(&build_journalnode_service(cluster)),
+        serde_yaml::to_string(&build_journalnode_statefulset(cluster)?),
+        serde_yaml::to_string(&build_namenode_service(cluster)),
+        serde_yaml::to_string(&build_namenode_statefulset(cluster)?),
+    ]);
+    for (svc, sts) in build_datanode_services(cluster)
+        .iter()
+        .zip(build_datanode_statefulsets(cluster)?)
+    {
+        docs.extend([serde_yaml::to_string(svc), serde_yaml::to_string(&sts)]);
+    }
+    Ok(docs
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .context(RenderYaml)?
+        .join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::ByteString;
+    use std::path::PathBuf;
+
+    const SIMPLE: &str = r#"
+apiVersion: hdfs.stackable.tech/v1alpha1
+kind: HdfsCluster
+metadata:
+  name: simple
+  namespace: default
+  uid: 4c1a5f0e-7d3b-4e55-9f6a-2b8c1d0e9a71
+spec:
+  namenodeReplicas: 2
+  datanodeReplicas: 3
+  journalnodeReplicas: 3
+  zookeeperConfigMapName: simple-znode
+  kerberos:
+    realm: STACKABLE
+    kdc: krb5-kdc.default.svc.cluster.local
+"#;
+
+    const FULL: &str = r#"
+apiVersion: hdfs.stackable.tech/v1alpha1
+kind: HdfsCluster
+metadata:
+  name: full
+  namespace: hdfs
+  uid: 9e2d7b14-3a6f-4c08-8b5e-61f0a4c2d3b9
+spec:
+  namenodeReplicas: 2
+  datanodeReplicas: 4
+  journalnodeReplicas: 3
+  namenodeZnodeConfigMap: full-znode
+  ports:
+    namenodeRpc: 9000
+    datanodeHttp: 19864
+  dfsReplication: 2
+  dfsReplicationMin: 1
+  dfsDatanodeDuReserved: 1073741824
+  trash:
+    interval: 1440
+    checkpointInterval: 60
+  snapshot:
+    captureOpenFiles: true
+    maxLimit: 100
+  kerberos:
+    realm: EXAMPLE.COM
+    kdc: kdc.example.com
+  security:
+    authorization:
+      defaultAcl:
+        users: [hdfs]
+        groups: [hadoop]
+      acls:
+        security.client.protocol.acl:
+          users: ["*"]
+    authorizer:
+      providerClass: tech.stackable.hadoop.OpaAttributeProvider
+      libConfigMap: opa-authorizer-lib
+      opaConfigMap: opa
+  hostNetwork: false
+  networkIsolation:
+    allowedClients:
+      - podLabels:
+          hdfs-client: "true"
+  serviceAccount:
+    imagePullSecrets: [registry-credentials]
+    clusterRole: hdfs-clusterrole
+  datanode:
+    priorityClassName: hdfs-datanode
+    podOverrides:
+      spec:
+        nodeSelector:
+          storage: hdd
+  auditLog:
+    target: file
+"#;
+
+    fn parse_hdfs(yaml: &str) -> HdfsCluster {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn with_spec(yaml: &str, patch: serde_json::Value) -> HdfsCluster {
+        let mut hdfs = serde_json::to_value(parse_hdfs(yaml)).unwrap();
+        merge_json(&mut hdfs, serde_json::json!({ "spec": patch }));
+        serde_json::from_value(hdfs).unwrap()
+    }
+
+    /// Compares `actual` to the golden file `tests/golden/<name>`
+    ///
+    /// Run the tests with `UPDATE_GOLDEN=1` to regenerate the golden files after an intentional change.
+    fn assert_golden(name: &str, actual: &str) {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden")
+            .join(name);
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, actual).unwrap();
+        }
+        let expected = std::fs::read_to_string(&path)
+            .unwrap_or_else(|err| panic!("failed to read {}: {}", path.display(), err));
+        assert_eq!(actual, expected, "{} differs from its golden file", name);
+    }
+
+    fn file<'a>(config_map: &'a ConfigMap, file: &str) -> &'a str {
+        &config_map.data.as_ref().unwrap()[file]
+    }
+
+    fn env_value<'a>(container: &'a Container, name: &str) -> Option<&'a str> {
+        container
+            .env
+            .iter()
+            .flatten()
+            .find(|env| env.name == name)?
+            .value
+            .as_deref()
+    }
+
+    fn pod_spec(sts: &StatefulSet) -> &PodSpec {
+        sts.spec.as_ref().unwrap().template.spec.as_ref().unwrap()
+    }
+
+    #[test]
+    fn simple_config() {
+        let hdfs = parse_hdfs(SIMPLE);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        for role in ["journalnode", "namenode", "datanode"] {
+            let config = build_config_map(&cluster, role);
+            assert_eq!(
+                config.metadata.name,
+                Some(format!("simple-{}-config", role))
+            );
+            for name in ["core-site.xml", "krb5.conf"] {
+                assert_golden(&format!("simple/config/{}", name), file(&config, name));
+            }
+            assert_golden(
+                &format!("simple/config/{}/hdfs-site.xml", role),
+                file(&config, "hdfs-site.xml"),
+            );
+            assert!(!config
+                .data
+                .as_ref()
+                .unwrap()
+                .contains_key("hadoop-policy.xml"));
+            // Only the namenodes decommission datanodes
+            assert_eq!(
+                config.data.as_ref().unwrap().contains_key("dfs.hosts.json"),
+                role == "namenode"
+            );
+        }
+
+        let discovery = build_discovery_config_map(&cluster);
+        assert_eq!(discovery.metadata.name.as_deref(), Some("simple"));
+        assert_eq!(discovery.data.as_ref().unwrap().len(), 2);
+        for name in ["core-site.xml", "hdfs-site.xml"] {
+            assert_golden(
+                &format!("simple/discovery/{}", name),
+                file(&discovery, name),
+            );
+        }
+    }
+
+    #[test]
+    fn endpoints_match_the_client_config() {
+        let hdfs = parse_hdfs(SIMPLE);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let endpoints = build_endpoints(&cluster);
+        assert_eq!(endpoints.default_fs, "hdfs://simple/");
+        assert_eq!(
+            endpoints.namenode_rpc,
+            [
+                "simple-namenode-0.simple-namenode.default.svc.cluster.local:8020",
+                "simple-namenode-1.simple-namenode.default.svc.cluster.local:8020",
+            ]
+        );
+        let hdfs_site = file(&build_discovery_config_map(&cluster), "hdfs-site.xml").to_string();
+        for (i, (rpc, http)) in endpoints
+            .namenode_rpc
+            .iter()
+            .zip(&endpoints.namenode_http)
+            .enumerate()
+        {
+            assert!(hdfs_site.contains(&format!(
+                "<name>dfs.namenode.rpc-address.simple.name-{}</name><value>{}</value>",
+                i, rpc
+            )));
+            assert!(hdfs_site.contains(&format!(
+                "<name>dfs.namenode.http-address.simple.name-{}</name><value>{}</value>",
+                i, http
+            )));
+        }
+        assert!(
+            file(&build_config_map(&cluster, "namenode"), "hdfs-site.xml").contains(&format!(
+                "<name>dfs.namenode.shared.edits.dir</name><value>{}</value>",
+                endpoints.journalnode_quorum
+            ))
+        );
+        assert!(endpoints
+            .journalnode_quorum
+            .starts_with("qjournal://simple-journalnode-0."));
+    }
+
+    #[test]
+    fn full_config() {
+        let hdfs = parse_hdfs(FULL);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        for role in ["journalnode", "namenode", "datanode"] {
+            let config = build_config_map(&cluster, role);
+            for name in ["core-site.xml", "hadoop-policy.xml"] {
+                assert_golden(&format!("full/config/{}", name), file(&config, name));
+            }
+            assert_golden(
+                &format!("full/config/{}/hdfs-site.xml", role),
+                file(&config, "hdfs-site.xml"),
+            );
+            // The audit log is written by the namenodes
+            assert_eq!(
+                file(&config, "log4j.properties").ends_with(
+                    "log4j.logger.org.apache.hadoop.hdfs.server.namenode.FSNamesystem.audit=INFO,RFAAUDIT\n"
+                ),
+                role == "namenode"
+            );
+        }
+
+        let discovery = build_discovery_config_map(&cluster);
+        for name in ["core-site.xml", "hdfs-site.xml"] {
+            assert_golden(&format!("full/discovery/{}", name), file(&discovery, name));
+        }
+    }
+
+    #[test]
+    fn all_objects_are_owned_by_the_cluster() {
+        let hdfs = parse_hdfs(FULL);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let metadata = [
+            build_config_map(&cluster, "namenode").metadata,
+            build_discovery_config_map(&cluster).metadata,
+            build_service_account(&cluster).unwrap().metadata,
+            build_role_binding(&cluster).unwrap().metadata,
+            build_network_policy(&cluster).unwrap().metadata,
+            build_journalnode_service(&cluster).metadata,
+            build_namenode_service(&cluster).metadata,
+            build_datanode_service(&cluster).metadata,
+            build_journalnode_statefulset(&cluster).unwrap().metadata,
+            build_namenode_statefulset(&cluster).unwrap().metadata,
+            build_datanode_statefulset(&cluster).unwrap().metadata,
+        ];
+        for metadata in metadata {
+            assert_eq!(metadata.namespace.as_deref(), Some("hdfs"));
+            let owner_refs = metadata.owner_references.unwrap();
+            assert_eq!(owner_refs.len(), 1);
+            assert_eq!(owner_refs[0].uid, "9e2d7b14-3a6f-4c08-8b5e-61f0a4c2d3b9");
+            assert_eq!(owner_refs[0].controller, Some(true));
+        }
+    }
+
+    #[test]
+    fn objects_have_recommended_labels() {
+        let hdfs = with_spec(SIMPLE, serde_json::json!({ "image": { "tag": "3.2.2" } }));
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let config_labels = build_discovery_config_map(&cluster)
+            .metadata
+            .labels
+            .unwrap();
+        assert_eq!(config_labels["app.kubernetes.io/name"], "hdfs");
+        assert_eq!(config_labels["app.kubernetes.io/instance"], "simple");
+        assert_eq!(config_labels["app.kubernetes.io/version"], "3.2.2");
+        assert_eq!(
+            config_labels["app.kubernetes.io/managed-by"],
+            "hdfs-operator"
+        );
+        assert!(!config_labels.contains_key("app.kubernetes.io/component"));
+
+        let sts = build_namenode_statefulset(&cluster).unwrap();
+        let sts_labels = sts.metadata.labels.as_ref().unwrap();
+        assert_eq!(sts_labels["app.kubernetes.io/component"], "namenode");
+        assert_eq!(sts_labels["app.kubernetes.io/role-group"], "default");
+        assert!(sts_labels.contains_key("stackable.tech/operator-version"));
+        let pod_labels = sts.spec.unwrap().template.metadata.unwrap().labels.unwrap();
+        assert_eq!(pod_labels["app.kubernetes.io/component"], "namenode");
+        // Upgrading the operator must not restart the pods
+        assert!(!pod_labels.contains_key("stackable.tech/operator-version"));
+
+        let svc_labels = build_datanode_service(&cluster).metadata.labels.unwrap();
+        assert_eq!(svc_labels["app.kubernetes.io/component"], "datanode");
+    }
+
+    #[test]
+    fn role_services_are_configurable() {
+        let hdfs = parse_hdfs(SIMPLE);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let namenode = build_namenode_service(&cluster).spec.unwrap();
+        assert_eq!(namenode.cluster_ip.as_deref(), Some("None"));
+        assert_eq!(namenode.publish_not_ready_addresses, Some(true));
+        let datanode = build_datanode_service(&cluster).spec.unwrap();
+        assert_eq!(datanode.cluster_ip.as_deref(), Some("None"));
+        assert_eq!(datanode.publish_not_ready_addresses, None);
+
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "namenode": { "service": { "publishNotReadyAddresses": false } },
+                "datanode": { "service": { "headless": false, "publishNotReadyAddresses": true } },
+                "datanodeGroups": { "ssd": {} },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        validate(&cluster).unwrap();
+        let namenode = build_namenode_service(&cluster).spec.unwrap();
+        assert_eq!(namenode.cluster_ip.as_deref(), Some("None"));
+        assert_eq!(namenode.publish_not_ready_addresses, Some(false));
+        // The groups use the settings of `datanode`
+        for datanode in build_datanode_services(&cluster) {
+            let datanode = datanode.spec.unwrap();
+            assert_eq!(datanode.cluster_ip, None);
+            assert_eq!(datanode.publish_not_ready_addresses, Some(true));
+        }
+
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({ "journalnode": { "service": { "headless": false } } }),
+        );
+        assert!(matches!(
+            validate(&ClusterContext::new(&hdfs, "cluster.local").unwrap()),
+            Err(Error::NonHeadlessService { role }) if role == "journalnode"
+        ));
+    }
+
+    #[test]
+    fn user_labels_and_annotations_are_propagated() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "labels": { "team": "storage", "app.kubernetes.io/name": "mine" },
+                "annotations": { "prometheus.io/scrape": "true" },
+                "datanode": {
+                    "labels": { "team": "datanodes", "role": "mine" },
+                    "annotations": { "prometheus.io/port": "9864" },
+                },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let config_map = build_config_map(&cluster, "namenode").metadata;
+        assert_eq!(config_map.labels.unwrap()["team"], "storage");
+        let config_annotations = config_map.annotations.unwrap();
+        assert_eq!(config_annotations["prometheus.io/scrape"], "true");
+        assert!(!config_annotations.contains_key("prometheus.io/port"));
+
+        let sts = build_datanode_statefulset(&cluster).unwrap();
+        let sts_labels = sts.metadata.labels.unwrap();
+        assert_eq!(sts_labels["team"], "datanodes");
+        // Generated labels take precedence
+        assert_eq!(sts_labels["app.kubernetes.io/name"], "hdfs");
+        let pod = sts.spec.unwrap().template.metadata.unwrap();
+        let pod_labels = pod.labels.unwrap();
+        assert_eq!(pod_labels["team"], "datanodes");
+        assert_eq!(pod_labels["role"], "datanode");
+        assert_eq!(pod.annotations.unwrap()["prometheus.io/port"], "9864");
+
+        let svc_labels = build_namenode_service(&cluster).metadata.labels.unwrap();
+        assert_eq!(svc_labels["team"], "storage");
+        assert!(build_journalnode_statefulset(&cluster)
+            .unwrap()
+            .metadata
+            .annotations
+            .unwrap()
+            .contains_key("prometheus.io/scrape"));
+    }
+
+    #[test]
+    fn restart_annotations_are_copied_to_the_targeted_roles() {
+        let mut hdfs = parse_hdfs(SIMPLE);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let pod_annotations =
+            |sts: StatefulSet| sts.spec.unwrap().template.metadata.unwrap().annotations;
+        assert_eq!(
+            pod_annotations(build_namenode_statefulset(&cluster).unwrap()),
+            None
+        );
+
+        hdfs.metadata.annotations = Some(BTreeMap::from([
+            (
+                RESTART_ANNOTATION.to_string(),
+                "2021-11-01T12:00:00Z".to_string(),
+            ),
+            (
+                "hdfs.stackable.tech/restart-datanode".to_string(),
+                "2021-11-02T12:00:00Z".to_string(),
+            ),
+            ("unrelated".to_string(), "value".to_string()),
+        ]));
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let namenode = pod_annotations(build_namenode_statefulset(&cluster).unwrap()).unwrap();
+        assert_eq!(namenode.len(), 1);
+        assert_eq!(namenode[RESTART_ANNOTATION], "2021-11-01T12:00:00Z");
+        let datanode = pod_annotations(build_datanode_statefulset(&cluster).unwrap()).unwrap();
+        assert_eq!(datanode.len(), 2);
+        assert_eq!(
+            datanode["hdfs.stackable.tech/restart-datanode"],
+            "2021-11-02T12:00:00Z"
+        );
+    }
+
+    #[test]
+    fn secret_hashes_are_copied_to_the_pod_templates() {
+        let mut secret = Secret {
+            data: Some(BTreeMap::from([(
+                "nn.service.keytab".to_string(),
+                ByteString(b"keytab".to_vec()),
+            )])),
+            ..Secret::default()
+        };
+        let hash = secret_hash(&secret);
+        assert_eq!(hash, secret_hash(&secret));
+        secret.data.as_mut().unwrap().insert(
+            "nn.service.keytab".to_string(),
+            ByteString(b"rotated".to_vec()),
+        );
+        assert_ne!(hash, secret_hash(&secret));
+
+        let hdfs = parse_hdfs(SIMPLE);
+        let mut cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        cluster
+            .secret_hashes
+            .insert("namenode".to_string(), hash.clone());
+        let pod = |sts: StatefulSet| sts.spec.unwrap().template.metadata.unwrap();
+        let namenode = pod(build_namenode_statefulset(&cluster).unwrap());
+        assert_eq!(namenode.annotations.unwrap()[SECRET_HASH_ANNOTATION], hash);
+        let datanode = pod(build_datanode_statefulset(&cluster).unwrap());
+        assert_eq!(datanode.annotations, None);
+
+        cluster
+            .tls_secret_hashes
+            .insert("datanode".to_string(), hash.clone());
+        let datanode = pod(build_datanode_statefulset(&cluster).unwrap());
+        assert_eq!(
+            datanode.annotations.unwrap()[TLS_SECRET_HASH_ANNOTATION],
+            hash
+        );
+    }
+
+    #[test]
+    fn rollout_settings_are_configurable_per_role() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "namenode": { "podManagementPolicy": "OrderedReady" },
+                "datanode": {
+                    "updateStrategy": {
+                        "type": "RollingUpdate",
+                        "rollingUpdate": { "partition": 2 },
+                    },
+                },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let namenode = build_namenode_statefulset(&cluster).unwrap().spec.unwrap();
+        assert_eq!(
+            namenode.pod_management_policy.as_deref(),
+            Some("OrderedReady")
+        );
+        assert_eq!(namenode.update_strategy, None);
+        let datanode = build_datanode_statefulset(&cluster).unwrap().spec.unwrap();
+        assert_eq!(datanode.pod_management_policy.as_deref(), Some("Parallel"));
+        let update_strategy = datanode.update_strategy.unwrap();
+        assert_eq!(update_strategy.rolling_update.unwrap().partition, Some(2));
+    }
+
+    #[test]
+    fn batched_rollouts_are_driven_by_the_operator() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({ "datanodeRollout": { "maxUnavailable": "10%" } }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        assert!(validate(&cluster).is_ok());
+        let datanode = build_datanode_statefulset(&cluster).unwrap().spec.unwrap();
+        assert_eq!(
+            datanode.update_strategy.unwrap().type_.as_deref(),
+            Some("OnDelete")
+        );
+        let namenode = build_namenode_statefulset(&cluster).unwrap().spec.unwrap();
+        assert_eq!(namenode.update_strategy, None);
+
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({ "datanodeRollout": { "maxUnavailable": "ten" } }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        assert!(matches!(
+            validate(&cluster),
+            Err(Error::InvalidMaxUnavailable { .. })
+        ));
+    }
+
+    #[test]
+    fn active_namenodes_fail_over_before_stopping() {
+        let hdfs = parse_hdfs(SIMPLE);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let sts = build_namenode_statefulset(&cluster).unwrap();
+        let pod = pod_spec(&sts);
+        let pre_stop = |container: &Container| {
+            container
+                .lifecycle
+                .as_ref()
+                .unwrap()
+                .pre_stop
+                .as_ref()
+                .unwrap()
+                .exec
+                .as_ref()
+                .unwrap()
+                .command
+                .as_ref()
+                .unwrap()[2]
+                .clone()
+        };
+        let namenode = pre_stop(&pod.containers[0]);
+        assert!(namenode.contains("for other in name-0 name-1; do"));
+        assert!(namenode.contains("haadmin -failover $self $other"));
+        assert!(pre_stop(&pod.containers[1]).contains("-getServiceState $self"));
+        // Init containers may not have lifecycle hooks
+        assert!(pod.init_containers.as_ref().unwrap()[0].lifecycle.is_none());
+
+        let hdfs = with_spec(SIMPLE, serde_json::json!({ "namenodeReplicas": 1 }));
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let sts = build_namenode_statefulset(&cluster).unwrap();
+        assert!(pod_spec(&sts)
+            .containers
+            .iter()
+            .all(|container| container.lifecycle.is_none()));
+    }
+
+    #[test]
+    fn delegation_token_lifetimes_are_published_to_clients() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "delegationTokens": { "renewInterval": 3600000, "maxLifetime": 86400000 },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        for config_map in [
+            build_config_map(&cluster, "namenode"),
+            build_discovery_config_map(&cluster),
+        ] {
+            let hdfs_site = file(&config_map, "hdfs-site.xml");
+            assert!(hdfs_site.contains(
+                "<property><name>dfs.namenode.delegation.token.renew-interval</name><value>3600000</value></property>"
+            ));
+            assert!(hdfs_site.contains(
+                "<property><name>dfs.namenode.delegation.token.max-lifetime</name><value>86400000</value></property>"
+            ));
+            assert!(!hdfs_site.contains("dfs.namenode.delegation.key.update-interval"));
+        }
+    }
+
+    #[test]
+    fn secrets_can_be_provisioned_by_secret_operator() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "kerberos": { "secretClass": "kerberos" },
+                "tls": { "secretClass": "tls" },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let hdfs_site = file(&build_config_map(&cluster, "datanode"), "hdfs-site.xml").to_string();
+        assert!(hdfs_site.contains(
+            "<property><name>dfs.datanode.keytab.file</name><value>/kerberos/keytab</value></property>"
+        ));
+        let sts = build_datanode_statefulset(&cluster).unwrap();
+        let pod = pod_spec(&sts);
+        let volumes = pod.volumes.as_ref().unwrap();
+        let claim_annotations = |name: &str| {
+            let volume = volumes.iter().find(|volume| volume.name == name).unwrap();
+            let template = volume
+                .ephemeral
+                .as_ref()
+                .unwrap()
+                .volume_claim_template
+                .as_ref()
+                .unwrap();
+            assert_eq!(
+                template.spec.storage_class_name.as_deref(),
+                Some("secrets.stackable.tech")
+            );
+            template
+                .metadata
+                .as_ref()
+                .unwrap()
+                .annotations
+                .clone()
+                .unwrap()
+        };
+        let kerberos = claim_annotations("kerberos");
+        assert_eq!(kerberos["secrets.stackable.tech/class"], "kerberos");
+        assert_eq!(
+            kerberos["secrets.stackable.tech/scope"],
+            "service=simple-namenode"
+        );
+        assert_eq!(
+            kerberos["secrets.stackable.tech/kerberos.service.names"],
+            "dn"
+        );
+        let tls = claim_annotations("tls");
+        assert_eq!(
+            tls["secrets.stackable.tech/scope"],
+            "node,pod,service=simple-datanode"
+        );
+        let mounts = pod.containers[0].volume_mounts.as_ref().unwrap();
+        assert!(mounts
+            .iter()
+            .any(|mount| mount.name == "tls" && mount.mount_path == "/tls"));
+    }
+
+    #[test]
+    fn cert_manager_issues_certificates_for_each_role() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "tls": { "certManager": { "issuerName": "hdfs-ca", "issuerKind": "ClusterIssuer" } },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let certificate = build_certificate(&cluster, "namenode").unwrap();
+        assert_eq!(
+            certificate.metadata.name.as_deref(),
+            Some("simple-namenode")
+        );
+        assert_eq!(certificate.spec.secret_name, "simple-namenode-tls");
+        assert_eq!(
+            certificate.spec.dns_names,
+            [
+                "simple-namenode.default.svc.cluster.local",
+                "simple-namenode-0.simple-namenode.default.svc.cluster.local",
+                "simple-namenode-1.simple-namenode.default.svc.cluster.local",
+            ]
+        );
+        assert_eq!(certificate.spec.issuer_ref.kind, "ClusterIssuer");
+        let sts = build_datanode_statefulset(&cluster).unwrap();
+        let volumes = pod_spec(&sts).volumes.as_ref().unwrap();
+        let tls = volumes.iter().find(|volume| volume.name == "tls").unwrap();
+        assert_eq!(
+            tls.secret.as_ref().unwrap().secret_name.as_deref(),
+            Some("simple-datanode-tls")
+        );
+
+        assert!(build_certificate(
+            &ClusterContext::new(&parse_hdfs(SIMPLE), "cluster.local").unwrap(),
+            "namenode"
+        )
+        .is_none());
+        let conflicting = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "tls": { "secretClass": "tls", "certManager": { "issuerName": "hdfs-ca" } },
+            }),
+        );
+        assert!(matches!(
+            validate(&ClusterContext::new(&conflicting, "cluster.local").unwrap()),
+            Err(Error::ConflictingTlsProviders)
+        ));
+    }
+
+    #[test]
+    fn webhdfs_is_published_when_enabled() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({ "webhdfs": { "enabled": true }, "kerberos": { "secretClass": "kerberos" } }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let hdfs_site = file(&build_config_map(&cluster, "namenode"), "hdfs-site.xml").to_string();
+        for property in [
+            "<property><name>dfs.webhdfs.enabled</name><value>true</value></property>",
+            "<property><name>dfs.web.authentication.kerberos.principal</name>\
+             <value>HTTP/simple-namenode.default.svc.cluster.local@STACKABLE</value></property>",
+            "<property><name>dfs.web.authentication.kerberos.keytab</name><value>/kerberos/keytab</value></property>",
+        ] {
+            assert!(hdfs_site.contains(property), "{}", property);
+        }
+        let sts = build_namenode_statefulset(&cluster).unwrap();
+        let kerberos = pod_spec(&sts)
+            .volumes
+            .iter()
+            .flatten()
+            .find(|volume| volume.name == "kerberos")
+            .and_then(|volume| {
+                volume
+                    .ephemeral
+                    .clone()?
+                    .volume_claim_template?
+                    .metadata?
+                    .annotations
+            })
+            .unwrap();
+        assert_eq!(
+            kerberos["secrets.stackable.tech/kerberos.service.names"],
+            "nn,HTTP"
+        );
+        let discovery = build_discovery_config_map(&cluster);
+        assert_eq!(file(&discovery, "WEBHDFS_URL"), "webhdfs://simple/");
+        assert_eq!(
+            build_endpoints(&cluster).webhdfs,
+            [
+                "webhdfs://simple-namenode-0.simple-namenode.default.svc.cluster.local:9870",
+                "webhdfs://simple-namenode-1.simple-namenode.default.svc.cluster.local:9870",
+            ]
+        );
+
+        // With TLS, WebHDFS is published on the HTTPS ports
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "webhdfs": { "enabled": true },
+                "tls": { "secretClass": "tls" },
+                "ports": { "namenodeHttps": 8443 },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let discovery = build_discovery_config_map(&cluster);
+        assert_eq!(file(&discovery, "WEBHDFS_URL"), "swebhdfs://simple/");
+        assert!(file(&discovery, "hdfs-site.xml").contains(
+            "<property><name>dfs.namenode.https-address.simple.name-0</name>\
+             <value>simple-namenode-0.simple-namenode.default.svc.cluster.local:8443</value></property>"
+        ));
+        assert!(build_endpoints(&cluster).webhdfs[0].starts_with("swebhdfs://"));
+        assert!(file(&build_config_map(&cluster, "datanode"), "hdfs-site.xml").contains(
+            "<property><name>dfs.datanode.https.address</name><value>0.0.0.0:9865</value></property>"
+        ));
+        let sts = build_namenode_statefulset(&cluster).unwrap();
+        assert!(pod_spec(&sts).containers[0]
+            .ports
+            .iter()
+            .flatten()
+            .any(|port| port.name.as_deref() == Some("https") && port.container_port == 8443));
+        let service_ports = build_datanode_service(&cluster)
+            .spec
+            .unwrap()
+            .ports
+            .unwrap()
+            .into_iter()
+            .filter_map(|port| port.name)
+            .collect::<Vec<_>>();
+        assert_eq!(service_ports, ["ipc", "http", "https"]);
+
+        // Disabled explicitly, and without the HTTP principal
+        let cluster = ClusterContext::new(&parse_hdfs(SIMPLE), "cluster.local").unwrap();
+        assert!(!build_discovery_config_map(&cluster)
+            .data
+            .unwrap()
+            .contains_key("WEBHDFS_URL"));
+        assert!(build_endpoints(&cluster).webhdfs.is_empty());
+        assert!(
+            !file(&build_config_map(&cluster, "namenode"), "hdfs-site.xml")
+                .contains("dfs.web.authentication")
+        );
+    }
+
+    #[test]
+    fn journalnode_web_servers_are_exposed() {
+        let service_ports = |cluster: &ClusterContext| {
+            build_journalnode_service(cluster)
+                .spec
+                .unwrap()
+                .ports
+                .unwrap()
+                .into_iter()
+                .filter_map(|port| port.name)
+                .collect::<Vec<_>>()
+        };
+        let container_ports = |cluster: &ClusterContext| {
+            let sts = build_journalnode_statefulset(cluster).unwrap();
+            pod_spec(&sts).containers[0]
+                .ports
+                .iter()
+                .flatten()
+                .map(|port| (port.name.clone().unwrap(), port.container_port))
+                .collect::<Vec<_>>()
+        };
+        let cluster = ClusterContext::new(&parse_hdfs(SIMPLE), "cluster.local").unwrap();
+        assert_eq!(service_ports(&cluster), ["ipc", "http"]);
+        assert_eq!(
+            container_ports(&cluster),
+            [("ipc".to_string(), 8485), ("http".to_string(), 8480)]
+        );
+        assert!(
+            !file(&build_config_map(&cluster, "journalnode"), "hdfs-site.xml")
+                .contains("dfs.journalnode.https-address")
+        );
+
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "tls": { "secretClass": "tls" },
+                "ports": { "journalnodeHttp": 18480 },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        assert_eq!(service_ports(&cluster), ["ipc", "http", "https"]);
+        assert_eq!(
+            container_ports(&cluster),
+            [
+                ("ipc".to_string(), 8485),
+                ("http".to_string(), 18480),
+                ("https".to_string(), 8481),
+            ]
+        );
+        let hdfs_site =
+            file(&build_config_map(&cluster, "journalnode"), "hdfs-site.xml").to_string();
+        for property in [
+            "<property><name>dfs.journalnode.http-address</name><value>0.0.0.0:18480</value></property>",
+            "<property><name>dfs.journalnode.https-address</name><value>0.0.0.0:8481</value></property>",
+        ] {
+            assert!(hdfs_site.contains(property), "{}", property);
+        }
+        // Only the journalnodes bind them
+        assert!(
+            !file(&build_config_map(&cluster, "namenode"), "hdfs-site.xml")
+                .contains("dfs.journalnode.http")
+        );
+    }
+
+    #[test]
+    fn web_servers_only_serve_https_with_tls() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({ "tls": { "secretClass": "tls" } }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        for role in ["journalnode", "namenode", "datanode"] {
+            let config_map = build_config_map(&cluster, role);
+            assert!(
+                file(&config_map, "hdfs-site.xml").contains(
+                    "<property><name>dfs.http.policy</name><value>HTTPS_ONLY</value></property>"
+                ),
+                "{}",
+                role
+            );
+            // The namenodes fetch edits from the journalnodes over HTTPS, which needs both sides
+            assert!(file(&config_map, "ssl-server.xml").contains("ssl.server.keystore.location"));
+            assert!(file(&config_map, "ssl-client.xml").contains("ssl.client.truststore.location"));
+        }
+        let journalnode_site =
+            file(&build_config_map(&cluster, "journalnode"), "hdfs-site.xml").to_string();
+        assert!(journalnode_site.contains(
+            "<property><name>dfs.journalnode.https-address</name><value>0.0.0.0:8481</value></property>"
+        ));
+        assert!(cluster.ports.internal_ports().contains(&8481));
+        let sts = build_journalnode_statefulset(&cluster).unwrap();
+        let pod = pod_spec(&sts);
+        assert_eq!(
+            pod.init_containers.as_ref().unwrap()[0].name,
+            "create-keystores"
+        );
+        assert!(pod.containers[0]
+            .ports
+            .iter()
+            .flatten()
+            .any(|port| port.name.as_deref() == Some("https") && port.container_port == 8481));
+
+        // Everything that the operator queries moves to the HTTPS ports
+        assert_eq!(cluster.ports.namenode_web(), 9871);
+        assert_eq!(cluster.ports.datanode_web(), 9865);
+        assert_eq!(
+            build_endpoints(&cluster).namenode_http[0],
+            "simple-namenode-0.simple-namenode.default.svc.cluster.local:9871"
+        );
+        let sts = build_datanode_statefulset(&cluster).unwrap();
+        let pre_stop = pod_spec(&sts).containers[0]
+            .lifecycle
+            .as_ref()
+            .and_then(|lifecycle| lifecycle.pre_stop.as_ref())
+            .and_then(|handler| handler.exec.as_ref())
+            .and_then(|exec| exec.command.as_ref())
+            .unwrap();
+        assert!(pre_stop[2].contains("https://localhost:9865/jmx"));
+    }
+
+    #[test]
+    fn journal_storage_is_configurable() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "journalnodeStorage": {
+                    "size": "20Gi",
+                    "storageClassName": "fast-ssd",
+                    "editsSubdirectory": "journal/",
+                    "extraEditsRetained": 100000,
+                    "maxExtraEditSegmentsRetained": 500,
+                },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        validate(&cluster).unwrap();
+        let sts = build_journalnode_statefulset(&cluster).unwrap();
+        let claims = sts.spec.unwrap().volume_claim_templates.unwrap();
+        assert_eq!(claims.len(), 1);
+        let claim_spec = claims[0].spec.as_ref().unwrap();
+        assert_eq!(claim_spec.storage_class_name.as_deref(), Some("fast-ssd"));
+        assert_eq!(
+            claim_spec
+                .resources
+                .as_ref()
+                .unwrap()
+                .requests
+                .as_ref()
+                .unwrap()["storage"],
+            Quantity("20Gi".to_string())
+        );
+        assert!(
+            file(&build_config_map(&cluster, "journalnode"), "hdfs-site.xml").contains(
+                "<property><name>dfs.journalnode.edits.dir</name><value>/data/journal</value></property>"
+            )
+        );
+        // Retention is up to the namenodes, which purge the journals
+        let namenode_site =
+            file(&build_config_map(&cluster, "namenode"), "hdfs-site.xml").to_string();
+        for property in [
+            "<property><name>dfs.namenode.num.extra.edits.retained</name><value>100000</value></property>",
+            "<property><name>dfs.namenode.max.extra.edits.segments.retained</name><value>500</value></property>",
+        ] {
+            assert!(namenode_site.contains(property), "{}", property);
+        }
+        // The other roles keep the default volume
+        let namenode_sts = build_namenode_statefulset(&cluster).unwrap();
+        assert_eq!(
+            namenode_sts.spec.unwrap().volume_claim_templates.unwrap()[0]
+                .spec
+                .as_ref()
+                .unwrap()
+                .storage_class_name,
+            None
+        );
+
+        for subdirectory in ["/journal", "../journal", "a//b", "./journal", ""] {
+            let hdfs = with_spec(
+                SIMPLE,
+                serde_json::json!({ "journalnodeStorage": { "editsSubdirectory": subdirectory } }),
+            );
+            assert!(
+                matches!(
+                    validate(&ClusterContext::new(&hdfs, "cluster.local").unwrap()),
+                    Err(Error::InvalidJournalEditsSubdirectory { .. })
+                ),
+                "{}",
+                subdirectory
+            );
+        }
+    }
+
+    #[test]
+    fn checkpoints_are_configured_and_monitored() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({ "checkpoint": { "period": 600, "transactions": 1000 } }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        validate(&cluster).unwrap();
+        let hdfs_site = file(&build_config_map(&cluster, "namenode"), "hdfs-site.xml").to_string();
+        for property in [
+            "<property><name>dfs.namenode.checkpoint.period</name><value>600</value></property>",
+            "<property><name>dfs.namenode.checkpoint.txns</name><value>1000</value></property>",
+        ] {
+            assert!(hdfs_site.contains(property), "{}", property);
+        }
+        assert!(
+            !file(&build_config_map(&cluster, "datanode"), "hdfs-site.xml")
+                .contains("dfs.namenode.checkpoint")
+        );
+        assert_eq!(overdue_checkpoint(&cluster, 1000, 10), None);
+        assert!(overdue_checkpoint(&cluster, 1201, 10).is_some());
+        assert!(overdue_checkpoint(&cluster, 10, 2001).is_some());
+        // Nothing to checkpoint
+        assert_eq!(overdue_checkpoint(&cluster, 100_000, 0), None);
+
+        // Hadoop's defaults
+        let cluster = ClusterContext::new(&parse_hdfs(SIMPLE), "cluster.local").unwrap();
+        assert_eq!(overdue_checkpoint(&cluster, 7200, 1_000_000), None);
+        assert_eq!(
+            overdue_checkpoint(&cluster, 7201, 5).as_deref(),
+            Some("The last checkpoint was taken 7201s ago, and 5 transactions have happened since")
+        );
+
+        let invalid = with_spec(SIMPLE, serde_json::json!({ "checkpoint": { "period": 0 } }));
+        assert!(matches!(
+            validate(&ClusterContext::new(&invalid, "cluster.local").unwrap()),
+            Err(Error::InvalidCheckpointConfig {
+                field: "period",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn certificates_are_converted_into_keystores() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({ "tls": { "secretClass": "tls" } }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let config_map = build_config_map(&cluster, "namenode");
+        assert!(file(&config_map, "ssl-server.xml").contains(
+            "<property><name>ssl.server.keystore.location</name><value>/keystore/keystore.p12</value></property>"
+        ));
+        assert!(!file(&config_map, "ssl-client.xml").contains("ssl.server.keystore"));
+        assert!(file(&config_map, "hdfs-site.xml").contains(
+            "<property><name>dfs.http.policy</name><value>HTTPS_ONLY</value></property>"
+        ));
+        let sts = build_namenode_statefulset(&cluster).unwrap();
+        let pod = pod_spec(&sts);
+        let init_containers = pod.init_containers.as_ref().unwrap();
+        assert_eq!(init_containers[0].name, "create-keystores");
+        assert_eq!(init_containers[1].name, "format-namenode");
+        let password = pod.containers[0]
+            .env
+            .iter()
+            .flatten()
+            .find(|env| env.name == "KEYSTORE_PASSWORD")
+            .unwrap();
+        let secret_ref = password
+            .value_from
+            .as_ref()
+            .unwrap()
+            .secret_key_ref
+            .as_ref()
+            .unwrap();
+        assert_eq!(secret_ref.name.as_deref(), Some("simple-keystore"));
+        let volumes = pod.volumes.as_ref().unwrap();
+        for mount in pod.containers[0].volume_mounts.iter().flatten() {
+            assert!(
+                mount.name == "data" || volumes.iter().any(|volume| volume.name == mount.name),
+                "{} is not a volume",
+                mount.name
+            );
+        }
+
+        let config_map = build_config_map(
+            &ClusterContext::new(&parse_hdfs(SIMPLE), "cluster.local").unwrap(),
+            "namenode",
+        );
+        assert!(!config_map
+            .data
+            .as_ref()
+            .unwrap()
+            .contains_key("ssl-server.xml"));
+    }
+
+    #[test]
+    fn failover_controllers_authenticate_to_zookeeper() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "failoverController": {
+                    "parentZnode": "ha/simple",
+                    "sessionTimeout": 10000,
+                    "auth": { "digest": { "secretName": "simple-zookeeper" } },
+                },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let hdfs_site = file(&build_config_map(&cluster, "namenode"), "hdfs-site.xml").to_string();
+        for property in [
+            "<property><name>ha.zookeeper.parent-znode</name><value>${env.ZOOKEEPER_CHROOT}/ha/simple</value></property>",
+            "<property><name>ha.zookeeper.session-timeout.ms</name><value>10000</value></property>",
+            "<property><name>ha.zookeeper.auth</name><value>@/zookeeper-auth/auth</value></property>",
+        ] {
+            assert!(hdfs_site.contains(property), "{} is missing", property);
+        }
+        let sts = build_namenode_statefulset(&cluster).unwrap();
+        let pod = pod_spec(&sts);
+        let mounts_auth = |container: &Container| {
+            container
+                .volume_mounts
+                .iter()
+                .flatten()
+                .any(|mount| mount.name == "zookeeper-auth")
+        };
+        assert!(!mounts_auth(&pod.containers[0]));
+        assert!(mounts_auth(&pod.containers[1]));
+        assert!(mounts_auth(&pod.init_containers.as_ref().unwrap()[0]));
+
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({ "failoverController": { "auth": { "sasl": {} } } }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let config_map = build_config_map(&cluster, "namenode");
+        assert!(file(&config_map, "hdfs-site.xml").contains(
+            "<property><name>ha.zookeeper.acl</name><value>sasl:nn/simple-namenode.default.svc.cluster.local@STACKABLE:rwcda</value></property>"
+        ));
+        assert!(file(&config_map, "zookeeper-jaas.conf")
+            .contains("principal=\"nn/simple-namenode.default.svc.cluster.local@STACKABLE\";"));
+
+        for parent_znode in ["/hadoop-ha", "hadoop-ha/", "../hadoop-ha"] {
+            let hdfs = with_spec(
+                SIMPLE,
+                serde_json::json!({ "failoverController": { "parentZnode": parent_znode } }),
+            );
+            assert!(matches!(
+                validate(&ClusterContext::new(&hdfs, "cluster.local").unwrap()),
+                Err(Error::InvalidParentZnode { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn fencing_methods_are_rendered_and_mounted() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "failoverController": {
+                    "fencingMethods": [
+                        { "sshfence": { "privateKeySecret": "simple-fencing-key", "user": "hdfs", "port": 2222 } },
+                        { "shell": { "configMap": "simple-fencing", "script": "fence.sh" } },
+                    ],
+                },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        validate(&cluster).unwrap();
+        let hdfs_site = file(&build_config_map(&cluster, "namenode"), "hdfs-site.xml").to_string();
+        assert!(hdfs_site.contains(
+            "<property><name>dfs.ha.fencing.methods</name><value>sshfence(hdfs:2222)\nshell(sh /fencing/1/fence.sh)</value></property>"
+        ));
+        assert!(hdfs_site.contains(
+            "<property><name>dfs.ha.fencing.ssh.private-key-files</name><value>/fencing/0/id_rsa</value></property>"
+        ));
+        let sts = build_namenode_statefulset(&cluster).unwrap();
+        let pod = pod_spec(&sts);
+        let volumes = pod.volumes.as_ref().unwrap();
+        for (name, mount_path) in [("fencing-0", "/fencing/0"), ("fencing-1", "/fencing/1")] {
+            assert!(volumes.iter().any(|volume| volume.name == name));
+            assert!(pod.containers[1]
+                .volume_mounts
+                .iter()
+                .flatten()
+                .any(|mount| mount.name == name && mount.mount_path == mount_path));
+        }
+
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "failoverController": {
+                    "fencingMethods": [{ "shell": { "configMap": "simple-fencing", "script": "fence.sh)" } }],
+                },
+            }),
+        );
+        assert!(matches!(
+            validate(&ClusterContext::new(&hdfs, "cluster.local").unwrap()),
+            Err(Error::InvalidFencingMethod { index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn datanodes_can_have_multiple_volumes() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "datanodeStorage": { "volumes": 3, "failedVolumesTolerated": 1, "diskCheckTimeout": 60000 },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        validate(&cluster).unwrap();
+        let hdfs_site = file(&build_config_map(&cluster, "datanode"), "hdfs-site.xml").to_string();
+        for property in [
+            "<property><name>dfs.datanode.failed.volumes.tolerated</name><value>1</value></property>",
+            "<property><name>dfs.datanode.disk.check.timeout</name><value>60000</value></property>",
+        ] {
+            assert!(hdfs_site.contains(property), "{} is missing", property);
+        }
+        let sts = build_datanode_statefulset(&cluster).unwrap();
+        let claims = sts
+            .spec
+            .as_ref()
+            .unwrap()
+            .volume_claim_templates
+            .iter()
+            .flatten()
+            .filter_map(|claim| claim.metadata.name.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(claims, ["data", "data-1", "data-2"]);
+        assert_eq!(
+            env_value(&pod_spec(&sts).containers[0], "DATANODE_DATA_DIRS"),
+            Some("/data,/data-1,/data-2")
+        );
+        let mounts = pod_spec(&sts).containers[0]
+            .volume_mounts
+            .iter()
+            .flatten()
+            .map(|mount| mount.mount_path.as_str())
+            .collect::<Vec<_>>();
+        assert!(mounts.contains(&"/data-2"));
+        // The other roles only ever have a single volume
+        let sts = build_namenode_statefulset(&cluster).unwrap();
+        assert_eq!(sts.spec.unwrap().volume_claim_templates.unwrap().len(), 1);
+
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({ "datanodeStorage": { "failedVolumesTolerated": 1 } }),
+        );
+        assert!(matches!(
+            validate(&ClusterContext::new(&hdfs, "cluster.local").unwrap()),
+            Err(Error::InvalidFailedVolumesTolerated { volumes: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn images_can_be_pulled_from_a_mirror() {
+        let hdfs = parse_hdfs(SIMPLE);
+        let mut cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        cluster.image_options.image_repository_prefix = Some("mirror.local/stackable".to_string());
+        let sts = build_namenode_statefulset(&cluster).unwrap();
+        let pod = pod_spec(&sts);
+        for container in pod
+            .containers
+            .iter()
+            .chain(pod.init_containers.iter().flatten())
+        {
+            assert_eq!(
+                container.image.as_deref(),
+                Some("mirror.local/stackable/teozkr/hadoop:3.3.1"),
+                "{}",
+                container.name
+            );
+        }
+    }
+
+    #[test]
+    fn pods_are_restricted_to_the_image_architectures() {
+        let architectures = |image: serde_json::Value| {
+            let hdfs = with_spec(SIMPLE, serde_json::json!({ "image": image }));
+            let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+            let sts = build_namenode_statefulset(&cluster).unwrap();
+            let affinity = pod_spec(&sts).affinity.clone()?;
+            let terms = affinity
+                .node_affinity?
+                .required_during_scheduling_ignored_during_execution?
+                .node_selector_terms;
+            let requirement = &terms[0].match_expressions.as_ref()?[0];
+            assert_eq!(requirement.key, "kubernetes.io/arch");
+            requirement.values.clone()
+        };
+        assert_eq!(
+            architectures(serde_json::json!({})),
+            Some(vec!["amd64".to_string()])
+        );
+        // Unknown versions and other repositories are not restricted
+        assert_eq!(architectures(serde_json::json!({ "tag": "3.4.0" })), None);
+        assert_eq!(
+            architectures(serde_json::json!({ "repository": "example.com/hadoop" })),
+            None
+        );
+        assert_eq!(
+            architectures(serde_json::json!({
+                "repository": "example.com/hadoop",
+                "architectures": ["amd64", "arm64"],
+            })),
+            Some(vec!["amd64".to_string(), "arm64".to_string()])
+        );
+        assert_eq!(
+            architectures(serde_json::json!({ "architectures": [] })),
+            None
+        );
+    }
+
+    #[test]
+    fn config_overrides_are_merged_by_precedence() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "datanode": {
+                    "configOverrides": {
+                        "hdfs-site.xml": {
+                            "dfs.datanode.http.address": "0.0.0.0:9865",
+                            "dfs.datanode.handler.count": "20",
+                        },
+                        "core-site.xml": { "io.file.buffer.size": "131072" },
+                    },
+                },
+                "datanodeGroups": {
+                    "ssd": {
+                        "pods": {
+                            "configOverrides": {
+                                "hdfs-site.xml": { "dfs.datanode.handler.count": "40" },
+                            },
+                        },
+                    },
+                },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        validate(&cluster).unwrap();
+        let config_maps = build_config_maps(&cluster);
+        let (namenode, datanode, ssd) = (&config_maps[1], &config_maps[2], &config_maps[3]);
+
+        let datanode_site = file(datanode, "hdfs-site.xml");
+        assert!(datanode_site.contains(
+            "<property><name>dfs.datanode.handler.count</name><value>20</value></property>"
+        ));
+        assert!(file(ssd, "hdfs-site.xml").contains(
+            "<property><name>dfs.datanode.handler.count</name><value>40</value></property>"
+        ));
+        assert!(file(datanode, "core-site.xml").contains(
+            "<property><name>io.file.buffer.size</name><value>131072</value></property>"
+        ));
+        assert!(!file(namenode, "hdfs-site.xml").contains("dfs.datanode.handler.count"));
+        // Overridden properties keep their position in the file
+        let position = |name: &str| datanode_site.find(&format!("<name>{}</name>", name));
+        assert!(position("dfs.datanode.http.address") < position("dfs.block.access.token.enable"));
+
+        let effective: serde_yaml::Value =
+            serde_yaml::from_str(file(ssd, EFFECTIVE_CONFIG_KEY)).unwrap();
+        let source = |file: &str, name: &str| {
+            effective[file][name]["source"]
+                .as_str()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(source("core-site.xml", "fs.defaultFS"), "operatorDefaults");
+        assert_eq!(
+            source("hdfs-site.xml", "dfs.datanode.data.dir"),
+            "roleDefaults"
+        );
+        assert_eq!(
+            source("core-site.xml", "io.file.buffer.size"),
+            "roleOverrides"
+        );
+        assert_eq!(
+            source("hdfs-site.xml", "dfs.datanode.handler.count"),
+            "roleGroupOverrides"
+        );
+        assert_eq!(
+            effective["hdfs-site.xml"]["dfs.datanode.handler.count"]["value"],
+            "40"
+        );
+
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({ "namenode": { "configOverrides": { "hadoop-env.sh": {} } } }),
+        );
+        assert!(matches!(
+            validate(&ClusterContext::new(&hdfs, "cluster.local").unwrap()),
+            Err(Error::InvalidConfigOverrides { role, file })
+                if role == "namenode" && file == "hadoop-env.sh"
+        ));
+    }
+
+    #[test]
+    fn config_override_issues_are_reported() {
+        let overrides = |allow_unknown: bool| {
+            with_spec(
+                SIMPLE,
+                serde_json::json!({
+                    "propertyValidation": { "allowUnknown": allow_unknown },
+                    "namenode": {
+                        "configOverrides": {
+                            "hdfs-site.xml": {
+                                "dfs.replicaton": "2",
+                                "dfs.namenode.handler.count": "many",
+                                "dfs.namenode.safemode.threshold-pct": "0.9",
+                                // Generated by the operator, but not in the schema
+                                "ignore.secure.ports.for.testing": "true",
+                            },
+                        },
+                    },
+                }),
+            )
+        };
+        let hdfs = overrides(false);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        assert_eq!(
+            config_override_issues(&cluster),
+            [
+                "namenode hdfs-site.xml: dfs.namenode.handler.count must be an integer, not \"many\"",
+                "namenode hdfs-site.xml: dfs.replicaton is not a known property",
+            ]
+        );
+        // Invalid overrides are applied regardless
+        assert!(
+            file(&build_config_map(&cluster, "namenode"), "hdfs-site.xml")
+                .contains("<property><name>dfs.replicaton</name><value>2</value></property>")
+        );
+
+        let hdfs = overrides(true);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        assert_eq!(
+            config_override_issues(&cluster),
+            ["namenode hdfs-site.xml: dfs.namenode.handler.count must be an integer, not \"many\""]
+        );
+        let hdfs = parse_hdfs(FULL);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        assert!(config_override_issues(&cluster).is_empty());
+    }
+
+    #[test]
+    fn property_attributes_are_rendered() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "namenode": {
+                    "configOverrides": {
+                        "hdfs-site.xml": { "dfs.namenode.handler.count": "40" },
+                    },
+                    "propertyAttributes": {
+                        "core-site.xml": {
+                            "hadoop.security.authentication": { "tags": ["SECURITY"] },
+                        },
+                        "hdfs-site.xml": {
+                            "dfs.namenode.handler.count": {
+                                "final": true,
+                                "tags": ["HDFS", "PERFORMANCE"],
+                            },
+                            "dfs.block.access.token.enable": { "final": false },
+                            "dfs.namenode.handler.cont": { "final": true },
+                        },
+                    },
+                },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let namenode = build_config_map(&cluster, "namenode");
+        assert!(file(&namenode, "core-site.xml").contains(
+            "<name>hadoop.security.authentication</name><value>kerberos</value>\
+             <final>true</final><tag>SECURITY</tag></property>"
+        ));
+        let hdfs_site = file(&namenode, "hdfs-site.xml");
+        assert!(hdfs_site.contains(
+            "<name>dfs.namenode.handler.count</name><value>40</value>\
+             <final>true</final><tag>HDFS,PERFORMANCE</tag></property>"
+        ));
+        assert!(hdfs_site
+            .contains("<name>dfs.block.access.token.enable</name><value>true</value></property>"));
+        assert!(!hdfs_site.contains("dfs.namenode.handler.cont<"));
+        // Other roles keep the defaults
+        assert!(
+            file(&build_config_map(&cluster, "datanode"), "hdfs-site.xml").contains(
+                "<name>dfs.block.access.token.enable</name><value>true</value><final>true</final>"
+            )
+        );
+        assert_eq!(
+            config_override_issues(&cluster),
+            ["namenode hdfs-site.xml: dfs.namenode.handler.cont has propertyAttributes but is not set"]
+        );
+
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "datanode": { "propertyAttributes": { "hadoop-env.sh": {} } },
+            }),
+        );
+        assert!(matches!(
+            validate(&ClusterContext::new(&hdfs, "cluster.local").unwrap()),
+            Err(Error::InvalidConfigOverrides { role, file })
+                if role == "datanode" && file == "hadoop-env.sh"
+        ));
+    }
+
+    #[test]
+    fn known_placeholders_are_substituted() {
+        let hdfs = parse_hdfs(SIMPLE);
+        let mut cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        cluster.config_variables = BTreeMap::from([
+            (
+                "ZOOKEEPER_HOSTS".to_string(),
+                "zk-0:2181,zk-1:2181".to_string(),
+            ),
+            ("ZOOKEEPER_CHROOT".to_string(), "/znode-simple".to_string()),
+        ]);
+        let namenode = build_config_map(&cluster, "namenode");
+        let hdfs_site = file(&namenode, "hdfs-site.xml");
+        assert!(hdfs_site
+            .contains("<name>ha.zookeeper.quorum</name><value>zk-0:2181,zk-1:2181</value>"));
+        assert!(hdfs_site.contains("<name>ha.zookeeper.parent-znode</name><value>/znode-simple/"));
+        assert!(file(&namenode, EFFECTIVE_CONFIG_KEY).contains("zk-0:2181,zk-1:2181"));
+        // The data directories differ between the datanode groups, so they are left to the containers
+        assert!(
+            file(&build_config_map(&cluster, "datanode"), "hdfs-site.xml")
+                .contains("<value>${env.DATANODE_DATA_DIRS}</value>")
+        );
+    }
+
+    #[test]
+    fn unresolvable_placeholders_are_rejected() {
+        let with_overrides = |pod_overrides: serde_json::Value| {
+            with_spec(
+                SIMPLE,
+                serde_json::json!({
+                    "namenode": {
+                        "configOverrides": {
+                            "hdfs-site.xml": { "dfs.namenode.name.dir": "${env.NAME_DIRS}" },
+                        },
+                        "podOverrides": pod_overrides,
+                    },
+                }),
+            )
+        };
+        let hdfs = with_overrides(serde_json::json!({}));
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let config_maps = build_config_maps(&cluster);
+        assert!(matches!(
+            check_placeholders(&build_namenode_statefulset(&cluster).unwrap(), &config_maps),
+            Err(Error::UnresolvableVariable { sts, file, variable })
+                if sts == "simple-namenode" && file == "hdfs-site.xml" && variable == "NAME_DIRS"
+        ));
+        // Only the namenodes are overridden
+        check_placeholders(
+            &build_journalnode_statefulset(&cluster).unwrap(),
+            &config_maps,
+        )
+        .unwrap();
+        assert!(render(&cluster).is_err());
+
+        let hdfs = with_overrides(serde_json::json!({
+            "spec": {
+                "containers": [{
+                    "name": "namenode",
+                    "env": [{ "name": "NAME_DIRS", "value": "/data" }],
+                }],
+            },
+        }));
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        check_placeholders(
+            &build_namenode_statefulset(&cluster).unwrap(),
+            &build_config_maps(&cluster),
+        )
+        .unwrap();
+
+        let hdfs = parse_hdfs(FULL);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        render(&cluster).unwrap();
+    }
+
+    #[test]
+    fn teardown_stops_datanodes_first() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({ "datanodeGroups": { "ssd": {} } }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        assert_eq!(
+            teardown_order(&cluster),
+            [
+                (
+                    "datanode",
+                    vec![
+                        "simple-datanode".to_string(),
+                        "simple-datanode-ssd".to_string()
+                    ]
+                ),
+                ("namenode", vec!["simple-namenode".to_string()]),
+                ("journalnode", vec!["simple-journalnode".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn topology_spread_constraints_select_the_role_by_default() {
+        let zone_spread = serde_json::json!({
+            "maxSkew": 1,
+            "topologyKey": "topology.kubernetes.io/zone",
+            "whenUnsatisfiable": "DoNotSchedule",
+        });
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "datanode": { "topologySpreadConstraints": [zone_spread] },
+                "namenode": {
+                    "topologySpreadConstraints": [{
+                        "maxSkew": 1,
+                        "topologyKey": "kubernetes.io/hostname",
+                        "whenUnsatisfiable": "ScheduleAnyway",
+                        "labelSelector": { "matchLabels": { "app": "other" } },
+                    }],
+                },
+                "datanodeGroups": {
+                    "archive": {},
+                    "ssd": { "pods": { "topologySpreadConstraints": [zone_spread] } },
+                },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let selected_labels = |sts: &StatefulSet| {
+            let constraints = pod_spec(sts).topology_spread_constraints.clone().unwrap();
+            assert_eq!(constraints.len(), 1);
+            constraints[0]
+                .label_selector
+                .clone()
+                .unwrap()
+                .match_labels
+                .unwrap()
+        };
+
+        let statefulsets = build_datanode_statefulsets(&cluster).unwrap();
+        // Spread with all datanodes, including those of the groups
+        let datanode_labels = cluster.role_pod_labels("datanode");
+        assert_eq!(selected_labels(&statefulsets[0]), datanode_labels);
+        assert_eq!(selected_labels(&statefulsets[1]), datanode_labels);
+        // Spread only within the group
+        let ssd_labels = selected_labels(&statefulsets[2]);
+        assert_eq!(ssd_labels[DATANODE_GROUP_LABEL], "ssd");
+        assert_eq!(
+            Some(&ssd_labels),
+            statefulsets[2]
+                .spec
+                .as_ref()
+                .unwrap()
+                .selector
+                .match_labels
+                .as_ref()
+        );
+
+        let namenode_sts = build_namenode_statefulset(&cluster).unwrap();
+        assert_eq!(
+            selected_labels(&namenode_sts),
+            BTreeMap::from([("app".to_string(), "other".to_string())])
+        );
+        let journalnode_sts = build_journalnode_statefulset(&cluster).unwrap();
+        assert_eq!(pod_spec(&journalnode_sts).topology_spread_constraints, None);
+    }
+
+    #[test]
+    fn datanode_groups_have_their_own_statefulsets() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "datanodeStorage": { "volumes": 2, "storageType": "DISK" },
+                "datanodeGroups": {
+                    "ssd": {
+                        "replicas": 2,
+                        "volumes": 1,
+                        "storageType": "SSD",
+                        "storageClassName": "local-ssd",
+                        "nodeSelector": { "storage": "ssd" },
+                        "pods": { "priorityClassName": "hdfs-ssd" },
+                    },
+                },
+                "dfsReplication": 5,
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        validate(&cluster).unwrap();
+        let statefulsets = build_datanode_statefulsets(&cluster).unwrap();
+        let services = build_datanode_services(&cluster);
+        assert_eq!(
+            statefulsets
+                .iter()
+                .filter_map(|sts| sts.metadata.name.as_deref())
+                .collect::<Vec<_>>(),
+            ["simple-datanode", "simple-datanode-ssd"]
+        );
+        assert_eq!(
+            services
+                .iter()
+                .filter_map(|svc| svc.metadata.name.as_deref())
+                .collect::<Vec<_>>(),
+            ["simple-datanode", "simple-datanode-ssd"]
+        );
+
+        let (default, ssd) = (&statefulsets[0], &statefulsets[1]);
+        assert_eq!(
+            env_value(&pod_spec(default).containers[0], "DATANODE_DATA_DIRS"),
+            Some("[DISK]/data,[DISK]/data-1")
+        );
+        assert_eq!(
+            env_value(&pod_spec(ssd).containers[0], "DATANODE_DATA_DIRS"),
+            Some("[SSD]/data")
+        );
+        let ssd_spec = ssd.spec.as_ref().unwrap();
+        assert_eq!(ssd_spec.replicas, Some(2));
+        assert_eq!(ssd_spec.service_name, "simple-datanode-ssd");
+        let claims = ssd_spec.volume_claim_templates.as_ref().unwrap();
+        assert_eq!(claims.len(), 1);
+        assert_eq!(
+            claims[0]
+                .spec
+                .as_ref()
+                .unwrap()
+                .storage_class_name
+                .as_deref(),
+            Some("local-ssd")
+        );
+        let pod = pod_spec(ssd);
+        assert_eq!(
+            pod.node_selector.as_ref().unwrap()["storage"],
+            "ssd".to_string()
+        );
+        assert_eq!(pod.priority_class_name.as_deref(), Some("hdfs-ssd"));
+        assert!(pod_spec(default).node_selector.is_none());
+
+        // Each group selects only its own pods
+        let selector = ssd_spec.selector.match_labels.as_ref().unwrap();
+        assert_eq!(selector[DATANODE_GROUP_LABEL], "ssd");
+        let pod_labels = ssd_spec
+            .template
+            .metadata
+            .as_ref()
+            .unwrap()
+            .labels
+            .as_ref()
+            .unwrap();
+        assert!(selector
+            .iter()
+            .all(|(key, value)| pod_labels.get(key) == Some(value)));
+        assert_eq!(pod_labels[APP_ROLE_GROUP_LABEL], "ssd");
+        assert_eq!(
+            services[1]
+                .spec
+                .as_ref()
+                .unwrap()
+                .selector
+                .as_ref()
+                .unwrap()[DATANODE_GROUP_LABEL],
+            "ssd"
+        );
+
+        // Each group mounts its own ConfigMap, which only differs in the group's configOverrides
+        let config_maps = build_config_maps(&cluster);
+        assert_eq!(
+            config_maps[3].metadata.name.as_deref(),
+            Some("simple-datanode-ssd-config")
+        );
+        assert_eq!(
+            file(&config_maps[3], "hdfs-site.xml"),
+            file(&config_maps[2], "hdfs-site.xml")
+        );
+        assert!(file(&config_maps[3], "hdfs-site.xml").contains(
+            "<property><name>dfs.datanode.data.dir</name><value>${env.DATANODE_DATA_DIRS}</value></property>"
+        ));
+        let config_volume = |sts: &StatefulSet| {
+            pod_spec(sts)
+                .volumes
+                .iter()
+                .flatten()
+                .find(|volume| volume.name == "config")
+                .and_then(|volume| volume.config_map.as_ref()?.name.clone())
+        };
+        assert_eq!(
+            config_volume(default).as_deref(),
+            Some("simple-datanode-config")
+        );
+        assert_eq!(
+            config_volume(ssd).as_deref(),
+            Some("simple-datanode-ssd-config")
+        );
+        let rendered = render(&cluster).unwrap();
+        assert!(rendered.contains("name: simple-datanode-ssd"));
+
+        // The groups count towards the datanodes that blocks can be replicated to
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({ "datanodeGroups": { "ssd": {} }, "dfsReplication": 5 }),
+        );
+        assert!(matches!(
+            validate(&ClusterContext::new(&hdfs, "cluster.local").unwrap()),
+            Err(Error::InvalidReplication { datanodes: 4, .. })
+        ));
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({ "datanodeGroups": { "SSD": {} } }),
+        );
+        assert!(matches!(
+            validate(&ClusterContext::new(&hdfs, "cluster.local").unwrap()),
+            Err(Error::InvalidDatanodeGroupName { .. })
+        ));
+    }
+
+    #[test]
+    fn datanodes_are_autoscaled_within_bounds() {
+        let hdfs = with_spec(SIMPLE, serde_json::json!({ "datanodeReplicas": 4 }));
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        assert_eq!(autoscaled_datanode_replicas(&cluster, 95.0), None);
+
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "datanodeReplicas": 4,
+                "datanodeAutoscaling": { "maxReplicas": 6 },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        validate(&cluster).unwrap();
+        assert_eq!(autoscaled_datanode_replicas(&cluster, 50.0), Some(4));
+        // Always adds at least one datanode once the threshold is crossed
+        assert_eq!(autoscaled_datanode_replicas(&cluster, 80.0), Some(5));
+        assert_eq!(autoscaled_datanode_replicas(&cluster, 90.0), Some(5));
+        assert_eq!(autoscaled_datanode_replicas(&cluster, 99.0), Some(5));
+        assert_eq!(autoscaled_datanode_replicas(&cluster, 100.0), Some(5));
+
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "datanodeReplicas": 2,
+                "datanodeAutoscaling": {
+                    "minReplicas": 3,
+                    "maxReplicas": 10,
+                    "usedPercentThreshold": 50.0,
+                },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        assert_eq!(autoscaled_datanode_replicas(&cluster, 10.0), Some(3));
+        assert_eq!(autoscaled_datanode_replicas(&cluster, 100.0), Some(4));
+
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({ "datanodeAutoscaling": { "minReplicas": 3, "maxReplicas": 2 } }),
+        );
+        assert!(matches!(
+            validate(&ClusterContext::new(&hdfs, "cluster.local").unwrap()),
+            Err(Error::InvalidDatanodeAutoscaling { .. })
+        ));
+    }
+
+    #[test]
+    fn scaled_down_datanodes_are_decommissioned_first() {
+        let hdfs = with_spec(SIMPLE, serde_json::json!({ "datanodeReplicas": 2 }));
+        let mut cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        for i in 0..3 {
+            cluster
+                .datanode_ips
+                .insert(format!("simple-datanode-{}", i), format!("10.0.0.{}", i));
+        }
+        assert!(build_refresh_nodes_task(&cluster).is_none());
+        assert_eq!(
+            file(&build_config_map(&cluster, "namenode"), "dfs.hosts.json"),
+            "[]"
+        );
+
+        cluster.scale_downs.insert(
+            "datanode".to_string(),
+            ScaleDown {
+                pods: 3,
+                ..ScaleDown::default()
+            },
+        );
+        assert_eq!(
+            decommissioning_datanodes(&cluster),
+            [("datanode".to_string(), 2)]
+        );
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(file(
+                &build_config_map(&cluster, "namenode"),
+                "dfs.hosts.json"
+            ))
+            .unwrap(),
+            serde_json::json!([
+                { "hostName": "10.0.0.0", "adminState": "NORMAL" },
+                { "hostName": "10.0.0.1", "adminState": "NORMAL" },
+                { "hostName": "10.0.0.2", "adminState": "DECOMMISSIONED" },
+            ])
+        );
+        let replicas = |cluster: &ClusterContext| {
+            build_datanode_statefulsets(cluster).unwrap()[0]
+                .spec
+                .as_ref()
+                .unwrap()
+                .replicas
+        };
+        // The surplus pod is kept until it has been decommissioned
+        assert_eq!(replicas(&cluster), Some(3));
+        let task = build_refresh_nodes_task(&cluster).unwrap();
+        assert!(task
+            .metadata
+            .name
+            .as_deref()
+            .unwrap()
+            .starts_with("simple-refresh-nodes-"));
+        assert_eq!(task.spec.action, HdfsAdminAction::RefreshNodes);
+        assert_eq!(task.spec.cluster_name, "simple");
+
+        cluster
+            .scale_downs
+            .get_mut("datanode")
+            .unwrap()
+            .decommissioned = true;
+        assert_eq!(replicas(&cluster), Some(2));
+    }
+
+    #[test]
+    fn datanodes_are_put_into_maintenance() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "datanodeReplicas": 3,
+                "datanodeMaintenance": [
+                    { "pod": "simple-datanode-0", "until": "2021-10-16T10:00:00Z" },
+                    { "pod": "simple-datanode-2" },
+                ],
+            }),
+        );
+        let mut cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        validate(&cluster).unwrap();
+        assert_eq!(
+            maintenance_datanodes(&cluster),
+            [
+                ("simple-datanode-0".to_string(), Some(1_634_378_400_000)),
+                ("simple-datanode-2".to_string(), None),
+            ]
+        );
+        // Pods that haven't been scheduled yet are left out
+        cluster
+            .datanode_ips
+            .insert("simple-datanode-0".to_string(), "10.0.0.1".to_string());
+        cluster
+            .datanode_ips
+            .insert("simple-datanode-2".to_string(), "10.0.0.3".to_string());
+        assert_eq!(
+            datanode_hosts(&cluster),
+            [
+                serde_json::json!({
+                    "hostName": "10.0.0.1",
+                    "adminState": "IN_MAINTENANCE",
+                    "maintenanceExpireTimeInMS": 1_634_378_400_000_i64,
+                }),
+                serde_json::json!({ "hostName": "10.0.0.3", "adminState": "IN_MAINTENANCE" }),
+            ]
+        );
+        let task = build_refresh_nodes_task(&cluster).unwrap();
+        assert_eq!(task.spec.action, HdfsAdminAction::RefreshNodes);
+
+        // Decommissioning takes precedence
+        cluster.scale_downs.insert(
+            "datanode".to_string(),
+            ScaleDown {
+                pods: 3,
+                ..ScaleDown::default()
+            },
+        );
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "datanodeReplicas": 2,
+                "datanodeMaintenance": [{ "pod": "simple-datanode-2" }],
+            }),
+        );
+        let cluster = ClusterContext {
+            hdfs: &hdfs,
+            ..cluster
+        };
+        assert!(maintenance_datanodes(&cluster).is_empty());
+        // Only datanodes that are still wanted can be put into maintenance
+        assert!(matches!(
+            validate(&cluster),
+            Err(Error::InvalidDatanodeMaintenance { .. })
+        ));
+    }
+
+    #[test]
+    fn namenode_service_rpc_is_opt_in() {
+        let hdfs = with_spec(
+            FULL,
+            serde_json::json!({
+                "ports": { "namenodeServiceRpc": 8022 },
+                "namenodeRpc": { "handlerCount": 100, "serviceHandlerCount": 20 },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        validate(&cluster).unwrap();
+        let hdfs_site = file(&build_config_map(&cluster, "namenode"), "hdfs-site.xml").to_string();
+        for property in [
+            "<property><name>dfs.namenode.servicerpc-address.full.name-1</name><value>full-namenode-1.full-namenode.hdfs.svc.cluster.local:8022</value></property>",
+            "<property><name>dfs.namenode.handler.count</name><value>100</value></property>",
+            "<property><name>dfs.namenode.service.handler.count</name><value>20</value></property>",
+        ] {
+            assert!(hdfs_site.contains(property), "{} is missing", property);
+        }
+        // Clients only ever use the client RPC port
+        let client_site = hdfs_site_client_config(&cluster)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert!(!client_site.iter().any(|name| name.contains("servicerpc")));
+        let sts = build_namenode_statefulset(&cluster).unwrap();
+        let container_ports = pod_spec(&sts).containers[0]
+            .ports
+            .iter()
+            .flatten()
+            .map(|port| port.container_port)
+            .collect::<Vec<_>>();
+        assert!(container_ports.contains(&8022));
+        let service_ports = build_namenode_service(&cluster)
+            .spec
+            .unwrap()
+            .ports
+            .unwrap()
+            .into_iter()
+            .filter_map(|port| port.name)
+            .collect::<Vec<_>>();
+        assert_eq!(service_ports, ["ipc", "service-ipc", "http"]);
+        // The service RPC port is reserved for the daemons themselves
+        let rules = build_network_policy(&cluster)
+            .unwrap()
+            .spec
+            .unwrap()
+            .ingress
+            .unwrap();
+        let rule_ports = |rule: &NetworkPolicyIngressRule| {
+            rule.ports
+                .iter()
+                .flatten()
+                .filter_map(|port| port.port.clone())
+                .collect::<Vec<_>>()
+        };
+        assert!(!rule_ports(&rules[0]).contains(&IntOrString::Int(8022)));
+        assert!(rule_ports(&rules[1]).contains(&IntOrString::Int(8022)));
+
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({ "namenodeRpc": { "serviceHandlerCount": 20 } }),
+        );
+        assert!(matches!(
+            validate(&ClusterContext::new(&hdfs, "cluster.local").unwrap()),
+            Err(Error::ServiceHandlersWithoutServiceRpc)
+        ));
+    }
+
+    #[test]
+    fn node_drains_are_limited_by_disruption_budgets() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "namenode": { "safeToEvict": false },
+                "datanode": { "safeToEvict": true, "disruptionBudget": { "maxUnavailable": "10%" } },
+                "journalnode": { "disruptionBudget": { "enabled": false } },
+                "datanodeGroups": { "ssd": { "pods": { "safeToEvict": false } } },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let namenode = build_pod_disruption_budget(&cluster, "namenode").unwrap();
+        assert_eq!(namenode.metadata.name.as_deref(), Some("simple-namenode"));
+        let spec = namenode.spec.unwrap();
+        assert_eq!(spec.max_unavailable, Some(IntOrString::Int(1)));
+        // Other clusters' namenodes are left to their own budgets
+        let selector = spec.selector.unwrap().match_labels.unwrap();
+        assert_eq!(selector["role"], "namenode");
+        assert_eq!(selector[APP_INSTANCE_LABEL], "simple");
+        assert_eq!(
+            build_pod_disruption_budget(&cluster, "datanode")
+                .unwrap()
+                .spec
+                .unwrap()
+                .max_unavailable,
+            Some(IntOrString::String("10%".to_string()))
+        );
+        assert!(build_pod_disruption_budget(&cluster, "journalnode").is_none());
+
+        let safe_to_evict = |sts: &StatefulSet| {
+            sts.spec
+                .as_ref()
+                .unwrap()
+                .template
+                .metadata
+                .as_ref()
+                .and_then(|meta| meta.annotations.as_ref())
+                .and_then(|annotations| annotations.get(SAFE_TO_EVICT_ANNOTATION))
+                .cloned()
+        };
+        assert_eq!(
+            safe_to_evict(&build_namenode_statefulset(&cluster).unwrap()),
+            Some("false".to_string())
+        );
+        assert_eq!(
+            safe_to_evict(&build_journalnode_statefulset(&cluster).unwrap()),
+            None
+        );
+        let datanodes = build_datanode_statefulsets(&cluster).unwrap();
+        assert_eq!(safe_to_evict(&datanodes[0]), Some("true".to_string()));
+        // Group customizations are applied over those of `datanode`
+        assert_eq!(safe_to_evict(&datanodes[1]), Some("false".to_string()));
+    }
+
+    #[test]
+    fn existing_service_accounts_are_only_referenced() {
+        let hdfs = with_spec(
+            FULL,
+            serde_json::json!({"serviceAccount": {"name": "default"}}),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        assert!(build_service_account(&cluster).is_none());
+        let role_binding = build_role_binding(&cluster).unwrap();
+        assert_eq!(
+            role_binding.metadata.name.as_deref(),
+            Some("full-serviceaccount")
+        );
+        assert_eq!(role_binding.subjects.unwrap()[0].name, "default");
+        let sts = build_namenode_statefulset(&cluster).unwrap();
+        assert_eq!(
+            pod_spec(&sts).service_account_name.as_deref(),
+            Some("default")
+        );
+    }
+
+    /// The rules of the `restricted` Pod Security Standard that `pod` breaks
+    fn restricted_violations(pod: &PodSpec) -> Vec<String> {
+        let mut violations = Vec::new();
+        if [pod.host_network, pod.host_pid, pod.host_ipc].contains(&Some(true)) {
+            violations.push("uses host namespaces".to_string());
+        }
+        for volume in pod.volumes.iter().flatten() {
+            let allowed = volume.config_map.is_some()
+                || volume.csi.is_some()
+                || volume.downward_api.is_some()
+                || volume.empty_dir.is_some()
+                || volume.ephemeral.is_some()
+                || volume.persistent_volume_claim.is_some()
+                || volume.projected.is_some()
+                || volume.secret.is_some();
+            if !allowed {
+                violations.push(format!("volume {} has a forbidden type", volume.name));
+            }
+        }
+        let pod_context = pod.security_context.clone().unwrap_or_default();
+        for container in pod
+            .containers
+            .iter()
+            .chain(pod.init_containers.iter().flatten())
+        {
+            let name = &container.name;
+            let context = container.security_context.clone().unwrap_or_default();
+            if context.privileged == Some(true) {
+                violations.push(format!("{} is privileged", name));
+            }
+            if context.allow_privilege_escalation != Some(false) {
+                violations.push(format!("{} allows privilege escalation", name));
+            }
+            let capabilities = context.capabilities.unwrap_or_default();
+            if !capabilities.drop.iter().flatten().any(|cap| cap == "ALL") {
+                violations.push(format!("{} doesn't drop all capabilities", name));
+            }
+            if capabilities
+                .add
+                .iter()
+                .flatten()
+                .any(|cap| cap != "NET_BIND_SERVICE")
+            {
+                violations.push(format!("{} adds capabilities", name));
+            }
+            if context.run_as_non_root.or(pod_context.run_as_non_root) != Some(true)
+                || context.run_as_user.or(pod_context.run_as_user) == Some(0)
+            {
+                violations.push(format!("{} may run as root", name));
+            }
+            let seccomp_profile = context
+                .seccomp_profile
+                .or_else(|| pod_context.seccomp_profile.clone());
+            if !matches!(
+                seccomp_profile
+                    .as_ref()
+                    .map(|profile| profile.type_.as_str()),
+                Some("RuntimeDefault" | "Localhost")
+            ) {
+                violations.push(format!("{} has no seccomp profile", name));
+            }
+            if container
+                .ports
+                .iter()
+                .flatten()
+                .any(|port| port.host_port.is_some())
+            {
+                violations.push(format!("{} uses host ports", name));
+            }
+        }
+        violations
+    }
+
+    #[test]
+    fn pods_satisfy_the_restricted_pod_security_standard() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "hostNetwork": false,
+                "tls": { "secretClass": "tls" },
+                "tracing": { "endpoint": "http://collector:4317" },
+                "podSecurity": { "readOnlyRootFilesystem": true },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        for sts in [
+            build_journalnode_statefulset(&cluster).unwrap(),
+            build_namenode_statefulset(&cluster).unwrap(),
+            build_datanode_statefulset(&cluster).unwrap(),
+        ] {
+            assert_eq!(
+                restricted_violations(pod_spec(&sts)),
+                Vec::<String>::new(),
+                "{:?}",
+                sts.metadata.name
+            );
+        }
+
+        // The host network is only allowed by the `privileged` standard
+        let hdfs = parse_hdfs(SIMPLE);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        assert_eq!(
+            restricted_violations(pod_spec(&build_namenode_statefulset(&cluster).unwrap())),
+            ["uses host namespaces"]
+        );
+    }
+
+    #[test]
+    fn network_isolation_requires_the_pod_network() {
+        let hdfs = parse_hdfs(SIMPLE);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let sts = build_namenode_statefulset(&cluster).unwrap();
+        assert_eq!(pod_spec(&sts).host_network, Some(true));
+        assert_eq!(
+            pod_spec(&sts).dns_policy.as_deref(),
+            Some("ClusterFirstWithHostNet")
+        );
+
+        let hdfs = parse_hdfs(FULL);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        validate(&cluster).unwrap();
+        let sts = build_namenode_statefulset(&cluster).unwrap();
+        assert_eq!(pod_spec(&sts).host_network, Some(false));
+        assert_eq!(pod_spec(&sts).dns_policy, None);
+
+        let hdfs = with_spec(FULL, serde_json::json!({ "hostNetwork": true }));
+        assert!(matches!(
+            validate(&ClusterContext::new(&hdfs, "cluster.local").unwrap()),
+            Err(Error::NetworkIsolationWithHostNetwork)
+        ));
+    }
+
+    #[test]
+    fn optional_objects_are_only_built_when_enabled() {
+        let hdfs = parse_hdfs(SIMPLE);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        assert!(build_role_binding(&cluster).is_none());
+        assert!(build_network_policy(&cluster).is_none());
+
+        let hdfs = parse_hdfs(FULL);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let role_binding = build_role_binding(&cluster).unwrap();
+        assert_eq!(role_binding.role_ref.name, "hdfs-clusterrole");
+        assert_eq!(
+            role_binding.subjects.unwrap()[0].name,
+            "full-serviceaccount"
+        );
+        let spec = build_network_policy(&cluster).unwrap().spec.unwrap();
+        // Only this cluster's pods are isolated and admitted as members, not those of other clusters
+        let cluster_labels = Some(cluster.cluster_pod_labels());
+        assert_eq!(spec.pod_selector.match_labels, cluster_labels);
+        let ingress = spec.ingress.unwrap();
+        assert_eq!(
+            ingress[0].from.as_ref().unwrap()[0]
+                .pod_selector
+                .as_ref()
+                .unwrap()
+                .match_labels,
+            cluster_labels
+        );
+        // Cluster members and the allowed client may access the client ports
+        assert_eq!(ingress[0].from.as_ref().unwrap().len(), 2);
+        assert_eq!(ingress[0].ports.as_ref().unwrap().len(), 5);
+        assert_eq!(
+            ingress[1].ports.as_ref().unwrap()[0].port,
+            Some(IntOrString::Int(8485))
+        );
+        // The namenodes fetch edits from the journalnodes' web servers
+        assert_eq!(
+            ingress[1].ports.as_ref().unwrap()[1].port,
+            Some(IntOrString::Int(8480))
+        );
+    }
+
+    #[test]
+    fn render_lists_all_objects_in_order() {
+        let kinds = |hdfs: &HdfsCluster| {
+            let cluster = ClusterContext::new(hdfs, "cluster.local").unwrap();
+            render(&cluster)
+                .unwrap()
+                .split("---\n")
+                .filter(|doc| !doc.is_empty())
+                .map(|doc| {
+                    let obj: serde_yaml::Value = serde_yaml::from_str(doc).unwrap();
+                    obj["kind"].as_str().unwrap().to_string()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            kinds(&parse_hdfs(SIMPLE)),
+            [
+                "ConfigMap",
+                "ConfigMap",
+                "ConfigMap",
+                "ConfigMap",
+                "ServiceAccount",
+                "Service",
+                "StatefulSet",
+                "Service",
+                "StatefulSet",
+                "Service",
+                "StatefulSet",
+            ]
+        );
+        let full = kinds(&parse_hdfs(FULL));
+        assert_eq!(full.len(), 13);
+        assert_eq!(full[5..7], ["RoleBinding", "NetworkPolicy"]);
+    }
+
+    #[test]
+    fn statefulsets_select_their_own_pods() {
+        let hdfs = parse_hdfs(SIMPLE);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        for (role, sts, replicas) in [
+            ("journalnode", build_journalnode_statefulset(&cluster), 3),
+            ("namenode", build_namenode_statefulset(&cluster), 2),
+            ("datanode", build_datanode_statefulset(&cluster), 3),
+        ] {
+            let sts = sts.unwrap();
+            let spec = sts.spec.as_ref().unwrap();
+            assert_eq!(sts.metadata.name, Some(format!("simple-{}", role)));
+            assert_eq!(spec.service_name, format!("simple-{}", role));
+            assert_eq!(spec.replicas, Some(replicas));
+            let pod_labels = spec.template.metadata.as_ref().unwrap().labels.as_ref();
+            for (key, value) in spec.selector.match_labels.as_ref().unwrap() {
+                assert_eq!(pod_labels.unwrap().get(key), Some(value));
+            }
+            assert_eq!(spec.selector.match_labels.as_ref().unwrap()["role"], role);
+            let pod = pod_spec(&sts);
+            assert_eq!(
+                pod.service_account_name.as_deref(),
+                Some("simple-serviceaccount")
+            );
+            assert_eq!(
+                pod.volumes.as_ref().unwrap()[1]
+                    .secret
+                    .as_ref()
+                    .unwrap()
+                    .secret_name,
+                Some(format!("simple-{}-kerberos", role))
+            );
+        }
+    }
+
+    #[test]
+    fn namenode_pods_run_zkfc() {
+        let hdfs = parse_hdfs(FULL);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let sts = build_namenode_statefulset(&cluster).unwrap();
+        let pod = pod_spec(&sts);
+        let containers = pod
+            .containers
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(containers, ["namenode", "zkfc"]);
+        assert_eq!(
+            pod.init_containers.as_ref().unwrap()[0].name,
+            "format-namenode"
+        );
+        assert_eq!(
+            pod.containers[0].ports.as_ref().unwrap()[0].container_port,
+            9000
+        );
+        // The authorizer's jars are only added to the namenode itself
+        assert!(pod
+            .volumes
+            .iter()
+            .flatten()
+            .any(|volume| volume.name == "authorizer-lib"));
+        let env_names = |container: &Container| {
+            container
+                .env
+                .iter()
+                .flatten()
+                .map(|env| env.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert!(env_names(&pod.containers[0]).contains(&"OPA_URL".to_string()));
+        assert!(!env_names(&pod.containers[1]).contains(&"OPA_URL".to_string()));
+        assert!(env_names(&pod.containers[1]).contains(&"ZOOKEEPER_HOSTS".to_string()));
+        assert!(env_names(&pod.containers[1]).contains(&"ZOOKEEPER_CHROOT".to_string()));
+    }
+
+    #[test]
+    fn jvm_diagnostics_are_written_to_their_volume() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "namenode": {
+                    "jvm": {
+                        "heapDumpOnOutOfMemory": true,
+                        "flightRecorder": true,
+                        "diagnosticsVolumeSize": "8Gi",
+                    },
+                },
+                "datanode": { "jvm": { "heapDumpOnOutOfMemory": true } },
+                "datanodeGroups": { "ssd": {} },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let namenode_sts = build_namenode_statefulset(&cluster).unwrap();
+        let pod = pod_spec(&namenode_sts);
+        let namenode = pod
+            .containers
+            .iter()
+            .find(|container| container.name == "namenode")
+            .unwrap();
+        assert_eq!(
+            env_value(namenode, "HDFS_NAMENODE_OPTS"),
+            Some(
+                "-XX:+HeapDumpOnOutOfMemoryError -XX:HeapDumpPath=/diagnostics \
+                 -XX:StartFlightRecording=disk=true,dumponexit=true,filename=/diagnostics/flight-recording.jfr"
+            )
+        );
+        assert!(namenode
+            .volume_mounts
+            .iter()
+            .flatten()
+            .any(|mount| mount.name == "diagnostics" && mount.mount_path == "/diagnostics"));
+        let volume = pod
+            .volumes
+            .iter()
+            .flatten()
+            .find(|volume| volume.name == "diagnostics")
+            .unwrap();
+        assert_eq!(
+            volume.empty_dir.as_ref().unwrap().size_limit,
+            Some(Quantity("8Gi".to_string()))
+        );
+        // Only the namenode's own container is diagnosed
+        assert!(pod
+            .containers
+            .iter()
+            .filter(|container| container.name != "namenode")
+            .all(|container| env_value(container, "HDFS_NAMENODE_OPTS").is_none()));
+
+        // Datanode groups are diagnosed like the other datanodes
+        for sts in build_datanode_statefulsets(&cluster).unwrap() {
+            assert_eq!(
+                env_value(&pod_spec(&sts).containers[0], "HDFS_DATANODE_OPTS"),
+                Some("-XX:+HeapDumpOnOutOfMemoryError -XX:HeapDumpPath=/diagnostics")
+            );
+        }
+        let journalnode_sts = build_journalnode_statefulset(&cluster).unwrap();
+        assert!(!pod_spec(&journalnode_sts)
+            .volumes
+            .iter()
+            .flatten()
+            .any(|volume| volume.name == "diagnostics"));
+    }
+
+    #[test]
+    fn gc_presets_are_passed_to_the_daemons() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "namenode": {
+                    "jvm": { "gc": { "preset": "G1", "extraArgs": ["-XX:G1HeapRegionSize=16m"] } },
+                },
+                "journalnode": { "jvm": { "gc": { "preset": "SmallHeap" } } },
+                "failoverController": {
+                    "gc": { "preset": "SmallHeap", "extraArgs": ["-Xmx128m"] },
+                },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let namenode_sts = build_namenode_statefulset(&cluster).unwrap();
+        let pod = pod_spec(&namenode_sts);
+        assert_eq!(
+            env_value(&pod.containers[0], "HDFS_NAMENODE_OPTS"),
+            Some(
+                "-XX:+UseG1GC -XX:MaxGCPauseMillis=200 -XX:+ParallelRefProcEnabled \
+                 -XX:G1HeapRegionSize=16m"
+            )
+        );
+        // The extra flags come last, so they override the heap size
+        assert_eq!(
+            env_value(&pod.containers[1], "HDFS_ZKFC_OPTS"),
+            Some(
+                "-Xms192m -Xmx192m \
+                 -XX:+UseSerialGC -XX:MinHeapFreeRatio=10 -XX:MaxHeapFreeRatio=30 -Xmx128m"
+            )
+        );
+        // Nothing is written to a diagnostics volume
+        assert!(!pod
+            .volumes
+            .iter()
+            .flatten()
+            .any(|volume| volume.name == "diagnostics"));
+
+        let journalnode_sts = build_journalnode_statefulset(&cluster).unwrap();
+        assert_eq!(
+            env_value(
+                &pod_spec(&journalnode_sts).containers[0],
+                "HDFS_JOURNALNODE_OPTS"
+            ),
+            Some("-XX:+UseSerialGC -XX:MinHeapFreeRatio=10 -XX:MaxHeapFreeRatio=30")
+        );
+        for sts in build_datanode_statefulsets(&cluster).unwrap() {
+            assert_eq!(
+                env_value(&pod_spec(&sts).containers[0], "HDFS_DATANODE_OPTS"),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn daemons_are_traced_by_the_agent() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "tracing": {
+                    "endpoint": "https://otel-collector.monitoring.svc:4317",
+                    "samplingRatio": 0.25,
+                    "caSecretName": "otel-ca",
+                },
+                "datanodeGroups": { "ssd": {} },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        validate(&cluster).unwrap();
+        let namenode_sts = build_namenode_statefulset(&cluster).unwrap();
+        let pod = pod_spec(&namenode_sts);
+        let namenode = &pod.containers[0];
+        assert_eq!(
+            env_value(namenode, "HDFS_NAMENODE_OPTS"),
+            Some("-javaagent:/otel-agent/javaagent.jar")
+        );
+        assert_eq!(
+            env_value(namenode, "OTEL_SERVICE_NAME"),
+            Some("simple-namenode")
+        );
+        assert_eq!(
+            env_value(namenode, "OTEL_EXPORTER_OTLP_ENDPOINT"),
+            Some("https://otel-collector.monitoring.svc:4317")
+        );
+        assert_eq!(env_value(namenode, "OTEL_TRACES_SAMPLER_ARG"), Some("0.25"));
+        assert_eq!(
+            env_value(namenode, "OTEL_EXPORTER_OTLP_CERTIFICATE"),
+            Some("/otel-ca/ca.crt")
+        );
+        let copy_agent = pod
+            .init_containers
+            .iter()
+            .flatten()
+            .find(|container| container.name == "copy-otel-agent")
+            .unwrap();
+        assert_eq!(copy_agent.image.as_deref(), Some(DEFAULT_OTEL_AGENT_IMAGE));
+        assert!(pod
+            .volumes
+            .iter()
+            .flatten()
+            .any(|volume| volume.name == "otel-ca"
+                && volume.secret.as_ref().unwrap().secret_name.as_deref() == Some("otel-ca")));
+        // The ZKFC isn't traced
+        assert_eq!(env_value(&pod.containers[1], "OTEL_SERVICE_NAME"), None);
+
+        for sts in build_datanode_statefulsets(&cluster).unwrap() {
+            assert_eq!(
+                env_value(&pod_spec(&sts).containers[0], "OTEL_SERVICE_NAME"),
+                Some("simple-datanode")
+            );
+        }
+
+        let invalid = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "tracing": { "endpoint": "http://collector:4317", "samplingRatio": 2.0 },
+            }),
+        );
+        assert!(matches!(
+            validate(&ClusterContext::new(&invalid, "cluster.local").unwrap()),
+            Err(Error::InvalidTracingSamplingRatio { .. })
+        ));
+    }
+
+    #[test]
+    fn read_only_root_filesystems_get_scratch_volumes() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "podSecurity": { "readOnlyRootFilesystem": true },
+                "namenode": { "scratchVolumeSize": "1Gi" },
+                "journalnode": { "readOnlyRootFilesystem": false },
+            }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let read_only = |pod: &PodSpec| {
+            pod.containers
+                .iter()
+                .chain(pod.init_containers.iter().flatten())
+                .map(|container| {
+                    let mounts = container
+                        .volume_mounts
+                        .iter()
+                        .flatten()
+                        .map(|mount| mount.mount_path.as_str())
+                        .collect::<Vec<_>>();
+                    assert_eq!(
+                        mounts.contains(&"/tmp") && mounts.contains(&"/opt/hadoop/logs"),
+                        container
+                            .security_context
+                            .as_ref()
+                            .unwrap()
+                            .read_only_root_filesystem
+                            .unwrap(),
+                        "{}",
+                        container.name
+                    );
+                    container
+                        .security_context
+                        .as_ref()
+                        .unwrap()
+                        .read_only_root_filesystem
+                        .unwrap()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let namenode_sts = build_namenode_statefulset(&cluster).unwrap();
+        let pod = pod_spec(&namenode_sts);
+        // The namenode, the ZKFC, and the init container that formats the namenode
+        assert_eq!(read_only(pod), [true, true, true]);
+        let tmp = pod
+            .volumes
+            .iter()
+            .flatten()
+            .find(|volume| volume.name == "tmp")
+            .unwrap();
+        assert_eq!(
+            tmp.empty_dir.as_ref().unwrap().size_limit,
+            Some(Quantity("1Gi".to_string()))
+        );
+
+        // The role's setting takes precedence
+        let journalnode_sts = build_journalnode_statefulset(&cluster).unwrap();
+        let pod = pod_spec(&journalnode_sts);
+        assert_eq!(read_only(pod), [false]);
+        assert!(!pod
+            .volumes
+            .iter()
+            .flatten()
+            .any(|volume| volume.name == "tmp"));
+
+        for sts in build_datanode_statefulsets(&cluster).unwrap() {
+            assert!(read_only(pod_spec(&sts))
+                .into_iter()
+                .all(|read_only| read_only));
+        }
+    }
+
+    #[test]
+    fn zkfc_heap_fits_its_own_container() {
+        let zkfc = |hdfs: &HdfsCluster| {
+            let cluster = ClusterContext::new(hdfs, "cluster.local").unwrap();
+            let sts = build_namenode_statefulset(&cluster).unwrap();
+            let pod = pod_spec(&sts).clone();
+            let zkfc = pod.containers[1].clone();
+            let init = pod.init_containers.unwrap()[0].clone();
+            assert_eq!(init.resources, None);
+            zkfc
+        };
+        let zkfc_opts = |container: &Container| {
+            env_value(container, "HDFS_ZKFC_OPTS").map(|value| value.to_string())
+        };
+
+        let container = zkfc(&parse_hdfs(SIMPLE));
+        assert_eq!(
+            container
+                .resources
+                .as_ref()
+                .unwrap()
+                .limits
+                .as_ref()
+                .unwrap()["memory"],
+            Quantity("256Mi".to_string())
+        );
+        assert_eq!(zkfc_opts(&container).as_deref(), Some("-Xms192m -Xmx192m"));
+
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "failoverController": {
+                    "auth": { "sasl": {} },
+                    "resources": { "requests": { "memory": "1Gi" } },
+                },
+            }),
+        );
+        let container = zkfc(&hdfs);
+        assert_eq!(
+            zkfc_opts(&container).as_deref(),
+            Some(
+                "-Xms768m -Xmx768m \
+                 -Djava.security.auth.login.config=/config/zookeeper-jaas.conf"
+            )
+        );
+
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({ "failoverController": { "resources": { "limits": { "cpu": "1" } } } }),
+        );
+        assert_eq!(zkfc_opts(&zkfc(&hdfs)), None);
+
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({ "failoverController": { "resources": { "limits": { "memory": "lots" } } } }),
+        );
+        assert!(matches!(
+            validate(&ClusterContext::new(&hdfs, "cluster.local").unwrap()),
+            Err(Error::InvalidFailoverControllerMemory { .. })
+        ));
+    }
+
+    #[test]
+    fn datanodes_drain_before_stopping() {
+        let hdfs = parse_hdfs(SIMPLE);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let sts = build_datanode_statefulset(&cluster).unwrap();
+        let pod = pod_spec(&sts);
+        assert_eq!(
+            pod.termination_grace_period_seconds,
+            Some(DATANODE_DRAIN_TIMEOUT_SECONDS + 30)
+        );
+        let pre_stop = pod.containers[0]
+            .lifecycle
+            .as_ref()
+            .and_then(|lifecycle| lifecycle.pre_stop.as_ref())
+            .and_then(|handler| handler.exec.as_ref())
+            .and_then(|exec| exec.command.as_ref())
+            .unwrap();
+        assert!(pre_stop[2].contains("http://localhost:9864/jmx"));
+        // Without an HTTP client the hook fails, rather than skipping the drain
+        assert!(pre_stop[2].starts_with("if command -v curl"));
+        assert!(pre_stop[2].contains("exit 1"));
+    }
+
+    #[test]
+    fn role_config_is_applied() {
+        let hdfs = parse_hdfs(FULL);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let sts = build_datanode_statefulset(&cluster).unwrap();
+        let pod = pod_spec(&sts);
+        assert_eq!(pod.priority_class_name.as_deref(), Some("hdfs-datanode"));
+        assert_eq!(
+            pod.node_selector,
+            Some(BTreeMap::from([("storage".to_string(), "hdd".to_string())]))
+        );
+        // Overrides of one role don't leak into the others
+        let sts = build_namenode_statefulset(&cluster).unwrap();
+        assert_eq!(pod_spec(&sts).node_selector, None);
+    }
+
+    #[test]
+    fn invalid_pod_overrides_are_rejected() {
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({"journalnode": {"podOverrides": {"spec": {"containers": "journalnode"}}}}),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        assert!(matches!(
+            build_journalnode_statefulset(&cluster),
+            Err(Error::InvalidPodOverrides { role, .. }) if role == "journalnode"
+        ));
+    }
+
+    #[test]
+    fn replication_must_fit_the_datanodes() {
+        let hdfs = with_spec(SIMPLE, serde_json::json!({"dfsReplication": 4}));
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        assert!(matches!(
+            validate(&cluster),
+            Err(Error::InvalidReplication {
+                replication: 4,
+                datanodes: 3
+            })
+        ));
+
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({"dfsReplication": 2, "dfsReplicationMin": 3}),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        assert!(matches!(
+            validate(&cluster),
+            Err(Error::InvalidReplicationMin { .. })
+        ));
+
+        let hdfs = parse_hdfs(FULL);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        assert!(validate(&cluster).is_ok());
+    }
+
+    #[test]
+    fn clusters_without_namespace_are_rejected() {
+        let mut hdfs = parse_hdfs(SIMPLE);
+        hdfs.metadata.namespace = None;
+        assert!(matches!(
+            ClusterContext::new(&hdfs, "cluster.local"),
+            Err(Error::ObjectHasNoNamespace { .. })
+        ));
+    }
+
+    fn admin_task(spec: serde_json::Value) -> HdfsAdminTask {
+        serde_json::from_value(serde_json::json!({
+            "apiVersion": "hdfs.stackable.tech/v1alpha1",
+            "kind": "HdfsAdminTask",
+            "metadata": {
+                "name": "task",
+                "namespace": "default",
+                "uid": "0b6f3c2e-5d1a-4f7b-8e9c-3a2d1f0e4b5c",
+            },
+            "spec": spec,
+        }))
+        .unwrap()
+    }
+
+    fn job_script(job: &Job) -> &str {
+        let pod = job.spec.as_ref().unwrap().template.spec.as_ref().unwrap();
+        &pod.containers[0].args.as_ref().unwrap()[2]
+    }
+
+    #[test]
+    fn admin_task_job_runs_dfsadmin() {
+        let hdfs = parse_hdfs(SIMPLE);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let task =
+            admin_task(serde_json::json!({ "clusterName": "simple", "action": "safemodeEnter" }));
+        let job = build_admin_task_job(&cluster, &task).unwrap();
+        assert_eq!(job.metadata.name.as_deref(), Some("task-admintask"));
+        let owner_refs = job.metadata.owner_references.as_ref().unwrap();
+        assert_eq!(owner_refs[0].kind, "HdfsAdminTask");
+        assert_eq!(
+            job_script(&job),
+            "kinit -kt /kerberos/nn.service.keytab \
+             'nn/simple-namenode.default.svc.cluster.local@STACKABLE' \
+             && /opt/hadoop/bin/hdfs dfsadmin '-safemode' 'enter'"
+        );
+        let pod = job.spec.unwrap().template.spec.unwrap();
+        assert_eq!(pod.restart_policy.as_deref(), Some("Never"));
+        let volumes = pod.volumes.unwrap();
+        assert_eq!(
+            volumes[1].secret.as_ref().unwrap().secret_name.as_deref(),
+            Some("simple-namenode-kerberos")
+        );
+        // Every mount must refer to a volume of the pod
+        for mount in pod.containers[0].volume_mounts.as_ref().unwrap() {
+            assert!(volumes.iter().any(|volume| volume.name == mount.name));
+        }
+    }
+
+    #[test]
+    fn quotas_are_set_with_quoted_arguments() {
+        let hdfs = parse_hdfs(SIMPLE);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let task = admin_task(serde_json::json!({
+            "clusterName": "simple",
+            "action": "setQuota",
+            "quota": { "path": "/user/o'brien", "namespaceQuota": 1000, "spaceQuota": "10g" },
+        }));
+        let script = job_script(&build_admin_task_job(&cluster, &task).unwrap()).to_string();
+        assert!(script.ends_with(
+            "&& /opt/hadoop/bin/hdfs dfsadmin '-setQuota' '1000' '/user/o'\\''brien' \
+             && /opt/hadoop/bin/hdfs dfsadmin '-setSpaceQuota' '10g' '/user/o'\\''brien'"
+        ));
+
+        let task = admin_task(serde_json::json!({ "clusterName": "simple", "action": "setQuota" }));
+        assert!(matches!(
+            build_admin_task_job(&cluster, &task),
+            Err(Error::MissingQuota)
+        ));
+        let task = admin_task(serde_json::json!({
+            "clusterName": "simple",
+            "action": "setQuota",
+            "quota": { "path": "/tmp" },
+        }));
+        assert!(matches!(
+            build_admin_task_job(&cluster, &task),
+            Err(Error::EmptyQuota { .. })
+        ));
+    }
+
+    #[test]
+    fn snapshots_are_allowed_by_an_admin_task() {
+        let hdfs = parse_hdfs(SIMPLE);
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        assert!(build_allow_snapshot_task(&cluster).is_none());
+
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({ "snapshot": { "allowSnapshot": ["/data", "/user"] } }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        let task = build_allow_snapshot_task(&cluster).unwrap();
+        let name = task.metadata.name.clone().unwrap();
+        assert!(name.starts_with("simple-allow-snapshot-"));
+        assert_eq!(task.spec.action, HdfsAdminAction::AllowSnapshot);
+        assert!(
+            job_script(&build_admin_task_job(&cluster, &task).unwrap()).ends_with(
+                "&& /opt/hadoop/bin/hdfs dfsadmin '-allowSnapshot' '/data' \
+             && /opt/hadoop/bin/hdfs dfsadmin '-allowSnapshot' '/user'"
+            )
+        );
+
+        // Changing the directories reruns the task under a new name
+        let hdfs = with_spec(
+            SIMPLE,
+            serde_json::json!({ "snapshot": { "allowSnapshot": ["/data"] } }),
+        );
+        let cluster = ClusterContext::new(&hdfs, "cluster.local").unwrap();
+        assert_ne!(
+            build_allow_snapshot_task(&cluster).unwrap().metadata.name,
+            Some(name)
+        );
+
+        let task = admin_task(
+            serde_json::json!({ "clusterName": "simple", "action": "disallowSnapshot" }),
+        );
+        assert!(matches!(
+            build_admin_task_job(&cluster, &task),
+            Err(Error::MissingPaths)
+        ));
+    }
+
+    #[test]
+    fn deprecated_znode_config_map_is_still_used() {
+        let znode_config_map = |hdfs: &HdfsCluster| {
+            let cluster = ClusterContext::new(hdfs, "cluster.local").unwrap();
+            let sts = build_namenode_statefulset(&cluster).unwrap();
+            pod_spec(&sts).containers[1]
+                .env
+                .iter()
+                .flatten()
+                .find(|env| env.name == "ZOOKEEPER_HOSTS")
+                .and_then(|env| env.value_from.clone()?.config_map_key_ref?.name)
+        };
+        let hdfs = parse_hdfs(SIMPLE);
+        assert_eq!(znode_config_map(&hdfs).as_deref(), Some("simple-znode"));
+        assert!(hdfs.spec.deprecations().is_empty());
+
+        let hdfs = parse_hdfs(FULL);
+        assert_eq!(znode_config_map(&hdfs).as_deref(), Some("full-znode"));
+        assert_eq!(
+            hdfs.spec
+                .deprecations()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["namenodeZnodeConfigMap is deprecated, use zookeeperConfigMapName instead"]
+        );
+
+        let hdfs = with_spec(
+            FULL,
+            serde_json::json!({ "zookeeperConfigMapName": "renamed-znode" }),
+        );
+        assert_eq!(znode_config_map(&hdfs).as_deref(), Some("renamed-znode"));
+    }
+}
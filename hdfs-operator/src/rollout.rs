@@ -0,0 +1,144 @@
+//! Operator-driven rollouts, which replace a bounded batch of a `StatefulSet`'s pods at a time
+//!
+//! The `StatefulSet` uses the `OnDelete` update strategy, so its pods are only replaced once the operator deletes them.
+
+use k8s_openapi::{api::core::v1::Pod, apimachinery::pkg::util::intstr::IntOrString};
+
+/// Label that the `StatefulSet` controller sets to the revision that a pod was created from
+const REVISION_LABEL: &str = "controller-revision-hash";
+
+/// Resolves `max_unavailable` for a role of `replicas` pods
+///
+/// Percentages are rounded down, but at least one pod may always be unavailable. Returns `None` if
+/// `max_unavailable` is neither a number nor a percentage.
+pub fn max_unavailable(max_unavailable: Option<&IntOrString>, replicas: i32) -> Option<i32> {
+    let max = match max_unavailable {
+        None => 1,
+        Some(IntOrString::Int(max)) => *max,
+        Some(IntOrString::String(max)) => {
+            let percent = max.strip_suffix('%')?.parse::<i32>().ok()?;
+            replicas * percent / 100
+        }
+    };
+    Some(max.max(1))
+}
+
+fn is_ready(pod: &Pod) -> bool {
+    pod.metadata.deletion_timestamp.is_none()
+        && pod
+            .status
+            .iter()
+            .flat_map(|status| status.conditions.iter().flatten())
+            .any(|cond| cond.type_ == "Ready" && cond.status == "True")
+}
+
+/// Whether `pod` was created from an older revision than `update_revision`
+pub fn is_outdated(pod: &Pod, update_revision: &str) -> bool {
+    pod.metadata
+        .labels
+        .as_ref()
+        .and_then(|labels| labels.get(REVISION_LABEL))
+        .map_or(true, |revision| revision != update_revision)
+}
+
+/// The ordinal of a `StatefulSet`'s pod, parsed from its name
+fn ordinal(pod: &Pod) -> i32 {
+    pod.metadata
+        .name
+        .as_deref()
+        .and_then(|name| name.rsplit('-').next())
+        .and_then(|ordinal| ordinal.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Names of the outdated pods that should be deleted next, highest ordinals first
+///
+/// Nothing is deleted while any of the `replicas` pods is missing or unready, or while the previous batch has not
+/// `settled` yet, so that each batch only starts once the previous one has fully rejoined the cluster.
+pub fn next_batch(
+    pods: &[Pod],
+    replicas: i32,
+    update_revision: &str,
+    max_unavailable: i32,
+    settled: bool,
+) -> Vec<String> {
+    let ready = pods.iter().filter(|pod| is_ready(pod)).count();
+    if !settled || ready < replicas as usize {
+        return Vec::new();
+    }
+    let mut outdated = pods
+        .iter()
+        .filter(|pod| is_outdated(pod, update_revision))
+        .collect::<Vec<_>>();
+    outdated.sort_by_key(|pod| std::cmp::Reverse(ordinal(pod)));
+    outdated
+        .into_iter()
+        .take(max_unavailable as usize)
+        .filter_map(|pod| pod.metadata.name.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::{PodCondition, PodStatus};
+    use kube::api::ObjectMeta;
+    use std::collections::BTreeMap;
+
+    fn pod(ordinal: i32, revision: &str, ready: bool) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some(format!("hdfs-datanode-{}", ordinal)),
+                labels: Some(BTreeMap::from([(
+                    REVISION_LABEL.to_string(),
+                    revision.to_string(),
+                )])),
+                ..ObjectMeta::default()
+            },
+            status: Some(PodStatus {
+                conditions: Some(vec![PodCondition {
+                    type_: "Ready".to_string(),
+                    status: if ready { "True" } else { "False" }.to_string(),
+                    ..PodCondition::default()
+                }]),
+                ..PodStatus::default()
+            }),
+            ..Pod::default()
+        }
+    }
+
+    #[test]
+    fn max_unavailable_is_resolved() {
+        assert_eq!(max_unavailable(None, 100), Some(1));
+        assert_eq!(max_unavailable(Some(&IntOrString::Int(5)), 100), Some(5));
+        let percent = |percent: &str| IntOrString::String(percent.to_string());
+        assert_eq!(max_unavailable(Some(&percent("10%")), 95), Some(9));
+        assert_eq!(max_unavailable(Some(&percent("10%")), 5), Some(1));
+        assert_eq!(max_unavailable(Some(&percent("ten")), 5), None);
+    }
+
+    #[test]
+    fn batches_start_with_the_highest_ordinals() {
+        let pods = (0..5).map(|i| pod(i, "old", true)).collect::<Vec<_>>();
+        assert_eq!(
+            next_batch(&pods, 5, "new", 2, true),
+            ["hdfs-datanode-4", "hdfs-datanode-3"]
+        );
+    }
+
+    #[test]
+    fn batches_wait_for_the_previous_batch() {
+        let mut pods = (0..3).map(|i| pod(i, "old", true)).collect::<Vec<_>>();
+        pods.push(pod(3, "new", false));
+        assert!(next_batch(&pods, 4, "new", 2, true).is_empty());
+        pods[3] = pod(3, "new", true);
+        assert!(next_batch(&pods, 4, "new", 2, false).is_empty());
+        assert_eq!(
+            next_batch(&pods, 4, "new", 2, true),
+            ["hdfs-datanode-2", "hdfs-datanode-1"]
+        );
+        // A recreated pod that hasn't shown up yet also counts as unavailable
+        pods.pop();
+        assert!(next_batch(&pods, 4, "new", 2, true).is_empty());
+    }
+}
@@ -0,0 +1,41 @@
+//! The parts of secret-operator's `SecretClass` resource that the operator reads
+//!
+//! secret-operator owns the CRD itself, so it is never installed or printed by this operator.
+
+use k8s_openapi::api::core::v1::SecretReference;
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, CustomResource, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[kube(
+    group = "secrets.stackable.tech",
+    version = "v1alpha1",
+    kind = "SecretClass",
+    plural = "secretclasses"
+)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretClassSpec {
+    pub backend: SecretClassBackend,
+}
+
+/// Where the secrets of the class come from, only the backend that issues TLS certificates is of interest
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretClassBackend {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_tls: Option<AutoTlsBackend>,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoTlsBackend {
+    pub ca: AutoTlsCa,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoTlsCa {
+    /// `Secret` that the CA's certificate (`ca.crt`) and key (`ca.key`) are stored in
+    pub secret: SecretReference,
+}
@@ -0,0 +1,261 @@
+//! Runs `reconcile_hdfs` against a fake API server, and checks the requests that it sends
+
+use hdfs_operator::{
+    controller::{reconcile_hdfs, Ctx},
+    crd::HdfsCluster,
+};
+use k8s_openapi::api::{apps::v1::StatefulSet, core::v1::ConfigMap};
+use kube_runtime::controller::Context;
+use operator_commons::{
+    testing::{FakeApiServer, RecordedRequest},
+    ControllerOptions, ImageOptions,
+};
+use structopt::StructOpt;
+
+const SIMPLE: &str = r#"
+apiVersion: hdfs.stackable.tech/v1alpha1
+kind: HdfsCluster
+metadata:
+  name: simple
+  namespace: default
+  uid: 4c1a5f0e-7d3b-4e55-9f6a-2b8c1d0e9a71
+spec:
+  namenodeReplicas: 2
+  datanodeReplicas: 3
+  journalnodeReplicas: 3
+  zookeeperConfigMapName: simple-znode
+"#;
+
+const UID: &str = "4c1a5f0e-7d3b-4e55-9f6a-2b8c1d0e9a71";
+const HDFS_PATH: &str = "/apis/hdfs.stackable.tech/v1alpha1/namespaces/default/hdfsclusters/simple";
+const FIELD_MANAGER: &str = "hdfs.stackable.tech/hdfscluster";
+
+fn ctx(server: &FakeApiServer, dry_run: bool) -> Context<Ctx> {
+    let options = ControllerOptions::from_iter([
+        "hdfs-operator",
+        "--startup-jitter=0s",
+        "--apply-qps=1000",
+        "--apply-burst=1000",
+    ]);
+    Context::new(Ctx {
+        kube: server.client(),
+        cluster_domain: "cluster.local".to_string(),
+        image_options: ImageOptions::default(),
+        dry_run,
+        reconcile_permits: options.reconcile_permits(),
+        apply_rate_limiter: options.apply_rate_limiter(),
+        log_apply_diffs: false,
+        resync_interval: options.resync_interval,
+    })
+}
+
+/// A server with the cluster and the ConfigMap of its znode
+fn server() -> FakeApiServer {
+    let server = FakeApiServer::new();
+    server.insert(&serde_yaml::from_str::<HdfsCluster>(SIMPLE).unwrap());
+    server.insert(
+        &serde_json::from_value::<ConfigMap>(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "name": "simple-znode", "namespace": "default" },
+            "data": {
+                "ZOOKEEPER_HOSTS": "zk-0.zk.default.svc.cluster.local:2181",
+                "ZOOKEEPER_CHROOT": "/znode-simple",
+            },
+        }))
+        .unwrap(),
+    );
+    server
+}
+
+/// The cluster as it is currently stored, including the status of previous reconciles
+fn live_hdfs(server: &FakeApiServer) -> HdfsCluster {
+    serde_json::from_value(server.get(HDFS_PATH).unwrap()).unwrap()
+}
+
+fn sts_path(role: &str) -> String {
+    format!(
+        "/apis/apps/v1/namespaces/default/statefulsets/simple-{}",
+        role
+    )
+}
+
+/// Lets the `StatefulSet` of `role` report all of its replicas as ready, as the StatefulSet controller would
+fn mark_ready(server: &FakeApiServer, role: &str) {
+    let path = sts_path(role);
+    let sts = server.get(&path).unwrap();
+    let replicas = sts["spec"]["replicas"].clone();
+    server.update(
+        &path,
+        serde_json::json!({
+            "status": {
+                "observedGeneration": sts["metadata"]["generation"],
+                "replicas": replicas,
+                "readyReplicas": replicas,
+                "updatedReplicas": replicas,
+            },
+        }),
+    );
+}
+
+/// Paths of the `StatefulSet`s that were applied
+fn applied_statefulsets(requests: &[RecordedRequest]) -> Vec<&str> {
+    requests
+        .iter()
+        .filter(|request| request.is_apply() && request.path.contains("/statefulsets/"))
+        .map(|request| request.path.as_str())
+        .collect()
+}
+
+/// The reason of the cluster's `Progressing` condition
+fn progress_reason(server: &FakeApiServer) -> String {
+    let status = live_hdfs(server).status.unwrap();
+    let condition = status
+        .conditions
+        .iter()
+        .flatten()
+        .find(|condition| condition.type_ == "Progressing")
+        .unwrap();
+    condition.reason.clone()
+}
+
+/// Checks that every apply is a forced server-side apply by the controller, of an object that the cluster controls
+fn assert_applies_are_owned(requests: &[RecordedRequest]) {
+    let applies = requests
+        .iter()
+        .filter(|request| request.is_apply())
+        .collect::<Vec<_>>();
+    assert!(!applies.is_empty());
+    for apply in applies {
+        assert_eq!(apply.query["fieldManager"], FIELD_MANAGER, "{}", apply.path);
+        assert_eq!(apply.query["force"], "true", "{}", apply.path);
+        let body = apply.body.as_ref().unwrap();
+        let owner_refs = body["metadata"]["ownerReferences"].as_array();
+        assert!(
+            owner_refs
+                .into_iter()
+                .flatten()
+                .any(|owner_ref| owner_ref["uid"] == UID && owner_ref["controller"] == true),
+            "{} is not controlled by the cluster",
+            apply.path
+        );
+    }
+}
+
+#[tokio::test]
+async fn roles_are_rolled_out_in_order() {
+    let server = server();
+    let ctx = ctx(&server, false);
+
+    reconcile_hdfs(live_hdfs(&server), ctx.clone())
+        .await
+        .unwrap();
+    let requests = server.take_requests();
+    assert_applies_are_owned(&requests);
+    assert_eq!(applied_statefulsets(&requests), [sts_path("journalnode")]);
+    assert!(requests.iter().any(
+        |request| request.method == "PATCH" && request.path == format!("{}/status", HDFS_PATH)
+    ));
+    assert_eq!(progress_reason(&server), "WaitingForJournalnodes");
+
+    mark_ready(&server, "journalnode");
+    reconcile_hdfs(live_hdfs(&server), ctx.clone())
+        .await
+        .unwrap();
+    let requests = server.take_requests();
+    assert_applies_are_owned(&requests);
+    assert_eq!(
+        applied_statefulsets(&requests),
+        [sts_path("journalnode"), sts_path("namenode")]
+    );
+    assert_eq!(progress_reason(&server), "WaitingForNamenodes");
+
+    mark_ready(&server, "namenode");
+    reconcile_hdfs(live_hdfs(&server), ctx.clone())
+        .await
+        .unwrap();
+    let requests = server.take_requests();
+    assert_applies_are_owned(&requests);
+    assert_eq!(
+        applied_statefulsets(&requests),
+        [
+            sts_path("journalnode"),
+            sts_path("namenode"),
+            sts_path("datanode")
+        ]
+    );
+    assert_eq!(progress_reason(&server), "WaitingForDatanodes");
+
+    mark_ready(&server, "datanode");
+    reconcile_hdfs(live_hdfs(&server), ctx).await.unwrap();
+    assert_eq!(progress_reason(&server), "ReconcileComplete");
+    // Nothing was deleted, since all objects are still desired
+    assert!(!server
+        .take_requests()
+        .iter()
+        .any(|request| request.method == "DELETE"));
+}
+
+#[tokio::test]
+async fn objects_of_removed_roles_are_deleted() {
+    let server = server();
+    let ctx = ctx(&server, false);
+    reconcile_hdfs(live_hdfs(&server), ctx.clone())
+        .await
+        .unwrap();
+    // A StatefulSet of a datanode group that has since been removed from the spec, which is deleted even though the
+    // reconcile is still waiting for the journalnodes
+    let mut orphan = server.get(&sts_path("journalnode")).unwrap();
+    orphan["metadata"]["name"] = "simple-datanode-removed".into();
+    server.insert(&serde_json::from_value::<StatefulSet>(orphan).unwrap());
+    server.take_requests();
+
+    reconcile_hdfs(live_hdfs(&server), ctx).await.unwrap();
+    let deleted = server
+        .take_requests()
+        .into_iter()
+        .filter(|request| request.method == "DELETE")
+        .map(|request| request.path)
+        .collect::<Vec<_>>();
+    assert_eq!(deleted, [sts_path("datanode-removed")]);
+    assert!(server.get(&sts_path("datanode-removed")).is_none());
+}
+
+#[tokio::test]
+async fn statuses_of_removed_roles_are_cleared() {
+    let server = server();
+    server.update(
+        HDFS_PATH,
+        serde_json::json!({
+            "status": { "roles": { "removed": { "phase": "Applied" } } },
+        }),
+    );
+    reconcile_hdfs(live_hdfs(&server), ctx(&server, false))
+        .await
+        .unwrap();
+    let roles = live_hdfs(&server).status.unwrap().roles;
+    assert_eq!(
+        roles.keys().collect::<Vec<_>>(),
+        ["datanode", "journalnode", "namenode"]
+    );
+}
+
+#[tokio::test]
+async fn dry_runs_change_nothing() {
+    let server = server();
+    let paths = server.paths();
+    reconcile_hdfs(live_hdfs(&server), ctx(&server, true))
+        .await
+        .unwrap();
+    let requests = server.take_requests();
+    assert_applies_are_owned(&requests);
+    for request in requests.iter().filter(|request| request.is_write()) {
+        assert!(
+            request.is_dry_run(),
+            "{} {} is not a dry run",
+            request.method,
+            request.path
+        );
+    }
+    assert_eq!(server.paths(), paths);
+}
@@ -0,0 +1,315 @@
+//! Managing the objects that are owned by a custom resource
+
+use std::fmt::Debug;
+
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+use kube::{
+    api::{DeleteParams, ListParams, Patch, PatchParams},
+    Resource,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{controller::RateLimiter, diff::diff_objects, fault};
+
+/// How a controller writes the objects that it owns
+#[derive(Clone, Debug)]
+pub struct ApplyParams {
+    /// Identifies the controller as the manager of the fields that it applies
+    pub field_manager: String,
+    /// Only log the changes that would be made, instead of persisting them
+    pub dry_run: bool,
+    /// Logs how each apply changed the live object at debug level, at the cost of reading the object first
+    pub log_diffs: bool,
+    /// Throttles the writes, if set
+    pub rate_limiter: Option<RateLimiter>,
+}
+
+impl ApplyParams {
+    /// Waits until the rate limiter allows another write
+    async fn throttle(&self) {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire().await;
+        }
+    }
+}
+
+fn api_for<K>(kube: &kube::Client, obj: &K) -> kube::Api<K>
+where
+    K: Resource<DynamicType = ()>,
+{
+    if let Some(ns) = &obj.meta().namespace {
+        kube::Api::<K>::namespaced(kube.clone(), ns)
+    } else {
+        kube::Api::<K>::all(kube.clone())
+    }
+}
+
+/// Gets the object, or `None` if it does not exist
+async fn get_if_exists<K>(api: &kube::Api<K>, name: &str) -> kube::Result<Option<K>>
+where
+    K: Resource + DeserializeOwned + Clone + Debug,
+{
+    match api.get(name).await {
+        Ok(obj) => Ok(Some(obj)),
+        Err(kube::Error::Api(err)) if err.code == 404 => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Applies `obj` using server-side apply, taking over any fields that are owned by other managers
+///
+/// In dry-run mode the apply is only validated by the server, and the resulting diff is logged. With
+/// [`ApplyParams::log_diffs`] the diff between the live and the applied object is logged at debug level.
+#[tracing::instrument(skip_all, fields(kind = %K::kind(&()), name = ?obj.meta().name), err)]
+pub async fn apply_owned<K>(kube: &kube::Client, params: &ApplyParams, obj: &K) -> kube::Result<K>
+where
+    K: Resource<DynamicType = ()> + Serialize + DeserializeOwned + Clone + Debug,
+{
+    params.throttle().await;
+    if fault::fail_apply() {
+        return Err(fault::apply_error());
+    }
+    if params.dry_run {
+        let (applied, diff) = dry_run_apply(kube, &params.field_manager, obj).await?;
+        if !diff.is_empty() {
+            tracing::info!("Dry run, would apply:\n{}", diff);
+        }
+        return Ok(applied);
+    }
+    let api = api_for(kube, obj);
+    let name = obj.meta().name.clone().unwrap();
+    let live = if params.log_diffs {
+        get_if_exists(&api, &name).await?
+    } else {
+        None
+    };
+    let applied = api
+        .patch(
+            &name,
+            &PatchParams {
+                force: true,
+                field_manager: Some(params.field_manager.clone()),
+                ..PatchParams::default()
+            },
+            &Patch::Apply(obj),
+        )
+        .await?;
+    if params.log_diffs {
+        let diff = diff_objects(live.as_ref(), Some(&applied));
+        if diff.is_empty() {
+            tracing::debug!(created = live.is_none(), "Applied without changes");
+        } else {
+            tracing::debug!(created = live.is_none(), %diff, "Applied changes");
+        }
+    }
+    Ok(applied)
+}
+
+/// Applies `obj` in dry-run mode, returning the object that would result and its diff to the live object
+async fn dry_run_apply<K>(
+    kube: &kube::Client,
+    field_manager: &str,
+    obj: &K,
+) -> kube::Result<(K, String)>
+where
+    K: Resource<DynamicType = ()> + Serialize + DeserializeOwned + Clone + Debug,
+{
+    let api = api_for(kube, obj);
+    let name = obj.meta().name.clone().unwrap();
+    let live = get_if_exists(&api, &name).await?;
+    let applied = api
+        .patch(
+            &name,
+            &PatchParams {
+                force: true,
+                dry_run: true,
+                field_manager: Some(field_manager.to_string()),
+                ..PatchParams::default()
+            },
+            &Patch::Apply(obj),
+        )
+        .await?;
+    let diff = diff_objects(live.as_ref(), Some(&applied));
+    Ok((applied, diff))
+}
+
+/// Shows how applying `obj` would change the live object, without changing anything
+///
+/// The apply is validated by the server, so the diff includes defaulted fields and rejects invalid objects.
+pub async fn diff_owned<K>(
+    kube: &kube::Client,
+    field_manager: &str,
+    obj: &K,
+) -> kube::Result<String>
+where
+    K: Resource<DynamicType = ()> + Serialize + DeserializeOwned + Clone + Debug,
+{
+    Ok(dry_run_apply(kube, field_manager, obj).await?.1)
+}
+
+/// Shows how [`delete_if_exists`] would change the object, without changing anything
+pub async fn diff_deleted<K>(kube: &kube::Client, ns: &str, name: &str) -> kube::Result<String>
+where
+    K: Resource<DynamicType = ()> + Serialize + DeserializeOwned + Clone + Debug,
+{
+    let live = get_if_exists(&kube::Api::<K>::namespaced(kube.clone(), ns), name).await?;
+    Ok(diff_objects(live.as_ref(), None))
+}
+
+/// Deletes the object, succeeding if it has already been deleted
+#[tracing::instrument(skip(kube, params), fields(kind = %K::kind(&())), err)]
+pub async fn delete_if_exists<K>(
+    kube: &kube::Client,
+    params: &ApplyParams,
+    ns: &str,
+    name: &str,
+) -> kube::Result<()>
+where
+    K: Resource<DynamicType = ()> + DeserializeOwned + Clone + Debug,
+{
+    params.throttle().await;
+    let delete_params = DeleteParams {
+        dry_run: params.dry_run,
+        ..DeleteParams::default()
+    };
+    match kube::Api::<K>::namespaced(kube.clone(), ns)
+        .delete(name, &delete_params)
+        .await
+    {
+        Ok(_) => {
+            if params.dry_run {
+                tracing::info!(kind = %K::kind(&()), namespace = ns, name, "Dry run, would delete");
+            }
+            Ok(())
+        }
+        Err(kube::Error::Api(err)) if err.code == 404 => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Deletes the object if it is controlled by `owner`, leaving objects that belong to someone else alone
+#[tracing::instrument(skip(kube, params, owner), fields(kind = %K::kind(&())), err)]
+pub async fn delete_if_controlled_by<K>(
+    kube: &kube::Client,
+    params: &ApplyParams,
+    ns: &str,
+    name: &str,
+    owner: &OwnerReference,
+) -> kube::Result<()>
+where
+    K: Resource<DynamicType = ()> + DeserializeOwned + Clone + Debug,
+{
+    let api = kube::Api::<K>::namespaced(kube.clone(), ns);
+    match get_if_exists(&api, name).await? {
+        Some(obj) if is_controlled_by(&obj, owner) => {
+            delete_if_exists::<K>(kube, params, ns, name).await
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Deletes objects controlled by `owner` that are not listed in `desired`
+///
+/// Owner references only clean up after the whole cluster has been deleted, this also removes objects of roles
+/// (or optional features) that have since been dropped from the spec. Only the objects matching the label `selector`
+/// are listed, which should select all objects of the owner, so that the whole namespace isn't fetched each time.
+#[tracing::instrument(skip(kube, params, owner), fields(kind = %K::kind(&())), err)]
+pub async fn delete_orphans<K>(
+    kube: &kube::Client,
+    params: &ApplyParams,
+    ns: &str,
+    owner: &OwnerReference,
+    selector: &str,
+    desired: &[&str],
+) -> kube::Result<()>
+where
+    K: Resource<DynamicType = ()> + DeserializeOwned + Clone + Debug,
+{
+    let api = kube::Api::<K>::namespaced(kube.clone(), ns);
+    for obj in api.list(&ListParams::default().labels(selector)).await? {
+        if is_controlled_by(&obj, owner) {
+            let name = obj.meta().name.as_deref().unwrap_or_default();
+            if !desired.contains(&name) {
+                delete_if_exists::<K>(kube, params, ns, name).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether `owner` is the controller of `obj`
+fn is_controlled_by<K: Resource>(obj: &K, owner: &OwnerReference) -> bool {
+    obj.meta()
+        .owner_references
+        .iter()
+        .flatten()
+        .any(|owner_ref| owner_ref.controller == Some(true) && owner_ref.uid == owner.uid)
+}
+
+/// An `OwnerReference` that marks `obj` as the controller of the objects it is attached to
+///
+/// A foreground deletion of `obj` waits for these objects to be deleted first.
+pub fn controller_reference_to_obj<K: Resource<DynamicType = ()>>(obj: &K) -> OwnerReference {
+    OwnerReference {
+        api_version: K::api_version(&()).into_owned(),
+        kind: K::kind(&()).into_owned(),
+        controller: Some(true),
+        block_owner_deletion: Some(true),
+        name: obj.meta().name.clone().unwrap(),
+        uid: obj.meta().uid.clone().unwrap(),
+        ..OwnerReference::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{controller_reference_to_obj, is_controlled_by};
+    use k8s_openapi::{
+        api::core::v1::ConfigMap, apimachinery::pkg::apis::meta::v1::OwnerReference,
+    };
+    use kube::api::ObjectMeta;
+
+    fn config_map(name: &str, uid: &str) -> ConfigMap {
+        ConfigMap {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                uid: Some(uid.to_string()),
+                ..ObjectMeta::default()
+            },
+            ..ConfigMap::default()
+        }
+    }
+
+    #[test]
+    fn controller_reference_points_to_obj() {
+        let owner_ref = controller_reference_to_obj(&config_map("owner", "1234"));
+        assert_eq!(owner_ref.api_version, "v1");
+        assert_eq!(owner_ref.kind, "ConfigMap");
+        assert_eq!(owner_ref.name, "owner");
+        assert_eq!(owner_ref.uid, "1234");
+        assert_eq!(owner_ref.controller, Some(true));
+        assert_eq!(owner_ref.block_owner_deletion, Some(true));
+    }
+
+    #[test]
+    fn only_controller_references_count_as_controlled() {
+        let owner = config_map("owner", "1234");
+        let owner_ref = controller_reference_to_obj(&owner);
+        let mut obj = config_map("obj", "5678");
+        assert!(!is_controlled_by(&obj, &owner_ref));
+
+        obj.metadata.owner_references = Some(vec![OwnerReference {
+            controller: None,
+            ..owner_ref.clone()
+        }]);
+        assert!(!is_controlled_by(&obj, &owner_ref));
+
+        obj.metadata.owner_references = Some(vec![controller_reference_to_obj(&config_map(
+            "other", "9012",
+        ))]);
+        assert!(!is_controlled_by(&obj, &owner_ref));
+
+        obj.metadata.owner_references = Some(vec![owner_ref.clone()]);
+        assert!(is_controlled_by(&obj, &owner_ref));
+    }
+}
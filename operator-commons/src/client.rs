@@ -0,0 +1,181 @@
+//! Connecting to the Kubernetes cluster
+
+use std::{
+    future::Future,
+    path::PathBuf,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use hyper_timeout::TimeoutConnector;
+use kube::{
+    client::ConfigExt,
+    config::{KubeConfigOptions, Kubeconfig},
+};
+use snafu::{ResultExt, Snafu};
+use structopt::StructOpt;
+use tokio::time::Sleep;
+use tower::{Layer, Service, ServiceBuilder};
+
+use crate::{controller::RateLimiter, duration::parse_duration};
+
+#[derive(Snafu, Debug)]
+pub enum Error {
+    #[snafu(display("failed to read kubeconfig {}", path.display()))]
+    ReadKubeconfig { source: kube::Error, path: PathBuf },
+    #[snafu(display("failed to load kubeconfig"))]
+    LoadKubeconfig { source: kube::Error },
+    #[snafu(display("failed to infer the Kubernetes client configuration"))]
+    InferConfig { source: kube::Error },
+    #[snafu(display("failed to create Kubernetes client"))]
+    CreateClient { source: kube::Error },
+}
+
+/// Guesses the cluster domain from the DNS search path that Kubernetes configures for the operator's own pod
+pub fn detect_cluster_domain() -> Option<String> {
+    let resolv_conf = std::fs::read_to_string("/etc/resolv.conf").ok()?;
+    resolv_conf
+        .lines()
+        .filter_map(|line| line.strip_prefix("search "))
+        .flat_map(str::split_whitespace)
+        .find_map(|domain| domain.strip_prefix("svc."))
+        .map(str::to_string)
+}
+
+/// How to connect to the Kubernetes cluster
+#[derive(StructOpt, Clone, Debug, Default)]
+pub struct ClientOptions {
+    /// Kubeconfig file to use, instead of `$KUBECONFIG`, `~/.kube/config`, or the pod's service account
+    #[structopt(long)]
+    pub kubeconfig: Option<PathBuf>,
+    /// Kubeconfig context to use, instead of its current context
+    #[structopt(long)]
+    pub context: Option<String>,
+    /// User to impersonate
+    #[structopt(long = "as")]
+    pub impersonate: Option<String>,
+    /// Timeout of requests to the API server, such as `30s`
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    pub request_timeout: Option<Duration>,
+    /// Sustained rate of requests to the API server, per second, unlimited by default
+    #[structopt(long)]
+    pub client_qps: Option<f64>,
+    /// Number of requests that may exceed `--client-qps` in a short burst
+    #[structopt(long, default_value = "20")]
+    pub client_burst: u32,
+}
+
+impl ClientOptions {
+    /// Loads the client configuration, only falling back to the in-cluster configuration if no kubeconfig is selected
+    pub async fn config(&self) -> Result<kube::Config, Error> {
+        let kubeconfig_options = KubeConfigOptions {
+            context: self.context.clone(),
+            ..KubeConfigOptions::default()
+        };
+        let mut config = if let Some(path) = &self.kubeconfig {
+            let kubeconfig = Kubeconfig::read_from(path).context(ReadKubeconfig { path })?;
+            kube::Config::from_custom_kubeconfig(kubeconfig, &kubeconfig_options)
+                .await
+                .context(LoadKubeconfig)?
+        } else if self.context.is_some() {
+            kube::Config::from_kubeconfig(&kubeconfig_options)
+                .await
+                .context(LoadKubeconfig)?
+        } else {
+            kube::Config::infer().await.context(InferConfig)?
+        };
+        if let Some(timeout) = self.request_timeout {
+            config.timeout = Some(timeout);
+        }
+        if let Some(user) = &self.impersonate {
+            config.auth_info.impersonate = Some(user.clone());
+        }
+        Ok(config)
+    }
+
+    /// Creates a client using [`Self::config`], throttled to `--client-qps` if it is set
+    pub async fn client(&self) -> Result<kube::Client, Error> {
+        self.client_with_config(self.config().await?)
+    }
+
+    /// Creates a client using `config`, for callers that need to inspect the configuration first
+    pub fn client_with_config(&self, config: kube::Config) -> Result<kube::Client, Error> {
+        let qps = match self.client_qps {
+            Some(qps) => qps,
+            None => return kube::Client::try_from(config).context(CreateClient),
+        };
+        let mut connector =
+            TimeoutConnector::new(config.native_tls_https_connector().context(CreateClient)?);
+        connector.set_connect_timeout(config.timeout);
+        connector.set_read_timeout(config.timeout);
+        connector.set_write_timeout(config.timeout);
+        let service = ServiceBuilder::new()
+            .layer(RateLimitLayer {
+                rate_limiter: RateLimiter::new(qps, self.client_burst),
+            })
+            .layer(config.base_uri_layer())
+            .option_layer(config.auth_layer().context(CreateClient)?)
+            .service(hyper::Client::builder().build(connector));
+        Ok(kube::Client::new(service, config.default_namespace))
+    }
+}
+
+/// Delays requests until the [`RateLimiter`] allows them
+#[derive(Clone, Debug)]
+struct RateLimitLayer {
+    rate_limiter: RateLimiter,
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimited<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimited {
+            inner,
+            rate_limiter: self.rate_limiter.clone(),
+            reserved: false,
+            delay: None,
+        }
+    }
+}
+
+struct RateLimited<S> {
+    inner: S,
+    rate_limiter: RateLimiter,
+    /// Whether the next request has already taken its token
+    reserved: bool,
+    /// Waits until the token of the next request is available
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S, R> Service<R> for RateLimited<S>
+where
+    S: Service<R>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if !self.reserved {
+            self.reserved = true;
+            let delay = self.rate_limiter.reserve();
+            if !delay.is_zero() {
+                self.delay = Some(Box::pin(tokio::time::sleep(delay)));
+            }
+        }
+        if let Some(delay) = &mut self.delay {
+            if delay.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            self.delay = None;
+        }
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        self.reserved = false;
+        self.inner.call(request)
+    }
+}
@@ -0,0 +1,312 @@
+//! Scheduling reconciles
+
+use std::{
+    collections::hash_map::RandomState,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures::{Stream, StreamExt};
+use kube::{
+    api::DynamicObject,
+    runtime::{controller::ReconcilerAction, reflector::ObjectRef},
+    Resource,
+};
+use structopt::StructOpt;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::{Semaphore, SemaphorePermit},
+};
+
+use crate::{duration::parse_duration, fault, image::ImageOptions};
+
+/// How the controllers schedule reconciles
+#[derive(StructOpt, Clone, Debug)]
+pub struct ControllerOptions {
+    /// Maximum number of objects that are reconciled at the same time, across all controllers
+    #[structopt(long, default_value = "16")]
+    pub max_concurrent_reconciles: usize,
+    /// How often objects are reconciled even if nothing changed, to correct drift, such as `10m`
+    #[structopt(long, default_value = "10m", parse(try_from_str = parse_duration))]
+    pub resync_interval: Duration,
+    /// How long to wait for running reconciles to finish when shutting down
+    ///
+    /// Should be shorter than the pod's `terminationGracePeriodSeconds`.
+    #[structopt(long, default_value = "25s", parse(try_from_str = parse_duration))]
+    pub shutdown_grace_period: Duration,
+    /// Reconciles that start within this long after startup are delayed by a random part of it, so that restarting
+    /// the operator doesn't reconcile all objects at once
+    #[structopt(long, default_value = "10s", parse(try_from_str = parse_duration))]
+    pub startup_jitter: Duration,
+    /// Sustained rate of the writes (applies and deletes) of managed objects, per second and across all controllers
+    #[structopt(long, default_value = "10")]
+    pub apply_qps: f64,
+    /// Number of writes that may exceed `--apply-qps` in a short burst
+    #[structopt(long, default_value = "20")]
+    pub apply_burst: u32,
+    /// Logs the diff between the live and the applied object of each apply at debug level, which costs an additional
+    /// read per apply
+    #[structopt(long)]
+    pub log_apply_diffs: bool,
+}
+
+impl ControllerOptions {
+    /// Permits that each reconcile holds while it runs, shared by all controllers of the operator
+    pub fn reconcile_permits(&self) -> ReconcilePermits {
+        ReconcilePermits {
+            semaphore: Arc::new(Semaphore::new(self.max_concurrent_reconciles)),
+            max: self.max_concurrent_reconciles,
+            started: Instant::now(),
+            startup_jitter: self.startup_jitter,
+        }
+    }
+
+    /// Limits the writes of managed objects, shared by all controllers of the operator
+    pub fn apply_rate_limiter(&self) -> RateLimiter {
+        RateLimiter::new(self.apply_qps, self.apply_burst)
+    }
+}
+
+/// What the controllers of all operators that run in the same process share
+#[derive(Clone)]
+pub struct SharedContext {
+    pub kube: kube::Client,
+    /// DNS domain of the Kubernetes cluster, such as `cluster.local`
+    pub cluster_domain: String,
+    /// Where the images of the managed pods are pulled from
+    pub image_options: ImageOptions,
+    /// Only log the changes that would be made, instead of persisting them
+    pub dry_run: bool,
+    pub reconcile_permits: ReconcilePermits,
+    pub apply_rate_limiter: RateLimiter,
+    /// Log the diff of each apply at debug level
+    pub log_apply_diffs: bool,
+    /// How often objects are reconciled even if nothing changed
+    pub resync_interval: Duration,
+}
+
+/// The result of a reconcile, with the kind of the object erased so that the results of all controllers can be merged
+pub type ReconcileResult =
+    Result<(ObjectRef<DynamicObject>, ReconcilerAction), Box<dyn std::error::Error + Send + Sync>>;
+
+/// Erases the kind of the reconciled object, see [`ReconcileResult`]
+pub fn erase_controller_result<K, E>(
+    res: Result<(ObjectRef<K>, ReconcilerAction), E>,
+) -> ReconcileResult
+where
+    K: Resource,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let (obj_ref, action) = res?;
+    Ok((obj_ref.erase(), action))
+}
+
+/// Logs the result of each reconcile
+pub async fn log_reconcile_results(results: impl Stream<Item = ReconcileResult>) {
+    results
+        .for_each(|res| async move {
+            match res {
+                Ok((obj, _)) => tracing::info!(object = %obj, "Reconciled object"),
+                Err(err) => {
+                    tracing::error!(
+                        error = &*err as &dyn std::error::Error,
+                        "Failed to reconcile object",
+                    )
+                }
+            }
+        })
+        .await
+}
+
+/// Bounds the number of running reconciles, and lets shutdown wait for them to finish
+#[derive(Clone, Debug)]
+pub struct ReconcilePermits {
+    semaphore: Arc<Semaphore>,
+    max: usize,
+    started: Instant,
+    startup_jitter: Duration,
+}
+
+impl ReconcilePermits {
+    /// Waits until another reconcile may start, or returns `None` if the operator is shutting down
+    ///
+    /// Shortly after startup, this first waits for a random delay of up to the startup jitter.
+    pub async fn acquire(&self) -> Option<SemaphorePermit<'_>> {
+        if self.started.elapsed() < self.startup_jitter {
+            tokio::time::sleep(random_fraction(self.startup_jitter)).await;
+        }
+        fault::delay_reconcile().await;
+        self.semaphore.acquire().await.ok()
+    }
+
+    /// Stops handing out permits, and waits until all running reconciles have finished
+    async fn drain(&self) {
+        self.semaphore.close();
+        while self.semaphore.available_permits() < self.max {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+}
+
+/// A random duration between zero and `max`
+fn random_fraction(max: Duration) -> Duration {
+    // Each `RandomState` is seeded randomly, which is random enough for spreading out reconciles
+    let random = RandomState::new().build_hasher().finish();
+    max.mul_f64(random as f64 / u64::MAX as f64)
+}
+
+/// A token bucket that is shared by all clones, bounding the rate of requests to the API server
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<TokenBucket>>,
+}
+
+impl RateLimiter {
+    /// Allows `qps` requests per second on average, and bursts of up to `burst` requests
+    pub fn new(qps: f64, burst: u32) -> Self {
+        Self {
+            bucket: Arc::new(Mutex::new(TokenBucket::new(qps, burst, Instant::now()))),
+        }
+    }
+
+    /// Takes a token, returning how long the caller must wait before sending its request
+    pub(crate) fn reserve(&self) -> Duration {
+        self.bucket
+            .lock()
+            .expect("rate limiter must not be poisoned")
+            .reserve(Instant::now())
+    }
+
+    /// Waits until another request may be sent
+    pub async fn acquire(&self) {
+        let delay = self.reserve();
+        if !delay.is_zero() {
+            tracing::debug!(?delay, "Throttling request to the API server");
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    qps: f64,
+    burst: f64,
+    /// Negative while requests are waiting for tokens that have been reserved ahead of time
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(qps: f64, burst: u32, now: Instant) -> Self {
+        let burst = f64::from(burst.max(1));
+        Self {
+            qps,
+            burst,
+            tokens: burst,
+            updated: now,
+        }
+    }
+
+    /// Takes a token, returning how long the caller must wait until it is available
+    fn reserve(&mut self, now: Instant) -> Duration {
+        if self.qps <= 0.0 {
+            return Duration::ZERO;
+        }
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.qps).min(self.burst) - 1.0;
+        self.updated = now;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.qps)
+        }
+    }
+}
+
+/// Resolves to the name of the signal once the operator is asked to stop
+async fn shutdown_signal() -> &'static str {
+    let terminate = async {
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(err) => {
+                tracing::warn!(
+                    error = &err as &dyn std::error::Error,
+                    "Failed to listen for SIGTERM"
+                );
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    tokio::select! {
+        () = terminate => "SIGTERM",
+        _ = tokio::signal::ctrl_c() => "SIGINT",
+    }
+}
+
+/// Drives `controller` until SIGTERM or SIGINT, and then lets running reconciles finish for up to `grace_period`
+///
+/// Reconciles that are triggered after the signal are skipped, so that no new changes are started.
+pub async fn run_until_shutdown(
+    controller: impl Future<Output = ()>,
+    permits: &ReconcilePermits,
+    grace_period: Duration,
+) {
+    tokio::pin!(controller);
+    let signal = tokio::select! {
+        () = &mut controller => return,
+        signal = shutdown_signal() => signal,
+    };
+    tracing::info!(
+        signal,
+        "Shutting down, waiting for running reconciles to finish"
+    );
+    tokio::select! {
+        () = &mut controller => {}
+        () = permits.drain() => tracing::info!("All running reconciles have finished"),
+        () = tokio::time::sleep(grace_period) => {
+            tracing::warn!(?grace_period, "Gave up waiting for running reconciles to finish")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_bucket_allows_bursts_and_then_throttles() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(10.0, 2, start);
+        assert_eq!(bucket.reserve(start), Duration::ZERO);
+        assert_eq!(bucket.reserve(start), Duration::ZERO);
+        // Waiters queue up behind each other
+        assert_eq!(bucket.reserve(start), Duration::from_millis(100));
+        assert_eq!(bucket.reserve(start), Duration::from_millis(200));
+        // Tokens are refilled over time, but never beyond the burst
+        let later = start + Duration::from_secs(10);
+        assert_eq!(bucket.reserve(later), Duration::ZERO);
+        assert_eq!(bucket.reserve(later), Duration::ZERO);
+        assert_eq!(bucket.reserve(later), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn token_bucket_without_qps_is_unlimited() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(0.0, 1, start);
+        for _ in 0..10 {
+            assert_eq!(bucket.reserve(start), Duration::ZERO);
+        }
+    }
+
+    #[test]
+    fn random_fraction_stays_within_bounds() {
+        let max = Duration::from_secs(10);
+        for _ in 0..100 {
+            assert!(random_fraction(max) <= max);
+        }
+    }
+}
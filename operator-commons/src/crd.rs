@@ -0,0 +1,201 @@
+//! Publishing the operators' `CustomResourceDefinition`s
+
+use std::{str::FromStr, time::Duration};
+
+use k8s_openapi::{
+    api::admissionregistration::v1::MutatingWebhookConfiguration,
+    apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition,
+};
+use kube::runtime::wait::{await_condition, conditions::is_crd_established};
+use snafu::{ResultExt, Snafu};
+
+use crate::apply::{apply_owned, ApplyParams};
+
+/// How long to wait for installed CRDs to become established
+pub const CRD_ESTABLISHED_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Snafu, Debug)]
+pub enum Error {
+    #[snafu(display("failed to render CRDs as YAML"))]
+    RenderYaml { source: serde_yaml::Error },
+    #[snafu(display("failed to render CRDs as JSON"))]
+    RenderJson { source: serde_json::Error },
+    #[snafu(display("failed to apply CRD {}", name))]
+    ApplyCrd { source: kube::Error, name: String },
+    #[snafu(display("failed to wait for CRD {} to become established", name))]
+    AwaitCrd {
+        source: kube::runtime::wait::Error,
+        name: String,
+    },
+    #[snafu(display("failed to apply MutatingWebhookConfiguration {}", name))]
+    ApplyMutatingWebhooks { source: kube::Error, name: String },
+    #[snafu(display(
+        "CRD {} was not established within {:?}",
+        name,
+        CRD_ESTABLISHED_TIMEOUT
+    ))]
+    CrdNotEstablished { name: String },
+}
+
+/// Serialization format of printed CRDs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrdFormat {
+    /// A YAML stream with one document per CRD
+    Yaml,
+    /// A JSON `List` of all CRDs
+    Json,
+}
+
+impl FromStr for CrdFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "yaml" => Ok(Self::Yaml),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("unknown format {:?}, expected yaml or json", s)),
+        }
+    }
+}
+
+/// Renders `crds`, followed by the `MutatingWebhookConfiguration` of their defaulting webhooks, so that they can be
+/// applied with `kubectl apply -f`
+pub fn render_crds(
+    crds: &[CustomResourceDefinition],
+    mutating_webhooks: Option<&MutatingWebhookConfiguration>,
+    format: CrdFormat,
+) -> Result<String, Error> {
+    let mut objects = crds
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<_>, _>>()
+        .context(RenderJson)?;
+    objects.extend(
+        mutating_webhooks
+            .map(serde_json::to_value)
+            .transpose()
+            .context(RenderJson)?,
+    );
+    match format {
+        CrdFormat::Yaml => Ok(objects
+            .iter()
+            .map(serde_yaml::to_string)
+            .collect::<Result<Vec<_>, _>>()
+            .context(RenderYaml)?
+            .join("\n")),
+        CrdFormat::Json => serde_json::to_string_pretty(&serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "List",
+            "items": objects,
+        }))
+        .context(RenderJson),
+    }
+}
+
+/// Applies `crds` to the cluster, and waits until the API server serves them
+///
+/// The `MutatingWebhookConfiguration` of their defaulting webhooks is applied once they are established.
+pub async fn install_crds(
+    kube: &kube::Client,
+    field_manager: &str,
+    crds: &[CustomResourceDefinition],
+    mutating_webhooks: Option<&MutatingWebhookConfiguration>,
+) -> Result<(), Error> {
+    let params = ApplyParams {
+        field_manager: field_manager.to_string(),
+        dry_run: false,
+        log_diffs: false,
+        rate_limiter: None,
+    };
+    for crd in crds {
+        let name = crd.metadata.name.clone().unwrap_or_default();
+        apply_owned(kube, &params, crd)
+            .await
+            .with_context(|| ApplyCrd { name: &name })?;
+    }
+    let api = kube::Api::<CustomResourceDefinition>::all(kube.clone());
+    for crd in crds {
+        let name = crd.metadata.name.clone().unwrap_or_default();
+        match tokio::time::timeout(
+            CRD_ESTABLISHED_TIMEOUT,
+            await_condition(api.clone(), &name, is_crd_established()),
+        )
+        .await
+        {
+            Ok(result) => {
+                result.with_context(|| AwaitCrd { name: &name })?;
+                tracing::info!(crd = %name, "Installed CRD");
+            }
+            Err(_) => return CrdNotEstablished { name }.fail(),
+        }
+    }
+    if let Some(mutating_webhooks) = mutating_webhooks {
+        let name = mutating_webhooks.metadata.name.clone().unwrap_or_default();
+        apply_owned(kube, &params, mutating_webhooks)
+            .await
+            .with_context(|| ApplyMutatingWebhooks { name: &name })?;
+        tracing::info!(name = %name, "Installed MutatingWebhookConfiguration");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_crds, CrdFormat};
+    use k8s_openapi::{
+        api::admissionregistration::v1::MutatingWebhookConfiguration,
+        apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition,
+        apimachinery::pkg::apis::meta::v1::ObjectMeta,
+    };
+
+    fn crd(name: &str) -> CustomResourceDefinition {
+        CustomResourceDefinition {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                ..ObjectMeta::default()
+            },
+            ..CustomResourceDefinition::default()
+        }
+    }
+
+    #[test]
+    fn formats_are_parsed() {
+        assert_eq!("yaml".parse(), Ok(CrdFormat::Yaml));
+        assert_eq!("json".parse(), Ok(CrdFormat::Json));
+        assert!("toml".parse::<CrdFormat>().is_err());
+    }
+
+    #[test]
+    fn yaml_has_one_document_per_crd() {
+        let yaml = render_crds(
+            &[crd("a.example.com"), crd("b.example.com")],
+            None,
+            CrdFormat::Yaml,
+        )
+        .unwrap();
+        assert_eq!(yaml.matches("---\n").count(), 2);
+        assert!(yaml.contains("name: a.example.com"));
+        assert!(yaml.contains("name: b.example.com"));
+    }
+
+    #[test]
+    fn json_is_a_list() {
+        let json = render_crds(
+            &[crd("a.example.com"), crd("b.example.com")],
+            Some(&MutatingWebhookConfiguration {
+                metadata: ObjectMeta {
+                    name: Some("example".to_string()),
+                    ..ObjectMeta::default()
+                },
+                webhooks: None,
+            }),
+            CrdFormat::Json,
+        )
+        .unwrap();
+        let list: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(list["kind"], "List");
+        assert_eq!(list["items"][1]["metadata"]["name"], "b.example.com");
+        assert_eq!(list["items"][0]["kind"], "CustomResourceDefinition");
+        assert_eq!(list["items"][2]["kind"], "MutatingWebhookConfiguration");
+    }
+}
@@ -0,0 +1,107 @@
+//! Showing how objects would change
+
+use kube::Resource;
+use serde::Serialize;
+use serde_json::Value;
+use similar::TextDiff;
+
+/// Metadata that is maintained by the API server, and would only add noise to diffs
+const SERVER_MANAGED_METADATA: &[&str] = &[
+    "creationTimestamp",
+    "generation",
+    "managedFields",
+    "resourceVersion",
+    "selfLink",
+    "uid",
+];
+
+/// Renders `obj` as YAML, without its status and server-managed metadata
+fn normalized_yaml<K: Serialize>(obj: &K) -> String {
+    let mut value = serde_json::to_value(obj).unwrap_or(Value::Null);
+    if let Some(obj) = value.as_object_mut() {
+        obj.remove("status");
+    }
+    if let Some(metadata) = value.get_mut("metadata").and_then(Value::as_object_mut) {
+        for field in SERVER_MANAGED_METADATA {
+            metadata.remove(*field);
+        }
+    }
+    serde_yaml::to_string(&value).unwrap_or_default()
+}
+
+/// Renders the change from `old` to `new` as a unified diff, which is empty if nothing would change
+///
+/// `None` stands for an object that does not exist (yet, or any more).
+pub fn diff_objects<K>(old: Option<&K>, new: Option<&K>) -> String
+where
+    K: Resource<DynamicType = ()> + Serialize,
+{
+    let meta = match old.or(new) {
+        Some(obj) => obj.meta(),
+        None => return String::new(),
+    };
+    let label = format!(
+        "{}/{}/{}",
+        K::kind(&()),
+        meta.namespace.as_deref().unwrap_or_default(),
+        meta.name.as_deref().unwrap_or_default()
+    );
+    let old = old.map(normalized_yaml).unwrap_or_default();
+    let new = new.map(normalized_yaml).unwrap_or_default();
+    TextDiff::from_lines(&old, &new)
+        .unified_diff()
+        .header(&format!("a/{}", label), &format!("b/{}", label))
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::diff_objects;
+    use k8s_openapi::api::core::v1::ConfigMap;
+    use kube::api::ObjectMeta;
+
+    fn config_map(data: &str) -> ConfigMap {
+        ConfigMap {
+            metadata: ObjectMeta {
+                name: Some("cm".to_string()),
+                namespace: Some("default".to_string()),
+                ..ObjectMeta::default()
+            },
+            data: Some([("key".to_string(), data.to_string())].into()),
+            ..ConfigMap::default()
+        }
+    }
+
+    #[test]
+    fn unchanged_objects_have_no_diff() {
+        assert_eq!(
+            diff_objects(Some(&config_map("a")), Some(&config_map("a"))),
+            ""
+        );
+        assert_eq!(diff_objects::<ConfigMap>(None, None), "");
+    }
+
+    #[test]
+    fn server_managed_metadata_is_ignored() {
+        let mut live = config_map("a");
+        live.metadata.uid = Some("1234".to_string());
+        live.metadata.resource_version = Some("42".to_string());
+        assert_eq!(diff_objects(Some(&live), Some(&config_map("a"))), "");
+    }
+
+    #[test]
+    fn changes_are_rendered_as_unified_diff() {
+        let diff = diff_objects(Some(&config_map("a")), Some(&config_map("b")));
+        assert!(diff.starts_with("--- a/ConfigMap/default/cm\n+++ b/ConfigMap/default/cm\n"));
+        assert!(diff.contains("\n-  key: a\n"));
+        assert!(diff.contains("\n+  key: b\n"));
+    }
+
+    #[test]
+    fn created_and_deleted_objects_are_rendered_in_full() {
+        let created = diff_objects(None, Some(&config_map("a")));
+        assert!(created.lines().skip(3).all(|line| line.starts_with('+')));
+        let deleted = diff_objects(Some(&config_map("a")), None);
+        assert!(deleted.lines().skip(3).all(|line| line.starts_with('-')));
+    }
+}
@@ -0,0 +1,52 @@
+//! Parsing durations from the command line
+
+use std::time::Duration;
+
+/// Parses a duration such as `500ms`, `30s`, `5m`, or `1h`, plain numbers are interpreted as seconds
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let duration = duration.trim();
+    let split = duration
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(duration.len());
+    let (number, unit) = duration.split_at(split);
+    let number = number
+        .parse::<u64>()
+        .map_err(|_| format!("invalid duration {:?}", duration))?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(number)),
+        "" | "s" => Ok(Duration::from_secs(number)),
+        "m" => Ok(Duration::from_secs(number * 60)),
+        "h" => Ok(Duration::from_secs(number * 60 * 60)),
+        _ => Err(format!(
+            "unknown unit {:?} in duration {:?}, expected ms, s, m, or h",
+            unit, duration
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_duration;
+    use std::time::Duration;
+
+    #[test]
+    fn units() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn plain_seconds() {
+        assert_eq!(parse_duration("10"), Ok(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn invalid_durations() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration("1d").is_err());
+        assert!(parse_duration("1.5s").is_err());
+    }
+}
@@ -0,0 +1,90 @@
+//! Reporting reconciliation failures
+
+use std::time::Duration;
+
+use kube::runtime::{
+    controller::{Context, ReconcilerAction},
+    events::{Event, Recorder},
+};
+
+/// How long to wait before retrying a failed reconcile
+pub const ERROR_REQUEUE_DELAY: Duration = Duration::from_secs(5);
+
+/// Retries failed reconciles after [`ERROR_REQUEUE_DELAY`]
+pub fn error_policy<E, C>(_error: &E, _ctx: Context<C>) -> ReconcilerAction {
+    ReconcilerAction {
+        requeue_after: Some(ERROR_REQUEUE_DELAY),
+    }
+}
+
+/// Renders an error along with all of its causes
+pub fn error_chain(err: &dyn std::error::Error) -> String {
+    let mut chain = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        chain.push_str(": ");
+        chain.push_str(&err.to_string());
+        source = err.source();
+    }
+    chain
+}
+
+/// Publishes an `Event`, failures are only logged since events are informational
+pub async fn publish_event(recorder: &Recorder, event: Event) {
+    if let Err(err) = recorder.publish(event).await {
+        tracing::warn!(
+            error = &err as &dyn std::error::Error,
+            "Failed to publish event"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::error_chain;
+    use std::fmt::{self, Display, Formatter};
+
+    #[derive(Debug)]
+    struct TestError {
+        message: &'static str,
+        source: Option<Box<TestError>>,
+    }
+
+    impl Display for TestError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            f.write_str(self.message)
+        }
+    }
+
+    impl std::error::Error for TestError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            self.source
+                .as_deref()
+                .map(|source| source as &dyn std::error::Error)
+        }
+    }
+
+    #[test]
+    fn error_chain_includes_all_causes() {
+        let err = TestError {
+            message: "failed to apply StatefulSet",
+            source: Some(Box::new(TestError {
+                message: "connection refused",
+                source: None,
+            })),
+        };
+        assert_eq!(
+            error_chain(&err),
+            "failed to apply StatefulSet: connection refused"
+        );
+    }
+
+    #[test]
+    fn error_chain_of_root_cause_is_its_message() {
+        let err = TestError {
+            message: "connection refused",
+            source: None,
+        };
+        assert_eq!(error_chain(&err), "connection refused");
+    }
+}
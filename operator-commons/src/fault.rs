@@ -0,0 +1,120 @@
+//! Faults that are injected into the controllers, so that e2e tests can exercise retries, backoff, and status reporting
+//!
+//! Only active when built with the `fault-injection` feature, and configured through environment variables:
+//!
+//! - `FAULT_INJECTION_APPLY_FAILURE_RATE`: share of applies that fail with an internal error, between `0` and `1`
+//! - `FAULT_INJECTION_RECONCILE_DELAY`: how long each reconcile is delayed before it starts, such as `2s`
+//! - `FAULT_INJECTION_SEED`: makes the same applies fail on every run, instead of random ones
+//!
+//! The variables are read on every use, so that tests can change them while the operator runs.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::duration::parse_duration;
+
+pub const APPLY_FAILURE_RATE_VAR: &str = "FAULT_INJECTION_APPLY_FAILURE_RATE";
+pub const RECONCILE_DELAY_VAR: &str = "FAULT_INJECTION_RECONCILE_DELAY";
+pub const SEED_VAR: &str = "FAULT_INJECTION_SEED";
+
+/// Number of draws so far, so that a seed yields the same sequence of failures on every run
+static DRAWS: AtomicU64 = AtomicU64::new(0);
+
+/// Reads the environment variable `name`, ignoring it with a warning if it can't be parsed
+fn env_var<T>(name: &str, parse: impl FnOnce(&str) -> Result<T, String>) -> Option<T> {
+    if !cfg!(feature = "fault-injection") {
+        return None;
+    }
+    let value = std::env::var(name).ok()?;
+    match parse(&value) {
+        Ok(parsed) => Some(parsed),
+        Err(err) => {
+            tracing::warn!(name, %value, %err, "Ignoring invalid fault injection setting");
+            None
+        }
+    }
+}
+
+fn parse<T: FromStr>(value: &str) -> Result<T, String>
+where
+    T::Err: ToString,
+{
+    value.parse().map_err(|err: T::Err| err.to_string())
+}
+
+/// The `n`th number between 0 (inclusive) and 1 (exclusive) of the sequence that is determined by `seed`
+fn draw(seed: u64, n: u64) -> f64 {
+    // SplitMix64, which spreads consecutive inputs over the whole range
+    let mut z = seed.wrapping_add(n.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Whether the next apply should fail, according to `FAULT_INJECTION_APPLY_FAILURE_RATE`
+pub fn fail_apply() -> bool {
+    let rate = match env_var(APPLY_FAILURE_RATE_VAR, parse::<f64>) {
+        Some(rate) if rate > 0.0 => rate,
+        _ => return false,
+    };
+    let seed = env_var(SEED_VAR, parse::<u64>)
+        .unwrap_or_else(|| RandomState::new().build_hasher().finish());
+    draw(seed, DRAWS.fetch_add(1, Ordering::Relaxed)) < rate
+}
+
+/// The error that injected apply failures fail with
+pub fn apply_error() -> kube::Error {
+    kube::Error::Api(kube::error::ErrorResponse {
+        status: "Failure".to_string(),
+        message: format!("apply failed by {}", APPLY_FAILURE_RATE_VAR),
+        reason: "InternalError".to_string(),
+        code: 500,
+    })
+}
+
+/// Waits for `FAULT_INJECTION_RECONCILE_DELAY`, if set
+pub async fn delay_reconcile() {
+    if let Some(delay) = env_var(RECONCILE_DELAY_VAR, parse_duration) {
+        tracing::debug!(?delay, "Delaying reconcile by injected fault");
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Warns about the active faults at startup, so that they aren't enabled by accident
+pub fn log_active_faults() {
+    let apply_failure_rate = env_var(APPLY_FAILURE_RATE_VAR, parse::<f64>);
+    let reconcile_delay = env_var(RECONCILE_DELAY_VAR, parse_duration);
+    if apply_failure_rate.is_some() || reconcile_delay.is_some() {
+        tracing::warn!(
+            ?apply_failure_rate,
+            ?reconcile_delay,
+            seed = ?env_var(SEED_VAR, parse::<u64>),
+            "Fault injection is active"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_are_deterministic_and_bounded() {
+        let first = (0..1000).map(|n| draw(42, n)).collect::<Vec<_>>();
+        let second = (0..1000).map(|n| draw(42, n)).collect::<Vec<_>>();
+        assert_eq!(first, second);
+        assert!(first.iter().all(|x| (0.0..1.0).contains(x)));
+        assert_ne!(first, (0..1000).map(|n| draw(43, n)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn draws_follow_the_rate() {
+        let failures = (0..10_000).filter(|n| draw(7, *n) < 0.25).count();
+        assert!((2_200..2_800).contains(&failures), "{}", failures);
+    }
+}
@@ -0,0 +1,71 @@
+//! HTTP endpoints for probing the operator
+
+use std::{convert::Infallible, net::SocketAddr};
+
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, StatusCode,
+};
+use snafu::{ResultExt, Snafu};
+
+use crate::version::OperatorVersion;
+
+#[derive(Snafu, Debug)]
+pub enum Error {
+    #[snafu(display("failed to listen on {}", addr))]
+    Bind {
+        source: hyper::Error,
+        addr: SocketAddr,
+    },
+    #[snafu(display("failed to serve health endpoints"))]
+    Serve { source: hyper::Error },
+}
+
+fn respond(req: &Request<Body>, version: &OperatorVersion) -> Response<Body> {
+    let (status, body) = match req.uri().path() {
+        "/healthz" => (StatusCode::OK, "ok".to_string()),
+        "/version" => (StatusCode::OK, version.to_json().to_string()),
+        _ => (StatusCode::NOT_FOUND, "not found".to_string()),
+    };
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response
+}
+
+/// Serves `/healthz` for liveness probes, and the operator's build information on `/version`
+pub async fn serve_health(addr: SocketAddr, version: OperatorVersion) -> Result<(), Error> {
+    let make_service = make_service_fn(move |_conn| async move {
+        Ok::<_, Infallible>(service_fn(move |req| async move {
+            Ok::<_, Infallible>(respond(&req, &version))
+        }))
+    });
+    hyper::Server::try_bind(&addr)
+        .context(Bind { addr })?
+        .serve(make_service)
+        .await
+        .context(Serve)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::respond;
+    use crate::version::OperatorVersion;
+    use hyper::{Body, Request, StatusCode};
+
+    const VERSION: OperatorVersion = OperatorVersion {
+        name: "hdfs-operator",
+        version: "0.1.0",
+        git_commit: None,
+    };
+
+    fn get(path: &str) -> StatusCode {
+        respond(&Request::get(path).body(Body::empty()).unwrap(), &VERSION).status()
+    }
+
+    #[test]
+    fn known_paths_are_served() {
+        assert_eq!(get("/healthz"), StatusCode::OK);
+        assert_eq!(get("/version"), StatusCode::OK);
+        assert_eq!(get("/metrics"), StatusCode::NOT_FOUND);
+    }
+}
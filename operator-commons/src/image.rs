@@ -0,0 +1,71 @@
+//! Pulling images from a private mirror
+
+use structopt::StructOpt;
+
+/// Where the images of the managed pods are pulled from
+#[derive(StructOpt, Clone, Debug, Default)]
+pub struct ImageOptions {
+    /// Registry (and path) that replaces the registry of every image the operator deploys, such as
+    /// `mirror.example.com/stackable`, for clusters that can't reach the public registries
+    ///
+    /// `docker.stackable.tech/stackable/zookeeper:3.5.8-stackable0` is then pulled from
+    /// `mirror.example.com/stackable/stackable/zookeeper:3.5.8-stackable0`.
+    #[structopt(long)]
+    pub image_repository_prefix: Option<String>,
+}
+
+impl ImageOptions {
+    /// The reference that `image` is pulled from
+    pub fn rewrite(&self, image: &str) -> String {
+        let prefix = match &self.image_repository_prefix {
+            Some(prefix) => prefix.trim_end_matches('/'),
+            None => return image.to_string(),
+        };
+        // Like Docker, only treat the first component as a registry if it looks like a host
+        let path = match image.split_once('/') {
+            Some((registry, path))
+                if registry.contains(|c| c == '.' || c == ':') || registry == "localhost" =>
+            {
+                path
+            }
+            _ => image,
+        };
+        format!("{}/{}", prefix, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ImageOptions;
+
+    #[test]
+    fn images_are_pulled_from_the_mirror() {
+        let options = ImageOptions {
+            image_repository_prefix: Some("mirror.local:5000/stackable/".to_string()),
+        };
+        assert_eq!(
+            options.rewrite("docker.stackable.tech/stackable/hadoop:3.3.1"),
+            "mirror.local:5000/stackable/stackable/hadoop:3.3.1"
+        );
+        assert_eq!(
+            options.rewrite("localhost/hadoop:3.3.1"),
+            "mirror.local:5000/stackable/hadoop:3.3.1"
+        );
+        assert_eq!(
+            options.rewrite("library/alpine"),
+            "mirror.local:5000/stackable/library/alpine"
+        );
+        assert_eq!(
+            options.rewrite("alpine"),
+            "mirror.local:5000/stackable/alpine"
+        );
+    }
+
+    #[test]
+    fn images_are_kept_without_a_mirror() {
+        assert_eq!(
+            ImageOptions::default().rewrite("docker.stackable.tech/stackable/hadoop:3.3.1"),
+            "docker.stackable.tech/stackable/hadoop:3.3.1"
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! [Recommended labels](https://kubernetes.io/docs/concepts/overview/working-with-objects/common-labels/) for
+//! the objects of a cluster
+//!
+//! These match the labels generated by `stackable_operator::labels`, so that existing selectors keep matching.
+
+use std::collections::BTreeMap;
+
+use kube::Resource;
+
+use crate::version::OperatorVersion;
+
+pub const APP_NAME_LABEL: &str = "app.kubernetes.io/name";
+pub const APP_INSTANCE_LABEL: &str = "app.kubernetes.io/instance";
+pub const APP_VERSION_LABEL: &str = "app.kubernetes.io/version";
+pub const APP_COMPONENT_LABEL: &str = "app.kubernetes.io/component";
+pub const APP_ROLE_GROUP_LABEL: &str = "app.kubernetes.io/role-group";
+pub const APP_MANAGED_BY_LABEL: &str = "app.kubernetes.io/managed-by";
+pub const OPERATOR_VERSION_LABEL: &str = "stackable.tech/operator-version";
+
+/// Labels that record which operator build manages an object
+///
+/// Must not be added to pod templates, since that would restart all pods whenever the operator is upgraded.
+pub fn managed_by_labels(operator: &OperatorVersion) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        (APP_MANAGED_BY_LABEL.to_string(), operator.name.to_string()),
+        (OPERATOR_VERSION_LABEL.to_string(), operator.label_value()),
+    ])
+}
+
+/// Recommended labels for objects that belong to the whole cluster `owner`, rather than one of its roles
+pub fn cluster_labels<K: Resource>(
+    owner: &K,
+    app_name: &str,
+    app_version: &str,
+) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        (APP_NAME_LABEL.to_string(), app_name.to_string()),
+        (
+            APP_INSTANCE_LABEL.to_string(),
+            owner.meta().name.clone().unwrap_or_default(),
+        ),
+        (APP_VERSION_LABEL.to_string(), app_version.to_string()),
+        (
+            APP_MANAGED_BY_LABEL.to_string(),
+            format!("{}-operator", app_name),
+        ),
+    ])
+}
+
+/// Labels that select all pods of a role of the cluster `owner`
+pub fn role_selector_labels<K: Resource>(
+    owner: &K,
+    app_name: &str,
+    role: &str,
+) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        (APP_NAME_LABEL.to_string(), app_name.to_string()),
+        (
+            APP_INSTANCE_LABEL.to_string(),
+            owner.meta().name.clone().unwrap_or_default(),
+        ),
+        (APP_COMPONENT_LABEL.to_string(), role.to_string()),
+    ])
+}
+
+/// Labels that select all pods of a role group of the cluster `owner`
+pub fn role_group_selector_labels<K: Resource>(
+    owner: &K,
+    app_name: &str,
+    role: &str,
+    role_group: &str,
+) -> BTreeMap<String, String> {
+    let mut labels = role_selector_labels(owner, app_name, role);
+    labels.insert(APP_ROLE_GROUP_LABEL.to_string(), role_group.to_string());
+    labels
+}
+
+/// All recommended labels for objects of a role group of the cluster `owner`
+///
+/// Includes the product version, so these must not be used as selectors.
+pub fn recommended_labels<K: Resource>(
+    owner: &K,
+    app_name: &str,
+    app_version: &str,
+    role: &str,
+    role_group: &str,
+) -> BTreeMap<String, String> {
+    let mut labels = role_group_selector_labels(owner, app_name, role, role_group);
+    labels.insert(APP_VERSION_LABEL.to_string(), app_version.to_string());
+    labels.insert(
+        APP_MANAGED_BY_LABEL.to_string(),
+        format!("{}-operator", app_name),
+    );
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cluster_labels, managed_by_labels, recommended_labels, role_selector_labels};
+    use crate::version::OperatorVersion;
+    use k8s_openapi::api::core::v1::ConfigMap;
+    use kube::api::ObjectMeta;
+    use std::collections::BTreeMap;
+
+    fn owner() -> ConfigMap {
+        ConfigMap {
+            metadata: ObjectMeta {
+                name: Some("simple".to_string()),
+                ..ObjectMeta::default()
+            },
+            ..ConfigMap::default()
+        }
+    }
+
+    fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn role_selector() {
+        assert_eq!(
+            role_selector_labels(&owner(), "zookeeper", "servers"),
+            labels(&[
+                ("app.kubernetes.io/name", "zookeeper"),
+                ("app.kubernetes.io/instance", "simple"),
+                ("app.kubernetes.io/component", "servers"),
+            ])
+        );
+    }
+
+    #[test]
+    fn recommended() {
+        assert_eq!(
+            recommended_labels(&owner(), "zookeeper", "3.7.0", "servers", "default"),
+            labels(&[
+                ("app.kubernetes.io/name", "zookeeper"),
+                ("app.kubernetes.io/instance", "simple"),
+                ("app.kubernetes.io/component", "servers"),
+                ("app.kubernetes.io/role-group", "default"),
+                ("app.kubernetes.io/version", "3.7.0"),
+                ("app.kubernetes.io/managed-by", "zookeeper-operator"),
+            ])
+        );
+    }
+
+    #[test]
+    fn cluster() {
+        assert_eq!(
+            cluster_labels(&owner(), "hdfs", "3.3.1"),
+            labels(&[
+                ("app.kubernetes.io/name", "hdfs"),
+                ("app.kubernetes.io/instance", "simple"),
+                ("app.kubernetes.io/version", "3.3.1"),
+                ("app.kubernetes.io/managed-by", "hdfs-operator"),
+            ])
+        );
+    }
+
+    #[test]
+    fn managed_by() {
+        let operator = OperatorVersion {
+            name: "zookeeper-operator",
+            version: "0.1.0",
+            git_commit: Some("4c1a5f0e7d3b"),
+        };
+        assert_eq!(
+            managed_by_labels(&operator),
+            labels(&[
+                ("app.kubernetes.io/managed-by", "zookeeper-operator"),
+                ("stackable.tech/operator-version", "0.1.0-4c1a5f0"),
+            ])
+        );
+    }
+}
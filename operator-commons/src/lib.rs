@@ -0,0 +1,38 @@
+//! Controller plumbing shared by the operators in this workspace
+
+pub mod apply;
+pub mod client;
+pub mod controller;
+pub mod crd;
+pub mod diff;
+pub mod duration;
+pub mod error;
+pub mod fault;
+pub mod health;
+pub mod image;
+pub mod labels;
+pub mod merge;
+pub mod quantity;
+pub mod spec;
+pub mod telemetry;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod version;
+pub mod webhook;
+
+pub use apply::{
+    apply_owned, controller_reference_to_obj, delete_if_controlled_by, delete_if_exists,
+    delete_orphans, diff_deleted, diff_owned, ApplyParams,
+};
+pub use client::{detect_cluster_domain, ClientOptions};
+pub use controller::{
+    erase_controller_result, log_reconcile_results, run_until_shutdown, ControllerOptions,
+    RateLimiter, ReconcilePermits, ReconcileResult, SharedContext,
+};
+pub use diff::diff_objects;
+pub use duration::parse_duration;
+pub use error::{error_chain, error_policy, publish_event};
+pub use image::ImageOptions;
+pub use merge::merge_json;
+pub use quantity::parse_memory_quantity;
+pub use version::OperatorVersion;
@@ -0,0 +1,94 @@
+//! Merging user-provided overrides into generated objects
+
+use serde_json::Value;
+
+/// Merges `overlay` into `base`, similar to a strategic merge patch
+///
+/// Lists whose elements all have a `name` are merged by name, other values in `overlay` replace those in `base`.
+pub fn merge_json(base: &mut Value, overlay: Value) {
+    fn is_named_list(list: &[Value]) -> bool {
+        list.iter()
+            .all(|item| item.get("name").map_or(false, Value::is_string))
+    }
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                merge_json(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (Value::Array(base), Value::Array(overlay))
+            if is_named_list(base) && is_named_list(&overlay) =>
+        {
+            for item in overlay {
+                let name = item.get("name").cloned();
+                match base.iter_mut().find(|old| old.get("name") == name.as_ref()) {
+                    Some(old) => merge_json(old, item),
+                    None => base.push(item),
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Schema for free-form `PodTemplateSpec` fragments, which are validated once merged into the generated pod template
+pub fn pod_overrides_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+    serde_json::from_value(serde_json::json!({
+        "type": "object",
+        "x-kubernetes-preserve-unknown-fields": true,
+    }))
+    .expect("pod overrides schema must be valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::merge_json;
+    use serde_json::json;
+
+    #[test]
+    fn objects_are_merged_recursively() {
+        let mut base = json!({"spec": {"priorityClassName": "low", "hostNetwork": false}});
+        merge_json(&mut base, json!({"spec": {"priorityClassName": "high"}}));
+        assert_eq!(
+            base,
+            json!({"spec": {"priorityClassName": "high", "hostNetwork": false}})
+        );
+    }
+
+    #[test]
+    fn named_lists_are_merged_by_name() {
+        let mut base = json!([
+            {"name": "zookeeper", "image": "zookeeper:3.5.8"},
+            {"name": "sidecar", "image": "busybox"},
+        ]);
+        merge_json(
+            &mut base,
+            json!([
+                {"name": "zookeeper", "image": "zookeeper:3.7.0"},
+                {"name": "debug", "image": "alpine"},
+            ]),
+        );
+        assert_eq!(
+            base,
+            json!([
+                {"name": "zookeeper", "image": "zookeeper:3.7.0"},
+                {"name": "sidecar", "image": "busybox"},
+                {"name": "debug", "image": "alpine"},
+            ])
+        );
+    }
+
+    #[test]
+    fn unnamed_lists_are_replaced() {
+        let mut base = json!({"args": ["start-foreground", "/config/zoo.cfg"]});
+        merge_json(&mut base, json!({"args": ["version"]}));
+        assert_eq!(base, json!({"args": ["version"]}));
+    }
+
+    #[test]
+    fn scalars_are_replaced() {
+        let mut base = json!({"replicas": 3});
+        merge_json(&mut base, json!({"replicas": null}));
+        assert_eq!(base, json!({"replicas": null}));
+    }
+}
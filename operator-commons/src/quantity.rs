@@ -0,0 +1,54 @@
+//! Interpreting Kubernetes resource quantities
+
+/// Parses a Kubernetes memory quantity (such as `512Mi` or `2G`) into bytes
+pub fn parse_memory_quantity(quantity: &str) -> Option<u64> {
+    let quantity = quantity.trim();
+    let split = quantity
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(quantity.len());
+    let (number, suffix) = quantity.split_at(split);
+    let multiplier = match suffix {
+        "" => 1.0,
+        "k" => 1e3,
+        "M" => 1e6,
+        "G" => 1e9,
+        "T" => 1e12,
+        "Ki" => 1024.0,
+        "Mi" => 1024.0 * 1024.0,
+        "Gi" => 1024.0 * 1024.0 * 1024.0,
+        "Ti" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    Some((number.parse::<f64>().ok()? * multiplier) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_memory_quantity;
+
+    #[test]
+    fn binary_suffixes() {
+        assert_eq!(parse_memory_quantity("512Mi"), Some(512 * 1024 * 1024));
+        assert_eq!(parse_memory_quantity("2Gi"), Some(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_memory_quantity("1.5Ki"), Some(1536));
+    }
+
+    #[test]
+    fn decimal_suffixes() {
+        assert_eq!(parse_memory_quantity("2G"), Some(2_000_000_000));
+        assert_eq!(parse_memory_quantity("100k"), Some(100_000));
+    }
+
+    #[test]
+    fn plain_bytes() {
+        assert_eq!(parse_memory_quantity("1024"), Some(1024));
+        assert_eq!(parse_memory_quantity(" 1024 "), Some(1024));
+    }
+
+    #[test]
+    fn invalid_quantities() {
+        assert_eq!(parse_memory_quantity("1Xi"), None);
+        assert_eq!(parse_memory_quantity("Gi"), None);
+        assert_eq!(parse_memory_quantity(""), None);
+    }
+}
@@ -0,0 +1,236 @@
+//! Settings that the custom resources of all operators share
+//!
+//! The operators re-export these from their CRD modules, so that the generated pods are configured the same way.
+
+use std::collections::BTreeMap;
+
+use k8s_openapi::{
+    api::{
+        core::v1::{
+            Capabilities, LocalObjectReference, PodSecurityContext, SeccompProfile, SecurityContext,
+        },
+        networking::v1::NetworkPolicyPeer,
+    },
+    apimachinery::pkg::apis::meta::v1::LabelSelector,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// The settings of an [`ImageConfig`] that only concern one product's image, along with its default image
+pub trait ProductImage {
+    /// Repository of the image that is run unless `repository` is set
+    const DEFAULT_REPOSITORY: &'static str;
+    /// Tag of the image that is run unless `tag` is set, which is the product version that the operator was tested with
+    const DEFAULT_TAG: &'static str;
+}
+
+/// The image of the product, where `P` holds the settings that only concern that product, see [`ProductImage`]
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageConfig<P> {
+    /// Image repository, defaults to the operator's image of the product
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository: Option<String>,
+    /// Image tag, which is also the product version, defaults to the version that the operator was tested with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// Pull policy for all containers (`Always`, `IfNotPresent`, or `Never`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull_policy: Option<String>,
+    /// Names of `Secret`s used to pull the images
+    #[serde(default)]
+    pub pull_secrets: Vec<String>,
+    #[serde(flatten)]
+    pub product: P,
+}
+
+impl<P: ProductImage> ImageConfig<P> {
+    /// The full image reference, including the tag
+    pub fn image(&self) -> String {
+        format!(
+            "{}:{}",
+            self.repository.as_deref().unwrap_or(P::DEFAULT_REPOSITORY),
+            self.tag()
+        )
+    }
+
+    /// The image tag, which is also the product version
+    pub fn tag(&self) -> &str {
+        self.tag.as_deref().unwrap_or(P::DEFAULT_TAG)
+    }
+}
+
+impl<P> ImageConfig<P> {
+    pub fn pull_secret_refs(&self) -> Option<Vec<LocalObjectReference>> {
+        if self.pull_secrets.is_empty() {
+            None
+        } else {
+            Some(
+                self.pull_secrets
+                    .iter()
+                    .map(|secret| LocalObjectReference {
+                        name: Some(secret.clone()),
+                    })
+                    .collect(),
+            )
+        }
+    }
+}
+
+/// Security settings applied to all generated pods and containers
+///
+/// The defaults satisfy the `restricted` Pod Security Standard, as long as the pods don't use the host network.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PodSecurityConfig {
+    /// UID that all containers run as, defaults to `1000`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_as_user: Option<i64>,
+    /// GID that all containers run as, defaults to `1000`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_as_group: Option<i64>,
+    /// Group that owns mounted volumes (such as the data PVCs), defaults to `1000`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fs_group: Option<i64>,
+    /// Seccomp profile type (`RuntimeDefault`, `Localhost`, or `Unconfined`), defaults to `RuntimeDefault`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seccomp_profile: Option<String>,
+    /// Path of the profile on the node, only used when `seccompProfile` is `Localhost`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seccomp_localhost_profile: Option<String>,
+    /// Whether containers' root filesystems are mounted read-only, defaults to `false`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_only_root_filesystem: Option<bool>,
+}
+
+impl PodSecurityConfig {
+    pub fn pod_security_context(&self) -> PodSecurityContext {
+        let run_as_user = self.run_as_user.unwrap_or(1000);
+        PodSecurityContext {
+            run_as_user: Some(run_as_user),
+            run_as_group: Some(self.run_as_group.unwrap_or(1000)),
+            run_as_non_root: Some(run_as_user != 0),
+            fs_group: Some(self.fs_group.unwrap_or(1000)),
+            seccomp_profile: Some(SeccompProfile {
+                type_: self
+                    .seccomp_profile
+                    .clone()
+                    .unwrap_or_else(|| "RuntimeDefault".to_string()),
+                localhost_profile: self.seccomp_localhost_profile.clone(),
+            }),
+            ..PodSecurityContext::default()
+        }
+    }
+
+    pub fn container_security_context(&self) -> SecurityContext {
+        SecurityContext {
+            allow_privilege_escalation: Some(false),
+            capabilities: Some(Capabilities {
+                drop: Some(vec!["ALL".to_string()]),
+                ..Capabilities::default()
+            }),
+            read_only_root_filesystem: Some(self.read_only_root_filesystem.unwrap_or(false)),
+            ..SecurityContext::default()
+        }
+    }
+}
+
+/// Restricts ingress to the cluster's ports to its own pods and explicitly allowed clients
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkIsolationConfig {
+    /// Clients that may connect to the cluster, in addition to its own pods
+    #[serde(default)]
+    pub allowed_clients: Vec<AllowedClient>,
+}
+
+/// Selects clients by namespace and/or pod labels
+///
+/// Clients must match both selectors if both are given. Pods are only matched in the cluster's own namespace
+/// unless `namespaceLabels` is given, leaving both empty allows all pods in all namespaces.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AllowedClient {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace_labels: Option<BTreeMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pod_labels: Option<BTreeMap<String, String>>,
+}
+
+impl AllowedClient {
+    pub fn network_policy_peer(&self) -> NetworkPolicyPeer {
+        let selector = |labels: &BTreeMap<String, String>| LabelSelector {
+            match_labels: Some(labels.clone()),
+            ..LabelSelector::default()
+        };
+        let pod_selector = self.pod_labels.as_ref().map(selector);
+        let namespace_selector = match (&self.namespace_labels, &pod_selector) {
+            (Some(labels), _) => Some(selector(labels)),
+            // Peers without a namespace selector only match pods in the policy's own namespace
+            (None, Some(_)) => None,
+            (None, None) => Some(LabelSelector::default()),
+        };
+        NetworkPolicyPeer {
+            namespace_selector,
+            pod_selector,
+            ..NetworkPolicyPeer::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceAccountConfig {
+    /// Name of an existing `ServiceAccount` to run the pods as, instead of generating `<cluster name>-serviceaccount`
+    ///
+    /// The existing account is not managed by the operator, so it must be set up (and deleted) by its owner.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Names of `Secret`s that are attached to the generated `ServiceAccount` as image pull secrets
+    #[serde(default)]
+    pub image_pull_secrets: Vec<String>,
+    /// Name of a `ClusterRole` that is granted to the `ServiceAccount` within the cluster's namespace
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cluster_role: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ImageConfig, ProductImage};
+    use schemars::JsonSchema;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+    #[serde(rename_all = "camelCase")]
+    struct TestImage {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        init_image: Option<String>,
+    }
+
+    impl ProductImage for TestImage {
+        const DEFAULT_REPOSITORY: &'static str = "example.com/product";
+        const DEFAULT_TAG: &'static str = "1.0.0";
+    }
+
+    #[test]
+    fn images_default_to_the_products_image() {
+        let image = ImageConfig::<TestImage>::default();
+        assert_eq!(image.image(), "example.com/product:1.0.0");
+        assert_eq!(image.pull_secret_refs(), None);
+
+        let image = serde_json::from_value::<ImageConfig<TestImage>>(serde_json::json!({
+            "tag": "1.1.0",
+            "pullSecrets": ["registry"],
+            "initImage": "busybox",
+        }))
+        .unwrap();
+        assert_eq!(image.image(), "example.com/product:1.1.0");
+        assert_eq!(image.tag(), "1.1.0");
+        assert_eq!(
+            image.pull_secret_refs().unwrap()[0].name.as_deref(),
+            Some("registry")
+        );
+        // The product's settings are flattened into the image settings
+        assert_eq!(image.product.init_image.as_deref(), Some("busybox"));
+    }
+}
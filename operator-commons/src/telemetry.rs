@@ -0,0 +1,68 @@
+//! Logging and trace export
+
+use opentelemetry::{sdk::trace, trace::TraceError, KeyValue};
+use snafu::{ResultExt, Snafu};
+use structopt::StructOpt;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+#[derive(Snafu, Debug)]
+pub enum Error {
+    #[snafu(display("failed to set up OTLP trace export to {}", endpoint))]
+    InitOtlp {
+        source: TraceError,
+        endpoint: String,
+    },
+}
+
+/// Where to export traces to
+#[derive(StructOpt, Clone, Debug, Default)]
+pub struct TracingOptions {
+    /// OTLP (gRPC) endpoint that traces of reconciles are exported to, such as `http://localhost:4317`
+    ///
+    /// Traces are only exported if this is set.
+    #[structopt(long)]
+    pub otlp_endpoint: Option<String>,
+}
+
+/// Logs to stdout, filtered by the environment variable `log_env_var` (in `RUST_LOG` syntax), and exports traces
+///
+/// Call [`shutdown_tracing`] before exiting, so that the remaining traces are exported.
+pub fn init_tracing(
+    log_env_var: &str,
+    service_name: &'static str,
+    options: &TracingOptions,
+) -> Result<(), Error> {
+    let filter = EnvFilter::try_from_env(log_env_var).unwrap_or_else(|_| EnvFilter::new("info"));
+    let otlp = match &options.otlp_endpoint {
+        Some(endpoint) => {
+            let tracer = opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(trace::config().with_resource(
+                    opentelemetry::sdk::Resource::new([KeyValue::new(
+                        "service.name",
+                        service_name,
+                    )]),
+                ))
+                .install_batch(opentelemetry::runtime::Tokio)
+                .context(InitOtlp { endpoint })?;
+            Some(tracing_opentelemetry::layer().with_tracer(tracer))
+        }
+        None => None,
+    };
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(otlp)
+        .init();
+    Ok(())
+}
+
+/// Exports the traces that have not been exported yet
+pub fn shutdown_tracing() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
@@ -0,0 +1,590 @@
+//! An in-memory stand-in for the Kubernetes API server, so that controllers can be tested end-to-end
+//!
+//! Only built with the `testing` feature. It understands just enough of the API for the controllers in this workspace:
+//! objects are stored by their path, server-side applies replace the stored object (keeping its status), merge
+//! patches are merged into it, and lists can be filtered by equality-based label selectors. Every request is
+//! recorded, so that tests can assert on what the controllers sent.
+
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    sync::{Arc, Mutex},
+};
+
+use hyper::{Body, Request, Response, StatusCode};
+use kube::Resource;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// A request that a client sent to the [`FakeApiServer`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// Path without the query, such as `/apis/apps/v1/namespaces/default/statefulsets/simple`
+    pub path: String,
+    /// Decoded query parameters, such as `fieldManager`
+    pub query: BTreeMap<String, String>,
+    pub content_type: Option<String>,
+    pub body: Option<Value>,
+}
+
+impl RecordedRequest {
+    /// Whether this request changes objects, rather than only reading them
+    pub fn is_write(&self) -> bool {
+        self.method != "GET"
+    }
+
+    /// Whether this request is a server-side apply
+    pub fn is_apply(&self) -> bool {
+        self.method == "PATCH"
+            && self.content_type.as_deref() == Some("application/apply-patch+yaml")
+    }
+
+    /// Whether this request was only validated, without persisting anything
+    pub fn is_dry_run(&self) -> bool {
+        self.query.contains_key("dryRun")
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Objects by path
+    objects: BTreeMap<String, Value>,
+    requests: Vec<RecordedRequest>,
+    /// Number of objects that have been created, used for their uids and generated names
+    created: u64,
+}
+
+/// A fake API server that clients from [`FakeApiServer::client`] talk to
+#[derive(Clone, Debug, Default)]
+pub struct FakeApiServer {
+    state: Arc<Mutex<State>>,
+}
+
+impl FakeApiServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A client whose requests are served by this fake, with `default` as its default namespace
+    pub fn client(&self) -> kube::Client {
+        let state = self.state.clone();
+        let service = tower::service_fn(move |request: Request<Body>| {
+            let state = state.clone();
+            async move { Ok::<_, Infallible>(serve(&state, request).await) }
+        });
+        kube::Client::new(service, "default")
+    }
+
+    /// Stores `obj`, as if it had been created by someone else
+    pub fn insert<K>(&self, obj: &K)
+    where
+        K: Resource<DynamicType = ()> + Serialize,
+    {
+        let meta = obj.meta();
+        let path = format!(
+            "{}/{}",
+            K::url_path(&(), meta.namespace.as_deref()),
+            meta.name.as_deref().unwrap_or_default()
+        );
+        let obj = serde_json::to_value(obj).expect("object must be serializable");
+        self.lock().objects.insert(path, obj);
+    }
+
+    /// The object stored at `path`, if any
+    pub fn get(&self, path: &str) -> Option<Value> {
+        self.lock().objects.get(path).cloned()
+    }
+
+    /// The paths of all stored objects
+    pub fn paths(&self) -> Vec<String> {
+        self.lock().objects.keys().cloned().collect()
+    }
+
+    /// Merges `patch` into the object at `path`, such as to simulate the status updates of other controllers
+    ///
+    /// Panics if there is no object at `path`.
+    pub fn update(&self, path: &str, patch: Value) {
+        let mut state = self.lock();
+        let obj = state
+            .objects
+            .get_mut(path)
+            .unwrap_or_else(|| panic!("no object at {}", path));
+        merge_patch(obj, patch);
+    }
+
+    /// Returns the requests that have been recorded since the last call
+    pub fn take_requests(&self) -> Vec<RecordedRequest> {
+        std::mem::take(&mut self.lock().requests)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .expect("fake API server must not be poisoned")
+    }
+}
+
+async fn serve(state: &Mutex<State>, request: Request<Body>) -> Response<Body> {
+    let (parts, body) = request.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) if body.is_empty() => None,
+        Ok(body) => serde_json::from_slice(&body).ok(),
+        Err(_) => None,
+    };
+    let request = RecordedRequest {
+        method: parts.method.as_str().to_string(),
+        path: parts.uri.path().to_string(),
+        query: parts.uri.query().map(parse_query).unwrap_or_default(),
+        content_type: parts
+            .headers
+            .get(hyper::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+        body,
+    };
+    let mut state = state.lock().expect("fake API server must not be poisoned");
+    let (status, body) = state.handle(&request);
+    state.requests.push(request);
+    Response::builder()
+        .status(status)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .expect("response must be valid")
+}
+
+/// The parts of a resource path, such as `/apis/apps/v1/namespaces/default/statefulsets/simple/status`
+#[derive(Debug, PartialEq)]
+struct ResourcePath {
+    /// Path of the collection, such as `/apis/apps/v1/namespaces/default/statefulsets`
+    collection: String,
+    namespace: Option<String>,
+    name: Option<String>,
+    subresource: Option<String>,
+}
+
+impl ResourcePath {
+    fn parse(path: &str) -> Option<Self> {
+        let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+        let prefix_len = match segments.first()? {
+            &"api" => 2,
+            &"apis" => 3,
+            _ => return None,
+        };
+        let rest = segments.get(prefix_len..)?;
+        // `/api/v1/namespaces/default` is the namespace itself rather than a collection in it
+        let namespace = match rest {
+            ["namespaces", namespace, _, ..] => Some(namespace.to_string()),
+            _ => None,
+        };
+        let resource_index = prefix_len + if namespace.is_some() { 2 } else { 0 };
+        Some(Self {
+            collection: format!("/{}", segments.get(..=resource_index)?.join("/")),
+            namespace,
+            name: segments
+                .get(resource_index + 1)
+                .map(|name| name.to_string()),
+            subresource: segments
+                .get(resource_index + 2)
+                .map(|subresource| subresource.to_string()),
+        })
+    }
+}
+
+impl State {
+    fn handle(&mut self, request: &RecordedRequest) -> (StatusCode, Value) {
+        let path = match ResourcePath::parse(&request.path) {
+            Some(path) => path,
+            None => return not_found(&request.path),
+        };
+        let dry_run = request.is_dry_run();
+        let body = request.body.clone().unwrap_or(Value::Null);
+        match (request.method.as_str(), &path.name) {
+            ("GET", None) => (StatusCode::OK, self.list(&path, &request.query)),
+            ("GET", Some(name)) => self.get(&path, name),
+            ("POST", None) => self.create(&path, body, dry_run),
+            ("PATCH", Some(name)) if request.is_apply() && path.subresource.is_none() => {
+                self.apply(&path, name, body, dry_run)
+            }
+            ("PATCH", Some(name)) => self.patch(&path, name, body, dry_run),
+            ("PUT", Some(name)) => self.apply(&path, name, body, dry_run),
+            ("DELETE", Some(name)) => self.delete(&path, name, dry_run),
+            _ => status(
+                StatusCode::METHOD_NOT_ALLOWED,
+                "MethodNotAllowed",
+                format!("{} is not supported on {}", request.method, request.path),
+            ),
+        }
+    }
+
+    fn list(&self, path: &ResourcePath, query: &BTreeMap<String, String>) -> Value {
+        let prefix = format!("{}/", path.collection);
+        let selector = query.get("labelSelector").map(String::as_str).unwrap_or("");
+        let items = self
+            .objects
+            .iter()
+            .filter(|(key, _)| {
+                key.strip_prefix(&prefix)
+                    .map_or(false, |name| !name.contains('/'))
+            })
+            .map(|(_, obj)| obj)
+            .filter(|obj| matches_selector(&obj["metadata"]["labels"], selector))
+            .cloned()
+            .collect::<Vec<_>>();
+        serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "List",
+            "metadata": {},
+            "items": items,
+        })
+    }
+
+    fn get(&self, path: &ResourcePath, name: &str) -> (StatusCode, Value) {
+        let key = format!("{}/{}", path.collection, name);
+        match self.objects.get(&key) {
+            Some(obj) => (StatusCode::OK, obj.clone()),
+            None => not_found(&key),
+        }
+    }
+
+    fn create(
+        &mut self,
+        path: &ResourcePath,
+        mut obj: Value,
+        dry_run: bool,
+    ) -> (StatusCode, Value) {
+        self.created += 1;
+        let name = match (
+            obj["metadata"]["name"].as_str(),
+            obj["metadata"]["generateName"].as_str(),
+        ) {
+            (Some(name), _) => name.to_string(),
+            (None, Some(prefix)) => format!("{}{}", prefix, self.created),
+            (None, None) => {
+                return status(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "Invalid",
+                    "metadata.name is required".to_string(),
+                )
+            }
+        };
+        let key = format!("{}/{}", path.collection, name);
+        if self.objects.contains_key(&key) {
+            return status(
+                StatusCode::CONFLICT,
+                "AlreadyExists",
+                format!("{} already exists", key),
+            );
+        }
+        obj["metadata"]["name"] = name.into();
+        self.set_server_metadata(path, &mut obj, None);
+        if !dry_run {
+            self.objects.insert(key, obj.clone());
+        }
+        (StatusCode::CREATED, obj)
+    }
+
+    fn apply(
+        &mut self,
+        path: &ResourcePath,
+        name: &str,
+        mut obj: Value,
+        dry_run: bool,
+    ) -> (StatusCode, Value) {
+        let key = format!("{}/{}", path.collection, name);
+        let old = self.objects.get(&key).cloned();
+        if old.is_none() {
+            self.created += 1;
+        }
+        // The status can only be changed through its subresource
+        match old.as_ref().and_then(|old| old.get("status")) {
+            Some(old_status) => obj["status"] = old_status.clone(),
+            None => {
+                if let Some(obj) = obj.as_object_mut() {
+                    obj.remove("status");
+                }
+            }
+        }
+        self.set_server_metadata(path, &mut obj, old.as_ref());
+        if !dry_run {
+            self.objects.insert(key, obj.clone());
+        }
+        let code = if old.is_some() {
+            StatusCode::OK
+        } else {
+            StatusCode::CREATED
+        };
+        (code, obj)
+    }
+
+    fn patch(
+        &mut self,
+        path: &ResourcePath,
+        name: &str,
+        patch: Value,
+        dry_run: bool,
+    ) -> (StatusCode, Value) {
+        let key = format!("{}/{}", path.collection, name);
+        let old = match self.objects.get(&key) {
+            Some(old) => old.clone(),
+            None => return not_found(&key),
+        };
+        let mut obj = old.clone();
+        merge_patch(&mut obj, patch);
+        self.set_server_metadata(path, &mut obj, Some(&old));
+        if !dry_run {
+            self.objects.insert(key, obj.clone());
+        }
+        (StatusCode::OK, obj)
+    }
+
+    fn delete(&mut self, path: &ResourcePath, name: &str, dry_run: bool) -> (StatusCode, Value) {
+        let key = format!("{}/{}", path.collection, name);
+        let obj = if dry_run {
+            self.objects.get(&key).cloned()
+        } else {
+            self.objects.remove(&key)
+        };
+        match obj {
+            Some(obj) => (StatusCode::OK, obj),
+            None => not_found(&key),
+        }
+    }
+
+    /// Sets the metadata that the API server maintains, keeping that of `old`
+    fn set_server_metadata(&self, path: &ResourcePath, obj: &mut Value, old: Option<&Value>) {
+        let old_meta = old.map_or(Value::Null, |old| old["metadata"].clone());
+        let generation = match old_meta["generation"].as_i64() {
+            Some(generation) if old.and_then(|old| old.get("spec")) != obj.get("spec") => {
+                generation + 1
+            }
+            Some(generation) => generation,
+            None => 1,
+        };
+        let meta = &mut obj["metadata"];
+        if let Some(namespace) = &path.namespace {
+            meta["namespace"] = namespace.clone().into();
+        }
+        meta["generation"] = generation.into();
+        meta["uid"] = match old_meta["uid"].as_str() {
+            Some(uid) => uid.into(),
+            None => format!("00000000-0000-0000-0000-{:012}", self.created).into(),
+        };
+    }
+}
+
+/// Merges `patch` into `target` as a JSON merge patch, where `null` removes a field
+fn merge_patch(target: &mut Value, patch: Value) {
+    match patch {
+        Value::Object(patch) => {
+            if !target.is_object() {
+                *target = Value::Object(Map::new());
+            }
+            let target = target.as_object_mut().expect("target was made an object");
+            for (key, value) in patch {
+                if value.is_null() {
+                    target.remove(&key);
+                } else {
+                    merge_patch(target.entry(key).or_insert(Value::Null), value);
+                }
+            }
+        }
+        patch => *target = patch,
+    }
+}
+
+/// Whether `labels` match the equality-based requirements of `selector`, such as `app=hdfs,!group`
+///
+/// Set-based requirements are not supported.
+fn matches_selector(labels: &Value, selector: &str) -> bool {
+    selector
+        .split(',')
+        .filter(|requirement| !requirement.is_empty())
+        .all(|requirement| {
+            if let Some((key, value)) = requirement.split_once("!=") {
+                labels[key].as_str() != Some(value)
+            } else if let Some((key, value)) = requirement
+                .split_once("==")
+                .or_else(|| requirement.split_once('='))
+            {
+                labels[key].as_str() == Some(value)
+            } else if let Some(key) = requirement.strip_prefix('!') {
+                labels.get(key).is_none()
+            } else {
+                labels.get(requirement).is_some()
+            }
+        })
+}
+
+fn parse_query(query: &str) -> BTreeMap<String, String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (decode(key), decode(value)))
+        .collect()
+}
+
+/// Decodes a form-urlencoded query component
+fn decode(component: &str) -> String {
+    let mut bytes = Vec::with_capacity(component.len());
+    let mut input = component.bytes();
+    while let Some(byte) = input.next() {
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex = [input.next().unwrap_or(b'0'), input.next().unwrap_or(b'0')];
+                let hex = std::str::from_utf8(&hex).unwrap_or("00");
+                bytes.push(u8::from_str_radix(hex, 16).unwrap_or(b'?'));
+            }
+            byte => bytes.push(byte),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn status(code: StatusCode, reason: &str, message: String) -> (StatusCode, Value) {
+    let body = serde_json::json!({
+        "apiVersion": "v1",
+        "kind": "Status",
+        "metadata": {},
+        "status": "Failure",
+        "message": message,
+        "reason": reason,
+        "code": code.as_u16(),
+    });
+    (code, body)
+}
+
+fn not_found(path: &str) -> (StatusCode, Value) {
+    status(
+        StatusCode::NOT_FOUND,
+        "NotFound",
+        format!("{} not found", path),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::ConfigMap;
+    use kube::api::{DeleteParams, ListParams, Patch, PatchParams};
+
+    fn config_map(name: &str, labels: Value) -> ConfigMap {
+        serde_json::from_value(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "name": name, "labels": labels },
+            "data": { "key": name },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn paths_are_parsed() {
+        assert_eq!(
+            ResourcePath::parse("/apis/apps/v1/namespaces/default/statefulsets/simple/status"),
+            Some(ResourcePath {
+                collection: "/apis/apps/v1/namespaces/default/statefulsets".to_string(),
+                namespace: Some("default".to_string()),
+                name: Some("simple".to_string()),
+                subresource: Some("status".to_string()),
+            })
+        );
+        assert_eq!(
+            ResourcePath::parse("/api/v1/namespaces/default"),
+            Some(ResourcePath {
+                collection: "/api/v1/namespaces".to_string(),
+                namespace: None,
+                name: Some("default".to_string()),
+                subresource: None,
+            })
+        );
+        assert_eq!(ResourcePath::parse("/healthz"), None);
+    }
+
+    #[test]
+    fn selectors_are_matched() {
+        let labels = serde_json::json!({ "app": "hdfs", "role": "datanode" });
+        assert!(matches_selector(&labels, ""));
+        assert!(matches_selector(&labels, "app=hdfs,role==datanode,!group"));
+        assert!(!matches_selector(&labels, "app=hdfs,role!=datanode"));
+        assert!(!matches_selector(&labels, "group"));
+    }
+
+    #[test]
+    fn queries_are_decoded() {
+        let query = parse_query(
+            "fieldManager=hdfs.stackable.tech%2Fhdfscluster&labelSelector=a%3Db%2C%21c",
+        );
+        assert_eq!(query["fieldManager"], "hdfs.stackable.tech/hdfscluster");
+        assert_eq!(query["labelSelector"], "a=b,!c");
+    }
+
+    #[tokio::test]
+    async fn objects_are_applied_listed_and_deleted() {
+        let server = FakeApiServer::new();
+        let api = kube::Api::<ConfigMap>::namespaced(server.client(), "default");
+        let params = PatchParams::apply("test").force();
+        let applied = api
+            .patch(
+                "a",
+                &params,
+                &Patch::Apply(config_map("a", serde_json::json!({ "app": "x" }))),
+            )
+            .await
+            .unwrap();
+        assert_eq!(applied.metadata.namespace.as_deref(), Some("default"));
+        assert_eq!(applied.metadata.generation, Some(1));
+        api.patch(
+            "b",
+            &params,
+            &Patch::Apply(config_map("b", serde_json::json!({ "app": "y" }))),
+        )
+        .await
+        .unwrap();
+
+        let listed = api
+            .list(&ListParams::default().labels("app=x"))
+            .await
+            .unwrap();
+        assert_eq!(listed.items.len(), 1);
+        assert_eq!(listed.items[0].metadata.name.as_deref(), Some("a"));
+
+        api.delete("a", &DeleteParams::default()).await.unwrap();
+        assert!(matches!(
+            api.get("a").await,
+            Err(kube::Error::Api(err)) if err.code == 404
+        ));
+        assert_eq!(
+            server.paths(),
+            vec!["/api/v1/namespaces/default/configmaps/b".to_string()]
+        );
+
+        let requests = server.take_requests();
+        assert!(requests[0].is_apply());
+        assert_eq!(requests[0].query["fieldManager"], "test");
+        assert_eq!(
+            requests
+                .iter()
+                .map(|request| request.method.as_str())
+                .collect::<Vec<_>>(),
+            ["PATCH", "PATCH", "GET", "DELETE", "GET"]
+        );
+        assert!(server.take_requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn dry_runs_are_not_persisted() {
+        let server = FakeApiServer::new();
+        let api = kube::Api::<ConfigMap>::namespaced(server.client(), "default");
+        let params = PatchParams {
+            dry_run: true,
+            ..PatchParams::apply("test").force()
+        };
+        api.patch("a", &params, &Patch::Apply(config_map("a", Value::Null)))
+            .await
+            .unwrap();
+        assert!(server.paths().is_empty());
+        assert!(server.take_requests()[0].is_dry_run());
+    }
+}
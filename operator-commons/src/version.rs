@@ -0,0 +1,72 @@
+//! Identifying the operator build that manages an object
+
+use std::fmt::{self, Display};
+
+/// Build information of an operator binary, usually taken from its `built` output
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OperatorVersion {
+    /// Crate name, such as `zookeeper-operator`
+    pub name: &'static str,
+    /// Crate version
+    pub version: &'static str,
+    /// Git commit that the operator was built from, if known
+    pub git_commit: Option<&'static str>,
+}
+
+impl OperatorVersion {
+    /// The version and abbreviated commit, in a form that is valid as a label value
+    pub fn label_value(&self) -> String {
+        match self.git_commit {
+            Some(commit) => format!("{}-{}", self.version, &commit[..commit.len().min(7)]),
+            None => self.version.to_string(),
+        }
+    }
+
+    /// Build information as served on `/version`
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "name": self.name,
+            "version": self.version,
+            "gitCommit": self.git_commit,
+        })
+    }
+}
+
+impl Display for OperatorVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.version)?;
+        if let Some(commit) = self.git_commit {
+            write!(f, " (git {})", commit)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OperatorVersion;
+
+    const VERSION: OperatorVersion = OperatorVersion {
+        name: "zookeeper-operator",
+        version: "0.1.0",
+        git_commit: Some("4c1a5f0e7d3b4e559f6a2b8c1d0e9a71c3d5e7f9"),
+    };
+
+    #[test]
+    fn label_value_abbreviates_the_commit() {
+        assert_eq!(VERSION.label_value(), "0.1.0-4c1a5f0");
+        let without_git = OperatorVersion {
+            git_commit: None,
+            ..VERSION
+        };
+        assert_eq!(without_git.label_value(), "0.1.0");
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            VERSION.to_string(),
+            "zookeeper-operator 0.1.0 (git 4c1a5f0e7d3b4e559f6a2b8c1d0e9a71c3d5e7f9)"
+        );
+    }
+}
@@ -0,0 +1,615 @@
+//! Webhooks that the API server calls for custom resources
+//!
+//! The API server sends a `ConversionReview` to `/convert/<crd name>` whenever an object is read or written in a
+//! version other than the one it is stored in, and an `AdmissionReview` to `/mutate/<crd name>` whenever an object
+//! is created or updated, so that its defaults are written into it. Webhooks must be served over HTTPS.
+
+use std::{collections::BTreeMap, convert::Infallible, net::SocketAddr, path::PathBuf, sync::Arc};
+
+use hyper::{server::conn::Http, service::service_fn, Body, Request, Response, StatusCode};
+use k8s_openapi::{
+    api::admissionregistration::v1 as admissionregistration,
+    apiextensions_apiserver::pkg::apis::apiextensions::v1::{
+        CustomResourceConversion, CustomResourceDefinition, ServiceReference, WebhookClientConfig,
+        WebhookConversion,
+    },
+    apimachinery::pkg::apis::meta::v1::{ObjectMeta, Status},
+    ByteString,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use snafu::{OptionExt, ResultExt, Snafu};
+use structopt::StructOpt;
+use tokio::net::TcpListener;
+use tokio_native_tls::{native_tls, TlsAcceptor};
+
+use crate::error::error_chain;
+
+#[derive(Snafu, Debug)]
+pub enum Error {
+    #[snafu(display("webhook service {:?} is not of the form namespace/name", service))]
+    InvalidService { service: String },
+    #[snafu(display("failed to read {}", path.display()))]
+    ReadFile {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+    #[snafu(display("failed to load the webhook server's certificate"))]
+    LoadIdentity { source: native_tls::Error },
+    #[snafu(display("failed to listen on {}", addr))]
+    Bind {
+        source: std::io::Error,
+        addr: SocketAddr,
+    },
+    #[snafu(display("failed to accept connection"))]
+    Accept { source: std::io::Error },
+}
+
+/// Converts a custom resource object to `desiredAPIVersion`, failing with a message for the API server
+pub type Converter = fn(serde_json::Value, &str) -> Result<serde_json::Value, String>;
+
+/// Writes the defaults of a custom resource object into it, failing with a message for the API server
+pub type Defaulter = fn(serde_json::Value) -> Result<serde_json::Value, String>;
+
+/// The webhooks that the operator serves, keyed by CRD name
+#[derive(Clone, Default)]
+pub struct Webhooks {
+    pub converters: BTreeMap<String, Converter>,
+    pub defaulters: BTreeMap<String, Defaulter>,
+}
+
+impl Webhooks {
+    /// Adds the webhooks of another operator
+    pub fn extend(&mut self, other: Webhooks) {
+        self.converters.extend(other.converters);
+        self.defaulters.extend(other.defaulters);
+    }
+}
+
+/// Where the API server reaches the webhook server, published in the CRDs and the `MutatingWebhookConfiguration`
+#[derive(StructOpt, Clone, Debug, Default)]
+pub struct WebhookServiceOptions {
+    /// Service (as `namespace/name`) that routes to the operator's webhook server
+    ///
+    /// If not specified, the CRDs only serve their storage version, and defaults are not written into new objects.
+    #[structopt(long)]
+    pub webhook_service: Option<String>,
+    /// Port of the webhook service
+    #[structopt(long, default_value = "443")]
+    pub webhook_service_port: i32,
+    /// PEM file with the CA certificate that signed the webhook server's certificate
+    #[structopt(long)]
+    pub webhook_ca_bundle: Option<PathBuf>,
+}
+
+impl WebhookServiceOptions {
+    /// The namespace and name of the webhook service, and the CA bundle, `None` if no webhook service is configured
+    fn service(&self) -> Result<Option<(&str, &str, Option<ByteString>)>, Error> {
+        let service = match &self.webhook_service {
+            Some(service) => service,
+            None => return Ok(None),
+        };
+        let (namespace, name) = service
+            .split_once('/')
+            .filter(|(namespace, name)| !namespace.is_empty() && !name.is_empty())
+            .context(InvalidService { service })?;
+        let ca_bundle = self
+            .webhook_ca_bundle
+            .as_ref()
+            .map(|path| std::fs::read(path).context(ReadFile { path }))
+            .transpose()?;
+        Ok(Some((namespace, name, ca_bundle.map(ByteString))))
+    }
+
+    /// How the CRD `crd_name` converts between its versions, `None` if no webhook service is configured
+    pub fn conversion(&self, crd_name: &str) -> Result<Option<CustomResourceConversion>, Error> {
+        let (namespace, name, ca_bundle) = match self.service()? {
+            Some(service) => service,
+            None => return Ok(None),
+        };
+        Ok(Some(CustomResourceConversion {
+            strategy: "Webhook".to_string(),
+            webhook: Some(WebhookConversion {
+                client_config: Some(WebhookClientConfig {
+                    ca_bundle,
+                    service: Some(ServiceReference {
+                        namespace: namespace.to_string(),
+                        name: name.to_string(),
+                        path: Some(format!("/convert/{}", crd_name)),
+                        port: Some(self.webhook_service_port),
+                    }),
+                    url: None,
+                }),
+                conversion_review_versions: vec!["v1".to_string()],
+            }),
+        }))
+    }
+
+    /// Registers the defaulting webhooks of `webhooks` for those of `crds` that have one
+    ///
+    /// Objects are defaulted in their storage version. Failures are ignored, so that objects can still be written
+    /// while the operator is unavailable, which is why the controllers keep falling back to the defaults themselves.
+    /// Returns `None` if no webhook service is configured, or none of the CRDs has a defaulting webhook.
+    pub fn mutating_webhook_configuration(
+        &self,
+        config_name: &str,
+        crds: &[CustomResourceDefinition],
+        webhooks: &Webhooks,
+    ) -> Result<Option<admissionregistration::MutatingWebhookConfiguration>, Error> {
+        let (namespace, name, ca_bundle) = match self.service()? {
+            Some(service) => service,
+            None => return Ok(None),
+        };
+        let mutating_webhooks = crds
+            .iter()
+            .filter_map(|crd| {
+                let crd_name = crd.metadata.name.as_deref()?;
+                webhooks.defaulters.get(crd_name)?;
+                let storage_version = crd.spec.versions.iter().find(|version| version.storage)?;
+                Some(admissionregistration::MutatingWebhook {
+                    name: crd_name.to_string(),
+                    client_config: admissionregistration::WebhookClientConfig {
+                        ca_bundle: ca_bundle.clone(),
+                        service: Some(admissionregistration::ServiceReference {
+                            namespace: namespace.to_string(),
+                            name: name.to_string(),
+                            path: Some(format!("/mutate/{}", crd_name)),
+                            port: Some(self.webhook_service_port),
+                        }),
+                        url: None,
+                    },
+                    rules: Some(vec![admissionregistration::RuleWithOperations {
+                        api_groups: Some(vec![crd.spec.group.clone()]),
+                        api_versions: Some(vec![storage_version.name.clone()]),
+                        operations: Some(vec!["CREATE".to_string(), "UPDATE".to_string()]),
+                        resources: Some(vec![crd.spec.names.plural.clone()]),
+                        scope: Some(crd.spec.scope.clone()),
+                    }]),
+                    match_policy: Some("Equivalent".to_string()),
+                    failure_policy: Some("Ignore".to_string()),
+                    side_effects: "None".to_string(),
+                    admission_review_versions: vec!["v1".to_string()],
+                    ..admissionregistration::MutatingWebhook::default()
+                })
+            })
+            .collect::<Vec<_>>();
+        if mutating_webhooks.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(admissionregistration::MutatingWebhookConfiguration {
+            metadata: ObjectMeta {
+                name: Some(config_name.to_string()),
+                ..ObjectMeta::default()
+            },
+            webhooks: Some(mutating_webhooks),
+        }))
+    }
+}
+
+/// The webhook server's listener
+#[derive(StructOpt, Clone, Debug)]
+pub struct WebhookServerOptions {
+    /// Address to serve the conversion webhooks on
+    #[structopt(long, default_value = "0.0.0.0:8443")]
+    pub webhook_address: SocketAddr,
+    /// PEM file with the webhook server's certificate, the webhooks are only served if specified
+    #[structopt(long, requires = "webhook-tls-key")]
+    pub webhook_tls_cert: Option<PathBuf>,
+    /// PEM file with the PKCS #8 private key of the webhook server's certificate
+    #[structopt(long, requires = "webhook-tls-cert")]
+    pub webhook_tls_key: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversionReview {
+    pub api_version: String,
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<ConversionRequest>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<ConversionResponse>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversionRequest {
+    pub uid: String,
+    #[serde(rename = "desiredAPIVersion")]
+    pub desired_api_version: String,
+    #[serde(default)]
+    pub objects: Vec<serde_json::Value>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversionResponse {
+    pub uid: String,
+    pub result: Status,
+    #[serde(default)]
+    pub converted_objects: Vec<serde_json::Value>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AdmissionReview {
+    pub api_version: String,
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<AdmissionRequest>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<AdmissionResponse>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AdmissionRequest {
+    pub uid: String,
+    /// The object as it is created or updated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object: Option<serde_json::Value>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AdmissionResponse {
+    pub uid: String,
+    pub allowed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patch_type: Option<String>,
+    /// A JSON patch, serialized as base64
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patch: Option<ByteString>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// Writes the defaults of a custom resource object's spec with `set_defaults`, for implementing [`Defaulter`]s
+pub fn default_spec<S>(
+    mut obj: serde_json::Value,
+    set_defaults: impl FnOnce(&mut S),
+) -> Result<serde_json::Value, String>
+where
+    S: DeserializeOwned + Serialize,
+{
+    let spec = obj
+        .get_mut("spec")
+        .map(serde_json::Value::take)
+        .unwrap_or_else(|| serde_json::json!({}));
+    let mut spec = serde_json::from_value::<S>(spec).map_err(|err| err.to_string())?;
+    set_defaults(&mut spec);
+    obj["spec"] = serde_json::to_value(spec).map_err(|err| err.to_string())?;
+    Ok(obj)
+}
+
+/// Answers `review` with a patch that replaces the object's spec with the one defaulted by `default`
+///
+/// Objects that can't be defaulted are admitted unchanged with a warning, since validating them is up to the schema.
+pub fn review_admission(review: AdmissionReview, default: Defaulter) -> AdmissionReview {
+    let request = review.request.unwrap_or_default();
+    let mut response = AdmissionResponse {
+        uid: request.uid,
+        allowed: true,
+        ..AdmissionResponse::default()
+    };
+    match request.object.map(default).transpose() {
+        Ok(Some(defaulted)) => {
+            let patch = serde_json::json!([{
+                "op": "add",
+                "path": "/spec",
+                "value": defaulted["spec"],
+            }]);
+            response.patch_type = Some("JSONPatch".to_string());
+            response.patch = Some(ByteString(patch.to_string().into_bytes()));
+        }
+        Ok(None) => {}
+        Err(message) => response
+            .warnings
+            .push(format!("defaults could not be applied: {}", message)),
+    }
+    AdmissionReview {
+        api_version: review.api_version,
+        kind: review.kind,
+        request: None,
+        response: Some(response),
+    }
+}
+
+/// Answers `review` by converting all of its objects with `convert`, the review fails if any of them fails
+pub fn review_conversion(review: ConversionReview, convert: Converter) -> ConversionReview {
+    let request = review.request.unwrap_or_default();
+    let converted = request
+        .objects
+        .into_iter()
+        .map(|obj| convert(obj, &request.desired_api_version))
+        .collect::<Result<Vec<_>, _>>();
+    let response = match converted {
+        Ok(converted_objects) => ConversionResponse {
+            uid: request.uid,
+            result: Status {
+                status: Some("Success".to_string()),
+                ..Status::default()
+            },
+            converted_objects,
+        },
+        Err(message) => ConversionResponse {
+            uid: request.uid,
+            result: Status {
+                status: Some("Failure".to_string()),
+                message: Some(message),
+                ..Status::default()
+            },
+            converted_objects: Vec::new(),
+        },
+    };
+    ConversionReview {
+        api_version: review.api_version,
+        kind: review.kind,
+        request: None,
+        response: Some(response),
+    }
+}
+
+async fn respond(req: Request<Body>, webhooks: &Webhooks) -> Response<Body> {
+    let path = req.uri().path().to_string();
+    if let Some(convert) = path
+        .strip_prefix("/convert/")
+        .and_then(|crd| webhooks.converters.get(crd))
+    {
+        let convert = *convert;
+        answer(req, |review| review_conversion(review, convert)).await
+    } else if let Some(default) = path
+        .strip_prefix("/mutate/")
+        .and_then(|crd| webhooks.defaulters.get(crd))
+    {
+        let default = *default;
+        answer(req, |review| review_admission(review, default)).await
+    } else {
+        status_response(StatusCode::NOT_FOUND, "not found".to_string())
+    }
+}
+
+/// Responds to the review in the body of `req`
+async fn answer<R>(req: Request<Body>, review: impl FnOnce(R) -> R) -> Response<Body>
+where
+    R: DeserializeOwned + Serialize,
+{
+    let request = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(body) => serde_json::from_slice::<R>(&body),
+        Err(err) => return status_response(StatusCode::BAD_REQUEST, err.to_string()),
+    };
+    match request {
+        Ok(request) => match serde_json::to_vec(&review(request)) {
+            Ok(body) => Response::new(Body::from(body)),
+            Err(err) => status_response(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+        },
+        Err(err) => status_response(StatusCode::BAD_REQUEST, err.to_string()),
+    }
+}
+
+fn status_response(status: StatusCode, body: String) -> Response<Body> {
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response
+}
+
+/// Serves `webhooks` over HTTPS
+///
+/// Does nothing if no certificate is configured.
+pub async fn serve_webhooks(
+    options: WebhookServerOptions,
+    webhooks: Webhooks,
+) -> Result<(), Error> {
+    let (cert, key) = match (&options.webhook_tls_cert, &options.webhook_tls_key) {
+        (Some(cert), Some(key)) => (cert, key),
+        _ => return Ok(()),
+    };
+    let cert_pem = std::fs::read(cert).context(ReadFile { path: cert })?;
+    let key_pem = std::fs::read(key).context(ReadFile { path: key })?;
+    let identity = native_tls::Identity::from_pkcs8(&cert_pem, &key_pem).context(LoadIdentity)?;
+    let acceptor = TlsAcceptor::from(native_tls::TlsAcceptor::new(identity).context(LoadIdentity)?);
+    let addr = options.webhook_address;
+    let listener = TcpListener::bind(addr).await.context(Bind { addr })?;
+    tracing::info!(
+        %addr,
+        conversion = ?webhooks.converters.keys().collect::<Vec<_>>(),
+        defaulting = ?webhooks.defaulters.keys().collect::<Vec<_>>(),
+        "Serving webhooks"
+    );
+    let webhooks = Arc::new(webhooks);
+    loop {
+        let (tcp, peer) = listener.accept().await.context(Accept)?;
+        let acceptor = acceptor.clone();
+        let webhooks = webhooks.clone();
+        tokio::spawn(async move {
+            let tls = match acceptor.accept(tcp).await {
+                Ok(tls) => tls,
+                Err(err) => {
+                    tracing::warn!(%peer, error = %error_chain(&err), "TLS handshake failed");
+                    return;
+                }
+            };
+            let service = service_fn(|req| {
+                let webhooks = webhooks.clone();
+                async move { Ok::<_, Infallible>(respond(req, &webhooks).await) }
+            });
+            if let Err(err) = Http::new().serve_connection(tls, service).await {
+                tracing::warn!(%peer, error = %error_chain(&err), "Failed to serve webhook request");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rename(
+        mut obj: serde_json::Value,
+        desired_api_version: &str,
+    ) -> Result<serde_json::Value, String> {
+        if obj["kind"] == "Broken" {
+            return Err("cannot convert Broken".to_string());
+        }
+        obj["apiVersion"] = desired_api_version.into();
+        Ok(obj)
+    }
+
+    fn review(objects: Vec<serde_json::Value>) -> ConversionReview {
+        serde_json::from_value(serde_json::json!({
+            "apiVersion": "apiextensions.k8s.io/v1",
+            "kind": "ConversionReview",
+            "request": {
+                "uid": "705ab4f5-6393-11e8-b7cc-42010a800002",
+                "desiredAPIVersion": "example.com/v2",
+                "objects": objects,
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn all_objects_are_converted() {
+        let review = review_conversion(
+            review(vec![
+                serde_json::json!({ "apiVersion": "example.com/v1", "kind": "Foo" }),
+                serde_json::json!({ "apiVersion": "example.com/v1", "kind": "Bar" }),
+            ]),
+            rename,
+        );
+        let json = serde_json::to_value(&review).unwrap();
+        assert_eq!(json["kind"], "ConversionReview");
+        assert_eq!(json["request"], serde_json::Value::Null);
+        assert_eq!(
+            json["response"]["uid"],
+            "705ab4f5-6393-11e8-b7cc-42010a800002"
+        );
+        assert_eq!(json["response"]["result"]["status"], "Success");
+        assert_eq!(
+            json["response"]["convertedObjects"][1]["apiVersion"],
+            "example.com/v2"
+        );
+    }
+
+    #[test]
+    fn failed_conversions_fail_the_review() {
+        let review = review_conversion(
+            review(vec![
+                serde_json::json!({ "apiVersion": "example.com/v1", "kind": "Foo" }),
+                serde_json::json!({ "apiVersion": "example.com/v1", "kind": "Broken" }),
+            ]),
+            rename,
+        );
+        let response = review.response.unwrap();
+        assert_eq!(response.result.status.as_deref(), Some("Failure"));
+        assert_eq!(
+            response.result.message.as_deref(),
+            Some("cannot convert Broken")
+        );
+        assert!(response.converted_objects.is_empty());
+    }
+
+    #[test]
+    fn conversion_points_to_the_service() {
+        let options = WebhookServiceOptions {
+            webhook_service: Some("stackable/stackable-operators".to_string()),
+            webhook_service_port: 443,
+            webhook_ca_bundle: None,
+        };
+        let conversion = options
+            .conversion("hdfsclusters.hdfs.stackable.tech")
+            .unwrap()
+            .unwrap();
+        assert_eq!(conversion.strategy, "Webhook");
+        let service = conversion
+            .webhook
+            .unwrap()
+            .client_config
+            .unwrap()
+            .service
+            .unwrap();
+        assert_eq!(service.namespace, "stackable");
+        assert_eq!(service.name, "stackable-operators");
+        assert_eq!(
+            service.path.as_deref(),
+            Some("/convert/hdfsclusters.hdfs.stackable.tech")
+        );
+        assert!(WebhookServiceOptions::default()
+            .conversion("hdfsclusters.hdfs.stackable.tech")
+            .unwrap()
+            .is_none());
+        assert!(WebhookServiceOptions {
+            webhook_service: Some("stackable-operators".to_string()),
+            ..WebhookServiceOptions::default()
+        }
+        .conversion("hdfsclusters.hdfs.stackable.tech")
+        .is_err());
+    }
+
+    #[derive(Debug, Default, Deserialize, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct TestSpec {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        replicas: Option<i32>,
+    }
+
+    fn default_replicas(obj: serde_json::Value) -> Result<serde_json::Value, String> {
+        default_spec(obj, |spec: &mut TestSpec| {
+            spec.replicas.get_or_insert(1);
+        })
+    }
+
+    fn admission_review(object: serde_json::Value) -> AdmissionReview {
+        serde_json::from_value(serde_json::json!({
+            "apiVersion": "admission.k8s.io/v1",
+            "kind": "AdmissionReview",
+            "request": {
+                "uid": "705ab4f5-6393-11e8-b7cc-42010a800002",
+                "operation": "CREATE",
+                "object": object,
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn defaults_are_patched_into_the_spec() {
+        let review = review_admission(
+            admission_review(serde_json::json!({
+                "apiVersion": "example.com/v1",
+                "kind": "Foo",
+                "spec": {},
+            })),
+            default_replicas,
+        );
+        let response = review.response.unwrap();
+        assert!(response.allowed);
+        assert_eq!(response.patch_type.as_deref(), Some("JSONPatch"));
+        let patch: serde_json::Value = serde_json::from_slice(&response.patch.unwrap().0).unwrap();
+        assert_eq!(
+            patch,
+            serde_json::json!([{ "op": "add", "path": "/spec", "value": { "replicas": 1 } }])
+        );
+        // Serialized as base64, as the API server expects
+        let json = serde_json::to_value(&review_admission(
+            admission_review(serde_json::json!({ "kind": "Foo" })),
+            default_replicas,
+        ))
+        .unwrap();
+        assert_eq!(
+            json["response"]["patch"],
+            "W3sib3AiOiJhZGQiLCJwYXRoIjoiL3NwZWMiLCJ2YWx1ZSI6eyJyZXBsaWNhcyI6MX19XQ=="
+        );
+    }
+
+    #[test]
+    fn objects_that_cant_be_defaulted_are_admitted() {
+        let review = review_admission(
+            admission_review(serde_json::json!({ "kind": "Foo", "spec": { "replicas": "many" } })),
+            default_replicas,
+        );
+        let response = review.response.unwrap();
+        assert!(response.allowed);
+        assert_eq!(response.patch, None);
+        assert_eq!(response.warnings.len(), 1);
+    }
+}
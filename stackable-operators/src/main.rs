@@ -0,0 +1,238 @@
+//! Runs the controllers of the HDFS and ZooKeeper operators in one process, sharing the Kubernetes client, the
+//! health endpoints, and the limits on concurrent reconciles and API server writes
+
+use std::{net::SocketAddr, path::PathBuf, str::FromStr, time::Duration};
+
+use futures::{compat::Future01CompatExt, stream::LocalBoxStream, StreamExt};
+use operator_commons::{
+    crd::{install_crds, render_crds, CrdFormat},
+    detect_cluster_domain,
+    health::serve_health,
+    log_reconcile_results, parse_duration, run_until_shutdown,
+    telemetry::{init_tracing, shutdown_tracing, TracingOptions},
+    webhook::{serve_webhooks, WebhookServerOptions, WebhookServiceOptions, Webhooks},
+    ClientOptions, ControllerOptions, ImageOptions, OperatorVersion, ReconcileResult,
+    SharedContext,
+};
+use structopt::StructOpt;
+
+mod built_info {
+    include!(concat!(env!("OUT_DIR"), "/built.rs"));
+}
+
+/// Build of this binary, reported on `/version`
+pub const OPERATOR_VERSION: OperatorVersion = OperatorVersion {
+    name: built_info::PKG_NAME,
+    version: built_info::PKG_VERSION,
+    git_commit: built_info::GIT_COMMIT_HASH,
+};
+
+/// An operator whose controllers can be run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Product {
+    Hdfs,
+    Zookeeper,
+}
+
+impl FromStr for Product {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hdfs" => Ok(Self::Hdfs),
+            "zookeeper" => Ok(Self::Zookeeper),
+            _ => Err(format!(
+                "unknown operator {:?}, expected hdfs or zookeeper",
+                s
+            )),
+        }
+    }
+}
+
+/// The webhooks of all operators
+fn webhooks() -> Webhooks {
+    let mut webhooks = hdfs_operator::webhooks();
+    webhooks.extend(zookeeper_operator::webhooks());
+    webhooks
+}
+
+#[derive(StructOpt)]
+#[structopt(about = built_info::PKG_DESCRIPTION, author = "Stackable GmbH - info@stackable.de")]
+struct Opts {
+    #[structopt(subcommand)]
+    cmd: Cmd,
+    #[structopt(flatten)]
+    tracing: TracingOptions,
+}
+
+#[derive(StructOpt)]
+enum Cmd {
+    /// Print the CRD objects of all operators
+    Crd {
+        /// Output format, `yaml` or `json`
+        #[structopt(long, default_value = "yaml")]
+        format: CrdFormat,
+        /// Write the CRDs to this file instead of printing them
+        #[structopt(long, short)]
+        output: Option<PathBuf>,
+        /// Apply the CRDs to the cluster and wait until they are established, instead of printing them
+        #[structopt(long)]
+        install: bool,
+        #[structopt(flatten)]
+        client: ClientOptions,
+        #[structopt(flatten)]
+        webhook: WebhookServiceOptions,
+    },
+    /// Print the build information
+    Version,
+    /// Run the controllers of the selected operators
+    Run {
+        /// Operators whose controllers are run, separated by commas
+        #[structopt(
+            long,
+            default_value = "hdfs,zookeeper",
+            use_delimiter = true,
+            require_delimiter = true
+        )]
+        controllers: Vec<Product>,
+        /// DNS domain of the Kubernetes cluster, detected from `/etc/resolv.conf` if not specified
+        #[structopt(long)]
+        cluster_domain: Option<String>,
+        /// Only log the changes that would be made, using server-side dry-run, instead of persisting them
+        ///
+        /// ZookeeperZnodes are not reconciled in this mode, since they are managed directly in ZooKeeper.
+        #[structopt(long)]
+        dry_run: bool,
+        #[structopt(flatten)]
+        client: ClientOptions,
+        #[structopt(flatten)]
+        controller_options: ControllerOptions,
+        #[structopt(flatten)]
+        image_options: ImageOptions,
+        /// How often the ZooKeeper servers' admin servers are queried for the ensemble's health, which is published
+        /// in the status of each ZookeeperCluster
+        #[structopt(long, default_value = "1m", parse(try_from_str = parse_duration))]
+        health_probe_interval: Duration,
+        /// Address to serve `/healthz` and `/version` on
+        #[structopt(long, default_value = "0.0.0.0:8080")]
+        health_address: SocketAddr,
+        #[structopt(flatten)]
+        webhook_server: WebhookServerOptions,
+    },
+}
+
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    let opts = Opts::from_args();
+    init_tracing("RUST_LOG", "stackable-operators", &opts.tracing)?;
+    operator_commons::fault::log_active_faults();
+    // tokio-zookeeper depends on Tokio 0.1
+    let tokio01_runtime = tokio01::runtime::Runtime::new()?;
+    match opts.cmd {
+        Cmd::Crd {
+            format,
+            output,
+            install,
+            client,
+            webhook,
+        } => {
+            let crds = [hdfs_operator::crds(&webhook)?, zookeeper_operator::crds()].concat();
+            let mutating_webhooks = webhook.mutating_webhook_configuration(
+                "stackable-operators",
+                &crds,
+                &webhooks(),
+            )?;
+            if install {
+                let kube = client.client().await?;
+                install_crds(
+                    &kube,
+                    "stackable-operators",
+                    &crds,
+                    mutating_webhooks.as_ref(),
+                )
+                .await?;
+            } else if let Some(output) = output {
+                std::fs::write(
+                    output,
+                    render_crds(&crds, mutating_webhooks.as_ref(), format)?,
+                )?;
+            } else {
+                println!(
+                    "{}",
+                    render_crds(&crds, mutating_webhooks.as_ref(), format)?
+                );
+            }
+        }
+        Cmd::Version => println!("{}", OPERATOR_VERSION),
+        Cmd::Run {
+            controllers,
+            cluster_domain,
+            dry_run,
+            client,
+            controller_options,
+            image_options,
+            health_probe_interval,
+            health_address,
+            webhook_server,
+        } => {
+            tracing::info!(version = %OPERATOR_VERSION, ?controllers, "Starting operators");
+            let cluster_domain = cluster_domain
+                .or_else(detect_cluster_domain)
+                .unwrap_or_else(|| "cluster.local".to_string());
+            tracing::info!(cluster_domain = %cluster_domain, "Using cluster domain");
+            if dry_run {
+                tracing::warn!("Running in dry-run mode, no changes will be persisted");
+            }
+            tokio::spawn(async move {
+                if let Err(err) = serve_health(health_address, OPERATOR_VERSION).await {
+                    tracing::error!(
+                        error = &err as &dyn std::error::Error,
+                        "Failed to serve health endpoints"
+                    );
+                }
+            });
+            // Webhooks are needed as long as the CRDs are installed, even if their controllers are not run
+            tokio::spawn(async move {
+                if let Err(err) = serve_webhooks(webhook_server, webhooks()).await {
+                    tracing::error!(
+                        error = &err as &dyn std::error::Error,
+                        "Failed to serve webhooks"
+                    );
+                }
+            });
+            let kube = client.client().await?;
+            let reconcile_permits = controller_options.reconcile_permits();
+            let shared = SharedContext {
+                kube,
+                cluster_domain,
+                image_options,
+                dry_run,
+                reconcile_permits: reconcile_permits.clone(),
+                apply_rate_limiter: controller_options.apply_rate_limiter(),
+                log_apply_diffs: controller_options.log_apply_diffs,
+                resync_interval: controller_options.resync_interval,
+            };
+            let mut results: Vec<LocalBoxStream<'static, ReconcileResult>> = Vec::new();
+            if controllers.contains(&Product::Hdfs) {
+                results.push(hdfs_operator::run_controllers(&shared));
+            }
+            if controllers.contains(&Product::Zookeeper) {
+                results.push(zookeeper_operator::run_controllers(
+                    &shared,
+                    health_probe_interval,
+                    tokio01_runtime.executor(),
+                ));
+            }
+            run_until_shutdown(
+                log_reconcile_results(futures::stream::select_all(results)),
+                &reconcile_permits,
+                controller_options.shutdown_grace_period,
+            )
+            .await;
+        }
+    }
+
+    tokio01_runtime.shutdown_now().compat().await.unwrap();
+    shutdown_tracing();
+    Ok(())
+}
@@ -1,5 +1,18 @@
+use std::collections::BTreeMap;
+
+use crate::ports::ZookeeperPorts;
+pub use operator_commons::spec::{
+    AllowedClient, NetworkIsolationConfig, PodSecurityConfig, ServiceAccountConfig,
+};
+use operator_commons::{merge::pod_overrides_schema, spec::ProductImage};
 use serde::{Deserialize, Serialize};
 use stackable_operator::{
+    k8s_openapi::{
+        api::core::v1::{
+            Affinity, Probe, ResourceRequirements, Toleration, TopologySpreadConstraint,
+        },
+        apimachinery::pkg::util::intstr::IntOrString,
+    },
     kube::CustomResource,
     schemars::{self, JsonSchema},
 };
@@ -13,6 +26,7 @@ use stackable_operator::{
     plural = "zookeeperclusters",
     shortname = "zk",
     namespaced,
+    status = "ZookeeperClusterStatus",
     kube_core = "stackable_operator::kube::core",
     k8s_openapi = "stackable_operator::k8s_openapi",
     schemars = "stackable_operator::schemars"
@@ -25,6 +39,348 @@ pub struct ZookeeperClusterSpec {
     /// Emergency stop button, if `true` then all pods are stopped without affecting configuration (as setting `replicas` to `0` would)
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub stopped: Option<bool>,
+    /// Added to each pod's ordinal when deriving its server id (`myid`), defaults to `0` (so that ids start at `1`)
+    ///
+    /// Useful when migrating from an existing ensemble whose ids must be preserved. Changing this on a running cluster
+    /// changes the identity of every server, and should only be done while the cluster is stopped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub myid_offset: Option<i32>,
+    /// Maximum number of concurrent connections that a single client IP may open to a single server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_client_cnxns: Option<i32>,
+    /// Minimum session timeout in milliseconds that clients may negotiate, defaults to twice the tick time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_session_timeout: Option<i32>,
+    /// Maximum session timeout in milliseconds that clients may negotiate, defaults to 20 times the tick time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_session_timeout: Option<i32>,
+    /// Maximum number of queued requests before the server starts throttling clients
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub global_outstanding_limit: Option<i32>,
+    #[serde(default)]
+    pub ports: ZookeeperPortsConfig,
+    #[serde(default)]
+    pub autopurge: AutopurgeConfig,
+    #[serde(default)]
+    pub data_dir: StorageConfig,
+    /// Stores transaction logs on a separate volume from snapshots, rather than in `dataDir`
+    ///
+    /// Like `dataDir`, this cannot be changed once the cluster has been created, such changes are rejected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_log_dir: Option<StorageConfig>,
+    /// Compute resources of the `zookeeper` container
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceRequirements>,
+    #[serde(default)]
+    pub jvm: JvmConfig,
+    #[serde(default)]
+    pub features: ServerFeaturesConfig,
+    #[serde(default)]
+    pub probes: ProbesConfig,
+    /// The ZooKeeper image, defaults to `docker.stackable.tech/stackable/zookeeper:3.5.8-stackable0`
+    #[serde(default)]
+    pub image: ImageConfig,
+    #[serde(default)]
+    pub pod_security: PodSecurityConfig,
+    /// Generates `NetworkPolicy` objects that only admit traffic from ensemble members and the allowed clients
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network_isolation: Option<NetworkIsolationConfig>,
+    #[serde(default)]
+    pub service_account: ServiceAccountConfig,
+    /// Which nodes the servers are scheduled on, and how they are spread across them
+    #[serde(default)]
+    pub scheduling: SchedulingConfig,
+    /// Name of the `PriorityClass` assigned to the pods
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority_class_name: Option<String>,
+    /// Time that the pods are given to shut down gracefully before they are killed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub termination_grace_period_seconds: Option<i64>,
+    /// Fragment of a `PodTemplateSpec` that is merged over the generated pod template
+    ///
+    /// Lists of named objects (such as `containers`, `volumes`, or `env`) are merged by name, other values replace the generated ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "pod_overrides_schema")]
+    pub pod_overrides: Option<serde_json::Value>,
+    /// Sets the cluster autoscaler's `cluster-autoscaler.kubernetes.io/safe-to-evict` annotation on the pods, so that
+    /// it may (`true`) or may not (`false`) remove their nodes, left to the autoscaler if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub safe_to_evict: Option<bool>,
+    /// Limits how many servers node drains may evict at once
+    #[serde(default)]
+    pub disruption_budget: DisruptionBudgetConfig,
+    /// How the headless `Service` of the servers publishes them
+    #[serde(default)]
+    pub server_service: ServerServiceConfig,
+    /// Labels that are added to all generated objects and pods, generated labels take precedence
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Annotations that are added to all generated objects and pods
+    #[serde(default)]
+    pub annotations: BTreeMap<String, String>,
+}
+
+/// The addresses that clients connect to the cluster at
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ZookeeperClusterStatus {
+    /// All servers, such as `zk-servers-0.zk-servers.default.svc.cluster.local:2181,...`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connection_string: Option<String>,
+    /// The load-balanced `Service`, such as `zk.default.svc.cluster.local:2181`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service_endpoint: Option<String>,
+    /// Health of the ensemble, as last reported by the servers' admin servers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ensemble: Option<ZookeeperEnsembleStatus>,
+}
+
+/// Health of the ensemble, taken from the `monitor` admin command of each server
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ZookeeperEnsembleStatus {
+    /// Pod of the current leader, unset if no server reports being the leader
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub leader: Option<String>,
+    /// Number of followers that the leader has not synchronized yet, and that are thus lagging behind it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lagging_followers: Option<u64>,
+    /// Number of znodes, as reported by the leader (or the standalone server)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub znode_count: Option<u64>,
+    #[serde(default)]
+    pub servers: Vec<ZookeeperServerStatus>,
+}
+
+/// Health of a single server
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ZookeeperServerStatus {
+    pub pod: String,
+    /// `leader`, `follower`, `observer`, or `standalone`, unset if the server couldn't be queried
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+    /// Requests that the server has queued but not processed yet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outstanding_requests: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub znode_count: Option<u64>,
+    /// Why the server couldn't be queried
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Overrides for the ports that the servers listen on
+///
+/// Changing the quorum or leader election port of a running ensemble can break its quorum until all servers have been
+/// restarted, since each server expects the others to listen on its own ports.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ZookeeperPortsConfig {
+    /// Port that clients connect to, defaults to `2181`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client: Option<i32>,
+    /// Port that followers connect to the leader on, defaults to `2888`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quorum: Option<i32>,
+    /// Port that the servers elect the leader on, defaults to `3888`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub leader_election: Option<i32>,
+    /// Port of the AdminServer, which the liveness probe queries, defaults to `8080`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin: Option<i32>,
+}
+
+/// Periodic cleanup of old snapshots and transaction logs
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AutopurgeConfig {
+    /// Number of most recent snapshots (and their transaction logs) to keep, defaults to `3`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snap_retain_count: Option<i32>,
+    /// Interval between purges in hours, defaults to `24`, `0` disables purging
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub purge_interval: Option<i32>,
+}
+
+/// Settings for the ZooKeeper server JVM
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct JvmConfig {
+    /// Maximum heap size as a memory quantity (such as `2Gi`), defaults to 80% of the memory limit in `resources`
+    ///
+    /// The image's default heap size is used if neither is set. Heaps below `64Mi` are rejected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heap: Option<String>,
+    /// Additional flags passed to the server JVM, such as GC settings
+    #[serde(default)]
+    pub extra_flags: Vec<String>,
+}
+
+/// Optional server features, which ZooKeeper only reads from Java system properties
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerFeaturesConfig {
+    /// Enables extended znode types, such as TTL nodes (`zookeeper.extendedTypesEnabled`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extended_types_enabled: Option<bool>,
+    /// Lets clients create TTL nodes with the API of ZooKeeper 3.5.3 (`zookeeper.emulate353TTLNodes`), which
+    /// requires `extendedTypesEnabled`
+    #[serde(
+        default,
+        rename = "emulate353TTLNodes",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub emulate_353_ttl_nodes: Option<bool>,
+    /// Lets servers start from transaction logs without a snapshot, such as after upgrading from ZooKeeper 3.4
+    /// (`zookeeper.snapshot.trust.empty`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot_trust_empty: Option<bool>,
+}
+
+/// Health checks of the `zookeeper` container
+///
+/// The liveness probe queries the admin server's `ruok` command, the readiness probe
+/// requires the server to have joined the ensemble (as reported by `zkServer.sh status`).
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbesConfig {
+    #[serde(default)]
+    pub liveness: ProbeConfig,
+    #[serde(default)]
+    pub readiness: ProbeConfig,
+}
+
+/// Timings and thresholds of a probe, unset fields use the operator's defaults
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub initial_delay_seconds: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub period_seconds: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_seconds: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure_threshold: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub success_threshold: Option<i32>,
+}
+
+impl ProbeConfig {
+    /// Overrides the timings of `probe` with those that are set
+    pub fn apply_to(&self, probe: Probe) -> Probe {
+        Probe {
+            initial_delay_seconds: self.initial_delay_seconds.or(probe.initial_delay_seconds),
+            period_seconds: self.period_seconds.or(probe.period_seconds),
+            timeout_seconds: self.timeout_seconds.or(probe.timeout_seconds),
+            failure_threshold: self.failure_threshold.or(probe.failure_threshold),
+            success_threshold: self.success_threshold.or(probe.success_threshold),
+            ..probe
+        }
+    }
+}
+
+/// Persistent volume requested for each pod
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageConfig {
+    /// Requested capacity, defaults to `1Gi`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capacity: Option<String>,
+    /// Name of the `StorageClass` to provision the volume from, defaults to the cluster's default `StorageClass`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_class_name: Option<String>,
+}
+
+/// Scheduling constraints of the servers' pods, which are set before `podOverrides` are merged
+///
+/// A `topologySpreadConstraint` with a `maxSkew` of `1` over `topology.kubernetes.io/zone` stretches the ensemble
+/// evenly across zones, so that losing a single zone keeps the quorum if there are at least three of them.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SchedulingConfig {
+    /// Labels of the nodes that the servers may be scheduled on
+    #[serde(default)]
+    pub node_selector: BTreeMap<String, String>,
+    /// Taints of nodes that the servers tolerate
+    #[serde(default)]
+    pub tolerations: Vec<Toleration>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub affinity: Option<Affinity>,
+    /// How the servers are spread across topology domains, such as zones or nodes
+    ///
+    /// Constraints without a `labelSelector` select the cluster's servers.
+    #[serde(default)]
+    pub topology_spread_constraints: Vec<TopologySpreadConstraint>,
+}
+
+/// The headless `Service` that gives each server a stable DNS name
+///
+/// It stays headless, since the servers address each other by these DNS names.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerServiceConfig {
+    /// Whether servers are published before they are ready, defaults to `true`
+    ///
+    /// Servers only become ready once they have joined the quorum, which they can't do before they can reach each
+    /// other, so disabling this can keep a new ensemble from ever forming.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publish_not_ready_addresses: Option<bool>,
+}
+
+/// The `PodDisruptionBudget` of the servers
+///
+/// Node drains and the cluster autoscaler evict pods through the eviction API, which respects the budget. Since a
+/// stopping leader waits for its followers to catch up first, evicting one server at a time keeps the quorum.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DisruptionBudgetConfig {
+    /// Whether the operator generates the budget, defaults to `true`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// Number (such as `1`) or percentage (such as `10%`) of servers that may be evicted at once, defaults to `1`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_unavailable: Option<IntOrString>,
+}
+
+/// The parts of the image settings that only concern the ZooKeeper image
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ZookeeperImage {
+    /// Full reference of the image that the init container writing each server's `myid` runs, which only needs `sh`,
+    /// `sed` and `expr`
+    ///
+    /// Defaults to the ZooKeeper image itself, so that no other image has to be pulled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub init_image: Option<String>,
+}
+
+impl ProductImage for ZookeeperImage {
+    const DEFAULT_REPOSITORY: &'static str = "docker.stackable.tech/stackable/zookeeper";
+    const DEFAULT_TAG: &'static str = "3.5.8-stackable0";
+}
+
+pub type ImageConfig = operator_commons::spec::ImageConfig<ZookeeperImage>;
+
+/// The image of the init containers
+pub fn init_image(image: &ImageConfig) -> String {
+    image
+        .product
+        .init_image
+        .clone()
+        .unwrap_or_else(|| image.image())
+}
+
+impl ZookeeperClusterSpec {
+    /// Sets the defaults that the operator otherwise assumes, so that they show up in the object
+    ///
+    /// `replicas` defaults to `1`, as the `StatefulSet` does if it is missing.
+    pub fn set_defaults(&mut self) {
+        self.replicas.get_or_insert(1);
+        self.autopurge.snap_retain_count.get_or_insert(3);
+        self.autopurge.purge_interval.get_or_insert(24);
+    }
 }
 
 impl ZookeeperCluster {
@@ -33,12 +389,24 @@ impl ZookeeperCluster {
         self.metadata.name.clone()
     }
 
+    /// The client addresses of all servers, separated by commas
+    pub fn hosts(&self, cluster_domain: &str) -> Option<String> {
+        let port = ZookeeperPorts::new(&self.spec.ports).client;
+        Some(
+            self.pods()?
+                .map(|pod| format!("{}:{}", pod.fqdn(cluster_domain), port))
+                .collect::<Vec<_>>()
+                .join(","),
+        )
+    }
+
     /// The fully-qualified domain name of the "global" load-balanced Kubernetes `Service`
-    pub fn global_service_fqdn(&self) -> Option<String> {
+    pub fn global_service_fqdn(&self, cluster_domain: &str) -> Option<String> {
         Some(format!(
-            "{}.{}.svc.cluster.local",
+            "{}.{}.svc.{}",
             self.global_service_name()?,
-            self.metadata.namespace.as_ref()?
+            self.metadata.namespace.as_ref()?,
+            cluster_domain
         ))
     }
 
@@ -51,12 +419,13 @@ impl ZookeeperCluster {
     pub fn pods(&self) -> Option<impl Iterator<Item = ZookeeperPodRef>> {
         let ns = self.metadata.namespace.clone()?;
         let role_svc_name = self.server_role_service_name()?;
+        let myid_offset = self.spec.myid_offset.unwrap_or(0);
         Some(
             (0..self.spec.replicas.unwrap_or(0)).map(move |i| ZookeeperPodRef {
                 namespace: ns.clone(),
                 role_service_name: role_svc_name.clone(),
                 pod_name: format!("{}-{}", role_svc_name, i),
-                zookeeper_id: i + 1,
+                zookeeper_id: i + 1 + myid_offset,
             }),
         )
     }
@@ -73,10 +442,10 @@ pub struct ZookeeperPodRef {
 }
 
 impl ZookeeperPodRef {
-    pub fn fqdn(&self) -> String {
+    pub fn fqdn(&self, cluster_domain: &str) -> String {
         format!(
-            "{}.{}.{}.svc.cluster.local",
-            self.pod_name, self.role_service_name, self.namespace
+            "{}.{}.{}.svc.{}",
+            self.pod_name, self.role_service_name, self.namespace, cluster_domain
         )
     }
 }
@@ -85,6 +454,7 @@ impl ZookeeperPodRef {
 ///
 /// A `ConfigMap` will automatically be created with the same name, containing the connection string in the field `ZOOKEEPER_BROKERS`.
 /// Each `ZookeeperZnode` gets an isolated ZNode chroot, which the `ZOOKEEPER_BROKERS` automatically contains.
+/// Clients that take the chroot separately can use `ZOOKEEPER_HOSTS` and `ZOOKEEPER_CHROOT` instead.
 /// All data inside of this chroot will be deleted when the corresponding `ZookeeperZnode` is.
 #[derive(Clone, CustomResource, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
 #[kube(
@@ -95,6 +465,7 @@ impl ZookeeperPodRef {
     shortname = "zno",
     shortname = "znode",
     namespaced,
+    status = "ZookeeperZnodeStatus",
     kube_core = "stackable_operator::kube::core",
     k8s_openapi = "stackable_operator::k8s_openapi",
     schemars = "stackable_operator::schemars"
@@ -103,6 +474,20 @@ impl ZookeeperPodRef {
 pub struct ZookeeperZnodeSpec {
     #[serde(default)]
     pub cluster_ref: ZookeeperClusterRef,
+    /// Path of the chroot, such as `/hdfs/production`, defaults to `/znode-<uid>`
+    ///
+    /// Must not be shared with other `ZookeeperZnode`s, since it is deleted along with this object. Changing it leaves
+    /// the previous chroot behind.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chroot: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ZookeeperZnodeStatus {
+    /// The servers' addresses, followed by the chroot, as in the `ZOOKEEPER_BROKERS` of the `ConfigMap`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connection_string: Option<String>,
 }
 
 /// A reference to a [`ZookeeperCluster`]
@@ -0,0 +1,129 @@
+//! Stackable Operator for Apache ZooKeeper
+//!
+//! The controllers are run by the `zookeeper-operator` binary, or together with those of the other operators by
+//! `stackable-operators`.
+
+pub mod crd;
+mod monitor;
+mod ports;
+mod resources;
+mod utils;
+pub mod zk_controller;
+mod znode_controller;
+
+use crate::utils::Tokio01ExecutorExt;
+use crd::{ZookeeperCluster, ZookeeperClusterSpec, ZookeeperZnode};
+use futures::{stream::LocalBoxStream, StreamExt};
+use operator_commons::{
+    erase_controller_result,
+    webhook::{default_spec, Webhooks},
+    OperatorVersion, ReconcileResult, SharedContext,
+};
+use stackable_operator::{
+    k8s_openapi::{
+        api::{
+            apps::v1::StatefulSet,
+            core::v1::{ConfigMap, Service},
+        },
+        apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition,
+    },
+    kube::{
+        self,
+        api::ListParams,
+        runtime::{controller::Context, Controller},
+        CustomResourceExt,
+    },
+};
+use std::time::Duration;
+
+pub mod built_info {
+    include!(concat!(env!("OUT_DIR"), "/built.rs"));
+}
+
+/// Build of this operator, recorded on the objects that it manages
+pub const OPERATOR_VERSION: OperatorVersion = OperatorVersion {
+    name: built_info::PKG_NAME,
+    version: built_info::PKG_VERSION,
+    git_commit: built_info::GIT_COMMIT_HASH,
+};
+
+/// The CRDs of the objects that this operator manages
+pub fn crds() -> Vec<CustomResourceDefinition> {
+    vec![ZookeeperCluster::crd(), ZookeeperZnode::crd()]
+}
+
+/// The webhooks that this operator serves for its CRDs
+pub fn webhooks() -> Webhooks {
+    let mut webhooks = Webhooks::default();
+    webhooks
+        .defaulters
+        .insert(ZookeeperCluster::crd_name().to_string(), |obj| {
+            default_spec(obj, ZookeeperClusterSpec::set_defaults)
+        });
+    webhooks
+}
+
+/// Runs the `ZookeeperCluster` and `ZookeeperZnode` controllers, yielding the result of each reconcile
+///
+/// ZookeeperZnodes are managed with tokio-zookeeper, which depends on Tokio 0.1 and so runs on `tokio01_executor`.
+/// They are not reconciled in dry-run mode, since they are managed directly in ZooKeeper.
+pub fn run_controllers(
+    shared: &SharedContext,
+    health_probe_interval: Duration,
+    tokio01_executor: tokio01::runtime::TaskExecutor,
+) -> LocalBoxStream<'static, ReconcileResult> {
+    let kube = &shared.kube;
+    let zks = kube::Api::<ZookeeperCluster>::all(kube.clone());
+    let znodes = kube::Api::<ZookeeperZnode>::all(kube.clone());
+    let zk_controller = Controller::new(zks, ListParams::default())
+        .owns(
+            kube::Api::<Service>::all(kube.clone()),
+            ListParams::default(),
+        )
+        .owns(
+            kube::Api::<StatefulSet>::all(kube.clone()),
+            ListParams::default(),
+        )
+        .run(
+            zk_controller::reconcile_zk,
+            operator_commons::error_policy,
+            Context::new(zk_controller::Ctx {
+                kube: kube.clone(),
+                cluster_domain: shared.cluster_domain.clone(),
+                image_options: shared.image_options.clone(),
+                dry_run: shared.dry_run,
+                reconcile_permits: shared.reconcile_permits.clone(),
+                apply_rate_limiter: shared.apply_rate_limiter.clone(),
+                log_apply_diffs: shared.log_apply_diffs,
+                resync_interval: shared.resync_interval,
+                health_probe_interval,
+            }),
+        )
+        .map(erase_controller_result);
+    if shared.dry_run {
+        return zk_controller.boxed_local();
+    }
+    let znode_controller = Controller::new(znodes, ListParams::default())
+        .owns(
+            kube::Api::<ConfigMap>::all(kube.clone()),
+            ListParams::default(),
+        )
+        .run(
+            move |znode, ctx| {
+                tokio01_executor
+                    .clone()
+                    .run_in_ctx(znode_controller::reconcile_znode(znode, ctx))
+            },
+            operator_commons::error_policy,
+            Context::new(znode_controller::Ctx {
+                kube: kube.clone(),
+                cluster_domain: shared.cluster_domain.clone(),
+                reconcile_permits: shared.reconcile_permits.clone(),
+                apply_rate_limiter: shared.apply_rate_limiter.clone(),
+                log_apply_diffs: shared.log_apply_diffs,
+                resync_interval: shared.resync_interval,
+            }),
+        )
+        .map(erase_controller_result);
+    futures::stream::select(zk_controller, znode_controller).boxed_local()
+}
@@ -1,69 +1,147 @@
-mod crd;
-mod utils;
-mod zk_controller;
-mod znode_controller;
-
-use crate::utils::Tokio01ExecutorExt;
-use crd::{ZookeeperCluster, ZookeeperZnode};
-use futures::{compat::Future01CompatExt, StreamExt};
-use stackable_operator::{
-    k8s_openapi::api::{
-        apps::v1::StatefulSet,
-        core::v1::{ConfigMap, Service},
-    },
-    kube::{
-        self,
-        api::{DynamicObject, ListParams},
-        runtime::{
-            controller::{Context, ReconcilerAction},
-            reflector::ObjectRef,
-            Controller,
-        },
-        CustomResourceExt, Resource,
-    },
+use futures::compat::Future01CompatExt;
+use operator_commons::{
+    crd::{install_crds, render_crds, CrdFormat},
+    detect_cluster_domain,
+    health::serve_health,
+    log_reconcile_results, parse_duration, run_until_shutdown,
+    telemetry::{init_tracing, shutdown_tracing, TracingOptions},
+    webhook::{serve_webhooks, WebhookServerOptions, WebhookServiceOptions},
+    ClientOptions, ControllerOptions, ImageOptions, SharedContext,
 };
+use stackable_operator::kube;
+use std::{fs::File, net::SocketAddr, path::PathBuf, time::Duration};
 use structopt::StructOpt;
-
-mod built_info {
-    include!(concat!(env!("OUT_DIR"), "/built.rs"));
-}
+use zookeeper_operator::{
+    built_info, crd::ZookeeperCluster, crds, run_controllers, webhooks, zk_controller,
+    OPERATOR_VERSION,
+};
 
 #[derive(StructOpt)]
 #[structopt(about = built_info::PKG_DESCRIPTION, author = "Stackable GmbH - info@stackable.de")]
 struct Opts {
     #[structopt(subcommand)]
     cmd: Cmd,
+    #[structopt(flatten)]
+    tracing: TracingOptions,
 }
 
 #[derive(StructOpt)]
 enum Cmd {
     /// Print CRD objects
-    Crd,
+    Crd {
+        /// Output format, `yaml` or `json`
+        #[structopt(long, default_value = "yaml")]
+        format: CrdFormat,
+        /// Write the CRDs to this file instead of printing them
+        #[structopt(long, short)]
+        output: Option<PathBuf>,
+        /// Apply the CRDs to the cluster and wait until they are established, instead of printing them
+        #[structopt(long)]
+        install: bool,
+        #[structopt(flatten)]
+        client: ClientOptions,
+        #[structopt(flatten)]
+        webhook: WebhookServiceOptions,
+    },
+    /// Print the operator's build information
+    Version,
     /// Run operator
-    Run,
+    Run {
+        /// DNS domain of the Kubernetes cluster, detected from `/etc/resolv.conf` if not specified
+        #[structopt(long)]
+        cluster_domain: Option<String>,
+        /// Only log the changes that would be made, using server-side dry-run, instead of persisting them
+        ///
+        /// ZookeeperZnodes are not reconciled in this mode, since they are managed directly in ZooKeeper.
+        #[structopt(long)]
+        dry_run: bool,
+        #[structopt(flatten)]
+        client: ClientOptions,
+        #[structopt(flatten)]
+        controller_options: ControllerOptions,
+        #[structopt(flatten)]
+        image_options: ImageOptions,
+        /// How often the servers' admin servers are queried for the ensemble's health, which is published in the
+        /// status of each ZookeeperCluster
+        #[structopt(long, default_value = "1m", parse(try_from_str = parse_duration))]
+        health_probe_interval: Duration,
+        /// Address to serve `/healthz` and `/version` on
+        #[structopt(long, default_value = "0.0.0.0:8080")]
+        health_address: SocketAddr,
+        #[structopt(flatten)]
+        webhook_server: WebhookServerOptions,
+    },
+    /// Show how the operator would change the objects of a ZookeeperCluster, without changing anything
+    Diff {
+        /// ZookeeperCluster manifest to diff, the namespace defaults to that of the current kubeconfig context
+        file: PathBuf,
+        /// DNS domain of the Kubernetes cluster
+        #[structopt(long, default_value = "cluster.local")]
+        cluster_domain: String,
+        #[structopt(flatten)]
+        image_options: ImageOptions,
+        #[structopt(flatten)]
+        client: ClientOptions,
+    },
 }
 
-fn erase_controller_result<K: Resource, E: std::error::Error + Send + Sync + 'static>(
-    res: Result<(ObjectRef<K>, ReconcilerAction), E>,
-) -> eyre::Result<(ObjectRef<DynamicObject>, ReconcilerAction)> {
-    let (obj_ref, action) = res?;
-    Ok((obj_ref.erase(), action))
-}
+/// Stands in for the uid of clusters that don't exist yet
+const PLACEHOLDER_UID: &str = "00000000-0000-0000-0000-000000000000";
 
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
-    stackable_operator::logging::initialize_logging("ZOOKEEPER_OPERATOR_LOG");
+    let opts = Opts::from_args();
+    init_tracing(
+        "ZOOKEEPER_OPERATOR_LOG",
+        "zookeeper-operator",
+        &opts.tracing,
+    )?;
+    operator_commons::fault::log_active_faults();
     // tokio-zookeeper depends on Tokio 0.1
     let tokio01_runtime = tokio01::runtime::Runtime::new()?;
-
-    let opts = Opts::from_args();
     match opts.cmd {
-        Cmd::Crd => println!(
-            "{}{}",
-            serde_yaml::to_string(&ZookeeperCluster::crd())?,
-            serde_yaml::to_string(&ZookeeperZnode::crd())?
-        ),
-        Cmd::Run => {
+        Cmd::Crd {
+            format,
+            output,
+            install,
+            client,
+            webhook,
+        } => {
+            let crds = crds();
+            let mutating_webhooks =
+                webhook.mutating_webhook_configuration("zookeeper-operator", &crds, &webhooks())?;
+            if install {
+                let kube = client.client().await?;
+                install_crds(
+                    &kube,
+                    "zookeeper-operator",
+                    &crds,
+                    mutating_webhooks.as_ref(),
+                )
+                .await?;
+            } else if let Some(output) = output {
+                std::fs::write(
+                    output,
+                    render_crds(&crds, mutating_webhooks.as_ref(), format)?,
+                )?;
+            } else {
+                println!(
+                    "{}",
+                    render_crds(&crds, mutating_webhooks.as_ref(), format)?
+                );
+            }
+        }
+        Cmd::Version => println!("{}", OPERATOR_VERSION),
+        Cmd::Run {
+            cluster_domain,
+            dry_run,
+            client,
+            controller_options,
+            image_options,
+            health_probe_interval,
+            health_address,
+            webhook_server,
+        } => {
             stackable_operator::utils::print_startup_string(
                 built_info::PKG_DESCRIPTION,
                 built_info::PKG_VERSION,
@@ -72,56 +150,86 @@ async fn main() -> eyre::Result<()> {
                 built_info::BUILT_TIME_UTC,
                 built_info::RUSTC_VERSION,
             );
-            let kube = kube::Client::try_default().await?;
-            let zks = kube::Api::<ZookeeperCluster>::all(kube.clone());
-            let znodes = kube::Api::<ZookeeperZnode>::all(kube.clone());
-            let zk_controller = Controller::new(zks, ListParams::default())
-                .owns(
-                    kube::Api::<Service>::all(kube.clone()),
-                    ListParams::default(),
-                )
-                .owns(
-                    kube::Api::<StatefulSet>::all(kube.clone()),
-                    ListParams::default(),
-                )
-                .run(
-                    zk_controller::reconcile_zk,
-                    zk_controller::error_policy,
-                    Context::new(zk_controller::Ctx { kube: kube.clone() }),
-                );
-            let znode_controller = Controller::new(znodes, ListParams::default())
-                .owns(
-                    kube::Api::<ConfigMap>::all(kube.clone()),
-                    ListParams::default(),
-                )
-                .run(
-                    |znode, ctx| {
-                        tokio01_runtime
-                            .executor()
-                            .run_in_ctx(znode_controller::reconcile_znode(znode, ctx))
-                    },
-                    znode_controller::error_policy,
-                    Context::new(znode_controller::Ctx { kube }),
-                );
-            futures::stream::select(
-                zk_controller.map(erase_controller_result),
-                znode_controller.map(erase_controller_result),
-            )
-            .for_each(|res| async {
-                match res {
-                    Ok((obj, _)) => tracing::info!(object = %obj, "Reconciled object"),
-                    Err(err) => {
-                        tracing::error!(
-                            error = &*err as &dyn std::error::Error,
-                            "Failed to reconcile object",
-                        )
-                    }
+            let cluster_domain = cluster_domain
+                .or_else(detect_cluster_domain)
+                .unwrap_or_else(|| "cluster.local".to_string());
+            tracing::info!(cluster_domain = %cluster_domain, "Using cluster domain");
+            if dry_run {
+                tracing::warn!("Running in dry-run mode, no changes will be persisted");
+            }
+            tokio::spawn(async move {
+                if let Err(err) = serve_health(health_address, OPERATOR_VERSION).await {
+                    tracing::error!(
+                        error = &err as &dyn std::error::Error,
+                        "Failed to serve health endpoints"
+                    );
+                }
+            });
+            tokio::spawn(async move {
+                if let Err(err) = serve_webhooks(webhook_server, webhooks()).await {
+                    tracing::error!(
+                        error = &err as &dyn std::error::Error,
+                        "Failed to serve webhooks"
+                    );
                 }
-            })
+            });
+            let kube = client.client().await?;
+            let reconcile_permits = controller_options.reconcile_permits();
+            let shared = SharedContext {
+                kube,
+                cluster_domain,
+                image_options,
+                dry_run,
+                reconcile_permits: reconcile_permits.clone(),
+                apply_rate_limiter: controller_options.apply_rate_limiter(),
+                log_apply_diffs: controller_options.log_apply_diffs,
+                resync_interval: controller_options.resync_interval,
+            };
+            let results = log_reconcile_results(run_controllers(
+                &shared,
+                health_probe_interval,
+                tokio01_runtime.executor(),
+            ));
+            run_until_shutdown(
+                results,
+                &reconcile_permits,
+                controller_options.shutdown_grace_period,
+            )
             .await;
         }
+        Cmd::Diff {
+            file,
+            cluster_domain,
+            image_options,
+            client,
+        } => {
+            let config = client.config().await?;
+            let default_namespace = config.default_namespace.clone();
+            let kube = client.client_with_config(config)?;
+            let mut zk: ZookeeperCluster = serde_yaml::from_reader(File::open(&file)?)?;
+            let ns = zk
+                .metadata
+                .namespace
+                .get_or_insert(default_namespace)
+                .clone();
+            let name = zk.metadata.name.clone().unwrap_or_default();
+            // Owner references point to the live cluster, or to a placeholder if it hasn't been created yet
+            zk.metadata.uid = match kube::Api::<ZookeeperCluster>::namespaced(kube.clone(), &ns)
+                .get(&name)
+                .await
+            {
+                Ok(live) => live.metadata.uid,
+                Err(kube::Error::Api(err)) if err.code == 404 => Some(PLACEHOLDER_UID.to_string()),
+                Err(err) => return Err(err.into()),
+            };
+            print!(
+                "{}",
+                zk_controller::diff_zk(&kube, &zk, &cluster_domain, &image_options).await?
+            );
+        }
     }
 
     tokio01_runtime.shutdown_now().compat().await.unwrap();
+    shutdown_tracing();
     Ok(())
 }
@@ -0,0 +1,189 @@
+//! Probes the health of the ensemble through the `monitor` command of each server's admin server
+
+use std::time::Duration;
+
+use hyper::{body, Client, Uri};
+use operator_commons::error_chain;
+use serde::Deserialize;
+use snafu::{OptionExt, ResultExt, Snafu};
+
+use crate::{
+    crd::{ZookeeperEnsembleStatus, ZookeeperServerStatus},
+    resources::ClusterContext,
+};
+
+/// How long to wait for a server to respond, so that unresponsive servers don't stall reconciles
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Snafu, Debug)]
+pub enum Error {
+    #[snafu(display("invalid admin server URL {}", url))]
+    InvalidUrl {
+        source: hyper::http::uri::InvalidUri,
+        url: String,
+    },
+    #[snafu(display("failed to query {}", url))]
+    Request { source: hyper::Error, url: String },
+    #[snafu(display("{} did not respond within {:?}", url, REQUEST_TIMEOUT))]
+    Timeout { url: String },
+    #[snafu(display("failed to parse the response of {}", url))]
+    ParseResponse {
+        source: serde_json::Error,
+        url: String,
+    },
+}
+
+/// The values of the `monitor` command that are published in the status
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+pub struct Monitor {
+    pub server_state: String,
+    pub outstanding_requests: Option<u64>,
+    pub znode_count: Option<u64>,
+    /// Only reported by the leader, ZooKeeper 3.6 calls it `learners`
+    #[serde(alias = "learners")]
+    pub followers: Option<u64>,
+    /// Only reported by the leader
+    pub synced_followers: Option<u64>,
+}
+
+/// Runs the `monitor` command of the server whose admin server listens on `host:port`
+pub async fn get_monitor(host: &str, port: i32) -> Result<Monitor, Error> {
+    let url = format!("http://{}:{}/commands/monitor", host, port);
+    let uri = url.parse::<Uri>().context(InvalidUrl { url: &url })?;
+    let response = async {
+        let response = Client::new().get(uri).await?;
+        body::to_bytes(response.into_body()).await
+    };
+    let response = tokio::time::timeout(REQUEST_TIMEOUT, response)
+        .await
+        .ok()
+        .context(Timeout { url: &url })?
+        .context(Request { url: &url })?;
+    serde_json::from_slice(&response).context(ParseResponse { url })
+}
+
+/// Summarizes the `monitor` results of each server pod
+pub fn ensemble_status(
+    servers: impl IntoIterator<Item = (String, Result<Monitor, Error>)>,
+) -> ZookeeperEnsembleStatus {
+    let mut status = ZookeeperEnsembleStatus::default();
+    for (pod, monitor) in servers {
+        let monitor = match monitor {
+            Ok(monitor) => monitor,
+            Err(err) => {
+                status.servers.push(ZookeeperServerStatus {
+                    pod,
+                    error: Some(error_chain(&err)),
+                    ..ZookeeperServerStatus::default()
+                });
+                continue;
+            }
+        };
+        match monitor.server_state.as_str() {
+            "leader" => {
+                status.leader = Some(pod.clone());
+                status.lagging_followers = monitor.followers.map(|followers| {
+                    followers.saturating_sub(monitor.synced_followers.unwrap_or(0))
+                });
+                status.znode_count = monitor.znode_count;
+            }
+            "standalone" => status.znode_count = monitor.znode_count,
+            _ => {}
+        }
+        status.servers.push(ZookeeperServerStatus {
+            pod,
+            state: Some(monitor.server_state),
+            outstanding_requests: monitor.outstanding_requests,
+            znode_count: monitor.znode_count,
+            error: None,
+        });
+    }
+    status
+}
+
+/// Queries all servers of `cluster` at the same time
+pub async fn probe_ensemble(cluster: &ClusterContext<'_>) -> ZookeeperEnsembleStatus {
+    let pods = cluster
+        .zk
+        .pods()
+        .into_iter()
+        .flatten()
+        .map(|pod| async move {
+            let monitor =
+                get_monitor(&pod.fqdn(&cluster.cluster_domain), cluster.ports.admin).await;
+            (pod.pod_name, monitor)
+        });
+    ensemble_status(futures::future::join_all(pods).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(json: serde_json::Value) -> Result<Monitor, Error> {
+        Ok(serde_json::from_value(json).unwrap())
+    }
+
+    #[test]
+    fn ensemble_status_summarizes_the_servers() {
+        let status = ensemble_status(vec![
+            (
+                "zk-servers-0".to_string(),
+                monitor(serde_json::json!({
+                    "command": "monitor",
+                    "error": null,
+                    "server_state": "follower",
+                    "outstanding_requests": 0,
+                    "znode_count": 41,
+                })),
+            ),
+            (
+                "zk-servers-1".to_string(),
+                monitor(serde_json::json!({
+                    "server_state": "leader",
+                    "outstanding_requests": 3,
+                    "znode_count": 42,
+                    "learners": 2,
+                    "synced_followers": 1,
+                })),
+            ),
+            (
+                "zk-servers-2".to_string(),
+                Err(Error::Timeout {
+                    url: "http://zk-servers-2:8080/commands/monitor".to_string(),
+                }),
+            ),
+        ]);
+        assert_eq!(status.leader.as_deref(), Some("zk-servers-1"));
+        assert_eq!(status.lagging_followers, Some(1));
+        assert_eq!(status.znode_count, Some(42));
+        assert_eq!(
+            status.servers[0],
+            ZookeeperServerStatus {
+                pod: "zk-servers-0".to_string(),
+                state: Some("follower".to_string()),
+                outstanding_requests: Some(0),
+                znode_count: Some(41),
+                error: None,
+            }
+        );
+        assert_eq!(status.servers[1].outstanding_requests, Some(3));
+        assert_eq!(status.servers[2].state, None);
+        assert!(status.servers[2]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("did not respond"));
+    }
+
+    #[test]
+    fn ensemble_without_leader_has_no_leader_values() {
+        let status = ensemble_status(vec![(
+            "zk-servers-0".to_string(),
+            monitor(serde_json::json!({ "server_state": "follower", "znode_count": 5 })),
+        )]);
+        assert_eq!(status.leader, None);
+        assert_eq!(status.lagging_followers, None);
+        assert_eq!(status.znode_count, None);
+    }
+}
@@ -0,0 +1,31 @@
+//! The network ports that the ZooKeeper servers listen on
+//!
+//! All generated `ContainerPort`s, `ServicePort`s, `NetworkPolicy` rules, and `zoo.cfg` entries take their ports
+//! from [`ZookeeperPorts`], so that they stay consistent when users override them.
+
+use crate::crd::ZookeeperPortsConfig;
+
+pub const DEFAULT_CLIENT: i32 = 2181;
+pub const DEFAULT_QUORUM: i32 = 2888;
+pub const DEFAULT_LEADER_ELECTION: i32 = 3888;
+pub const DEFAULT_ADMIN: i32 = 8080;
+
+/// The ports of a [`ZookeeperCluster`](crate::crd::ZookeeperCluster), with the user's overrides applied
+#[derive(Clone, Copy, Debug)]
+pub struct ZookeeperPorts {
+    pub client: i32,
+    pub quorum: i32,
+    pub leader_election: i32,
+    pub admin: i32,
+}
+
+impl ZookeeperPorts {
+    pub fn new(config: &ZookeeperPortsConfig) -> Self {
+        Self {
+            client: config.client.unwrap_or(DEFAULT_CLIENT),
+            quorum: config.quorum.unwrap_or(DEFAULT_QUORUM),
+            leader_election: config.leader_election.unwrap_or(DEFAULT_LEADER_ELECTION),
+            admin: config.admin.unwrap_or(DEFAULT_ADMIN),
+        }
+    }
+}
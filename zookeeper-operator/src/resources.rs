@@ -0,0 +1,1536 @@
+//! Builds the objects that make up a [`ZookeeperCluster`]
+//!
+//! Nothing in here talks to Kubernetes, so the generated objects can be tested in isolation.
+//! Applying them is left to [`crate::zk_controller`].
+
+use std::{
+    collections::BTreeMap,
+    hash::{Hash, Hasher},
+};
+
+use crate::{
+    crd::{init_image, AllowedClient, StorageConfig, ZookeeperCluster, ZookeeperClusterStatus},
+    ports::ZookeeperPorts,
+};
+use fnv::FnvHasher;
+use operator_commons::{
+    controller_reference_to_obj,
+    labels::{managed_by_labels, recommended_labels, APP_INSTANCE_LABEL, APP_NAME_LABEL},
+    merge_json, parse_memory_quantity, ImageOptions,
+};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use stackable_operator::{
+    builder::{ConfigMapBuilder, ContainerBuilder},
+    k8s_openapi::{
+        api::{
+            apps::v1::{StatefulSet, StatefulSetSpec},
+            core::v1::{
+                ConfigMap, ConfigMapVolumeSource, EnvVar, EnvVarSource, ExecAction, HTTPGetAction,
+                Handler, Lifecycle, LocalObjectReference, ObjectFieldSelector,
+                PersistentVolumeClaim, PersistentVolumeClaimSpec, PodSpec, PodTemplateSpec, Probe,
+                ResourceRequirements, Service, ServiceAccount, ServicePort, ServiceSpec,
+                TopologySpreadConstraint, Volume,
+            },
+            networking::v1::{
+                NetworkPolicy, NetworkPolicyIngressRule, NetworkPolicyPeer, NetworkPolicyPort,
+                NetworkPolicySpec,
+            },
+            policy::v1::{PodDisruptionBudget, PodDisruptionBudgetSpec},
+            rbac::v1::{RoleBinding, RoleRef, Subject},
+        },
+        apimachinery::pkg::{
+            api::resource::Quantity,
+            apis::meta::v1::{LabelSelector, OwnerReference},
+            util::intstr::IntOrString,
+        },
+    },
+    kube::{api::ObjectMeta, runtime::reflector::ObjectRef},
+};
+
+/// How long a stopping leader waits for its followers to catch up before shutting down
+const LEADER_SYNC_TIMEOUT_SECONDS: i64 = 30;
+
+/// Annotation on a `ZookeeperCluster` that restarts its servers whenever its value changes, such as to the current time
+///
+/// The annotation is copied to the pod template, so the `StatefulSet` replaces the servers one by one.
+pub const RESTART_ANNOTATION: &str = "zookeeper.stackable.tech/restart";
+
+/// Pod template annotation with the hash of `zoo.cfg`, since the servers only read it on startup
+pub const CONFIG_HASH_ANNOTATION: &str = "zookeeper.stackable.tech/config-hash";
+
+/// Pod annotation that tells the cluster autoscaler whether it may evict the pod to remove its node
+pub const SAFE_TO_EVICT_ANNOTATION: &str = "cluster-autoscaler.kubernetes.io/safe-to-evict";
+
+#[derive(Snafu, Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum Error {
+    #[snafu(display("object {} has no namespace", obj_ref))]
+    ObjectHasNoNamespace {
+        obj_ref: ObjectRef<ZookeeperCluster>,
+    },
+    #[snafu(display("failed to calculate global service name for {}", obj_ref))]
+    GlobalServiceNameNotFound {
+        obj_ref: ObjectRef<ZookeeperCluster>,
+    },
+    #[snafu(display("failed to calculate service name for role {} of {}", role, obj_ref))]
+    RoleServiceNameNotFound {
+        obj_ref: ObjectRef<ZookeeperCluster>,
+        role: String,
+    },
+    #[snafu(display("podOverrides of {} do not form a valid pod template", zk))]
+    InvalidPodOverrides {
+        source: serde_json::Error,
+        zk: ObjectRef<ZookeeperCluster>,
+    },
+    #[snafu(display("jvm.heap {:?} of {} is not a valid memory quantity", heap, zk))]
+    InvalidJvmHeap {
+        heap: String,
+        zk: ObjectRef<ZookeeperCluster>,
+    },
+    #[snafu(display(
+        "the JVM heap of {} would only be {} MiB, raise jvm.heap or the memory limit (the heap defaults to 80% of \
+         it) to at least {} MiB",
+        zk,
+        heap_mib,
+        MIN_HEAP_MIB
+    ))]
+    JvmHeapTooSmall {
+        heap_mib: u64,
+        zk: ObjectRef<ZookeeperCluster>,
+    },
+}
+
+/// A [`ZookeeperCluster`], along with everything that its objects' names and addresses are derived from
+pub struct ClusterContext<'a> {
+    pub zk: &'a ZookeeperCluster,
+    pub zk_ref: ObjectRef<ZookeeperCluster>,
+    pub namespace: String,
+    /// DNS domain of the Kubernetes cluster, such as `cluster.local`
+    pub cluster_domain: String,
+    /// Where the operator is configured to pull images from
+    pub image_options: ImageOptions,
+    pub owner_ref: OwnerReference,
+    /// Name of the load-balanced `Service` that clients connect to
+    pub global_service_name: String,
+    /// Name of the headless `Service`, `ConfigMap`, and `StatefulSet` of the server role
+    pub server_role_name: String,
+    pub ports: ZookeeperPorts,
+}
+
+impl<'a> ClusterContext<'a> {
+    pub fn new(zk: &'a ZookeeperCluster, cluster_domain: &str) -> Result<Self, Error> {
+        let zk_ref = ObjectRef::from_obj(zk);
+        let namespace = zk
+            .metadata
+            .namespace
+            .clone()
+            .with_context(|| ObjectHasNoNamespace {
+                obj_ref: zk_ref.clone(),
+            })?;
+        let global_service_name =
+            zk.global_service_name()
+                .with_context(|| GlobalServiceNameNotFound {
+                    obj_ref: zk_ref.clone(),
+                })?;
+        let server_role_name =
+            zk.server_role_service_name()
+                .with_context(|| RoleServiceNameNotFound {
+                    obj_ref: zk_ref.clone(),
+                    role: "servers",
+                })?;
+        Ok(Self {
+            zk,
+            zk_ref,
+            namespace,
+            cluster_domain: cluster_domain.to_string(),
+            image_options: ImageOptions::default(),
+            owner_ref: controller_reference_to_obj(zk),
+            global_service_name,
+            server_role_name,
+            ports: ZookeeperPorts::new(&zk.spec.ports),
+        })
+    }
+
+    pub fn service_account_name(&self) -> String {
+        self.zk
+            .spec
+            .service_account
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("{}-serviceaccount", self.global_service_name))
+    }
+
+    /// Name of the `RoleBinding` that grants `serviceAccount.clusterRole`
+    ///
+    /// Derived from the cluster rather than the `ServiceAccount`, since an existing account's name may already be
+    /// taken by someone else's `RoleBinding`.
+    pub fn role_binding_name(&self) -> String {
+        format!("{}-serviceaccount", self.global_service_name)
+    }
+
+    pub fn network_policy_name(&self) -> String {
+        format!("{}-network-isolation", self.global_service_name)
+    }
+
+    /// Labels of the server pods
+    fn pod_labels(&self) -> BTreeMap<String, String> {
+        recommended_labels(self.zk, "zookeeper", "3.7.0", "servers", "servers")
+    }
+
+    /// Annotations that the user added to the spec
+    fn user_annotations(&self) -> Option<BTreeMap<String, String>> {
+        Some(self.zk.spec.annotations.clone()).filter(|annotations| !annotations.is_empty())
+    }
+
+    /// Labels that all objects of the cluster carry, see `cluster_selector`
+    fn cluster_selector_labels(&self) -> BTreeMap<String, String> {
+        BTreeMap::from([
+            (APP_NAME_LABEL.to_string(), "zookeeper".to_string()),
+            (
+                APP_INSTANCE_LABEL.to_string(),
+                self.global_service_name.clone(),
+            ),
+        ])
+    }
+
+    /// Label selector of all objects of the cluster
+    pub fn cluster_selector(&self) -> String {
+        format!(
+            "{}=zookeeper,{}={}",
+            APP_NAME_LABEL, APP_INSTANCE_LABEL, self.global_service_name
+        )
+    }
+
+    fn metadata(&self, name: String) -> ObjectMeta {
+        let mut labels = self.zk.spec.labels.clone();
+        labels.extend(self.cluster_selector_labels());
+        labels.extend(managed_by_labels(&crate::OPERATOR_VERSION));
+        ObjectMeta {
+            annotations: self.user_annotations(),
+            labels: Some(labels),
+            name: Some(name),
+            namespace: Some(self.namespace.clone()),
+            owner_references: Some(vec![self.owner_ref.clone()]),
+            ..ObjectMeta::default()
+        }
+    }
+
+    /// Annotations of the server pods: the user's ones, the restart annotation, the hash of `zoo.cfg`, and whether the
+    /// cluster autoscaler may evict them
+    fn pod_annotations(&self) -> BTreeMap<String, String> {
+        let mut annotations = self.zk.spec.annotations.clone();
+        annotations.extend(
+            self.zk
+                .metadata
+                .annotations
+                .iter()
+                .flatten()
+                .filter(|(key, _)| **key == RESTART_ANNOTATION)
+                .map(|(key, value)| (key.clone(), value.clone())),
+        );
+        annotations.insert(CONFIG_HASH_ANNOTATION.to_string(), config_hash(self));
+        if let Some(safe_to_evict) = self.zk.spec.safe_to_evict {
+            annotations.insert(
+                SAFE_TO_EVICT_ANNOTATION.to_string(),
+                safe_to_evict.to_string(),
+            );
+        }
+        annotations
+    }
+
+    /// Whether the cluster runs as a single standalone server
+    fn standalone(&self) -> bool {
+        self.zk.spec.replicas.unwrap_or(0) <= 1
+    }
+}
+
+fn zk_service_port(cluster: &ClusterContext) -> ServicePort {
+    ServicePort {
+        name: Some("zk".to_string()),
+        port: cluster.ports.client,
+        protocol: Some("TCP".to_string()),
+        ..ServicePort::default()
+    }
+}
+
+/// The `Service` that load-balances clients over all servers
+pub fn build_global_service(cluster: &ClusterContext) -> Service {
+    Service {
+        metadata: cluster.metadata(cluster.global_service_name.clone()),
+        spec: Some(ServiceSpec {
+            ports: Some(vec![zk_service_port(cluster)]),
+            selector: Some(cluster.pod_labels()),
+            type_: Some("NodePort".to_string()),
+            ..ServiceSpec::default()
+        }),
+        status: None,
+    }
+}
+
+/// The headless `Service` that gives each server a stable DNS name
+pub fn build_server_role_service(cluster: &ClusterContext) -> Service {
+    Service {
+        metadata: cluster.metadata(cluster.server_role_name.clone()),
+        spec: Some(ServiceSpec {
+            cluster_ip: Some("None".to_string()),
+            ports: Some(vec![zk_service_port(cluster)]),
+            selector: Some(cluster.pod_labels()),
+            publish_not_ready_addresses: Some(
+                cluster
+                    .zk
+                    .spec
+                    .server_service
+                    .publish_not_ready_addresses
+                    .unwrap_or(true),
+            ),
+            ..ServiceSpec::default()
+        }),
+        status: None,
+    }
+}
+
+/// The cluster's own `ServiceAccount`, unless `serviceAccount.name` refers to an existing one
+///
+/// An existing `ServiceAccount` is only referenced, so that it is neither taken over nor deleted along with the cluster.
+pub fn build_service_account(cluster: &ClusterContext) -> Option<ServiceAccount> {
+    if cluster.zk.spec.service_account.name.is_some() {
+        return None;
+    }
+    Some(ServiceAccount {
+        metadata: cluster.metadata(cluster.service_account_name()),
+        image_pull_secrets: Some(
+            cluster
+                .zk
+                .spec
+                .service_account
+                .image_pull_secrets
+                .iter()
+                .map(|secret| LocalObjectReference {
+                    name: Some(secret.clone()),
+                })
+                .collect(),
+        ),
+        ..ServiceAccount::default()
+    })
+}
+
+/// Grants the configured `ClusterRole` to the cluster's `ServiceAccount`, if any
+pub fn build_role_binding(cluster: &ClusterContext) -> Option<RoleBinding> {
+    let cluster_role = cluster.zk.spec.service_account.cluster_role.as_ref()?;
+    Some(RoleBinding {
+        metadata: cluster.metadata(cluster.role_binding_name()),
+        role_ref: RoleRef {
+            api_group: "rbac.authorization.k8s.io".to_string(),
+            kind: "ClusterRole".to_string(),
+            name: cluster_role.clone(),
+        },
+        subjects: Some(vec![Subject {
+            kind: "ServiceAccount".to_string(),
+            name: cluster.service_account_name(),
+            namespace: Some(cluster.namespace.clone()),
+            ..Subject::default()
+        }]),
+    })
+}
+
+/// Limits how many servers may be evicted at once, unless the `disruptionBudget` is disabled
+pub fn build_pod_disruption_budget(cluster: &ClusterContext) -> Option<PodDisruptionBudget> {
+    let budget = &cluster.zk.spec.disruption_budget;
+    if !budget.enabled.unwrap_or(true) {
+        return None;
+    }
+    Some(PodDisruptionBudget {
+        metadata: cluster.metadata(cluster.server_role_name.clone()),
+        spec: Some(PodDisruptionBudgetSpec {
+            max_unavailable: Some(
+                budget
+                    .max_unavailable
+                    .clone()
+                    .unwrap_or(IntOrString::Int(1)),
+            ),
+            selector: Some(LabelSelector {
+                match_labels: Some(cluster.pod_labels()),
+                ..LabelSelector::default()
+            }),
+            ..PodDisruptionBudgetSpec::default()
+        }),
+        status: None,
+    })
+}
+
+/// Restricts ingress to the servers, if network isolation is enabled
+pub fn build_network_policy(cluster: &ClusterContext) -> Option<NetworkPolicy> {
+    let network_isolation = cluster.zk.spec.network_isolation.as_ref()?;
+    let member_peer = NetworkPolicyPeer {
+        pod_selector: Some(LabelSelector {
+            match_labels: Some(cluster.pod_labels()),
+            ..LabelSelector::default()
+        }),
+        ..NetworkPolicyPeer::default()
+    };
+    let tcp_ports = |ports: &[i32]| {
+        ports
+            .iter()
+            .map(|port| NetworkPolicyPort {
+                port: Some(IntOrString::Int(*port)),
+                protocol: Some("TCP".to_string()),
+                ..NetworkPolicyPort::default()
+            })
+            .collect::<Vec<_>>()
+    };
+    Some(NetworkPolicy {
+        metadata: cluster.metadata(cluster.network_policy_name()),
+        spec: Some(NetworkPolicySpec {
+            pod_selector: LabelSelector {
+                match_labels: Some(cluster.pod_labels()),
+                ..LabelSelector::default()
+            },
+            policy_types: Some(vec!["Ingress".to_string()]),
+            ingress: Some(vec![
+                NetworkPolicyIngressRule {
+                    from: Some(
+                        std::iter::once(member_peer.clone())
+                            .chain(
+                                network_isolation
+                                    .allowed_clients
+                                    .iter()
+                                    .map(AllowedClient::network_policy_peer),
+                            )
+                            .collect(),
+                    ),
+                    // The operator probes the ensemble's health through the admin server, so it must be an allowed client
+                    ports: Some(tcp_ports(&[cluster.ports.client, cluster.ports.admin])),
+                },
+                // Quorum and leader election traffic stays within the ensemble
+                NetworkPolicyIngressRule {
+                    from: Some(vec![member_peer]),
+                    ports: Some(tcp_ports(&[
+                        cluster.ports.quorum,
+                        cluster.ports.leader_election,
+                    ])),
+                },
+            ]),
+            ..NetworkPolicySpec::default()
+        }),
+    })
+}
+
+/// Renders the servers' `zoo.cfg`
+fn zoo_cfg(cluster: &ClusterContext) -> String {
+    let spec = &cluster.zk.spec;
+    let ports = cluster.ports;
+    let standalone = cluster.standalone();
+    [
+        ("standaloneEnabled", standalone.then(|| "true".to_string())),
+        ("tickTime", Some("2000".to_string())),
+        ("initLimit", Some("10".to_string())),
+        ("syncLimit", Some("5".to_string())),
+        ("dataDir", Some("/data".to_string())),
+        (
+            "dataLogDir",
+            spec.data_log_dir.as_ref().map(|_| "/datalog".to_string()),
+        ),
+        ("clientPort", Some(ports.client.to_string())),
+        ("4lw.commands.whitelist", Some("srvr, mntr".to_string())),
+        ("admin.enableServer", Some("true".to_string())),
+        ("admin.serverPort", Some(ports.admin.to_string())),
+        (
+            "maxClientCnxns",
+            spec.max_client_cnxns.map(|n| n.to_string()),
+        ),
+        (
+            "minSessionTimeout",
+            spec.min_session_timeout.map(|t| t.to_string()),
+        ),
+        (
+            "maxSessionTimeout",
+            spec.max_session_timeout.map(|t| t.to_string()),
+        ),
+        (
+            "globalOutstandingLimit",
+            spec.global_outstanding_limit.map(|n| n.to_string()),
+        ),
+        (
+            "autopurge.snapRetainCount",
+            Some(spec.autopurge.snap_retain_count.unwrap_or(3).to_string()),
+        ),
+        (
+            "autopurge.purgeInterval",
+            Some(spec.autopurge.purge_interval.unwrap_or(24).to_string()),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| Some((key.to_string(), value?)))
+    .chain(
+        cluster
+            .zk
+            .pods()
+            .into_iter()
+            .flatten()
+            .map(|pod| {
+                (
+                    format!("server.{}", pod.zookeeper_id),
+                    format!(
+                        "{}:{}:{};{}",
+                        pod.fqdn(&cluster.cluster_domain),
+                        ports.quorum,
+                        ports.leader_election,
+                        ports.client
+                    ),
+                )
+            })
+            // A lone server runs in standalone mode, since a single-member quorum would only log election errors
+            .filter(|_| !standalone),
+    )
+    .map(|(key, value)| format!("{}={}\n", key, value))
+    .collect()
+}
+
+/// Hashes the servers' configuration, so that changes to it can be detected
+fn config_hash(cluster: &ClusterContext) -> String {
+    let mut hasher = FnvHasher::default();
+    zoo_cfg(cluster).hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// The addresses that clients connect to, as published in the cluster's status
+pub fn build_status(cluster: &ClusterContext) -> ZookeeperClusterStatus {
+    let zk = cluster.zk;
+    ZookeeperClusterStatus {
+        connection_string: zk.hosts(&cluster.cluster_domain),
+        service_endpoint: zk
+            .global_service_fqdn(&cluster.cluster_domain)
+            .map(|fqdn| format!("{}:{}", fqdn, cluster.ports.client)),
+        // Probed separately by the controller, see `monitor::probe_ensemble`
+        ensemble: None,
+    }
+}
+
+/// The `ConfigMap` holding the servers' `zoo.cfg`
+pub fn build_config_map(cluster: &ClusterContext) -> ConfigMap {
+    ConfigMapBuilder::new()
+        .metadata(cluster.metadata(cluster.server_role_name.clone()))
+        .add_data("zoo.cfg", zoo_cfg(cluster))
+        .build()
+        .unwrap()
+}
+
+/// Smallest heap that the servers are started with, anything less is rejected rather than failing at runtime
+const MIN_HEAP_MIB: u64 = 64;
+
+/// The `-Xmx` and extra flags that the servers' JVM is started with
+fn server_jvm_flags(cluster: &ClusterContext) -> Result<Vec<String>, Error> {
+    let spec = &cluster.zk.spec;
+    let heap_bytes = match &spec.jvm.heap {
+        Some(heap) => Some(parse_memory_quantity(heap).with_context(|| InvalidJvmHeap {
+            heap: heap.clone(),
+            zk: cluster.zk_ref.clone(),
+        })?),
+        None => spec
+            .resources
+            .as_ref()
+            .and_then(|resources| resources.limits.as_ref()?.get("memory"))
+            .and_then(|limit| parse_memory_quantity(&limit.0))
+            .map(|limit| limit / 10 * 8),
+    };
+    let heap_mib = heap_bytes.map(|bytes| bytes / 1024 / 1024);
+    if let Some(heap_mib) = heap_mib {
+        ensure!(
+            heap_mib >= MIN_HEAP_MIB,
+            JvmHeapTooSmall {
+                heap_mib,
+                zk: cluster.zk_ref.clone(),
+            }
+        );
+    }
+    let features = [
+        (
+            "zookeeper.extendedTypesEnabled",
+            spec.features.extended_types_enabled,
+        ),
+        (
+            "zookeeper.emulate353TTLNodes",
+            spec.features.emulate_353_ttl_nodes,
+        ),
+        (
+            "zookeeper.snapshot.trust.empty",
+            spec.features.snapshot_trust_empty,
+        ),
+    ];
+    Ok(heap_mib
+        .map(|heap_mib| format!("-Xmx{}m", heap_mib))
+        .into_iter()
+        .chain(
+            features
+                .into_iter()
+                .filter_map(|(property, enabled)| Some(format!("-D{}={}", property, enabled?))),
+        )
+        .chain(spec.jvm.extra_flags.iter().cloned())
+        .collect())
+}
+
+/// The `StatefulSet` running the servers
+pub fn build_statefulset(cluster: &ClusterContext) -> Result<StatefulSet, Error> {
+    let zk = cluster.zk;
+    let mut container_decide_myid = ContainerBuilder::new("decide-myid")
+        .image(cluster.image_options.rewrite(&init_image(&zk.spec.image)))
+        .args(vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            format!(
+                "expr {} + $(echo $POD_NAME | sed 's/.*-//') > /data/myid",
+                1 + zk.spec.myid_offset.unwrap_or(0)
+            ),
+        ])
+        .add_env_vars(vec![EnvVar {
+            name: "POD_NAME".to_string(),
+            value_from: Some(EnvVarSource {
+                field_ref: Some(ObjectFieldSelector {
+                    api_version: Some("v1".to_string()),
+                    field_path: "metadata.name".to_string(),
+                }),
+                ..EnvVarSource::default()
+            }),
+            ..EnvVar::default()
+        }])
+        .add_volume_mount("data", "/data")
+        .build();
+    container_decide_myid.image_pull_policy = zk.spec.image.pull_policy.clone();
+    container_decide_myid.security_context =
+        Some(zk.spec.pod_security.container_security_context());
+    let mut container_zk_builder = ContainerBuilder::new("zookeeper");
+    container_zk_builder
+        .image(cluster.image_options.rewrite(&zk.spec.image.image()))
+        .args(vec![
+            "bin/zkServer.sh".to_string(),
+            "start-foreground".to_string(),
+            "/config/zoo.cfg".to_string(),
+        ])
+        .add_container_port("zk", cluster.ports.client)
+        .add_container_port("zk-leader", cluster.ports.quorum)
+        .add_container_port("zk-election", cluster.ports.leader_election)
+        .add_container_port("admin", cluster.ports.admin)
+        .add_volume_mount("data", "/data")
+        .add_volume_mount("config", "/config");
+    if zk.spec.data_log_dir.is_some() {
+        container_zk_builder.add_volume_mount("datalog", "/datalog");
+    }
+    let server_jvm_flags = server_jvm_flags(cluster)?;
+    if !server_jvm_flags.is_empty() {
+        // zkEnv.sh prepends its own default -Xmx, so ours takes precedence
+        container_zk_builder.add_env_vars(vec![EnvVar {
+            name: "SERVER_JVMFLAGS".to_string(),
+            value: Some(server_jvm_flags.join(" ")),
+            ..EnvVar::default()
+        }]);
+    }
+    let mut container_zk = container_zk_builder.build();
+    container_zk.resources = zk.spec.resources.clone();
+    container_zk.liveness_probe = Some(zk.spec.probes.liveness.apply_to(Probe {
+        http_get: Some(HTTPGetAction {
+            path: Some("/commands/ruok".to_string()),
+            port: IntOrString::String("admin".to_string()),
+            ..HTTPGetAction::default()
+        }),
+        initial_delay_seconds: Some(30),
+        period_seconds: Some(10),
+        timeout_seconds: Some(5),
+        failure_threshold: Some(3),
+        ..Probe::default()
+    }));
+    container_zk.readiness_probe = Some(zk.spec.probes.readiness.apply_to(Probe {
+        exec: Some(ExecAction {
+            command: Some(vec![
+                "bin/zkServer.sh".to_string(),
+                "status".to_string(),
+                "/config/zoo.cfg".to_string(),
+            ]),
+        }),
+        // zkServer.sh starts a JVM to query the server, so this is too slow for the default timeout
+        period_seconds: Some(5),
+        timeout_seconds: Some(10),
+        ..Probe::default()
+    }));
+    container_zk.lifecycle = Some(Lifecycle {
+        pre_stop: Some(Handler {
+            exec: Some(ExecAction {
+                command: Some(vec![
+                    "sh".to_string(),
+                    "-c".to_string(),
+                    // Followers can leave at any time, but the leader should hand over an up-to-date ensemble.
+                    // The admin server is queried with whichever HTTP client the image ships.
+                    format!(
+                        "if command -v curl >/dev/null; then get='curl -s'
+                         elif command -v wget >/dev/null; then get='wget -qO-'
+                         else echo 'preStop needs curl or wget to query the admin server' >&2; exit 1
+                         fi
+                         $get http://localhost:{port}/commands/srvr \
+                           | grep -Eq '\"server_state\" *: *\"leader\"' || exit 0
+                         for i in $(seq {timeout}); do
+                           mntr=$($get http://localhost:{port}/commands/mntr)
+                           followers=$(echo \"$mntr\" | sed -n 's/^ *\"followers\" *: *\\([0-9]*\\).*/\\1/p')
+                           synced=$(echo \"$mntr\" | sed -n 's/^ *\"synced_followers\" *: *\\([0-9]*\\).*/\\1/p')
+                           [ \"$followers\" = \"$synced\" ] && exit 0
+                           sleep 1
+                         done",
+                        port = cluster.ports.admin,
+                        timeout = LEADER_SYNC_TIMEOUT_SECONDS,
+                    ),
+                ]),
+            }),
+            ..Handler::default()
+        }),
+        ..Lifecycle::default()
+    });
+    container_zk.image_pull_policy = zk.spec.image.pull_policy.clone();
+    container_zk.security_context = Some(zk.spec.pod_security.container_security_context());
+    let mut pod_labels = zk.spec.labels.clone();
+    pod_labels.extend(cluster.pod_labels());
+    let scheduling = &zk.spec.scheduling;
+    let mut pod_template = PodTemplateSpec {
+        metadata: Some(ObjectMeta {
+            annotations: Some(cluster.pod_annotations()),
+            labels: Some(pod_labels),
+            ..ObjectMeta::default()
+        }),
+        spec: Some(PodSpec {
+            init_containers: Some(vec![container_decide_myid]),
+            containers: vec![container_zk],
+            volumes: Some(vec![Volume {
+                name: "config".to_string(),
+                config_map: Some(ConfigMapVolumeSource {
+                    name: Some(cluster.server_role_name.clone()),
+                    ..ConfigMapVolumeSource::default()
+                }),
+                ..Volume::default()
+            }]),
+            security_context: Some(zk.spec.pod_security.pod_security_context()),
+            service_account_name: Some(cluster.service_account_name()),
+            image_pull_secrets: zk.spec.image.pull_secret_refs(),
+            node_selector: Some(scheduling.node_selector.clone())
+                .filter(|labels| !labels.is_empty()),
+            tolerations: Some(scheduling.tolerations.clone())
+                .filter(|tolerations| !tolerations.is_empty()),
+            affinity: scheduling.affinity.clone(),
+            topology_spread_constraints: Some(topology_spread_constraints(cluster))
+                .filter(|constraints| !constraints.is_empty()),
+            priority_class_name: zk.spec.priority_class_name.clone(),
+            termination_grace_period_seconds: Some(
+                zk.spec
+                    .termination_grace_period_seconds
+                    .unwrap_or(LEADER_SYNC_TIMEOUT_SECONDS + 30),
+            ),
+            ..PodSpec::default()
+        }),
+    };
+    if let Some(pod_overrides) = &zk.spec.pod_overrides {
+        let mut merged =
+            serde_json::to_value(&pod_template).with_context(|| InvalidPodOverrides {
+                zk: cluster.zk_ref.clone(),
+            })?;
+        merge_json(&mut merged, pod_overrides.clone());
+        pod_template = serde_json::from_value(merged).with_context(|| InvalidPodOverrides {
+            zk: cluster.zk_ref.clone(),
+        })?;
+    }
+    Ok(StatefulSet {
+        metadata: cluster.metadata(cluster.server_role_name.clone()),
+        spec: Some(StatefulSetSpec {
+            pod_management_policy: Some("Parallel".to_string()),
+            replicas: if zk.spec.stopped.unwrap_or(false) {
+                Some(0)
+            } else {
+                zk.spec.replicas
+            },
+            selector: LabelSelector {
+                match_labels: Some(cluster.pod_labels()),
+                ..LabelSelector::default()
+            },
+            service_name: cluster.server_role_name.clone(),
+            template: pod_template,
+            volume_claim_templates: Some(
+                std::iter::once(volume_claim_template("data", &zk.spec.data_dir))
+                    .chain(
+                        zk.spec
+                            .data_log_dir
+                            .as_ref()
+                            .map(|storage| volume_claim_template("datalog", storage)),
+                    )
+                    .collect(),
+            ),
+            ..StatefulSetSpec::default()
+        }),
+        status: None,
+    })
+}
+
+/// Hashes the pod template of `sts`, which only changes when its pods are restarted
+pub fn pod_template_hash(sts: &StatefulSet) -> String {
+    let mut hasher = FnvHasher::default();
+    if let Some(spec) = &sts.spec {
+        // Serializing the template can't fail, it only consists of strings, numbers, and maps with string keys
+        serde_json::to_string(&spec.template)
+            .unwrap_or_default()
+            .hash(&mut hasher);
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+/// The user's topology spread constraints, where those without a label selector select the cluster's servers
+fn topology_spread_constraints(cluster: &ClusterContext) -> Vec<TopologySpreadConstraint> {
+    cluster
+        .zk
+        .spec
+        .scheduling
+        .topology_spread_constraints
+        .iter()
+        .map(|constraint| TopologySpreadConstraint {
+            label_selector: constraint.label_selector.clone().or_else(|| {
+                Some(LabelSelector {
+                    match_labels: Some(cluster.pod_labels()),
+                    ..LabelSelector::default()
+                })
+            }),
+            ..constraint.clone()
+        })
+        .collect()
+}
+
+fn volume_claim_template(name: &str, storage: &StorageConfig) -> PersistentVolumeClaim {
+    PersistentVolumeClaim {
+        metadata: ObjectMeta {
+            name: Some(name.to_string()),
+            ..ObjectMeta::default()
+        },
+        spec: Some(PersistentVolumeClaimSpec {
+            access_modes: Some(vec!["ReadWriteOnce".to_string()]),
+            resources: Some(ResourceRequirements {
+                requests: Some({
+                    let mut map = BTreeMap::new();
+                    map.insert(
+                        "storage".to_string(),
+                        Quantity(
+                            storage
+                                .capacity
+                                .clone()
+                                .unwrap_or_else(|| "1Gi".to_string()),
+                        ),
+                    );
+                    map
+                }),
+                ..ResourceRequirements::default()
+            }),
+            storage_class_name: storage.storage_class_name.clone(),
+            ..PersistentVolumeClaimSpec::default()
+        }),
+        ..PersistentVolumeClaim::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const SIMPLE: &str = r#"
+apiVersion: zookeeper.stackable.tech/v1alpha1
+kind: ZookeeperCluster
+metadata:
+  name: simple
+  namespace: default
+  uid: 0f6b2a5c-1e4d-4b7a-9c3e-8d2f1a0b6c54
+spec:
+  replicas: 3
+"#;
+
+    const STANDALONE: &str = r#"
+apiVersion: zookeeper.stackable.tech/v1alpha1
+kind: ZookeeperCluster
+metadata:
+  name: standalone
+  namespace: default
+  uid: 5d1c9e7a-2b3f-4a6d-8e0c-7f4b3a2d1e98
+spec:
+  replicas: 1
+"#;
+
+    const FULL: &str = r#"
+apiVersion: zookeeper.stackable.tech/v1alpha1
+kind: ZookeeperCluster
+metadata:
+  name: full
+  namespace: zookeeper
+  uid: b7e3f1d2-6c4a-4e8b-a1d5-3c9f0e2b7a16
+spec:
+  replicas: 3
+  myidOffset: 10
+  maxClientCnxns: 100
+  minSessionTimeout: 4000
+  maxSessionTimeout: 60000
+  globalOutstandingLimit: 2000
+  autopurge:
+    snapRetainCount: 5
+    purgeInterval: 1
+  dataDir:
+    capacity: 10Gi
+    storageClassName: fast
+  dataLogDir:
+    capacity: 5Gi
+  resources:
+    limits:
+      memory: 2Gi
+  jvm:
+    extraFlags: ["-XX:+UseG1GC"]
+  probes:
+    liveness:
+      periodSeconds: 20
+  networkIsolation:
+    allowedClients:
+      - namespaceLabels:
+          zookeeper-client: "true"
+  serviceAccount:
+    clusterRole: zookeeper-clusterrole
+  priorityClassName: zookeeper
+  podOverrides:
+    spec:
+      nodeSelector:
+        disk: ssd
+"#;
+
+    fn parse_zk(yaml: &str) -> ZookeeperCluster {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn with_spec(yaml: &str, patch: serde_json::Value) -> ZookeeperCluster {
+        let mut zk = serde_json::to_value(parse_zk(yaml)).unwrap();
+        merge_json(&mut zk, serde_json::json!({ "spec": patch }));
+        serde_json::from_value(zk).unwrap()
+    }
+
+    /// Compares `actual` to the golden file `tests/golden/<name>`
+    ///
+    /// Run the tests with `UPDATE_GOLDEN=1` to regenerate the golden files after an intentional change.
+    fn assert_golden(name: &str, actual: &str) {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden")
+            .join(name);
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, actual).unwrap();
+        }
+        let expected = std::fs::read_to_string(&path)
+            .unwrap_or_else(|err| panic!("failed to read {}: {}", path.display(), err));
+        assert_eq!(actual, expected, "{} differs from its golden file", name);
+    }
+
+    fn pod_spec(sts: &StatefulSet) -> &PodSpec {
+        sts.spec.as_ref().unwrap().template.spec.as_ref().unwrap()
+    }
+
+    #[test]
+    fn zoo_cfg_matches_golden_files() {
+        for (fixture, yaml) in [
+            ("simple", SIMPLE),
+            ("standalone", STANDALONE),
+            ("full", FULL),
+        ] {
+            let zk = parse_zk(yaml);
+            let cluster = ClusterContext::new(&zk, "cluster.local").unwrap();
+            let config_map = build_config_map(&cluster);
+            assert_eq!(
+                config_map.metadata.name,
+                Some(format!("{}-servers", fixture))
+            );
+            assert_golden(
+                &format!("{}/zoo.cfg", fixture),
+                &config_map.data.unwrap()["zoo.cfg"],
+            );
+        }
+    }
+
+    #[test]
+    fn services_select_the_servers() {
+        let zk = parse_zk(SIMPLE);
+        let cluster = ClusterContext::new(&zk, "cluster.local").unwrap();
+        let sts = build_statefulset(&cluster).unwrap();
+        let pod_labels = sts.spec.unwrap().template.metadata.unwrap().labels;
+
+        let global = build_global_service(&cluster);
+        assert_eq!(global.metadata.name.as_deref(), Some("simple"));
+        let global = global.spec.unwrap();
+        assert_eq!(global.type_.as_deref(), Some("NodePort"));
+        assert_eq!(global.ports.unwrap()[0].port, 2181);
+        assert_eq!(global.selector, pod_labels);
+
+        let servers = build_server_role_service(&cluster);
+        assert_eq!(servers.metadata.name.as_deref(), Some("simple-servers"));
+        let servers = servers.spec.unwrap();
+        assert_eq!(servers.cluster_ip.as_deref(), Some("None"));
+        assert_eq!(servers.publish_not_ready_addresses, Some(true));
+        assert_eq!(servers.selector, pod_labels);
+
+        let zk = with_spec(
+            SIMPLE,
+            serde_json::json!({ "serverService": { "publishNotReadyAddresses": false } }),
+        );
+        let cluster = ClusterContext::new(&zk, "cluster.local").unwrap();
+        let servers = build_server_role_service(&cluster).spec.unwrap();
+        assert_eq!(servers.cluster_ip.as_deref(), Some("None"));
+        assert_eq!(servers.publish_not_ready_addresses, Some(false));
+    }
+
+    #[test]
+    fn status_lists_the_client_endpoints() {
+        let zk = with_spec(FULL, serde_json::json!({ "ports": { "client": 12181 } }));
+        let cluster = ClusterContext::new(&zk, "cluster.local").unwrap();
+        assert_eq!(
+            build_status(&cluster),
+            ZookeeperClusterStatus {
+                connection_string: Some(
+                    (0..3)
+                        .map(|i| format!(
+                            "full-servers-{}.full-servers.zookeeper.svc.cluster.local:12181",
+                            i
+                        ))
+                        .collect::<Vec<_>>()
+                        .join(",")
+                ),
+                service_endpoint: Some("full.zookeeper.svc.cluster.local:12181".to_string()),
+                ensemble: None,
+            }
+        );
+    }
+
+    #[test]
+    fn ports_can_be_overridden() {
+        let zk = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "ports": { "client": 12181, "quorum": 12888, "leaderElection": 13888, "admin": 18080 },
+                "networkIsolation": {},
+            }),
+        );
+        let cluster = ClusterContext::new(&zk, "cluster.local").unwrap();
+        let zoo_cfg = zoo_cfg(&cluster);
+        assert!(zoo_cfg.contains("clientPort=12181\n"));
+        assert!(zoo_cfg.contains("admin.serverPort=18080\n"));
+        assert!(zoo_cfg.contains(
+            "server.1=simple-servers-0.simple-servers.default.svc.cluster.local:12888:13888;12181\n"
+        ));
+
+        let sts = build_statefulset(&cluster).unwrap();
+        let container_ports = pod_spec(&sts).containers[0]
+            .ports
+            .iter()
+            .flatten()
+            .map(|port| port.container_port)
+            .collect::<Vec<_>>();
+        assert_eq!(container_ports, [12181, 12888, 13888, 18080]);
+        // The leader waits for its followers through the admin server, rather than with 4lw commands
+        let pre_stop = pod_spec(&sts).containers[0]
+            .lifecycle
+            .as_ref()
+            .and_then(|lifecycle| lifecycle.pre_stop.as_ref())
+            .and_then(|handler| handler.exec.as_ref())
+            .and_then(|exec| exec.command.as_ref())
+            .unwrap()[2]
+            .clone();
+        assert!(pre_stop.contains("http://localhost:18080/commands/srvr"));
+        assert!(pre_stop.contains("http://localhost:18080/commands/mntr"));
+        assert!(!pre_stop.contains("/dev/tcp"));
+        let service = build_global_service(&cluster).spec.unwrap();
+        assert_eq!(service.ports.unwrap()[0].port, 12181);
+        let ingress = build_network_policy(&cluster)
+            .unwrap()
+            .spec
+            .unwrap()
+            .ingress
+            .unwrap();
+        let ports = |rule: &NetworkPolicyIngressRule| {
+            rule.ports
+                .iter()
+                .flatten()
+                .filter_map(|port| port.port.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ports(&ingress[0]),
+            [IntOrString::Int(12181), IntOrString::Int(18080)]
+        );
+        assert_eq!(
+            ports(&ingress[1]),
+            [IntOrString::Int(12888), IntOrString::Int(13888)]
+        );
+    }
+
+    #[test]
+    fn user_labels_and_annotations_are_propagated() {
+        let zk = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "labels": { "team": "coordination", "app.kubernetes.io/name": "mine" },
+                "annotations": { "prometheus.io/scrape": "true" },
+            }),
+        );
+        let cluster = ClusterContext::new(&zk, "cluster.local").unwrap();
+        let config_map = build_config_map(&cluster).metadata;
+        assert_eq!(config_map.labels.unwrap()["team"], "coordination");
+        assert_eq!(
+            config_map.annotations.unwrap()["prometheus.io/scrape"],
+            "true"
+        );
+
+        let sts = build_statefulset(&cluster).unwrap();
+        assert_eq!(sts.metadata.labels.unwrap()["team"], "coordination");
+        let spec = sts.spec.unwrap();
+        let pod = spec.template.metadata.unwrap();
+        let pod_labels = pod.labels.unwrap();
+        assert_eq!(pod_labels["team"], "coordination");
+        // Generated labels take precedence, and the selector is unaffected
+        assert_eq!(pod_labels["app.kubernetes.io/name"], "zookeeper");
+        assert!(!spec.selector.match_labels.unwrap().contains_key("team"));
+        assert_eq!(pod.annotations.unwrap()["prometheus.io/scrape"], "true");
+        assert!(!build_global_service(&cluster)
+            .spec
+            .unwrap()
+            .selector
+            .unwrap()
+            .contains_key("team"));
+    }
+
+    #[test]
+    fn simple_statefulset() {
+        let zk = parse_zk(SIMPLE);
+        let cluster = ClusterContext::new(&zk, "cluster.local").unwrap();
+        let sts = build_statefulset(&cluster).unwrap();
+        assert_eq!(sts.metadata.name.as_deref(), Some("simple-servers"));
+        let spec = sts.spec.as_ref().unwrap();
+        assert_eq!(spec.replicas, Some(3));
+        assert_eq!(spec.service_name, "simple-servers");
+        assert_eq!(
+            spec.selector.match_labels.as_ref(),
+            spec.template.metadata.as_ref().unwrap().labels.as_ref()
+        );
+        let claims = spec.volume_claim_templates.as_ref().unwrap();
+        assert_eq!(claims.len(), 1);
+        assert_eq!(claims[0].metadata.name.as_deref(), Some("data"));
+
+        let pod = pod_spec(&sts);
+        assert_eq!(
+            pod.service_account_name.as_deref(),
+            Some("simple-serviceaccount")
+        );
+        assert_eq!(pod.termination_grace_period_seconds, Some(60));
+        let decide_myid = &pod.init_containers.as_ref().unwrap()[0];
+        assert!(decide_myid.args.as_ref().unwrap()[2].starts_with("expr 1 + "));
+        let zookeeper = &pod.containers[0];
+        assert_eq!(zookeeper.name, "zookeeper");
+        // Nothing but the ZooKeeper image is pulled by default
+        assert_eq!(decide_myid.image, zookeeper.image);
+        assert!(zookeeper
+            .env
+            .iter()
+            .flatten()
+            .all(|env| env.name != "SERVER_JVMFLAGS"));
+        let liveness = zookeeper.liveness_probe.as_ref().unwrap();
+        assert_eq!(liveness.period_seconds, Some(10));
+        assert_eq!(liveness.initial_delay_seconds, Some(30));
+    }
+
+    #[test]
+    fn full_statefulset() {
+        let zk = parse_zk(FULL);
+        let cluster = ClusterContext::new(&zk, "cluster.local").unwrap();
+        let sts = build_statefulset(&cluster).unwrap();
+        let claims = sts
+            .spec
+            .as_ref()
+            .unwrap()
+            .volume_claim_templates
+            .as_ref()
+            .unwrap();
+        let claim_sizes = claims
+            .iter()
+            .map(|claim| {
+                let spec = claim.spec.as_ref().unwrap();
+                (
+                    claim.metadata.name.clone().unwrap(),
+                    spec.resources.as_ref().unwrap().requests.as_ref().unwrap()["storage"]
+                        .0
+                        .clone(),
+                    spec.storage_class_name.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            claim_sizes,
+            [
+                (
+                    "data".to_string(),
+                    "10Gi".to_string(),
+                    Some("fast".to_string())
+                ),
+                ("datalog".to_string(), "5Gi".to_string(), None),
+            ]
+        );
+
+        let pod = pod_spec(&sts);
+        assert_eq!(pod.priority_class_name.as_deref(), Some("zookeeper"));
+        assert_eq!(
+            pod.node_selector,
+            Some(BTreeMap::from([("disk".to_string(), "ssd".to_string())]))
+        );
+        let decide_myid = &pod.init_containers.as_ref().unwrap()[0];
+        assert!(decide_myid.args.as_ref().unwrap()[2].starts_with("expr 11 + "));
+        let zookeeper = &pod.containers[0];
+        // 80% of the 2Gi memory limit
+        let server_jvm_flags = zookeeper
+            .env
+            .iter()
+            .flatten()
+            .find(|env| env.name == "SERVER_JVMFLAGS")
+            .and_then(|env| env.value.as_deref());
+        assert_eq!(server_jvm_flags, Some("-Xmx1638m -XX:+UseG1GC"));
+        assert!(zookeeper
+            .volume_mounts
+            .iter()
+            .flatten()
+            .any(|mount| mount.name == "datalog" && mount.mount_path == "/datalog"));
+        let liveness = zookeeper.liveness_probe.as_ref().unwrap();
+        assert_eq!(liveness.period_seconds, Some(20));
+        assert_eq!(liveness.initial_delay_seconds, Some(30));
+    }
+
+    #[test]
+    fn servers_can_be_stretched_across_zones() {
+        let zk = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "scheduling": {
+                    "nodeSelector": { "zookeeper": "true" },
+                    "tolerations": [{ "key": "dedicated", "operator": "Equal", "value": "zookeeper" }],
+                    "affinity": {
+                        "nodeAffinity": {
+                            "requiredDuringSchedulingIgnoredDuringExecution": {
+                                "nodeSelectorTerms": [{
+                                    "matchExpressions": [{
+                                        "key": "topology.kubernetes.io/zone",
+                                        "operator": "In",
+                                        "values": ["a", "b", "c"],
+                                    }],
+                                }],
+                            },
+                        },
+                    },
+                    "topologySpreadConstraints": [
+                        {
+                            "maxSkew": 1,
+                            "topologyKey": "topology.kubernetes.io/zone",
+                            "whenUnsatisfiable": "DoNotSchedule",
+                        },
+                        {
+                            "maxSkew": 2,
+                            "topologyKey": "kubernetes.io/hostname",
+                            "whenUnsatisfiable": "ScheduleAnyway",
+                            "labelSelector": { "matchLabels": { "app": "other" } },
+                        },
+                    ],
+                },
+                "podOverrides": { "spec": { "nodeSelector": { "disk": "ssd" } } },
+            }),
+        );
+        let cluster = ClusterContext::new(&zk, "cluster.local").unwrap();
+        let sts = build_statefulset(&cluster).unwrap();
+        let pod = pod_spec(&sts);
+        // podOverrides are merged over the scheduling constraints
+        assert_eq!(
+            pod.node_selector,
+            Some(BTreeMap::from([
+                ("disk".to_string(), "ssd".to_string()),
+                ("zookeeper".to_string(), "true".to_string()),
+            ]))
+        );
+        assert_eq!(
+            pod.tolerations.as_ref().unwrap()[0].value.as_deref(),
+            Some("zookeeper")
+        );
+        assert!(pod.affinity.as_ref().unwrap().node_affinity.is_some());
+        let constraints = pod.topology_spread_constraints.as_ref().unwrap();
+        assert_eq!(
+            constraints[0].label_selector.as_ref().unwrap().match_labels,
+            sts.spec.as_ref().unwrap().selector.match_labels
+        );
+        assert_eq!(
+            constraints[1].label_selector.as_ref().unwrap().match_labels,
+            Some(BTreeMap::from([("app".to_string(), "other".to_string())]))
+        );
+
+        let sts =
+            build_statefulset(&ClusterContext::new(&parse_zk(SIMPLE), "cluster.local").unwrap())
+                .unwrap();
+        let pod = pod_spec(&sts);
+        assert_eq!(pod.tolerations, None);
+        assert_eq!(pod.topology_spread_constraints, None);
+    }
+
+    #[test]
+    fn init_image_can_be_overridden() {
+        let zk = with_spec(
+            SIMPLE,
+            serde_json::json!({ "image": { "initImage": "registry.local/busybox:1.34" } }),
+        );
+        let cluster = ClusterContext::new(&zk, "cluster.local").unwrap();
+        let sts = build_statefulset(&cluster).unwrap();
+        let pod = pod_spec(&sts);
+        assert_eq!(
+            pod.init_containers.as_ref().unwrap()[0].image.as_deref(),
+            Some("registry.local/busybox:1.34")
+        );
+        assert_eq!(
+            pod.containers[0].image.as_deref(),
+            Some("docker.stackable.tech/stackable/zookeeper:3.5.8-stackable0")
+        );
+    }
+
+    #[test]
+    fn pod_template_hash_ignores_scaling() {
+        let hash = |zk: &ZookeeperCluster| {
+            let cluster = ClusterContext::new(zk, "cluster.local").unwrap();
+            pod_template_hash(&build_statefulset(&cluster).unwrap())
+        };
+        let simple = hash(&parse_zk(SIMPLE));
+        assert_eq!(
+            hash(&with_spec(SIMPLE, serde_json::json!({ "replicas": 5 }))),
+            simple
+        );
+        assert_ne!(
+            hash(&with_spec(
+                SIMPLE,
+                serde_json::json!({ "image": { "tag": "3.6.3-stackable0" } })
+            )),
+            simple
+        );
+    }
+
+    #[test]
+    fn images_can_be_pulled_from_a_mirror() {
+        let zk = parse_zk(SIMPLE);
+        let mut cluster = ClusterContext::new(&zk, "cluster.local").unwrap();
+        cluster.image_options.image_repository_prefix = Some("mirror.local".to_string());
+        let sts = build_statefulset(&cluster).unwrap();
+        let pod = pod_spec(&sts);
+        for container in pod
+            .containers
+            .iter()
+            .chain(pod.init_containers.iter().flatten())
+        {
+            assert_eq!(
+                container.image.as_deref(),
+                Some("mirror.local/stackable/zookeeper:3.5.8-stackable0")
+            );
+        }
+    }
+
+    #[test]
+    fn config_changes_restart_the_servers() {
+        let pod_annotations = |zk: &ZookeeperCluster| {
+            let cluster = ClusterContext::new(zk, "cluster.local").unwrap();
+            let sts = build_statefulset(&cluster).unwrap();
+            sts.spec
+                .unwrap()
+                .template
+                .metadata
+                .unwrap()
+                .annotations
+                .unwrap()
+        };
+        let zk = parse_zk(SIMPLE);
+        let annotations = pod_annotations(&zk);
+        assert_eq!(pod_annotations(&zk), annotations);
+        assert!(!annotations.contains_key(RESTART_ANNOTATION));
+
+        let changed = with_spec(SIMPLE, serde_json::json!({"maxClientCnxns": 100}));
+        assert_ne!(
+            pod_annotations(&changed)[CONFIG_HASH_ANNOTATION],
+            annotations[CONFIG_HASH_ANNOTATION]
+        );
+
+        let mut restarted = parse_zk(SIMPLE);
+        restarted.metadata.annotations = Some(BTreeMap::from([
+            (
+                RESTART_ANNOTATION.to_string(),
+                "2021-11-01T12:00:00Z".to_string(),
+            ),
+            ("unrelated".to_string(), "value".to_string()),
+        ]));
+        let restarted = pod_annotations(&restarted);
+        assert_eq!(restarted[RESTART_ANNOTATION], "2021-11-01T12:00:00Z");
+        assert!(!restarted.contains_key("unrelated"));
+    }
+
+    #[test]
+    fn server_features_are_passed_to_the_jvm() {
+        let zk = with_spec(
+            SIMPLE,
+            serde_json::json!({
+                "features": { "extendedTypesEnabled": true, "emulate353TTLNodes": false },
+                "jvm": { "extraFlags": ["-Dzookeeper.snapshot.trust.empty=true"] },
+            }),
+        );
+        let cluster = ClusterContext::new(&zk, "cluster.local").unwrap();
+        assert_eq!(
+            server_jvm_flags(&cluster).unwrap(),
+            [
+                "-Dzookeeper.extendedTypesEnabled=true",
+                "-Dzookeeper.emulate353TTLNodes=false",
+                "-Dzookeeper.snapshot.trust.empty=true",
+            ]
+        );
+    }
+
+    #[test]
+    fn stopped_clusters_have_no_replicas() {
+        let zk = with_spec(SIMPLE, serde_json::json!({"stopped": true}));
+        let cluster = ClusterContext::new(&zk, "cluster.local").unwrap();
+        let sts = build_statefulset(&cluster).unwrap();
+        assert_eq!(sts.spec.unwrap().replicas, Some(0));
+    }
+
+    #[test]
+    fn node_drains_are_limited_by_the_disruption_budget() {
+        let zk = parse_zk(SIMPLE);
+        let cluster = ClusterContext::new(&zk, "cluster.local").unwrap();
+        let pdb = build_pod_disruption_budget(&cluster).unwrap();
+        assert_eq!(
+            pdb.metadata.name.as_deref(),
+            Some(cluster.server_role_name.as_str())
+        );
+        let spec = pdb.spec.unwrap();
+        assert_eq!(spec.max_unavailable, Some(IntOrString::Int(1)));
+        assert_eq!(
+            spec.selector.unwrap().match_labels,
+            Some(cluster.pod_labels())
+        );
+        let annotations = build_statefulset(&cluster)
+            .unwrap()
+            .spec
+            .unwrap()
+            .template
+            .metadata
+            .unwrap()
+            .annotations
+            .unwrap();
+        assert!(!annotations.contains_key(SAFE_TO_EVICT_ANNOTATION));
+
+        let zk = with_spec(
+            SIMPLE,
+            serde_json::json!({ "safeToEvict": false, "disruptionBudget": { "enabled": false } }),
+        );
+        let cluster = ClusterContext::new(&zk, "cluster.local").unwrap();
+        assert!(build_pod_disruption_budget(&cluster).is_none());
+        let annotations = build_statefulset(&cluster)
+            .unwrap()
+            .spec
+            .unwrap()
+            .template
+            .metadata
+            .unwrap()
+            .annotations
+            .unwrap();
+        assert_eq!(annotations[SAFE_TO_EVICT_ANNOTATION], "false");
+    }
+
+    #[test]
+    fn optional_objects_are_only_built_when_enabled() {
+        let zk = parse_zk(SIMPLE);
+        let cluster = ClusterContext::new(&zk, "cluster.local").unwrap();
+        assert!(build_role_binding(&cluster).is_none());
+        assert!(build_network_policy(&cluster).is_none());
+
+        let zk = parse_zk(FULL);
+        let cluster = ClusterContext::new(&zk, "cluster.local").unwrap();
+        let role_binding = build_role_binding(&cluster).unwrap();
+        assert_eq!(
+            role_binding.metadata.name.as_deref(),
+            Some("full-serviceaccount")
+        );
+        assert_eq!(role_binding.role_ref.name, "zookeeper-clusterrole");
+        let network_policy = build_network_policy(&cluster).unwrap();
+        assert_eq!(
+            network_policy.metadata.name.as_deref(),
+            Some("full-network-isolation")
+        );
+        let ingress = network_policy.spec.unwrap().ingress.unwrap();
+        assert_eq!(ingress[0].from.as_ref().unwrap().len(), 2);
+        assert_eq!(ingress[1].from.as_ref().unwrap().len(), 1);
+        assert_eq!(ingress[1].ports.as_ref().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn existing_service_accounts_are_only_referenced() {
+        let zk = with_spec(
+            FULL,
+            serde_json::json!({"serviceAccount": {"name": "default"}}),
+        );
+        let cluster = ClusterContext::new(&zk, "cluster.local").unwrap();
+        assert!(build_service_account(&cluster).is_none());
+        let role_binding = build_role_binding(&cluster).unwrap();
+        assert_eq!(
+            role_binding.metadata.name.as_deref(),
+            Some("full-serviceaccount")
+        );
+        assert_eq!(role_binding.subjects.unwrap()[0].name, "default");
+        let pod = build_statefulset(&cluster)
+            .unwrap()
+            .spec
+            .unwrap()
+            .template
+            .spec
+            .unwrap();
+        assert_eq!(pod.service_account_name.as_deref(), Some("default"));
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        let zk = with_spec(SIMPLE, serde_json::json!({"jvm": {"heap": "lots"}}));
+        let cluster = ClusterContext::new(&zk, "cluster.local").unwrap();
+        assert!(matches!(
+            build_statefulset(&cluster),
+            Err(Error::InvalidJvmHeap { heap, .. }) if heap == "lots"
+        ));
+
+        // 80% of the limit would round down to an unusable heap
+        let zk = with_spec(
+            SIMPLE,
+            serde_json::json!({"resources": {"limits": {"memory": "1Mi"}}}),
+        );
+        let cluster = ClusterContext::new(&zk, "cluster.local").unwrap();
+        assert!(matches!(
+            build_statefulset(&cluster),
+            Err(Error::JvmHeapTooSmall { heap_mib: 0, .. })
+        ));
+        let zk = with_spec(SIMPLE, serde_json::json!({"jvm": {"heap": "32Mi"}}));
+        let cluster = ClusterContext::new(&zk, "cluster.local").unwrap();
+        assert!(matches!(
+            build_statefulset(&cluster),
+            Err(Error::JvmHeapTooSmall { heap_mib: 32, .. })
+        ));
+
+        let zk = with_spec(
+            SIMPLE,
+            serde_json::json!({"podOverrides": {"spec": {"containers": "zookeeper"}}}),
+        );
+        let cluster = ClusterContext::new(&zk, "cluster.local").unwrap();
+        assert!(matches!(
+            build_statefulset(&cluster),
+            Err(Error::InvalidPodOverrides { .. })
+        ));
+
+        let mut zk = parse_zk(SIMPLE);
+        zk.metadata.namespace = None;
+        assert!(matches!(
+            ClusterContext::new(&zk, "cluster.local"),
+            Err(Error::ObjectHasNoNamespace { .. })
+        ));
+    }
+}
@@ -1,58 +1,79 @@
 //! Ensures that `Pod`s are configured and running for each [`ZookeeperCluster`]
 
-use std::{collections::BTreeMap, time::Duration};
-
 use crate::{
     crd::ZookeeperCluster,
-    utils::{apply_owned, controller_reference_to_obj},
+    monitor,
+    resources::{
+        self, build_config_map, build_global_service, build_network_policy,
+        build_pod_disruption_budget, build_role_binding, build_server_role_service,
+        build_service_account, build_statefulset, build_status, pod_template_hash, ClusterContext,
+    },
 };
-use snafu::{OptionExt, ResultExt, Snafu};
+use operator_commons::{
+    apply_owned, delete_if_controlled_by, delete_orphans, diff_deleted, diff_owned, error_chain,
+    publish_event, ApplyParams, ImageOptions, RateLimiter, ReconcilePermits,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use snafu::{ensure, ResultExt, Snafu};
 use stackable_operator::{
-    builder::{ConfigMapBuilder, ContainerBuilder},
-    k8s_openapi::{
-        api::{
-            apps::v1::{StatefulSet, StatefulSetSpec},
-            core::v1::{
-                ConfigMapVolumeSource, EnvVar, EnvVarSource, ExecAction, ObjectFieldSelector,
-                PersistentVolumeClaim, PersistentVolumeClaimSpec, PodSpec, PodTemplateSpec, Probe,
-                ResourceRequirements, Service, ServicePort, ServiceSpec, Volume,
-            },
-        },
-        apimachinery::pkg::{api::resource::Quantity, apis::meta::v1::LabelSelector},
+    k8s_openapi::api::{
+        apps::v1::StatefulSet,
+        core::v1::{ConfigMap, Service, ServiceAccount},
+        networking::v1::NetworkPolicy,
+        policy::v1::PodDisruptionBudget,
+        rbac::v1::RoleBinding,
     },
     kube::{
         self,
-        api::ObjectMeta,
+        api::{Patch, PatchParams},
         runtime::{
             controller::{Context, ReconcilerAction},
+            events::{Event, EventType, Recorder, Reporter},
             reflector::ObjectRef,
         },
+        Resource,
     },
-    labels::get_recommended_labels,
 };
+use std::{fmt::Debug, time::Duration};
 
 const FIELD_MANAGER: &str = "zookeeper.stackable.tech/zookeepercluster";
 
 pub struct Ctx {
     pub kube: kube::Client,
+    /// DNS domain of the Kubernetes cluster, such as `cluster.local`
+    pub cluster_domain: String,
+    /// Where the images of the servers are pulled from
+    pub image_options: ImageOptions,
+    /// Only log the changes that would be made, instead of persisting them
+    pub dry_run: bool,
+    /// Bounds the number of reconciles that run at the same time
+    pub reconcile_permits: ReconcilePermits,
+    /// Throttles the writes of managed objects, shared by all controllers
+    pub apply_rate_limiter: RateLimiter,
+    /// Log the diff of each apply at debug level
+    pub log_apply_diffs: bool,
+    /// How often objects are reconciled even if nothing changed
+    pub resync_interval: Duration,
+    /// How often the servers are queried for the ensemble's health
+    pub health_probe_interval: Duration,
+}
+
+impl Ctx {
+    fn apply_params(&self) -> ApplyParams {
+        ApplyParams {
+            field_manager: FIELD_MANAGER.to_string(),
+            dry_run: self.dry_run,
+            log_diffs: self.log_apply_diffs,
+            rate_limiter: Some(self.apply_rate_limiter.clone()),
+        }
+    }
 }
 
 #[derive(Snafu, Debug)]
 #[allow(clippy::enum_variant_names)]
 pub enum Error {
-    #[snafu(display("object {} has no namespace", obj_ref))]
-    ObjectHasNoNamespace {
-        obj_ref: ObjectRef<ZookeeperCluster>,
-    },
-    #[snafu(display("failed to calculate global service name for {}", obj_ref))]
-    GlobalServiceNameNotFound {
-        obj_ref: ObjectRef<ZookeeperCluster>,
-    },
-    #[snafu(display("failed to calculate service name for role {} of {}", role, obj_ref))]
-    RoleServiceNameNotFound {
-        obj_ref: ObjectRef<ZookeeperCluster>,
-        role: String,
-    },
+    #[snafu(display("failed to build objects"))]
+    BuildResources { source: resources::Error },
     #[snafu(display("failed to apply global Service for {}", zk))]
     ApplyGlobalService {
         source: kube::Error,
@@ -70,262 +91,472 @@ pub enum Error {
         zk: ObjectRef<ZookeeperCluster>,
         role: String,
     },
+    #[snafu(display("failed to apply ServiceAccount for {}", zk))]
+    ApplyServiceAccount {
+        source: kube::Error,
+        zk: ObjectRef<ZookeeperCluster>,
+    },
+    #[snafu(display("failed to apply RoleBinding for {}", zk))]
+    ApplyRoleBinding {
+        source: kube::Error,
+        zk: ObjectRef<ZookeeperCluster>,
+    },
+    #[snafu(display("failed to delete RoleBinding for {}", zk))]
+    DeleteRoleBinding {
+        source: kube::Error,
+        zk: ObjectRef<ZookeeperCluster>,
+    },
+    #[snafu(display("failed to apply NetworkPolicy for {}", zk))]
+    ApplyNetworkPolicy {
+        source: kube::Error,
+        zk: ObjectRef<ZookeeperCluster>,
+    },
+    #[snafu(display("failed to delete NetworkPolicy for {}", zk))]
+    DeleteNetworkPolicy {
+        source: kube::Error,
+        zk: ObjectRef<ZookeeperCluster>,
+    },
+    #[snafu(display("failed to apply PodDisruptionBudget for {}", zk))]
+    ApplyPodDisruptionBudget {
+        source: kube::Error,
+        zk: ObjectRef<ZookeeperCluster>,
+    },
+    #[snafu(display("failed to delete PodDisruptionBudget for {}", zk))]
+    DeletePodDisruptionBudget {
+        source: kube::Error,
+        zk: ObjectRef<ZookeeperCluster>,
+    },
+    #[snafu(display("failed to delete orphaned {} objects of {}", kind, zk))]
+    DeleteOrphans {
+        source: kube::Error,
+        zk: ObjectRef<ZookeeperCluster>,
+        kind: String,
+    },
+    #[snafu(display("failed to get current StatefulSet for role {} of {}", role, zk))]
+    GetStatefulSet {
+        source: kube::Error,
+        zk: ObjectRef<ZookeeperCluster>,
+        role: String,
+    },
+    #[snafu(display(
+        "volumes of role {} of {} cannot be changed from [{}] to [{}], delete its StatefulSet \
+         (with --cascade=orphan to keep the pods) to apply the change",
+        role,
+        zk,
+        current,
+        desired
+    ))]
+    ChangedVolumeClaimTemplates {
+        zk: ObjectRef<ZookeeperCluster>,
+        role: String,
+        current: String,
+        desired: String,
+    },
     #[snafu(display("failed to apply StatefulSet for role {} of {}", role, zk))]
     ApplyStatefulSet {
         source: kube::Error,
         zk: ObjectRef<ZookeeperCluster>,
         role: String,
     },
+    #[snafu(display("failed to update status of {}", zk))]
+    UpdateStatus {
+        source: kube::Error,
+        zk: ObjectRef<ZookeeperCluster>,
+    },
+    #[snafu(display("failed to diff {} {}", kind, name))]
+    Diff {
+        source: kube::Error,
+        kind: String,
+        name: String,
+    },
 }
 
+#[tracing::instrument(skip_all, fields(object = %ObjectRef::from_obj(&zk)), err)]
 pub async fn reconcile_zk(
     zk: ZookeeperCluster,
     ctx: Context<Ctx>,
 ) -> Result<ReconcilerAction, Error> {
-    let zk_ref = ObjectRef::from_obj(&zk);
-    let ns = zk
-        .metadata
-        .namespace
-        .as_deref()
-        .with_context(|| ObjectHasNoNamespace {
-            obj_ref: zk_ref.clone(),
-        })?;
-    let kube = ctx.get_ref().kube.clone();
+    let _permit = match ctx.get_ref().reconcile_permits.acquire().await {
+        Some(permit) => permit,
+        // Shutting down, the object is reconciled again by the next instance of the operator
+        None => {
+            return Ok(ReconcilerAction {
+                requeue_after: None,
+            })
+        }
+    };
+    let recorder = Recorder::new(
+        ctx.get_ref().kube.clone(),
+        Reporter {
+            controller: FIELD_MANAGER.to_string(),
+            instance: None,
+        },
+        zk.object_ref(&()),
+    );
+    let result = reconcile_zk_resources(&zk, ctx.get_ref(), &recorder).await;
+    // Events are only written for changes that are actually made
+    if ctx.get_ref().dry_run {
+        return result;
+    }
+    if let Err(err) = &result {
+        publish_event(
+            &recorder,
+            Event {
+                type_: EventType::Warning,
+                reason: "ReconcileFailed".to_string(),
+                note: Some(error_chain(err)),
+                action: "Reconcile".to_string(),
+                secondary: None,
+            },
+        )
+        .await;
+    }
+    result
+}
 
-    let global_svc_name = zk
-        .global_service_name()
-        .with_context(|| RoleServiceNameNotFound {
-            obj_ref: zk_ref.clone(),
+/// Names of the `StatefulSet`'s volume claim templates, which cannot be changed once it has been created
+fn claim_template_names(sts: &StatefulSet) -> Vec<String> {
+    sts.spec
+        .iter()
+        .flat_map(|spec| spec.volume_claim_templates.iter().flatten())
+        .filter_map(|claim| claim.metadata.name.clone())
+        .collect()
+}
+
+/// The servers' live `StatefulSet`, if any, after checking that `sts` can be applied to it
+///
+/// The volume claim templates of a `StatefulSet` are immutable, so changing them is rejected up front.
+async fn live_statefulset(
+    kube: &kube::Client,
+    cluster: &ClusterContext<'_>,
+    sts: &StatefulSet,
+) -> Result<Option<StatefulSet>, Error> {
+    let old_sts = match kube::Api::<StatefulSet>::namespaced(kube.clone(), &cluster.namespace)
+        .get(&cluster.server_role_name)
+        .await
+    {
+        Ok(sts) => sts,
+        Err(kube::Error::Api(err)) if err.code == 404 => return Ok(None),
+        Err(err) => {
+            return Err(err).with_context(|| GetStatefulSet {
+                role: "servers",
+                zk: cluster.zk_ref.clone(),
+            })
+        }
+    };
+    let (current, desired) = (claim_template_names(&old_sts), claim_template_names(sts));
+    ensure!(
+        current == desired,
+        ChangedVolumeClaimTemplates {
+            zk: cluster.zk_ref.clone(),
+            role: "servers",
+            current: current.join(", "),
+            desired: desired.join(", "),
+        }
+    );
+    Ok(Some(old_sts))
+}
+
+async fn reconcile_zk_resources(
+    zk: &ZookeeperCluster,
+    ctx: &Ctx,
+    recorder: &Recorder,
+) -> Result<ReconcilerAction, Error> {
+    let zk_ref = ObjectRef::from_obj(zk);
+    let kube = ctx.kube.clone();
+    let params = &ctx.apply_params();
+    let mut cluster = ClusterContext::new(zk, &ctx.cluster_domain).context(BuildResources)?;
+    cluster.image_options = ctx.image_options.clone();
+    let ns = cluster.namespace.as_str();
+    let zk_owner_ref = &cluster.owner_ref;
+    let global_svc_name = &cluster.global_service_name;
+    let role_svc_servers_name = &cluster.server_role_name;
+    // Build the StatefulSet up front, so that invalid settings are rejected before anything is changed
+    let sts = build_statefulset(&cluster).context(BuildResources)?;
+    let old_sts = live_statefulset(&kube, &cluster, &sts).await?;
+
+    apply_owned(&kube, params, &build_global_service(&cluster))
+        .await
+        .with_context(|| ApplyGlobalService { zk: zk_ref.clone() })?;
+    apply_owned(&kube, params, &build_server_role_service(&cluster))
+        .await
+        .with_context(|| ApplyRoleService {
             role: "servers",
+            zk: zk_ref.clone(),
         })?;
-    let role_svc_servers_name =
-        zk.server_role_service_name()
-            .with_context(|| RoleServiceNameNotFound {
-                obj_ref: zk_ref.clone(),
-                role: "servers",
-            })?;
-    let zk_owner_ref = controller_reference_to_obj(&zk);
-    let pod_labels = get_recommended_labels(&zk, "zookeeper", "3.7.0", "servers", "servers");
-    apply_owned(
+    let service_account_name = cluster.service_account_name();
+    if let Some(service_account) = build_service_account(&cluster) {
+        apply_owned(&kube, params, &service_account)
+            .await
+            .with_context(|| ApplyServiceAccount { zk: zk_ref.clone() })?;
+    }
+    let role_binding_name = cluster.role_binding_name();
+    if let Some(role_binding) = build_role_binding(&cluster) {
+        apply_owned(&kube, params, &role_binding)
+            .await
+            .with_context(|| ApplyRoleBinding { zk: zk_ref.clone() })?;
+    } else {
+        delete_if_controlled_by::<RoleBinding>(&kube, params, ns, &role_binding_name, zk_owner_ref)
+            .await
+            .with_context(|| DeleteRoleBinding { zk: zk_ref.clone() })?;
+    }
+    let network_policy_name = cluster.network_policy_name();
+    if let Some(network_policy) = build_network_policy(&cluster) {
+        apply_owned(&kube, params, &network_policy)
+            .await
+            .with_context(|| ApplyNetworkPolicy { zk: zk_ref.clone() })?;
+    } else {
+        delete_if_controlled_by::<NetworkPolicy>(
+            &kube,
+            params,
+            ns,
+            &network_policy_name,
+            zk_owner_ref,
+        )
+        .await
+        .with_context(|| DeleteNetworkPolicy { zk: zk_ref.clone() })?;
+    }
+    if let Some(pod_disruption_budget) = build_pod_disruption_budget(&cluster) {
+        apply_owned(&kube, params, &pod_disruption_budget)
+            .await
+            .with_context(|| ApplyPodDisruptionBudget { zk: zk_ref.clone() })?;
+    } else {
+        delete_if_controlled_by::<PodDisruptionBudget>(
+            &kube,
+            params,
+            ns,
+            role_svc_servers_name,
+            zk_owner_ref,
+        )
+        .await
+        .with_context(|| DeletePodDisruptionBudget { zk: zk_ref.clone() })?;
+    }
+    apply_owned(&kube, params, &build_config_map(&cluster))
+        .await
+        .with_context(|| ApplyRoleConfig {
+            role: "servers",
+            zk: zk_ref.clone(),
+        })?;
+    let sts = apply_owned(&kube, params, &sts)
+        .await
+        .with_context(|| ApplyStatefulSet {
+            role: "servers",
+            zk: zk_ref.clone(),
+        })?;
+    if let Some(old_sts) = old_sts.filter(|_| !ctx.dry_run) {
+        let replicas = |sts: &StatefulSet| sts.spec.as_ref().and_then(|spec| spec.replicas);
+        if replicas(&old_sts) != replicas(&sts) {
+            publish_event(
+                recorder,
+                Event {
+                    type_: EventType::Normal,
+                    reason: "Scaled".to_string(),
+                    note: Some(format!(
+                        "Scaled role servers from {} to {} replicas",
+                        replicas(&old_sts).unwrap_or(1),
+                        replicas(&sts).unwrap_or(1)
+                    )),
+                    action: "Scale".to_string(),
+                    secondary: None,
+                },
+            )
+            .await;
+        } else if pod_template_hash(&old_sts) != pod_template_hash(&sts) {
+            publish_event(
+                recorder,
+                Event {
+                    type_: EventType::Normal,
+                    reason: "RollingRestart".to_string(),
+                    note: Some(
+                        "Configuration of role servers changed, restarting its pods".to_string(),
+                    ),
+                    action: "Restart".to_string(),
+                    secondary: None,
+                },
+            )
+            .await;
+        }
+    }
+
+    let selector = &cluster.cluster_selector();
+    delete_orphans::<StatefulSet>(
         &kube,
-        FIELD_MANAGER,
-        &Service {
-            metadata: ObjectMeta {
-                name: Some(global_svc_name.clone()),
-                namespace: Some(ns.to_string()),
-                owner_references: Some(vec![zk_owner_ref.clone()]),
-                ..ObjectMeta::default()
-            },
-            spec: Some(ServiceSpec {
-                ports: Some(vec![ServicePort {
-                    name: Some("zk".to_string()),
-                    port: 2181,
-                    protocol: Some("TCP".to_string()),
-                    ..ServicePort::default()
-                }]),
-                selector: Some(pod_labels.clone()),
-                type_: Some("NodePort".to_string()),
-                ..ServiceSpec::default()
-            }),
-            status: None,
-        },
+        params,
+        ns,
+        zk_owner_ref,
+        selector,
+        &[role_svc_servers_name],
     )
     .await
-    .with_context(|| ApplyGlobalService { zk: zk_ref.clone() })?;
-    apply_owned(
+    .with_context(|| DeleteOrphans {
+        zk: zk_ref.clone(),
+        kind: "StatefulSet",
+    })?;
+    delete_orphans::<Service>(
         &kube,
-        FIELD_MANAGER,
-        &Service {
-            metadata: ObjectMeta {
-                name: Some(role_svc_servers_name.clone()),
-                namespace: Some(ns.to_string()),
-                owner_references: Some(vec![zk_owner_ref.clone()]),
-                ..ObjectMeta::default()
-            },
-            spec: Some(ServiceSpec {
-                cluster_ip: Some("None".to_string()),
-                ports: Some(vec![ServicePort {
-                    name: Some("zk".to_string()),
-                    port: 2181,
-                    protocol: Some("TCP".to_string()),
-                    ..ServicePort::default()
-                }]),
-                selector: Some(pod_labels.clone()),
-                publish_not_ready_addresses: Some(true),
-                ..ServiceSpec::default()
-            }),
-            status: None,
-        },
+        params,
+        ns,
+        zk_owner_ref,
+        selector,
+        &[global_svc_name, role_svc_servers_name],
     )
     .await
-    .with_context(|| ApplyRoleService {
-        role: "servers",
+    .with_context(|| DeleteOrphans {
         zk: zk_ref.clone(),
+        kind: "Service",
     })?;
-    apply_owned(
+    delete_orphans::<ConfigMap>(
         &kube,
-        FIELD_MANAGER,
-        &ConfigMapBuilder::new()
-            .metadata(ObjectMeta {
-                name: Some(role_svc_servers_name.clone()),
-                namespace: Some(ns.to_string()),
-                owner_references: Some(vec![zk_owner_ref.clone()]),
-                ..ObjectMeta::default()
-            })
-            .add_data(
-                "zoo.cfg",
-                format!(
-                    "
-tickTime=2000
-initLimit=10
-syncLimit=5
-dataDir=/data
-clientPort=2181
-{}
-",
-                    zk.pods()
-                        .unwrap()
-                        .into_iter()
-                        .map(|pod| format!(
-                            "server.{}={}:2888:3888;2181",
-                            pod.zookeeper_id,
-                            pod.fqdn()
-                        ))
-                        .collect::<Vec<_>>()
-                        .join("\n")
-                ),
-            )
-            .build()
-            .unwrap(),
+        params,
+        ns,
+        zk_owner_ref,
+        selector,
+        &[role_svc_servers_name],
     )
     .await
-    .with_context(|| ApplyRoleConfig {
-        role: "servers",
+    .with_context(|| DeleteOrphans {
         zk: zk_ref.clone(),
+        kind: "ConfigMap",
     })?;
-    let container_decide_myid = ContainerBuilder::new("decide-myid")
-        .image("alpine")
-        .args(vec![
-            "sh".to_string(),
-            "-c".to_string(),
-            "expr 1 + $(echo $POD_NAME | sed 's/.*-//') > /data/myid".to_string(),
-        ])
-        .add_env_vars(vec![EnvVar {
-            name: "POD_NAME".to_string(),
-            value_from: Some(EnvVarSource {
-                field_ref: Some(ObjectFieldSelector {
-                    api_version: Some("v1".to_string()),
-                    field_path: "metadata.name".to_string(),
-                }),
-                ..EnvVarSource::default()
-            }),
-            ..EnvVar::default()
-        }])
-        .add_volume_mount("data", "/data")
-        .build();
-    let mut container_zk = ContainerBuilder::new("zookeeper")
-        .image("docker.stackable.tech/stackable/zookeeper:3.5.8-stackable0")
-        .args(vec![
-            "bin/zkServer.sh".to_string(),
-            "start-foreground".to_string(),
-            "/config/zoo.cfg".to_string(),
-        ])
-        .add_container_port("zk", 2181)
-        .add_container_port("zk-leader", 2888)
-        .add_container_port("zk-election", 3888)
-        .add_volume_mount("data", "/data")
-        .add_volume_mount("config", "/config")
-        .build();
-    container_zk.readiness_probe = Some(Probe {
-        exec: Some(ExecAction {
-            command: Some(vec![
-                "sh".to_string(),
-                "-c".to_string(),
-                "exec 3<>/dev/tcp/localhost/2181 && echo srvr >&3 && grep '^Mode: ' <&3"
-                    .to_string(),
-            ]),
-        }),
-        period_seconds: Some(1),
-        ..Probe::default()
-    });
-    apply_owned(
+    // Earlier versions also generated the account named by `serviceAccount.name`, which the pods still run as
+    delete_orphans::<ServiceAccount>(
         &kube,
-        FIELD_MANAGER,
-        &StatefulSet {
-            metadata: ObjectMeta {
-                name: Some(role_svc_servers_name.clone()),
-                namespace: Some(ns.to_string()),
-                owner_references: Some(vec![zk_owner_ref.clone()]),
-                ..ObjectMeta::default()
-            },
-            spec: Some(StatefulSetSpec {
-                pod_management_policy: Some("Parallel".to_string()),
-                replicas: if zk.spec.stopped.unwrap_or(false) {
-                    Some(0)
-                } else {
-                    zk.spec.replicas
-                },
-                selector: LabelSelector {
-                    match_labels: Some(pod_labels.clone()),
-                    ..LabelSelector::default()
-                },
-                service_name: role_svc_servers_name.clone(),
-                template: PodTemplateSpec {
-                    metadata: Some(ObjectMeta {
-                        labels: Some(pod_labels.clone()),
-                        ..ObjectMeta::default()
-                    }),
-                    spec: Some(PodSpec {
-                        init_containers: Some(vec![container_decide_myid]),
-                        containers: vec![container_zk],
-                        volumes: Some(vec![Volume {
-                            name: "config".to_string(),
-                            config_map: Some(ConfigMapVolumeSource {
-                                name: Some(role_svc_servers_name.clone()),
-                                ..ConfigMapVolumeSource::default()
-                            }),
-                            ..Volume::default()
-                        }]),
-                        ..PodSpec::default()
-                    }),
-                },
-                volume_claim_templates: Some(vec![PersistentVolumeClaim {
-                    metadata: ObjectMeta {
-                        name: Some("data".to_string()),
-                        ..ObjectMeta::default()
-                    },
-                    spec: Some(PersistentVolumeClaimSpec {
-                        access_modes: Some(vec!["ReadWriteOnce".to_string()]),
-                        resources: Some(ResourceRequirements {
-                            requests: Some({
-                                let mut map = BTreeMap::new();
-                                map.insert("storage".to_string(), Quantity("1Gi".to_string()));
-                                map
-                            }),
-                            ..ResourceRequirements::default()
-                        }),
-                        ..PersistentVolumeClaimSpec::default()
-                    }),
-                    ..PersistentVolumeClaim::default()
-                }]),
-                ..StatefulSetSpec::default()
-            }),
-            status: None,
-        },
+        params,
+        ns,
+        zk_owner_ref,
+        selector,
+        &[&service_account_name],
     )
     .await
-    .with_context(|| ApplyStatefulSet {
-        role: "servers",
+    .with_context(|| DeleteOrphans {
         zk: zk_ref.clone(),
+        kind: "ServiceAccount",
     })?;
+    delete_orphans::<RoleBinding>(
+        &kube,
+        params,
+        ns,
+        zk_owner_ref,
+        selector,
+        &[&role_binding_name],
+    )
+    .await
+    .with_context(|| DeleteOrphans {
+        zk: zk_ref.clone(),
+        kind: "RoleBinding",
+    })?;
+    delete_orphans::<NetworkPolicy>(
+        &kube,
+        params,
+        ns,
+        zk_owner_ref,
+        selector,
+        &[&network_policy_name],
+    )
+    .await
+    .with_context(|| DeleteOrphans {
+        zk: zk_ref.clone(),
+        kind: "NetworkPolicy",
+    })?;
+    let mut status = build_status(&cluster);
+    status.ensemble = Some(monitor::probe_ensemble(&cluster).await);
+    // The global Service is named like the cluster itself
+    kube::Api::<ZookeeperCluster>::namespaced(kube.clone(), ns)
+        .patch_status(
+            global_svc_name,
+            &PatchParams {
+                dry_run: ctx.dry_run,
+                ..PatchParams::default()
+            },
+            &Patch::Merge(serde_json::json!({ "status": status })),
+        )
+        .await
+        .with_context(|| UpdateStatus { zk: zk_ref.clone() })?;
 
     Ok(ReconcilerAction {
-        requeue_after: None,
+        requeue_after: Some(ctx.resync_interval.min(ctx.health_probe_interval)),
     })
 }
 
-pub fn error_policy(_error: &Error, _ctx: Context<Ctx>) -> ReconcilerAction {
-    ReconcilerAction {
-        requeue_after: Some(Duration::from_secs(5)),
+/// Appends how applying `obj` would change the live object to `diff`
+async fn diff_applied<K>(kube: &kube::Client, obj: &K, diff: &mut String) -> Result<(), Error>
+where
+    K: Resource<DynamicType = ()> + Serialize + DeserializeOwned + Clone + Debug,
+{
+    diff.push_str(
+        &diff_owned(kube, FIELD_MANAGER, obj)
+            .await
+            .with_context(|| Diff {
+                kind: K::kind(&()),
+                name: obj.meta().name.clone().unwrap_or_default(),
+            })?,
+    );
+    Ok(())
+}
+
+/// Appends how deleting the object `name` would change it to `diff`
+async fn diff_removed<K>(
+    kube: &kube::Client,
+    ns: &str,
+    name: &str,
+    diff: &mut String,
+) -> Result<(), Error>
+where
+    K: Resource<DynamicType = ()> + Serialize + DeserializeOwned + Clone + Debug,
+{
+    diff.push_str(
+        &diff_deleted::<K>(kube, ns, name)
+            .await
+            .with_context(|| Diff {
+                kind: K::kind(&()),
+                name,
+            })?,
+    );
+    Ok(())
+}
+
+/// Shows how reconciling `zk` would change its objects, without changing anything
+pub async fn diff_zk(
+    kube: &kube::Client,
+    zk: &ZookeeperCluster,
+    cluster_domain: &str,
+    image_options: &ImageOptions,
+) -> Result<String, Error> {
+    let mut cluster = ClusterContext::new(zk, cluster_domain).context(BuildResources)?;
+    cluster.image_options = image_options.clone();
+    let ns = cluster.namespace.as_str();
+    let sts = build_statefulset(&cluster).context(BuildResources)?;
+    live_statefulset(kube, &cluster, &sts).await?;
+
+    let mut diff = String::new();
+    diff_applied(kube, &build_global_service(&cluster), &mut diff).await?;
+    diff_applied(kube, &build_server_role_service(&cluster), &mut diff).await?;
+    if let Some(service_account) = build_service_account(&cluster) {
+        diff_applied(kube, &service_account, &mut diff).await?;
+    }
+    match build_role_binding(&cluster) {
+        Some(role_binding) => diff_applied(kube, &role_binding, &mut diff).await?,
+        None => {
+            diff_removed::<RoleBinding>(kube, ns, &cluster.role_binding_name(), &mut diff).await?
+        }
+    }
+    match build_network_policy(&cluster) {
+        Some(network_policy) => diff_applied(kube, &network_policy, &mut diff).await?,
+        None => {
+            diff_removed::<NetworkPolicy>(kube, ns, &cluster.network_policy_name(), &mut diff)
+                .await?
+        }
+    }
+    match build_pod_disruption_budget(&cluster) {
+        Some(pod_disruption_budget) => {
+            diff_applied(kube, &pod_disruption_budget, &mut diff).await?
+        }
+        None => {
+            diff_removed::<PodDisruptionBudget>(kube, ns, &cluster.server_role_name, &mut diff)
+                .await?
+        }
     }
+    diff_applied(kube, &build_config_map(&cluster), &mut diff).await?;
+    diff_applied(kube, &sts, &mut diff).await?;
+    Ok(diff)
 }
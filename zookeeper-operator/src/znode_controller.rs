@@ -3,15 +3,18 @@
 use std::{convert::Infallible, time::Duration};
 
 use crate::{
-    crd::{ZookeeperCluster, ZookeeperClusterRef, ZookeeperZnode},
-    utils::{apply_owned, controller_reference_to_obj},
+    crd::{ZookeeperCluster, ZookeeperClusterRef, ZookeeperZnode, ZookeeperZnodeStatus},
+    ports::ZookeeperPorts,
 };
-use snafu::{OptionExt, ResultExt, Snafu};
+use operator_commons::{
+    apply_owned, controller_reference_to_obj, ApplyParams, RateLimiter, ReconcilePermits,
+};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
 use stackable_operator::{
     k8s_openapi::api::core::v1::ConfigMap,
     kube::{
         self,
-        api::ObjectMeta,
+        api::{ObjectMeta, Patch, PatchParams},
         runtime::{
             controller::{Context, ReconcilerAction},
             finalizer,
@@ -24,6 +27,16 @@ const FIELD_MANAGER: &str = "zookeeper.stackable.tech/zookeeperznode";
 
 pub struct Ctx {
     pub kube: kube::Client,
+    /// DNS domain of the Kubernetes cluster, such as `cluster.local`
+    pub cluster_domain: String,
+    /// Bounds the number of reconciles that run at the same time
+    pub reconcile_permits: ReconcilePermits,
+    /// Throttles the writes of managed objects, shared by all controllers
+    pub apply_rate_limiter: RateLimiter,
+    /// Log the diff of each apply at debug level
+    pub log_apply_diffs: bool,
+    /// How often objects are reconciled even if nothing changed
+    pub resync_interval: Duration,
 }
 
 #[derive(Snafu, Debug)]
@@ -41,6 +54,15 @@ pub enum Error {
         source: kube::Error,
         obj_ref: ObjectRef<ZookeeperCluster>,
     },
+    #[snafu(display(
+        "chroot {:?} of {} must be an absolute ZNode path, such as /hdfs",
+        chroot,
+        znode
+    ))]
+    InvalidChroot {
+        chroot: String,
+        znode: ObjectRef<ZookeeperZnode>,
+    },
     #[snafu(display("failed to calculate FQDN for {}", zk))]
     NoZkFqdn { zk: ObjectRef<ZookeeperCluster> },
     #[snafu(display("failed to ensure that ZNode {} exists in {}", znode_path, zk))]
@@ -60,6 +82,11 @@ pub enum Error {
         source: kube::Error,
         obj_ref: ObjectRef<ConfigMap>,
     },
+    #[snafu(display("failed to update status of {}", obj_ref))]
+    UpdateStatus {
+        source: kube::Error,
+        obj_ref: ObjectRef<ZookeeperZnode>,
+    },
     Finalizer {
         source: finalizer::Error<Infallible>,
     },
@@ -83,6 +110,7 @@ impl Error {
     }
 }
 
+#[tracing::instrument(skip_all, fields(object = %ObjectRef::from_obj(&znode)), err)]
 pub async fn reconcile_znode(
     znode: ZookeeperZnode,
     ctx: Context<Ctx>,
@@ -101,17 +129,40 @@ pub async fn reconcile_znode(
         }
         .fail();
     };
+    let _permit = match ctx.get_ref().reconcile_permits.acquire().await {
+        Some(permit) => permit,
+        // Shutting down, the object is reconciled again by the next instance of the operator
+        None => {
+            return Ok(ReconcilerAction {
+                requeue_after: None,
+            })
+        }
+    };
     let kube = ctx.get_ref().kube.clone();
+    let cluster_domain = &ctx.get_ref().cluster_domain;
     let znodes = kube::Api::<ZookeeperZnode>::namespaced(kube.clone(), &ns);
 
     let zk = find_zk_of_znode(&kube, &znode).await?;
-    let zk_port = 2181;
-    let znode_path = format!("/znode-{}", uid);
+    let zk_port = ZookeeperPorts::new(&zk.spec.ports).client;
+    let znode_path = match &znode.spec.chroot {
+        Some(chroot) => {
+            ensure!(
+                is_valid_chroot(chroot),
+                InvalidChroot {
+                    chroot,
+                    znode: ObjectRef::from_obj(&znode),
+                }
+            );
+            chroot.clone()
+        }
+        None => format!("/znode-{}", uid),
+    };
     let zk_mgmt_addr = format!(
         "{}:{}",
-        zk.global_service_fqdn().with_context(|| NoZkFqdn {
-            zk: ObjectRef::from_obj(&zk),
-        })?,
+        zk.global_service_fqdn(cluster_domain)
+            .with_context(|| NoZkFqdn {
+                zk: ObjectRef::from_obj(&zk),
+            })?,
         zk_port,
     );
 
@@ -129,13 +180,8 @@ pub async fn reconcile_znode(
                             znode_path: &znode_path,
                         })?;
 
-                    let mut znode_conn_str = zk
-                        .pods()
-                        .unwrap()
-                        .map(|pod| format!("{}:{}", pod.fqdn(), zk_port))
-                        .collect::<Vec<_>>()
-                        .join(",");
-                    znode_conn_str.push_str(&znode_path);
+                    let hosts = zk.hosts(cluster_domain).unwrap();
+                    let znode_conn_str = format!("{}{}", hosts, znode_path);
 
                     let discovery_cm = ConfigMap {
                         metadata: ObjectMeta {
@@ -144,16 +190,44 @@ pub async fn reconcile_znode(
                             owner_references: Some(vec![controller_reference_to_obj(&znode)]),
                             ..ObjectMeta::default()
                         },
-                        data: Some([("ZOOKEEPER_BROKERS".to_string(), znode_conn_str)].into()),
+                        data: Some(
+                            [
+                                ("ZOOKEEPER_BROKERS".to_string(), znode_conn_str.clone()),
+                                ("ZOOKEEPER_HOSTS".to_string(), hosts),
+                                ("ZOOKEEPER_CHROOT".to_string(), znode_path.clone()),
+                            ]
+                            .into(),
+                        ),
                         ..ConfigMap::default()
                     };
-                    apply_owned(&kube, FIELD_MANAGER, &discovery_cm)
+                    // ZookeeperZnodes are not reconciled in dry-run mode at all
+                    let params = ApplyParams {
+                        field_manager: FIELD_MANAGER.to_string(),
+                        dry_run: false,
+                        log_diffs: ctx.get_ref().log_apply_diffs,
+                        rate_limiter: Some(ctx.get_ref().apply_rate_limiter.clone()),
+                    };
+                    apply_owned(&kube, &params, &discovery_cm)
                         .await
                         .context(ApplyConfigMap {
                             obj_ref: ObjectRef::from_obj(&discovery_cm),
                         })?;
+                    znodes
+                        .patch_status(
+                            &name,
+                            &PatchParams::default(),
+                            &Patch::Merge(serde_json::json!({
+                                "status": ZookeeperZnodeStatus {
+                                    connection_string: Some(znode_conn_str),
+                                },
+                            })),
+                        )
+                        .await
+                        .context(UpdateStatus {
+                            obj_ref: ObjectRef::from_obj(&znode),
+                        })?;
                     Ok(ReconcilerAction {
-                        requeue_after: None,
+                        requeue_after: Some(ctx.get_ref().resync_interval),
                     })
                 }
                 finalizer::Event::Cleanup(_znode) => {
@@ -174,6 +248,16 @@ pub async fn reconcile_znode(
     .map_err(Error::extract_finalizer_err)
 }
 
+/// Whether `chroot` is an absolute path of a ZNode below the root
+fn is_valid_chroot(chroot: &str) -> bool {
+    match chroot.strip_prefix('/') {
+        Some(path) => path
+            .split('/')
+            .all(|segment| !matches!(segment, "" | "." | "..")),
+        None => false,
+    }
+}
+
 async fn find_zk_of_znode(
     kube: &kube::Client,
     znode: &ZookeeperZnode,
@@ -195,12 +279,6 @@ async fn find_zk_of_znode(
     }
 }
 
-pub fn error_policy(_error: &Error, _ctx: Context<Ctx>) -> ReconcilerAction {
-    ReconcilerAction {
-        requeue_after: Some(Duration::from_secs(5)),
-    }
-}
-
 mod znode_mgmt {
     use futures::compat::Future01CompatExt;
     use snafu::{OptionExt, ResultExt, Snafu};
@@ -269,34 +347,40 @@ mod znode_mgmt {
         Ok(zk)
     }
 
+    /// Creates the ZNode `path`, along with any missing parents
     #[tracing::instrument]
     pub async fn ensure_znode_exists(addr: &str, path: &str) -> Result<(), Error> {
-        let zk = connect(addr).await?;
-        let (_zk, create_res) = zk
-            .create(
-                path,
-                vec![],
-                vec![Acl {
-                    perms: Permission::ALL,
-                    scheme: "world".to_string(),
-                    id: "anyone".to_string(),
-                }],
-                tokio_zookeeper::CreateMode::Persistent,
-            )
-            .compat()
-            .await
-            .context(CreateZnodeProtocol { path })?;
-        match create_res {
-            Ok(_) => {
-                tracing::info!("Created ZNode");
-                Ok(())
-            }
-            Err(tokio_zookeeper::error::Create::NodeExists) => {
-                tracing::info!("ZNode already exists, ignoring...");
-                Ok(())
+        let mut zk = connect(addr).await?;
+        let ancestors = path
+            .match_indices('/')
+            .skip(1)
+            .map(|(i, _)| &path[..i])
+            .chain([path]);
+        for curr_path in ancestors {
+            let (zk2, create_res) = zk
+                .create(
+                    curr_path,
+                    vec![],
+                    vec![Acl {
+                        perms: Permission::ALL,
+                        scheme: "world".to_string(),
+                        id: "anyone".to_string(),
+                    }],
+                    tokio_zookeeper::CreateMode::Persistent,
+                )
+                .compat()
+                .await
+                .context(CreateZnodeProtocol { path: curr_path })?;
+            zk = zk2;
+            match create_res {
+                Ok(_) => tracing::info!(path = curr_path, "Created ZNode"),
+                Err(tokio_zookeeper::error::Create::NodeExists) => {
+                    tracing::info!(path = curr_path, "ZNode already exists, ignoring...")
+                }
+                Err(err) => return Err(err).context(CreateZnode { path: curr_path }),
             }
-            Err(err) => Err(err).context(CreateZnode { path }),
         }
+        Ok(())
     }
 
     #[tracing::instrument]
@@ -359,3 +443,24 @@ mod znode_mgmt {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::is_valid_chroot;
+
+    #[test]
+    fn chroots_must_be_absolute_paths() {
+        assert!(is_valid_chroot("/hdfs"));
+        assert!(is_valid_chroot("/hdfs/production"));
+        for chroot in [
+            "",
+            "/",
+            "hdfs",
+            "/hdfs/",
+            "/hdfs//production",
+            "/hdfs/../kafka",
+        ] {
+            assert!(!is_valid_chroot(chroot), "{:?} should be rejected", chroot);
+        }
+    }
+}
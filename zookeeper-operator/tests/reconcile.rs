@@ -0,0 +1,206 @@
+//! Runs `reconcile_zk` against a fake API server, and checks the requests that it sends
+
+use std::time::Duration;
+
+use operator_commons::{
+    testing::{FakeApiServer, RecordedRequest},
+    ControllerOptions, ImageOptions,
+};
+use stackable_operator::kube::runtime::controller::Context;
+use structopt::StructOpt;
+use zookeeper_operator::{
+    crd::ZookeeperCluster,
+    zk_controller::{reconcile_zk, Ctx},
+};
+
+const SIMPLE: &str = r#"
+apiVersion: zookeeper.stackable.tech/v1alpha1
+kind: ZookeeperCluster
+metadata:
+  name: simple
+  namespace: default
+  uid: 0f6b2a5c-1e4d-4b7a-9c3e-8d2f1a0b6c54
+spec:
+  replicas: 3
+"#;
+
+const UID: &str = "0f6b2a5c-1e4d-4b7a-9c3e-8d2f1a0b6c54";
+const ZK_PATH: &str =
+    "/apis/zookeeper.stackable.tech/v1alpha1/namespaces/default/zookeeperclusters/simple";
+const STS_PATH: &str = "/apis/apps/v1/namespaces/default/statefulsets/simple-servers";
+const FIELD_MANAGER: &str = "zookeeper.stackable.tech/zookeepercluster";
+
+fn ctx(server: &FakeApiServer, dry_run: bool) -> Context<Ctx> {
+    let options = ControllerOptions::from_iter([
+        "zookeeper-operator",
+        "--startup-jitter=0s",
+        "--apply-qps=1000",
+        "--apply-burst=1000",
+    ]);
+    Context::new(Ctx {
+        kube: server.client(),
+        cluster_domain: "cluster.local".to_string(),
+        image_options: ImageOptions::default(),
+        dry_run,
+        reconcile_permits: options.reconcile_permits(),
+        apply_rate_limiter: options.apply_rate_limiter(),
+        log_apply_diffs: false,
+        resync_interval: options.resync_interval,
+        health_probe_interval: Duration::from_secs(30),
+    })
+}
+
+fn server() -> FakeApiServer {
+    let server = FakeApiServer::new();
+    server.insert(&serde_yaml::from_str::<ZookeeperCluster>(SIMPLE).unwrap());
+    server
+}
+
+fn live_zk(server: &FakeApiServer) -> ZookeeperCluster {
+    serde_json::from_value(server.get(ZK_PATH).unwrap()).unwrap()
+}
+
+/// Checks that every apply is a forced server-side apply by the controller, of an object that the cluster controls
+fn assert_applies_are_owned(requests: &[RecordedRequest]) {
+    let applies = requests
+        .iter()
+        .filter(|request| request.is_apply())
+        .collect::<Vec<_>>();
+    assert!(!applies.is_empty());
+    for apply in applies {
+        assert_eq!(apply.query["fieldManager"], FIELD_MANAGER, "{}", apply.path);
+        assert_eq!(apply.query["force"], "true", "{}", apply.path);
+        let body = apply.body.as_ref().unwrap();
+        let owner_refs = body["metadata"]["ownerReferences"].as_array();
+        assert!(
+            owner_refs
+                .into_iter()
+                .flatten()
+                .any(|owner_ref| owner_ref["uid"] == UID && owner_ref["controller"] == true),
+            "{} is not controlled by the cluster",
+            apply.path
+        );
+    }
+}
+
+/// Reasons of the events that were published
+fn event_reasons(requests: &[RecordedRequest]) -> Vec<String> {
+    requests
+        .iter()
+        .filter(|request| request.method == "POST" && request.path.ends_with("/events"))
+        .filter_map(|request| {
+            request.body.as_ref()?["reason"]
+                .as_str()
+                .map(str::to_string)
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn cluster_objects_are_applied() {
+    let server = server();
+    reconcile_zk(live_zk(&server), ctx(&server, false))
+        .await
+        .unwrap();
+    let requests = server.take_requests();
+    assert_applies_are_owned(&requests);
+    let applied = requests
+        .iter()
+        .filter(|request| request.is_apply())
+        .map(|request| request.path.as_str())
+        .collect::<Vec<_>>();
+    for path in [
+        "/api/v1/namespaces/default/services/simple",
+        "/api/v1/namespaces/default/services/simple-servers",
+        "/api/v1/namespaces/default/configmaps/simple-servers",
+        STS_PATH,
+    ] {
+        assert!(applied.contains(&path), "{} was not applied", path);
+    }
+    let sts = server.get(STS_PATH).unwrap();
+    assert_eq!(sts["spec"]["replicas"], 3);
+    assert!(requests
+        .iter()
+        .any(|request| request.method == "PATCH" && request.path == format!("{}/status", ZK_PATH)));
+    // The StatefulSet is new, so it was neither scaled nor restarted
+    assert!(event_reasons(&requests).is_empty());
+}
+
+#[tokio::test]
+async fn scaling_is_announced() {
+    let server = server();
+    let ctx = ctx(&server, false);
+    reconcile_zk(live_zk(&server), ctx.clone()).await.unwrap();
+    server.take_requests();
+
+    server.update(ZK_PATH, serde_json::json!({ "spec": { "replicas": 5 } }));
+    reconcile_zk(live_zk(&server), ctx).await.unwrap();
+    let requests = server.take_requests();
+    assert_eq!(server.get(STS_PATH).unwrap()["spec"]["replicas"], 5);
+    assert_eq!(event_reasons(&requests), ["Scaled"]);
+}
+
+#[tokio::test]
+async fn restarts_are_announced() {
+    let server = server();
+    let ctx = ctx(&server, false);
+    reconcile_zk(live_zk(&server), ctx.clone()).await.unwrap();
+    reconcile_zk(live_zk(&server), ctx.clone()).await.unwrap();
+    // Reconciling an unchanged cluster restarts nothing
+    assert!(event_reasons(&server.take_requests()).is_empty());
+
+    server.update(
+        ZK_PATH,
+        serde_json::json!({ "spec": { "image": { "tag": "3.6.3-stackable0" } } }),
+    );
+    reconcile_zk(live_zk(&server), ctx).await.unwrap();
+    assert_eq!(event_reasons(&server.take_requests()), ["RollingRestart"]);
+}
+
+#[tokio::test]
+async fn volume_changes_are_rejected() {
+    let server = server();
+    let ctx = ctx(&server, false);
+    reconcile_zk(live_zk(&server), ctx.clone()).await.unwrap();
+    server.take_requests();
+
+    server.update(ZK_PATH, serde_json::json!({ "spec": { "dataLogDir": {} } }));
+    let err = reconcile_zk(live_zk(&server), ctx).await.unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("cannot be changed from [data] to [data, datalog]"),
+        "{}",
+        err
+    );
+    let requests = server.take_requests();
+    // Nothing is applied, so that the cluster isn't left half-updated
+    assert!(!requests.iter().any(|request| request.is_apply()));
+    assert_eq!(
+        server.get(STS_PATH).unwrap()["spec"]["volumeClaimTemplates"]
+            .as_array()
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(event_reasons(&requests), ["ReconcileFailed"]);
+}
+
+#[tokio::test]
+async fn dry_runs_change_nothing() {
+    let server = server();
+    let paths = server.paths();
+    reconcile_zk(live_zk(&server), ctx(&server, true))
+        .await
+        .unwrap();
+    let requests = server.take_requests();
+    assert_applies_are_owned(&requests);
+    for request in requests.iter().filter(|request| request.is_write()) {
+        assert!(
+            request.is_dry_run(),
+            "{} {} is not a dry run",
+            request.method,
+            request.path
+        );
+    }
+    assert_eq!(server.paths(), paths);
+}